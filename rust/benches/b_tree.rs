@@ -0,0 +1,118 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use data_structures::b_tree::InsertStrategy;
+use data_structures::{BTree, ConstBTree};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+const N: u64 = 10_000;
+const ORDERS: [usize; 3] = [5, 16, 64];
+
+fn shuffled_keys() -> Vec<u64> {
+    let mut keys: Vec<u64> = (0..N).collect();
+    keys.shuffle(&mut StdRng::seed_from_u64(42));
+    keys
+}
+
+fn build(order: usize, strategy: InsertStrategy, keys: &[u64]) -> BTree<u64> {
+    let mut btree = BTree::with_strategy(order, strategy);
+    for &k in keys {
+        btree.insert(k);
+    }
+    btree
+}
+
+/// Returns the strategies to compare for an order (TopDown needs an even order)
+fn strategies(order: usize) -> Vec<(&'static str, InsertStrategy)> {
+    let mut strategies = vec![("bottom_up", InsertStrategy::BottomUp), ("b_star", InsertStrategy::BStar)];
+    if order.is_multiple_of(2) {
+        strategies.insert(0, ("top_down", InsertStrategy::TopDown));
+    }
+    strategies
+}
+
+/// Pre-emptive and on-overflow splits in two against B* (redistribute, then split two into three)
+fn bench_split_strategy_insert(c: &mut Criterion) {
+    let ascending: Vec<u64> = (0..N).collect();
+    let random = shuffled_keys();
+
+    let mut group = c.benchmark_group("split_strategy_insert");
+    group.throughput(Throughput::Elements(N));
+    for order in ORDERS {
+        for (input, keys) in [("ascending", &ascending), ("random", &random)] {
+            for (name, strategy) in strategies(order) {
+                group.bench_with_input(BenchmarkId::new(format!("{}/{}", name, input), order), &order, |b, &order| {
+                    b.iter(|| build(order, strategy, black_box(keys)))
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+/// Denser B* nodes make for shallower trees, which should show up in search times
+fn bench_split_strategy_search(c: &mut Criterion) {
+    let random = shuffled_keys();
+
+    let mut group = c.benchmark_group("split_strategy_search");
+    group.throughput(Throughput::Elements(N));
+    for order in ORDERS {
+        for (name, strategy) in strategies(order) {
+            let btree = build(order, strategy, &random);
+            group.bench_with_input(BenchmarkId::new(name, order), &btree, |b, btree| {
+                b.iter(|| {
+                    for &k in &random {
+                        black_box(btree.search(k));
+                    }
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Vec-backed nodes with a runtime order against inline ArrayVec nodes with a const order
+fn bench_node_storage(c: &mut Criterion) {
+    let random = shuffled_keys();
+
+    let mut group = c.benchmark_group("node_storage");
+    group.throughput(Throughput::Elements(N));
+    group.bench_function("insert/vec/16", |b| {
+        b.iter(|| build(16, InsertStrategy::BottomUp, black_box(&random)))
+    });
+    group.bench_function("insert/const/16", |b| {
+        b.iter(|| {
+            let mut btree = ConstBTree::<u64, 16>::new();
+            for &k in black_box(&random) {
+                btree.insert(k);
+            }
+            btree
+        })
+    });
+
+    let btree = build(16, InsertStrategy::BottomUp, &random);
+    group.bench_function("search/vec/16", |b| {
+        b.iter(|| {
+            for &k in &random {
+                black_box(btree.search(k));
+            }
+        })
+    });
+    let mut const_btree = ConstBTree::<u64, 16>::new();
+    for &k in &random {
+        const_btree.insert(k);
+    }
+    group.bench_function("search/const/16", |b| {
+        b.iter(|| {
+            for &k in &random {
+                black_box(const_btree.search(k));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_split_strategy_insert, bench_split_strategy_search, bench_node_storage);
+criterion_main!(benches);
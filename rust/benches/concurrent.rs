@@ -0,0 +1,133 @@
+use std::hint::black_box;
+use std::sync::RwLock;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use data_structures::{BLinkTree, BTree, ConcurrentBTree, OptimisticBTree};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const ORDER: usize = 32;
+const KEYS: u64 = 100_000;
+const OPS_PER_THREAD: u64 = 20_000;
+const THREADS: [u64; 4] = [1, 2, 4, 8];
+
+/// The operations the benchmarks need, over each of the thread-safe trees
+trait SharedTree: Sync {
+    fn search(&self, value: u64) -> bool;
+    fn insert(&self, value: u64);
+}
+
+/// Baseline: a single-threaded BTree behind one reader-writer lock
+impl SharedTree for RwLock<BTree<u64>> {
+    fn search(&self, value: u64) -> bool {
+        self.read().unwrap().search(value)
+    }
+
+    fn insert(&self, value: u64) {
+        let mut btree = self.write().unwrap();
+        // BTree keeps duplicates, so check first to keep the trees the same size
+        if !btree.search(value) {
+            btree.insert(value);
+        }
+    }
+}
+
+/// Pessimistic lock coupling (per-node RwLocks, crabbing)
+impl SharedTree for ConcurrentBTree<u64> {
+    fn search(&self, value: u64) -> bool {
+        ConcurrentBTree::search(self, value)
+    }
+
+    fn insert(&self, value: u64) {
+        ConcurrentBTree::insert(self, value);
+    }
+}
+
+/// Lehman-Yao right-links (one latch at a time)
+impl SharedTree for BLinkTree<u64> {
+    fn search(&self, value: u64) -> bool {
+        BLinkTree::search(self, value)
+    }
+
+    fn insert(&self, value: u64) {
+        BLinkTree::insert(self, value);
+    }
+}
+
+/// Optimistic lock coupling (version-validated reads)
+impl SharedTree for OptimisticBTree<u64> {
+    fn search(&self, value: u64) -> bool {
+        OptimisticBTree::search(self, value)
+    }
+
+    fn insert(&self, value: u64) {
+        OptimisticBTree::insert(self, value);
+    }
+}
+
+/// Returns a tree holding the even keys below 2 * KEYS
+fn build<S: SharedTree>(tree: S) -> S {
+    for val in 0..KEYS {
+        tree.insert(val * 2);
+    }
+    tree
+}
+
+/// Runs OPS_PER_THREAD operations on each of threads threads, iters times, and returns the total time
+///
+/// write_percent of the operations insert an odd key (so the tree grows), the rest search for any key
+fn run<S: SharedTree>(tree: &S, threads: u64, write_percent: u32, iters: u64) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for t in 0..threads {
+            s.spawn(move || {
+                let mut rng = StdRng::seed_from_u64(t);
+                for _ in 0..iters * OPS_PER_THREAD {
+                    let val = rng.random_range(0..2 * KEYS);
+                    if rng.random_ratio(write_percent, 100) {
+                        tree.insert(val | 1);
+                    } else {
+                        black_box(tree.search(val));
+                    }
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+/// Every tree over thread counts, for one mix of reads and writes
+fn bench_workload(c: &mut Criterion, name: &str, write_percent: u32) {
+    let mut group = c.benchmark_group(format!("concurrent/{}", name));
+    for threads in THREADS {
+        group.throughput(Throughput::Elements(threads * OPS_PER_THREAD));
+        // A fresh tree per thread count, so earlier inserts don't change the shape for later ones
+        let rwlock = build(RwLock::new(BTree::new(ORDER)));
+        group.bench_with_input(BenchmarkId::new("rwlock_btree", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| run(&rwlock, threads, write_percent, iters))
+        });
+        let coupling = build(ConcurrentBTree::new(ORDER));
+        group.bench_with_input(BenchmarkId::new("lock_coupling", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| run(&coupling, threads, write_percent, iters))
+        });
+        let b_link = build(BLinkTree::new(ORDER));
+        group.bench_with_input(BenchmarkId::new("b_link", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| run(&b_link, threads, write_percent, iters))
+        });
+        let optimistic = build(OptimisticBTree::new(ORDER));
+        group.bench_with_input(BenchmarkId::new("optimistic", threads), &threads, |b, &threads| {
+            b.iter_custom(|iters| run(&optimistic, threads, write_percent, iters))
+        });
+    }
+    group.finish();
+}
+
+fn bench_concurrent(c: &mut Criterion) {
+    bench_workload(c, "read_only", 0);
+    bench_workload(c, "read_mostly", 10);
+}
+
+criterion_group!(benches, bench_concurrent);
+criterion_main!(benches);
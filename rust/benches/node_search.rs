@@ -0,0 +1,63 @@
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use data_structures::b_tree::NodeSearch;
+use rand::distr::StandardUniform;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const PROBES: usize = 1024;
+const NODE_SIZES: [usize; 8] = [2, 4, 8, 16, 32, 64, 128, 256];
+const STRATEGIES: [(&str, NodeSearch); 4] = [
+    ("binary", NodeSearch::Binary),
+    ("linear", NodeSearch::Linear),
+    ("branchless_binary", NodeSearch::BranchlessBinary),
+    ("simd", NodeSearch::Simd),
+];
+
+/// Returns sorted distinct keys for one node, and values to search for (half of them present)
+fn node_and_probes<K: Ord + Copy>(size: usize, rng: &mut StdRng) -> (Vec<K>, Vec<K>)
+where
+    StandardUniform: rand::distr::Distribution<K>,
+{
+    let mut keys: Vec<K> = (0..size * 2).map(|_| rng.random()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.truncate(size);
+
+    let probes = (0..PROBES)
+        .map(|i| if i % 2 == 0 { keys[rng.random_range(0..keys.len())] } else { rng.random() })
+        .collect();
+    (keys, probes)
+}
+
+/// Every in-node search strategy over node sizes, to find where scanning stops beating binary search
+fn bench_key_type<K: Ord + Copy>(c: &mut Criterion, name: &str)
+where
+    StandardUniform: rand::distr::Distribution<K>,
+{
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut group = c.benchmark_group(format!("node_search/{}", name));
+    group.throughput(Throughput::Elements(PROBES as u64));
+    for size in NODE_SIZES {
+        let (keys, probes) = node_and_probes::<K>(size, &mut rng);
+        for (strategy_name, strategy) in STRATEGIES {
+            group.bench_with_input(BenchmarkId::new(strategy_name, size), &size, |b, _| {
+                b.iter(|| {
+                    for probe in &probes {
+                        black_box(strategy.search(black_box(&keys), probe));
+                    }
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_node_search(c: &mut Criterion) {
+    bench_key_type::<u32>(c, "u32");
+    bench_key_type::<u64>(c, "u64");
+}
+
+criterion_group!(benches, bench_node_search);
+criterion_main!(benches);
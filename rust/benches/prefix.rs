@@ -0,0 +1,132 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use data_structures::{BTree, PrefixBTree};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+const N: usize = 10_000;
+const ORDERS: [usize; 3] = [5, 16, 64];
+
+/// The system allocator, counting the bytes currently allocated
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+// SAFETY: every call is passed on to the system allocator unchanged
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        // SAFETY: the caller upholds the contract of GlobalAlloc::alloc
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        // SAFETY: the caller upholds the contract of GlobalAlloc::dealloc
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        // SAFETY: the caller upholds the contract of GlobalAlloc::realloc
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// URL-like keys, with long prefixes shared by many of them
+fn urls() -> Vec<Vec<u8>> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let hosts = ["https://www.example.com", "https://shop.example.com", "https://docs.example.org"];
+    let sections = ["/products/category/electronics/", "/products/category/garden/", "/api/v2/users/", "/blog/2025/"];
+    let mut keys: Vec<Vec<u8>> = (0..N)
+        .map(|i| format!("{}{}item-{:06}?ref={}", hosts[rng.random_range(0..hosts.len())], sections[rng.random_range(0..sections.len())], i, rng.random_range(0..10)).into_bytes())
+        .collect();
+    keys.shuffle(&mut rng);
+    keys
+}
+
+fn build_btree(order: usize, keys: &[Vec<u8>]) -> BTree<Vec<u8>> {
+    let mut btree = BTree::new(order);
+    for key in keys {
+        btree.insert(key.clone());
+    }
+    btree
+}
+
+fn build_prefix(order: usize, keys: &[Vec<u8>]) -> PrefixBTree {
+    let mut btree = PrefixBTree::new(order);
+    for key in keys {
+        btree.insert(key);
+    }
+    btree
+}
+
+/// Returns what build returns, and the bytes it holds on the heap
+fn heap_bytes<T>(build: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let built = build();
+    (built, ALLOCATED.load(Ordering::Relaxed) - before)
+}
+
+/// Heap bytes of BTree<Vec<u8>> against PrefixBTree for the same keys (printed, as criterion only
+/// measures time), then the time each takes to search for every key
+fn bench_prefix_memory(c: &mut Criterion) {
+    let keys = urls();
+    let key_bytes: usize = keys.iter().map(|key| key.len()).sum();
+    println!("{} keys, {} bytes in total", N, key_bytes);
+
+    let mut group = c.benchmark_group("prefix_search");
+    group.throughput(Throughput::Elements(N as u64));
+    for order in ORDERS {
+        let (btree, full) = heap_bytes(|| build_btree(order, &keys));
+        let (prefix, compressed) = heap_bytes(|| build_prefix(order, &keys));
+        println!(
+            "order {:>2}: BTree<Vec<u8>> {:>8} heap bytes, PrefixBTree {:>8} heap bytes ({} key bytes), {:.1}% saved",
+            order,
+            full,
+            compressed,
+            prefix.key_bytes(),
+            100.0 * (1.0 - compressed as f64 / full as f64)
+        );
+
+        group.bench_with_input(BenchmarkId::new("btree", order), &btree, |b, btree| {
+            b.iter(|| {
+                for key in &keys {
+                    black_box(btree.search(key.clone()));
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("prefix", order), &prefix, |b, prefix| {
+            b.iter(|| {
+                for key in &keys {
+                    black_box(prefix.search(key));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Inserting pays for keeping the prefixes up to date (and BTree for copying each key)
+fn bench_prefix_insert(c: &mut Criterion) {
+    let keys = urls();
+
+    let mut group = c.benchmark_group("prefix_insert");
+    group.throughput(Throughput::Elements(N as u64));
+    for order in ORDERS {
+        group.bench_with_input(BenchmarkId::new("btree", order), &order, |b, &order| b.iter(|| build_btree(order, black_box(&keys))));
+        group.bench_with_input(BenchmarkId::new("prefix", order), &order, |b, &order| b.iter(|| build_prefix(order, black_box(&keys))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_prefix_memory, bench_prefix_insert);
+criterion_main!(benches);
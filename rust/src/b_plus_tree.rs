@@ -0,0 +1,762 @@
+use std::fmt::Debug;
+use std::ops::{Bound, RangeBounds};
+
+mod mvcc;
+
+pub use mvcc::{GcThread, MvccBPlusTree, Transaction, WriteConflict};
+
+// https://en.wikipedia.org/wiki/B%2B_tree
+// A B+ tree keeps every key/value pair in its leaves, and internal nodes only hold separator keys
+// that route searches. The leaves are chained together in key order by sibling links, so a range
+// scan finds its first leaf once and then walks the chain instead of going back up and down the tree.
+
+// As with BTree, this uses the Knuth order m: an internal node has at most m children (m-1 separator
+// keys), and a leaf holds at most m-1 key/value pairs.
+// An internal node (other than the root): min ceiling(m/2) children, so min ceiling(m/2)-1 keys
+// A leaf node (other than the root): min floor(m/2) = ceiling((m-1)/2) key/value pairs
+
+// Separator keys[i] of an internal node satisfies: keys in children[i] < keys[i] <= keys in children[i+1]
+
+// Inserts are bottom-up: a pair is inserted into its leaf, and a node that overflows (holds m keys)
+// is split in two, with the separator pushed up into the parent (which may overflow in turn).
+
+// Nodes live in a Vec and refer to each other by index, which lets leaves link to their next
+// sibling without shared ownership. Nodes freed by merges and root shrinkage go on a free list.
+
+pub struct BPlusTree<K: PartialOrd + Debug + Clone, V> {
+    nodes: Vec<Node<K, V>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    order: usize,
+    len: usize,
+}
+
+struct Node<K, V> {
+    keys: Vec<K>,
+    // Values of a leaf (values[i] belongs to keys[i]), empty for internal nodes
+    values: Vec<V>,
+    // Indices of the children of an internal node, empty for leaves
+    children: Vec<usize>,
+    leaf: bool,
+    // Index of the next leaf in key order (leaves only)
+    next: Option<usize>,
+}
+
+/// Iterator over a range of key/value pairs of a BPlusTree, in key order
+///
+/// Created by BPlusTree::range and BPlusTree::iter
+pub struct Range<'a, K: PartialOrd + Debug + Clone, V> {
+    tree: &'a BPlusTree<K, V>,
+    leaf: Option<usize>,
+    idx: usize,
+    end: Bound<K>,
+}
+
+impl<K: PartialOrd + Debug + Clone, V> BPlusTree<K, V> {
+    /// Constructor method for BPlusTree
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    pub fn new(m: usize) -> Self {
+        assert!(m >= 3, "BPlusTree order must be at least 3");
+        BPlusTree { nodes: vec![], free: vec![], root: None, order: m, len: 0 }
+    }
+
+    /// Returns the number of key/value pairs in the tree
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the tree holds no key/value pairs
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns a reference to the value stored under key, if any
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let leaf = self.find_leaf(key)?;
+        let node = &self.nodes[leaf];
+        match node.search(key) {
+            (true, idx) => Some(&node.values[idx]),
+            (false, _) => None,
+        }
+    }
+
+    /// Returns a mutable reference to the value stored under key, if any
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let leaf = self.find_leaf(key)?;
+        let node = &mut self.nodes[leaf];
+        match node.search(key) {
+            (true, idx) => Some(&mut node.values[idx]),
+            (false, _) => None,
+        }
+    }
+
+    /// Returns true if key is present
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts a key/value pair into the tree
+    ///
+    /// Returns the previous value if the key was already present (the key itself is not replaced)
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let root = match self.root {
+            Some(r) => r,
+            None => {
+                // If the tree is empty, create a new root leaf holding the pair
+                let leaf = self.alloc(Node { keys: vec![key], values: vec![value], children: vec![], leaf: true, next: None });
+                self.root = Some(leaf);
+                self.len += 1;
+                return None;
+            },
+        };
+
+        let (old, split) = self.insert_rec(root, key, value);
+        if old.is_none() {
+            self.len += 1;
+        }
+
+        // If the root split, make a new root above the two halves
+        if let Some((separator, right)) = split {
+            let new_root = self.alloc(Node { keys: vec![separator], values: vec![], children: vec![root, right], leaf: false, next: None });
+            self.root = Some(new_root);
+        }
+        old
+    }
+
+    /// Removes a key from the tree
+    ///
+    /// Returns the value stored under the key, or None if it was not present
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let root = self.root?;
+        let removed = self.remove_rec(root, key);
+        if removed.is_some() {
+            self.len -= 1;
+        }
+
+        // Shrink the tree if the root is an internal node with a single child, or an empty leaf
+        let node = &self.nodes[root];
+        if !node.leaf && node.keys.is_empty() {
+            self.root = Some(node.children[0]);
+            self.release(root);
+        } else if node.leaf && node.keys.is_empty() {
+            self.root = None;
+            self.release(root);
+        }
+        removed
+    }
+
+    /// Returns an iterator over the key/value pairs whose keys fall in range, in key order
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V> {
+        let end = range.end_bound().cloned();
+        let (leaf, idx) = match range.start_bound() {
+            Bound::Unbounded => (self.first_leaf(), 0),
+            Bound::Included(start) => self.lower_bound(start, true),
+            Bound::Excluded(start) => self.lower_bound(start, false),
+        };
+        Range { tree: self, leaf, idx, end }
+    }
+
+    /// Returns an iterator over all key/value pairs, in key order
+    pub fn iter(&self) -> Range<'_, K, V> {
+        self.range(..)
+    }
+
+    /// Helper that stores a node in the arena (reusing a freed slot if possible) and returns its index
+    fn alloc(&mut self, node: Node<K, V>) -> usize {
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            },
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            },
+        }
+    }
+
+    /// Helper that clears a node that is no longer in the tree and puts its slot on the free list
+    fn release(&mut self, id: usize) {
+        let node = &mut self.nodes[id];
+        node.keys.clear();
+        node.values.clear();
+        node.children.clear();
+        node.next = None;
+        self.free.push(id);
+    }
+
+    /// Helper that returns mutable refs to two different nodes
+    fn two_mut(&mut self, a: usize, b: usize) -> (&mut Node<K, V>, &mut Node<K, V>) {
+        assert_ne!(a, b, "Cannot borrow the same node twice");
+        if a < b {
+            let (left, right) = self.nodes.split_at_mut(b);
+            (&mut left[a], &mut right[0])
+        } else {
+            let (left, right) = self.nodes.split_at_mut(a);
+            (&mut right[0], &mut left[b])
+        }
+    }
+
+    /// Helper that descends from the root to the leaf key belongs in
+    fn find_leaf(&self, key: &K) -> Option<usize> {
+        let mut id = self.root?;
+        loop {
+            let node = &self.nodes[id];
+            if node.leaf {
+                return Some(id);
+            }
+            id = node.children[node.child_idx(key)];
+        }
+    }
+
+    /// Helper that returns the leftmost leaf
+    fn first_leaf(&self) -> Option<usize> {
+        let mut id = self.root?;
+        while !self.nodes[id].leaf {
+            id = self.nodes[id].children[0];
+        }
+        Some(id)
+    }
+
+    /// Helper that finds the position of the first pair with a key >= start (or > start if not inclusive)
+    fn lower_bound(&self, start: &K, inclusive: bool) -> (Option<usize>, usize) {
+        let leaf = match self.find_leaf(start) {
+            Some(leaf) => leaf,
+            None => return (None, 0),
+        };
+        let (found, idx) = self.nodes[leaf].search(start);
+        let idx = if found && !inclusive { idx + 1 } else { idx };
+        (Some(leaf), idx)
+    }
+
+    /// Inserts a pair into the subtree rooted at id (called recursively)
+    ///
+    /// Returns the replaced value (if the key was present) and, if the node overflowed and was split,
+    /// the separator and index of the new right node for the parent to insert
+    fn insert_rec(&mut self, id: usize, key: K, value: V) -> (Option<V>, Option<(K, usize)>) {
+        if self.nodes[id].leaf {
+            // Base case: insert (or replace) in the leaf
+            let node = &mut self.nodes[id];
+            let (found, idx) = node.search(&key);
+            if found {
+                return (Some(std::mem::replace(&mut node.values[idx], value)), None);
+            }
+            node.keys.insert(idx, key);
+            node.values.insert(idx, value);
+        } else {
+            // Recursively insert into the child the key belongs in, and add its separator if it split
+            let child_idx = self.nodes[id].child_idx(&key);
+            let child = self.nodes[id].children[child_idx];
+            let (old, split) = self.insert_rec(child, key, value);
+            match split {
+                Some((separator, right)) => {
+                    let node = &mut self.nodes[id];
+                    node.keys.insert(child_idx, separator);
+                    node.children.insert(child_idx + 1, right);
+                },
+                None => return (old, None),
+            }
+        }
+
+        // Split the node if it now holds more than m-1 keys
+        if self.nodes[id].keys.len() < self.order {
+            return (None, None);
+        }
+        (None, Some(self.split(id)))
+    }
+
+    /// Splits an overflowing node in two, returning the separator for the parent and the new right node
+    fn split(&mut self, id: usize) -> (K, usize) {
+        let node = &mut self.nodes[id];
+        let mid = node.keys.len() / 2;
+
+        if node.leaf {
+            // Leaf: the right half keeps all of its pairs, and a copy of its first key becomes the separator
+            let right_keys = node.keys.split_off(mid);
+            let right_values = node.values.split_off(mid);
+            let separator = right_keys[0].clone();
+            let next = node.next;
+            let right = self.alloc(Node { keys: right_keys, values: right_values, children: vec![], leaf: true, next });
+            // Link the new leaf in after the old one
+            self.nodes[id].next = Some(right);
+            (separator, right)
+        } else {
+            // Internal: the middle key moves up into the parent (as in BTree::split_child)
+            let right_keys = node.keys.split_off(mid + 1);
+            let separator = node.keys.pop().expect("Middle key missing in split");
+            let right_children = node.children.split_off(mid + 1);
+            let right = self.alloc(Node { keys: right_keys, values: vec![], children: right_children, leaf: false, next: None });
+            (separator, right)
+        }
+    }
+
+    /// Removes a key from the subtree rooted at id (called recursively)
+    ///
+    /// Any child left with too few keys is fixed by borrowing from a sibling or merging with it,
+    /// so only the node at id itself may be left underfull (which the parent then fixes)
+    fn remove_rec(&mut self, id: usize, key: &K) -> Option<V> {
+        if self.nodes[id].leaf {
+            // Base case: remove the pair from the leaf
+            let node = &mut self.nodes[id];
+            return match node.search(key) {
+                (true, idx) => {
+                    node.keys.remove(idx);
+                    Some(node.values.remove(idx))
+                },
+                (false, _) => None,
+            };
+        }
+
+        let child_idx = self.nodes[id].child_idx(key);
+        let child = self.nodes[id].children[child_idx];
+        let removed = self.remove_rec(child, key);
+
+        if removed.is_some() && self.nodes[child].keys.len() < self.min_keys(child) {
+            self.rebalance(id, child_idx);
+        }
+        removed
+    }
+
+    /// Helper for the minimum number of keys a non-root node may hold
+    fn min_keys(&self, id: usize) -> usize {
+        if self.nodes[id].leaf {
+            self.order / 2
+        } else {
+            self.order.div_ceil(2) - 1
+        }
+    }
+
+    /// Fixes the underfull child at child_idx of node id by borrowing from a sibling, or merging if neither can spare a key
+    fn rebalance(&mut self, id: usize, child_idx: usize) {
+        let children = &self.nodes[id].children;
+        let left = if child_idx > 0 { Some(children[child_idx - 1]) } else { None };
+        let right = children.get(child_idx + 1).copied();
+
+        if let Some(left) = left
+            && self.nodes[left].keys.len() > self.min_keys(left) {
+            self.borrow_from_left(id, child_idx);
+        } else if let Some(right) = right
+            && self.nodes[right].keys.len() > self.min_keys(right) {
+            self.borrow_from_right(id, child_idx);
+        } else if left.is_some() {
+            self.merge(id, child_idx - 1);
+        } else {
+            self.merge(id, child_idx);
+        }
+    }
+
+    /// Helper that moves the last key of the left sibling into the child at child_idx
+    fn borrow_from_left(&mut self, id: usize, child_idx: usize) {
+        let left = self.nodes[id].children[child_idx - 1];
+        let child = self.nodes[id].children[child_idx];
+        let (left_node, child_node) = self.two_mut(left, child);
+
+        if child_node.leaf {
+            // Leaf: move the last pair across, and the child's new first key becomes the separator
+            child_node.keys.insert(0, left_node.keys.pop().expect("Left sibling has no keys"));
+            child_node.values.insert(0, left_node.values.pop().expect("Left sibling has no values"));
+            let separator = child_node.keys[0].clone();
+            self.nodes[id].keys[child_idx - 1] = separator;
+        } else {
+            // Internal: rotate through the parent (as in BTree's rotate_right)
+            let key = left_node.keys.pop().expect("Left sibling has no keys");
+            let last_child = left_node.children.pop().expect("Left sibling has no children");
+            child_node.children.insert(0, last_child);
+            let separator = std::mem::replace(&mut self.nodes[id].keys[child_idx - 1], key);
+            self.nodes[child].keys.insert(0, separator);
+        }
+    }
+
+    /// Helper that moves the first key of the right sibling into the child at child_idx
+    fn borrow_from_right(&mut self, id: usize, child_idx: usize) {
+        let child = self.nodes[id].children[child_idx];
+        let right = self.nodes[id].children[child_idx + 1];
+        let (child_node, right_node) = self.two_mut(child, right);
+
+        if child_node.leaf {
+            // Leaf: move the first pair across, and the sibling's new first key becomes the separator
+            child_node.keys.push(right_node.keys.remove(0));
+            child_node.values.push(right_node.values.remove(0));
+            let separator = right_node.keys[0].clone();
+            self.nodes[id].keys[child_idx] = separator;
+        } else {
+            // Internal: rotate through the parent (as in BTree's rotate_left)
+            let key = right_node.keys.remove(0);
+            let first_child = right_node.children.remove(0);
+            child_node.children.push(first_child);
+            let separator = std::mem::replace(&mut self.nodes[id].keys[child_idx], key);
+            self.nodes[child].keys.push(separator);
+        }
+    }
+
+    /// Helper that merges the child at child_idx + 1 into the child at child_idx and frees it
+    fn merge(&mut self, id: usize, child_idx: usize) {
+        let separator = self.nodes[id].keys.remove(child_idx);
+        let right = self.nodes[id].children.remove(child_idx + 1);
+        let left = self.nodes[id].children[child_idx];
+        let (left_node, right_node) = self.two_mut(left, right);
+
+        if left_node.leaf {
+            // Leaf: the separator is only a copy of a key, so it is dropped, and the leaf chain skips the right node
+            left_node.keys.append(&mut right_node.keys);
+            left_node.values.append(&mut right_node.values);
+            left_node.next = right_node.next;
+        } else {
+            // Internal: the separator comes down between the two halves (as in BTree's merge)
+            left_node.keys.push(separator);
+            left_node.keys.append(&mut right_node.keys);
+            left_node.children.append(&mut right_node.children);
+        }
+        self.release(right);
+    }
+
+    /// Helper (test) function that checks the B+ tree invariants
+    ///
+    /// Checks key counts, key ordering against the separators, uniform leaf depth, the leaf chain and len
+    #[cfg(test)]
+    fn check_invariants(&self) {
+        let root = match self.root {
+            Some(r) => r,
+            None => {
+                assert_eq!(self.len, 0);
+                return;
+            },
+        };
+
+        let mut leaves = vec![];
+        self.check_node(root, None, None, true, 0, &mut None, &mut leaves);
+
+        // The leaf chain visits every leaf left to right
+        let mut chain = vec![];
+        let mut leaf = self.first_leaf();
+        while let Some(id) = leaf {
+            chain.push(id);
+            leaf = self.nodes[id].next;
+        }
+        assert_eq!(chain, leaves, "Leaf chain does not match leaf order");
+
+        let count: usize = leaves.iter().map(|&id| self.nodes[id].keys.len()).sum();
+        assert_eq!(count, self.len);
+    }
+
+    #[cfg(test)]
+    #[allow(clippy::too_many_arguments)]
+    fn check_node(&self, id: usize, lower: Option<&K>, upper: Option<&K>, is_root: bool, depth: usize, leaf_depth: &mut Option<usize>, leaves: &mut Vec<usize>) {
+        let node = &self.nodes[id];
+        assert!(node.keys.len() < self.order, "Node has too many keys");
+        if !is_root {
+            assert!(node.keys.len() >= self.min_keys(id), "Node has too few keys");
+        }
+        assert!(node.keys.windows(2).all(|w| w[0] < w[1]), "Keys are not sorted");
+        if let Some(lower) = lower {
+            assert!(node.keys.iter().all(|k| k >= lower), "Key below separator");
+        }
+        if let Some(upper) = upper {
+            assert!(node.keys.iter().all(|k| k < upper), "Key above separator");
+        }
+
+        if node.leaf {
+            assert_eq!(node.keys.len(), node.values.len());
+            assert_eq!(*leaf_depth.get_or_insert(depth), depth, "Leaves are at different depths");
+            leaves.push(id);
+            return;
+        }
+
+        assert_eq!(node.children.len(), node.keys.len() + 1);
+        for (i, &child) in node.children.iter().enumerate() {
+            let lower = if i == 0 { lower } else { Some(&node.keys[i - 1]) };
+            let upper = if i == node.keys.len() { upper } else { Some(&node.keys[i]) };
+            self.check_node(child, lower, upper, false, depth + 1, leaf_depth, leaves);
+        }
+    }
+}
+
+impl<K: PartialOrd + Debug + Clone, V> Node<K, V> {
+    /// Binary search helper for B+ tree node
+    ///
+    /// Returns true and idx of key if present, false and idx of smallest key greater than key otherwise
+    fn search(&self, key: &K) -> (bool, usize) {
+        let mut left = 0;
+        let mut right = self.keys.len();
+
+        // Range is [left, right) - left inclusive, right exclusive
+        while left < right {
+            let mid = left + (right - left) / 2;
+
+            if self.keys[mid] == *key {
+                return (true, mid);
+            }
+
+            if self.keys[mid] < *key {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+
+        (false, left)
+    }
+
+    /// Returns the index of the child of an internal node that key belongs in
+    ///
+    /// A key equal to a separator belongs to the right of it
+    fn child_idx(&self, key: &K) -> usize {
+        match self.search(key) {
+            (true, idx) => idx + 1,
+            (false, idx) => idx,
+        }
+    }
+}
+
+impl<'a, K: PartialOrd + Debug + Clone, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = &self.tree.nodes[self.leaf?];
+
+            // Move on to the next leaf in the chain once this one is used up
+            if self.idx >= node.keys.len() {
+                self.leaf = node.next;
+                self.idx = 0;
+                continue;
+            }
+
+            let key = &node.keys[self.idx];
+            let in_range = match &self.end {
+                Bound::Unbounded => true,
+                Bound::Included(end) => key <= end,
+                Bound::Excluded(end) => key < end,
+            };
+            if !in_range {
+                self.leaf = None;
+                return None;
+            }
+
+            self.idx += 1;
+            return Some((key, &node.values[self.idx - 1]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_new_bplus_tree() {
+        let tree: BPlusTree<i32, i32> = BPlusTree::new(3);
+        assert!(tree.is_empty());
+        assert_eq!(tree.get(&1), None);
+        assert_eq!(tree.iter().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "BPlusTree order must be at least 3")]
+    fn test_invalid_order() {
+        let _tree: BPlusTree<i32, i32> = BPlusTree::new(2);
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let mut tree = BPlusTree::new(3);
+        for i in 1..=50 {
+            assert_eq!(tree.insert(i, i * 10), None);
+            tree.check_invariants();
+        }
+
+        assert_eq!(tree.len(), 50);
+        for i in 1..=50 {
+            assert_eq!(tree.get(&i), Some(&(i * 10)));
+        }
+        assert_eq!(tree.get(&0), None);
+        assert_eq!(tree.get(&51), None);
+    }
+
+    #[test]
+    fn test_insert_descending_order() {
+        let mut tree = BPlusTree::new(4);
+        for i in (1..=50).rev() {
+            tree.insert(i, i);
+            tree.check_invariants();
+        }
+
+        let keys: Vec<i32> = tree.iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, (1..=50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_insert_replaces_value() {
+        let mut tree = BPlusTree::new(3);
+        tree.insert("a", 1);
+        tree.insert("b", 2);
+
+        assert_eq!(tree.insert("a", 3), Some(1));
+        assert_eq!(tree.get(&"a"), Some(&3));
+        assert_eq!(tree.len(), 2);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut tree = BPlusTree::new(3);
+        for i in 1..=10 {
+            tree.insert(i, i);
+        }
+
+        *tree.get_mut(&7).unwrap() = 70;
+        assert_eq!(tree.get(&7), Some(&70));
+        assert!(tree.get_mut(&11).is_none());
+    }
+
+    #[test]
+    fn test_remove_from_leaf() {
+        let mut tree = BPlusTree::new(5);
+        for i in 1..=3 {
+            tree.insert(i, i);
+        }
+
+        assert_eq!(tree.remove(&2), Some(2));
+        assert_eq!(tree.remove(&2), None);
+        assert_eq!(tree.len(), 2);
+        assert!(!tree.contains_key(&2));
+        tree.check_invariants();
+    }
+
+    #[test]
+    fn test_remove_borrows_and_merges() {
+        let mut tree = BPlusTree::new(3);
+        for i in 1..=30 {
+            tree.insert(i, i);
+        }
+
+        // Removing from the front borrows from right siblings and merges
+        for i in 1..=15 {
+            assert_eq!(tree.remove(&i), Some(i));
+            tree.check_invariants();
+        }
+        // Removing from the back borrows from left siblings and merges
+        for i in (16..=30).rev() {
+            assert_eq!(tree.remove(&i), Some(i));
+            tree.check_invariants();
+        }
+
+        assert!(tree.is_empty());
+        assert!(tree.root.is_none());
+    }
+
+    #[test]
+    fn test_remove_nonexistent() {
+        let mut tree: BPlusTree<i32, i32> = BPlusTree::new(3);
+        assert_eq!(tree.remove(&1), None);
+
+        tree.insert(1, 1);
+        assert_eq!(tree.remove(&2), None);
+        assert_eq!(tree.len(), 1);
+    }
+
+    #[test]
+    fn test_freed_nodes_are_reused() {
+        let mut tree = BPlusTree::new(3);
+        for i in 1..=100 {
+            tree.insert(i, i);
+        }
+        let allocated = tree.nodes.len();
+
+        for i in 1..=100 {
+            tree.remove(&i);
+        }
+        assert_eq!(tree.free.len(), allocated);
+
+        // Rebuilding the same tree uses only recycled slots
+        for i in 1..=100 {
+            tree.insert(i, i);
+        }
+        assert_eq!(tree.nodes.len(), allocated);
+        tree.check_invariants();
+    }
+
+    #[test]
+    fn test_range_bounds() {
+        let mut tree = BPlusTree::new(4);
+        for i in (0..100).step_by(2) {
+            tree.insert(i, i * 10);
+        }
+
+        let keys = |r: Vec<(&i32, &i32)>| r.into_iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        assert_eq!(keys(tree.range(10..20).collect()), vec![10, 12, 14, 16, 18]);
+        assert_eq!(keys(tree.range(10..=20).collect()), vec![10, 12, 14, 16, 18, 20]);
+        assert_eq!(keys(tree.range(11..=19).collect()), vec![12, 14, 16, 18]);
+        assert_eq!(keys(tree.range((Bound::Excluded(10), Bound::Included(14))).collect()), vec![12, 14]);
+        assert_eq!(keys(tree.range(..6).collect()), vec![0, 2, 4]);
+        assert_eq!(keys(tree.range(94..).collect()), vec![94, 96, 98]);
+        assert_eq!(keys(tree.range(99..).collect()), Vec::<i32>::new());
+        assert_eq!(keys(tree.range((Bound::Included(20), Bound::Excluded(10))).collect()), Vec::<i32>::new());
+        assert_eq!(tree.range(..).count(), 50);
+
+        let values: Vec<i32> = tree.range(40..46).map(|(_, v)| *v).collect();
+        assert_eq!(values, vec![400, 420, 440]);
+    }
+
+    #[test]
+    fn test_range_follows_leaf_chain_after_removals() {
+        let mut tree = BPlusTree::new(3);
+        for i in 1..=40 {
+            tree.insert(i, ());
+        }
+        for i in (1..=40).filter(|i| i % 3 == 0) {
+            tree.remove(&i);
+        }
+        tree.check_invariants();
+
+        let keys: Vec<i32> = tree.range(5..=20).map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![5, 7, 8, 10, 11, 13, 14, 16, 17, 19, 20]);
+    }
+
+    #[test]
+    fn test_string_keys() {
+        let mut tree = BPlusTree::new(3);
+        for word in ["pear", "apple", "fig", "banana", "cherry", "date"] {
+            tree.insert(word.to_string(), word.len());
+        }
+
+        assert_eq!(tree.get(&"banana".to_string()), Some(&6));
+        let words: Vec<&String> = tree.range("b".to_string().."e".to_string()).map(|(k, _)| k).collect();
+        assert_eq!(words, vec!["banana", "cherry", "date"]);
+    }
+
+    #[test]
+    fn test_random_operations_match_std() {
+        use std::collections::BTreeMap;
+
+        let mut rng = StdRng::seed_from_u64(31);
+        for order in [3, 4, 5, 8] {
+            let mut tree = BPlusTree::new(order);
+            let mut expected = BTreeMap::new();
+
+            let mut keys: Vec<u32> = (0..300).collect();
+            keys.shuffle(&mut rng);
+            for &k in &keys {
+                assert_eq!(tree.insert(k, k * 2), expected.insert(k, k * 2));
+            }
+            tree.check_invariants();
+
+            keys.shuffle(&mut rng);
+            for &k in keys.iter().take(200) {
+                assert_eq!(tree.remove(&k), expected.remove(&k));
+                tree.check_invariants();
+            }
+
+            let actual: Vec<(u32, u32)> = tree.iter().map(|(k, v)| (*k, *v)).collect();
+            let wanted: Vec<(u32, u32)> = expected.iter().map(|(k, v)| (*k, *v)).collect();
+            assert_eq!(actual, wanted);
+
+            let actual: Vec<u32> = tree.range(50..150).map(|(k, _)| *k).collect();
+            let wanted: Vec<u32> = expected.range(50..150).map(|(k, _)| *k).collect();
+            assert_eq!(actual, wanted);
+        }
+    }
+}
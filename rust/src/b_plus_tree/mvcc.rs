@@ -0,0 +1,495 @@
+// Multi-version concurrency control over a BPlusTree: transactions with snapshot isolation
+//
+// The tree maps each key to its versions, oldest first, each stamped with the timestamp of the commit
+// that wrote it (a version with no value is a delete). Committing a transaction bumps a logical clock,
+// and a transaction reads from the snapshot of the last commit before it began: the newest version of
+// a key with a timestamp not after its read timestamp. So a reader never waits for a writer to finish,
+// and never sees part of a commit.
+//
+// A transaction's writes stay in its own write set (a BPlusTree of the keys it wrote) until commit, so
+// the shared tree is only locked for the length of a single lookup or of applying a commit. A commit
+// fails with a WriteConflict if another transaction committed a newer version of one of its keys
+// after it began (first committer wins), and then none of its writes are applied.
+//
+// Versions older than the newest one visible to the oldest transaction still running can never be
+// read again, so gc drops them (and keys whose only version left is a delete). It can be run by hand
+// or every so often on a background thread (see MvccBPlusTree::spawn_gc).
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use parking_lot::{Mutex, RwLock};
+
+use super::BPlusTree;
+
+/// A BPlusTree map read and written through transactions, each seeing a consistent snapshot
+///
+/// Clones share the same tree, so it can be handed to other threads
+pub struct MvccBPlusTree<K: PartialOrd + Debug + Clone, V> {
+    shared: Arc<Shared<K, V>>,
+}
+
+struct Shared<K: PartialOrd + Debug + Clone, V> {
+    data: RwLock<BPlusTree<K, Vec<Version<V>>>>,
+    clock: Mutex<Clock>,
+    order: usize,
+}
+
+struct Version<V> {
+    /// Timestamp of the commit that wrote it
+    ts: u64,
+    /// None for a delete
+    value: Option<V>,
+}
+
+struct Clock {
+    /// Timestamp of the last commit
+    committed: u64,
+    /// Number of running transactions per read timestamp
+    active: BTreeMap<u64, usize>,
+}
+
+/// A transaction on an MvccBPlusTree, created by MvccBPlusTree::begin
+///
+/// Dropping it without committing rolls it back
+pub struct Transaction<K: PartialOrd + Debug + Clone, V> {
+    shared: Arc<Shared<K, V>>,
+    read_ts: u64,
+    /// Keys written so far, with None for a remove
+    writes: BPlusTree<K, Option<V>>,
+    finished: bool,
+}
+
+/// The error of a commit that lost a write-write conflict
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteConflict<K> {
+    /// A key the transaction wrote that another transaction committed after it began
+    pub key: K,
+}
+
+impl<K: Debug> Display for WriteConflict<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "write conflict on key {:?}", self.key)
+    }
+}
+
+impl<K: Debug> std::error::Error for WriteConflict<K> {}
+
+impl<K: PartialOrd + Debug + Clone, V> Clone for MvccBPlusTree<K, V> {
+    fn clone(&self) -> Self {
+        MvccBPlusTree { shared: Arc::clone(&self.shared) }
+    }
+}
+
+impl<K: PartialOrd + Debug + Clone, V: Clone> MvccBPlusTree<K, V> {
+    /// Constructor method for MvccBPlusTree
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the underlying BPlusTree
+    pub fn new(m: usize) -> Self {
+        let data = RwLock::new(BPlusTree::new(m));
+        let clock = Mutex::new(Clock { committed: 0, active: BTreeMap::new() });
+        MvccBPlusTree { shared: Arc::new(Shared { data, clock, order: m }) }
+    }
+
+    /// Starts a transaction reading from the snapshot of the last commit
+    pub fn begin(&self) -> Transaction<K, V> {
+        let mut clock = self.shared.clock.lock();
+        let read_ts = clock.committed;
+        *clock.active.entry(read_ts).or_insert(0) += 1;
+        Transaction { shared: Arc::clone(&self.shared), read_ts, writes: BPlusTree::new(self.shared.order), finished: false }
+    }
+
+    /// Drops the versions that no running (or later) transaction can read, returning how many
+    pub fn gc(&self) -> usize {
+        // Every running transaction reads at or after the horizon, and later ones at the last commit
+        let horizon = {
+            let clock = self.shared.clock.lock();
+            clock.active.keys().next().copied().unwrap_or(clock.committed)
+        };
+
+        let mut data = self.shared.data.write();
+        let collectible: Vec<K> = data
+            .iter()
+            .filter(|(_, versions)| versions.len() > 1 && versions[1].ts <= horizon || versions[0].value.is_none() && versions[0].ts <= horizon)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let mut removed = 0;
+        for key in collectible {
+            let versions = data.get_mut(&key).expect("Key was just found");
+            // Keep the newest version visible at the horizon and every one after it
+            let visible = versions.iter().rposition(|version| version.ts <= horizon).expect("A version is visible at the horizon");
+            versions.drain(..visible);
+            removed += visible;
+            // A delete at the horizon reads the same as no version at all
+            if versions[0].value.is_none() {
+                versions.remove(0);
+                removed += 1;
+            }
+            if versions.is_empty() {
+                data.remove(&key);
+            }
+        }
+        removed
+    }
+
+    /// Returns the number of versions held, over all keys
+    pub fn version_count(&self) -> usize {
+        self.shared.data.read().iter().map(|(_, versions)| versions.len()).sum()
+    }
+
+    /// Returns the timestamp of the last commit
+    pub fn last_commit(&self) -> u64 {
+        self.shared.clock.lock().committed
+    }
+}
+
+impl<K: PartialOrd + Debug + Clone + Send + Sync + 'static, V: Clone + Send + Sync + 'static> MvccBPlusTree<K, V> {
+    /// Runs gc on a background thread every interval, until the returned GcThread is dropped
+    pub fn spawn_gc(&self, interval: Duration) -> GcThread {
+        let tree = self.clone();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                tree.gc();
+            }
+        });
+        GcThread { stop: Some(stop), handle: Some(handle) }
+    }
+}
+
+/// Handle of the background gc thread started by MvccBPlusTree::spawn_gc, which stops it when dropped
+pub struct GcThread {
+    stop: Option<mpsc::Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Drop for GcThread {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread up, which then finishes
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl<K: PartialOrd + Debug + Clone, V: Clone> Transaction<K, V> {
+    /// Returns the timestamp of the commit whose snapshot the transaction reads
+    pub fn read_ts(&self) -> u64 {
+        self.read_ts
+    }
+
+    /// Returns the value of key in the snapshot, or as written by this transaction
+    pub fn get(&self, key: &K) -> Option<V> {
+        if let Some(written) = self.writes.get(key) {
+            return written.clone();
+        }
+        let data = self.shared.data.read();
+        let versions = data.get(key)?;
+        let version = versions.iter().rev().find(|version| version.ts <= self.read_ts)?;
+        version.value.clone()
+    }
+
+    /// Returns true if key is present in the snapshot, or as written by this transaction
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts key with value, returning the value it had (see get)
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let old = self.get(&key);
+        self.writes.insert(key, Some(value));
+        old
+    }
+
+    /// Removes key, returning the value it had (see get)
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let old = self.get(key);
+        self.writes.insert(key.clone(), None);
+        old
+    }
+
+    /// Applies the writes of the transaction, returning the timestamp of its commit (or its read
+    /// timestamp, if it wrote nothing)
+    ///
+    /// Fails, applying none of them, if another transaction committed a key it wrote after it began
+    pub fn commit(mut self) -> Result<u64, WriteConflict<K>> {
+        // The transaction stays counted as running until it is done, so gc cannot drop a version it
+        // has to check for conflicts (when self is dropped, if it fails)
+        if self.writes.is_empty() {
+            return Ok(self.read_ts);
+        }
+
+        // Commits are serialized by the write lock, which is held until the new timestamp is published
+        let mut data = self.shared.data.write();
+        for (key, _) in self.writes.iter() {
+            if let Some(versions) = data.get(key)
+                && versions.last().is_some_and(|version| version.ts > self.read_ts) {
+                return Err(WriteConflict { key: key.clone() });
+            }
+        }
+
+        let ts = self.shared.clock.lock().committed + 1;
+        let writes = std::mem::replace(&mut self.writes, BPlusTree::new(self.shared.order));
+        for (key, value) in writes.iter() {
+            let version = Version { ts, value: value.clone() };
+            match data.get_mut(key) {
+                Some(versions) => versions.push(version),
+                None => {
+                    data.insert(key.clone(), vec![version]);
+                },
+            }
+        }
+        self.shared.clock.lock().committed = ts;
+        drop(data);
+        self.finish();
+        Ok(ts)
+    }
+
+    /// Drops the writes of the transaction
+    pub fn rollback(mut self) {
+        self.finish();
+    }
+}
+
+impl<K: PartialOrd + Debug + Clone, V> Transaction<K, V> {
+    /// Stops counting the transaction as running (which lets gc drop the versions it could read)
+    fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        let mut clock = self.shared.clock.lock();
+        let count = clock.active.get_mut(&self.read_ts).expect("Running transaction is counted");
+        *count -= 1;
+        if *count == 0 {
+            clock.active.remove(&self.read_ts);
+        }
+    }
+}
+
+impl<K: PartialOrd + Debug + Clone, V> Drop for Transaction<K, V> {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let tree = MvccBPlusTree::new(4);
+        let mut setup = tree.begin();
+        for i in 0..20 {
+            setup.insert(i, i);
+        }
+        assert_eq!(setup.commit(), Ok(1));
+
+        let reader = tree.begin();
+        let mut writer = tree.begin();
+        assert_eq!(writer.insert(3, 30), Some(3));
+        assert_eq!(writer.remove(&4), Some(4));
+        assert_eq!(writer.insert(25, 25), None);
+        // Uncommitted writes are only seen by their own transaction
+        assert_eq!(writer.get(&3), Some(30));
+        assert!(!writer.contains_key(&4));
+        assert_eq!(reader.get(&3), Some(3));
+        assert_eq!(writer.commit(), Ok(2));
+
+        // The reader keeps its snapshot, while a transaction begun after the commit sees it
+        assert_eq!(reader.get(&3), Some(3));
+        assert_eq!(reader.get(&4), Some(4));
+        assert_eq!(reader.get(&25), None);
+        let later = tree.begin();
+        assert_eq!(later.read_ts(), 2);
+        assert_eq!(later.get(&3), Some(30));
+        assert_eq!(later.get(&4), None);
+        assert_eq!(later.get(&25), Some(25));
+    }
+
+    #[test]
+    fn test_rollback() {
+        let tree = MvccBPlusTree::new(3);
+        let mut txn = tree.begin();
+        txn.insert("a", 1);
+        txn.rollback();
+        let mut txn = tree.begin();
+        txn.insert("b", 2);
+        drop(txn);
+
+        let txn = tree.begin();
+        assert_eq!(txn.get(&"a"), None);
+        assert_eq!(txn.get(&"b"), None);
+        assert_eq!(tree.last_commit(), 0);
+        assert_eq!(tree.version_count(), 0);
+        // A transaction that wrote nothing commits at its snapshot
+        assert_eq!(txn.commit(), Ok(0));
+    }
+
+    #[test]
+    fn test_write_conflicts() {
+        let tree = MvccBPlusTree::new(3);
+        let mut first = tree.begin();
+        let mut second = tree.begin();
+        let mut disjoint = tree.begin();
+        first.insert(1, "first");
+        second.insert(2, "second");
+        second.insert(1, "second");
+        disjoint.insert(3, "disjoint");
+
+        // First committer wins, and the loser applies none of its writes
+        assert_eq!(first.commit(), Ok(1));
+        let err = second.commit().unwrap_err();
+        assert_eq!(err, WriteConflict { key: 1 });
+        assert_eq!(err.to_string(), "write conflict on key 1");
+        assert_eq!(disjoint.commit(), Ok(2));
+
+        let txn = tree.begin();
+        assert_eq!(txn.get(&1), Some("first"));
+        assert_eq!(txn.get(&2), None);
+        assert_eq!(txn.get(&3), Some("disjoint"));
+
+        // Writing a key committed before the transaction began is no conflict, and neither is a remove
+        // of a key that was never there
+        let mut txn = tree.begin();
+        txn.insert(1, "again");
+        txn.remove(&9);
+        assert_eq!(txn.commit(), Ok(3));
+    }
+
+    #[test]
+    fn test_conflict_on_collected_delete() {
+        let tree = MvccBPlusTree::new(3);
+        let mut setup = tree.begin();
+        setup.insert(1, 1);
+        setup.commit().unwrap();
+
+        let mut late = tree.begin();
+        late.insert(1, 2);
+        let mut remover = tree.begin();
+        remover.remove(&1);
+        remover.commit().unwrap();
+        // late could still read the old value, and needs the delete to find its conflict
+        assert_eq!(tree.gc(), 0);
+        assert_eq!(late.commit(), Err(WriteConflict { key: 1 }));
+        assert_eq!(tree.gc(), 2);
+        assert_eq!(tree.version_count(), 0);
+    }
+
+    #[test]
+    fn test_gc_keeps_visible_versions() {
+        let tree = MvccBPlusTree::new(4);
+        for i in 0..5 {
+            let mut txn = tree.begin();
+            txn.insert("k", i);
+            txn.insert("gone", i);
+            if i == 4 {
+                txn.remove(&"gone");
+            }
+            txn.commit().unwrap();
+        }
+        let reader = tree.begin();
+        let mut writer = tree.begin();
+        writer.insert("k", 5);
+        writer.commit().unwrap();
+        assert_eq!(tree.version_count(), 11);
+
+        // The reader still sees the versions of commit 5, so only older ones go (and the delete, as
+        // no version reads the same)
+        assert_eq!(tree.gc(), 9);
+        assert_eq!(tree.version_count(), 2);
+        assert_eq!(reader.get(&"k"), Some(4));
+        assert_eq!(reader.get(&"gone"), None);
+        drop(reader);
+
+        // Now nothing reads before the last commit
+        assert_eq!(tree.gc(), 1);
+        assert_eq!(tree.version_count(), 1);
+        assert_eq!(tree.gc(), 0);
+        assert_eq!(tree.begin().get(&"k"), Some(5));
+    }
+
+    #[test]
+    fn test_background_gc() {
+        let tree = MvccBPlusTree::new(3);
+        let gc = tree.spawn_gc(Duration::from_millis(1));
+        for i in 0..100 {
+            let mut txn = tree.begin();
+            txn.insert(i % 10, i);
+            txn.commit().unwrap();
+        }
+        // Only the newest version of each key is left once the thread has caught up
+        for _ in 0..1000 {
+            if tree.version_count() == 10 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        drop(gc);
+        assert_eq!(tree.version_count(), 10);
+        let txn = tree.begin();
+        for i in 0..10 {
+            assert_eq!(txn.get(&i), Some(90 + i));
+        }
+    }
+
+    #[test]
+    fn test_concurrent_transfers() {
+        // Transfers between accounts keep the total, which every snapshot has to agree with
+        const ACCOUNTS: u32 = 8;
+        const THREADS: u32 = 4;
+        let tree = MvccBPlusTree::new(4);
+        let mut setup = tree.begin();
+        for account in 0..ACCOUNTS {
+            setup.insert(account, 100i64);
+        }
+        setup.commit().unwrap();
+        let gc = tree.spawn_gc(Duration::from_millis(1));
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|id| {
+                let tree = tree.clone();
+                thread::spawn(move || {
+                    let mut committed = 0;
+                    for i in 0..200 {
+                        let (from, to) = ((id + i) % ACCOUNTS, (id + 3 * i + 1) % ACCOUNTS);
+                        loop {
+                            let mut txn = tree.begin();
+                            let total: i64 = (0..ACCOUNTS).map(|account| txn.get(&account).unwrap()).sum();
+                            assert_eq!(total, 100 * ACCOUNTS as i64);
+                            if from == to {
+                                break;
+                            }
+                            let amount = (i % 7) as i64;
+                            let balance = txn.get(&from).unwrap();
+                            txn.insert(from, balance - amount);
+                            let balance = txn.get(&to).unwrap();
+                            txn.insert(to, balance + amount);
+                            if txn.commit().is_ok() {
+                                committed += 1;
+                                break;
+                            }
+                        }
+                    }
+                    committed
+                })
+            })
+            .collect();
+        let committed: u64 = handles.into_iter().map(|handle| handle.join().unwrap()).sum();
+        drop(gc);
+
+        assert_eq!(tree.last_commit(), committed + 1);
+        let txn = tree.begin();
+        let total: i64 = (0..ACCOUNTS).map(|account| txn.get(&account).unwrap()).sum();
+        assert_eq!(total, 100 * ACCOUNTS as i64);
+        tree.gc();
+        assert_eq!(tree.version_count(), ACCOUNTS as usize);
+    }
+}
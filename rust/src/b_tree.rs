@@ -1,4 +1,58 @@
 use std::fmt::Debug;
+
+mod arena;
+mod b_link;
+mod b_star;
+mod builder;
+mod bulk;
+mod concurrent;
+mod const_order;
+mod cow;
+mod dot;
+mod metrics;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod node_search;
+mod observer;
+mod optimistic;
+mod paged;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod persistent;
+mod prefix;
+#[cfg(feature = "serde")]
+mod serialize;
+mod snapshot;
+mod strategy;
+mod structure;
+mod trace;
+
+pub use arena::ArenaBTree;
+pub use b_link::BLinkTree;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+#[cfg(feature = "mmap")]
+pub use mmap::{MmapBTree, MmapRange};
+pub use node_search::NodeSearch;
+pub use builder::BTreeBuilder;
+pub use concurrent::ConcurrentBTree;
+pub use const_order::ConstBTree;
+pub use observer::BTreeObserver;
+pub use optimistic::{AtomicKey, OptimisticBTree};
+pub use paged::{BigEndian, Bytes, Encoding, PagedBTree, PagedOptions, PoolStats, Utf8, WalSync};
+#[cfg(feature = "rayon")]
+pub use parallel::ParIter;
+pub use persistent::PersistentBTree;
+pub use prefix::PrefixBTree;
+pub use snapshot::SnapshotKey;
+pub use strategy::InsertStrategy;
+pub use structure::Structure;
+pub use trace::{DeleteCase, NodeSnapshot, TraceAction, TraceStep};
+
+use cow::{make_mut, Child};
+use metrics::Counters;
+use observer::Hooks;
+
 // https://en.wikipedia.org/wiki/B-tree
 // A B-tree with order m will have a max of m children and thus a max of m-1 keys.
 
@@ -6,20 +60,36 @@ use std::fmt::Debug;
 // A root node when it is a leaf node: min 0 max K keys, min 0 max 0 children
 // A root node when it is an internal node: min 1 max K keys, min 2 max K+1 children
 // An internal node: min floor(K/2) max K keys, min ceiling((K+1)/2)=floor(K/2)+1 max K+1 children
-// A leaf node: min ceiling(K/2) max K keys, min 0 max 0 children
+// A leaf node: min floor(K/2) max K keys, min 0 max 0 children
 
 // NOTE: There are two main definitions of B-Trees (Knuth and CLRS): https://stackoverflow.com/questions/28846377/what-is-the-difference-btw-order-and-degree-in-terms-of-tree-data-structure
 // This uses the Knuth defintion, which allows for the special case of 2-3 trees
+// A CLRS tree of minimum degree t (t-1 to 2t-1 keys per non-root node) is the Knuth tree of order m = 2t,
+// since floor(K/2) = floor((2t-1)/2) = t-1 (see BTree::with_min_degree)
+
+// Splitting and merging depend on the parity of the order:
+// Even m (odd K, and every CLRS tree): a full node splits into two halves of floor(K/2) keys around the
+// median, and two nodes of floor(K/2) keys merge with their separator into K keys. So splits can be done
+// pre-emptively on the way down (InsertStrategy::TopDown), and merges never overflow.
+// Odd m (even K): a full node of K keys cannot be split into two halves of floor(K/2) keys (one half
+// would get K/2 - 1), and merging two nodes of K/2 keys with their separator gives K+1 keys. So nodes
+// may temporarily hold m = K+1 keys, and are split into two halves of K/2 once they overflow (on insert
+// with InsertStrategy::BottomUp, or after deleting from a merged node that is still overfull).
 
 pub struct BTree<T: PartialOrd + Debug + Clone> {
-    root: Option<Box<Node<T>>>,
+    root: Option<Child<T>>,
     order: usize, 
+    counters: Counters,
+    observer: Option<Box<dyn BTreeObserver<T>>>,
+    strategy: InsertStrategy,
+    node_search: NodeSearch,
 }
 
 // The number of child nodes will be 1 more than the number of keys -> ceiling(m/2) = floor(m/2) + 1
+#[derive(Clone)]
 struct Node<T: PartialOrd + Debug + Clone> {
     keys: Vec<T>,
-    children: Vec<Box<Node<T>>>,
+    children: Vec<Child<T>>,
     leaf: bool,
     order: usize,
 }
@@ -29,11 +99,12 @@ impl<T: PartialOrd + Debug + Clone> BTree<T> {
     /// 
     /// Takes in a usize parameter m representing the knuth order of a BTree
     /// 
-    /// Note: Order 3 is a special case (2-3 tree) where nodes can have 1-2 keys normally                                                                     
-    /// but temporarily hold 3 keys before splitting (since 2 keys cannot be split evenly) 
+    /// Note: Even orders insert top-down, while odd orders, such as 3 (2-3 tree) where nodes can have 1-2 keys
+    /// normally, insert bottom-up and temporarily hold m keys before splitting (since m-1 keys cannot be split evenly)
+    /// (see InsertStrategy and BTree::with_strategy)
     pub fn new(m: usize) -> Self {
         assert!(m >= 3, "BTree order must be at least 3");
-        BTree{ root: None, order: m }
+        BTree{ root: None, order: m, counters: Counters::new(), observer: None, strategy: InsertStrategy::default_for(m), node_search: NodeSearch::Binary }
     }
 
     /// Traverse method for BTree
@@ -59,7 +130,7 @@ impl<T: PartialOrd + Debug + Clone> BTree<T> {
 
         // Call search iteratively on each node
         loop {
-            let (found, idx) = node.search(&value);
+            let (found, idx) = node.search(&value, self.node_search, &self.counters);
             // If found, return true
             if found {
                 return true;
@@ -75,72 +146,115 @@ impl<T: PartialOrd + Debug + Clone> BTree<T> {
 
     /// Inserts a value into the b-tree
     pub fn insert(&mut self, value: T) {
+        self.insert_inner(value, None);
+    }
+
+    /// Inserts a value into the b-tree, recording each step into trace if given
+    fn insert_inner(&mut self, value: T, trace: Option<&mut Vec<TraceStep<T>>>) {
+        let mut hooks = Hooks::new(&self.counters, self.node_search, &mut self.observer, trace);
+
         // Check if root is empty
         match &mut self.root {
-            Some(r) => {
-                // For order 3, allow root to have 3 keys temporarily before splitting
-                let max_keys_before_split = if self.order == 3 { 3 } else { self.order - 1 };
-
-                if r.keys.len() < max_keys_before_split {
+            Some(r) if self.strategy == InsertStrategy::TopDown => {
+                if r.keys.len() < r.max_keys() {
                     // If root is not full, insert into root node recursively
-                    r.insert_non_full(value);
-
-                    // Check if root now needs splitting (for order 3 with 3 keys)
-                    if let Some(root) = &mut self.root {
-                        if self.order == 3 && root.keys.len() == 3 {
-                            let old_root = self.root.take().expect("Root must exist");
-                            let mut new_root: Node<T> = Node{ keys: vec![], children: vec![old_root], leaf: false, order: self.order };
-                            new_root.split_child(0);
-                            self.root = Some(Box::new(new_root));
-                        }
-                    }
+                    make_mut(r).insert_non_full(value, 0, &mut hooks);
                 } else {
                     // Else (root is full), make a new root, make old root a child of new root, split the old root, and insert into new root recursively
                     let old_root = self.root.take().expect("Root must exist in Some branch");
-                    let mut new_root: Node<T> = Node{ keys: vec![], children: vec![old_root], leaf: false, order: self.order };
-                    new_root.split_child(0);
-                    new_root.insert_non_full(value);
-                    self.root = Some(Box::new(new_root));
+                    let mut new_root = Node::split_root(old_root, &mut hooks);
+                    make_mut(&mut new_root).insert_non_full(value, 0, &mut hooks);
+                    self.root = Some(new_root);
+                }
+            },
+            Some(r) => {
+                // Bottom-up insertion (see strategy.rs and b_star.rs), so the root is only split once it overflows
+                let r = make_mut(r);
+                let max_keys = if self.strategy == InsertStrategy::BStar {
+                    r.insert_b_star(value, 0, &mut hooks);
+                    b_star::root_max_keys(self.order)
+                } else {
+                    r.insert_bottom_up(value, 0, &mut hooks);
+                    self.order - 1
+                };
+
+                if let Some(root) = &self.root
+                    && root.keys.len() > max_keys {
+                    let old_root = self.root.take().expect("Root must exist");
+                    self.root = Some(Node::split_root(old_root, &mut hooks));
                 }
             },
             None => {
                 // If root is empty, create a new root leaf node and insert value
                 let new_node: Node<T> = Node{ keys: vec![value], children: vec![], leaf: true, order: self.order };
-                self.root = Some(Box::new(new_node));
+                hooks.counters.allocation();
+                hooks.insert_key(0, 0, &new_node);
+                self.root = Some(Child::new(new_node));
             },
         }
     }
 
     /// Deletes a value from the b-tree
     pub fn delete(&mut self, value: T) {
+        self.delete_inner(value, None);
+    }
+
+    /// Deletes a value from the b-tree, recording each step into trace if given
+    fn delete_inner(&mut self, value: T, trace: Option<&mut Vec<TraceStep<T>>>) {
+        let mut hooks = Hooks::new(&self.counters, self.node_search, &mut self.observer, trace);
+
         // Check if root is empty
         let node = match &mut self.root {
-            Some(r) => r.as_mut(),
+            Some(r) => make_mut(r),
             None => panic!("Cannot delete from empty BTree"),
         };
 
         // If not empty, call delete on root
-        node.delete(&value);
+        node.delete(&value, 0, &mut hooks);
         
         // Shrink tree if root is empty but has children
         // Some(root) is part of if let pattern matching that executes the block if self.root is Some
-        if let Some(root) = &mut self.root {
-            if root.keys.is_empty() && !root.children.is_empty() {
-                self.root = Some(self.root.take().unwrap().children.remove(0));
-            }
+        if let Some(root) = &mut self.root
+            && root.keys.is_empty() && !root.children.is_empty() {
+            let new_root = make_mut(root).children.remove(0);
+            hooks.root_shrink(&new_root);
+            self.root = Some(new_root);
         }
     }
 
     /// Helper (test) function for printing b-tree structure
     #[cfg(test)]
     pub fn print_structure(&self) {
-        match &self.root {
-            Some(r) => {
-                println!("=== BTree Structure (Order {}) ===", self.order);
-                println!();
-                r.print_structure(0);
-            },
-            None => println!("Empty tree"),
+        print!("{}", self.structure());
+    }
+
+    /// Helper (test) function that checks every non-root node holds between floor(K/2) and K keys,
+    /// keys are in order (BTree keeps duplicates, so equal keys may follow each other) and every leaf
+    /// is at the same depth
+    #[cfg(test)]
+    fn check_invariants(&self) {
+        fn walk<T: PartialOrd + Debug + Clone>(node: &Node<T>, depth: usize, leaf_depth: &mut Option<usize>, keys: &mut Vec<T>) {
+            assert!(node.keys.len() <= node.max_keys(), "Node overfull: {:?}", node.keys);
+            if depth > 0 {
+                assert!(node.keys.len() >= node.min_keys(), "Node underfull: {:?}", node.keys);
+            }
+            if node.leaf {
+                assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+                keys.extend(node.keys.iter().cloned());
+                return;
+            }
+            assert_eq!(node.children.len(), node.keys.len() + 1);
+            for (i, child) in node.children.iter().enumerate() {
+                walk(child, depth + 1, leaf_depth, keys);
+                if i < node.keys.len() {
+                    keys.push(node.keys[i].clone());
+                }
+            }
+        }
+        if let Some(root) = &self.root {
+            let mut keys = vec![];
+            walk(root, 0, &mut None, &mut keys);
+            assert!(keys.windows(2).all(|w| w[0] <= w[1]), "Keys out of order: {:?}", keys);
         }
     }
 }
@@ -164,83 +278,65 @@ impl<T: PartialOrd + Debug + Clone> Node<T> {
         }
     }
 
-    /// Searches for value in keys (currently wraps binary search helper)
+    /// Returns the max number of keys K = m-1 a node holds (outside of a temporary overflow)
+    fn max_keys(&self) -> usize {
+        self.order - 1
+    }
+
+    /// Returns the min number of keys floor(K/2) = ceiling(m/2)-1 a non-root node holds
+    ///
+    /// This is t-1 for a CLRS tree of minimum degree t (m = 2t)
+    fn min_keys(&self) -> usize {
+        (self.order - 1) / 2
+    }
+
+    /// Searches for value in keys with the given strategy (see node_search.rs)
     /// 
     /// Returns true if value in keys and idx in keys
     /// 
     /// Returns false if value not in keys and idx of smallest key greater than search value
-    fn search(&self, value: &T) -> (bool, usize) {
-        let (found, idx) = self.binary_search(value);
-        (found, idx)
-    }
-
-    /// Binary search helper for B-tree node
-    fn binary_search(&self, value: &T) -> (bool, usize) {
-        let mut left = 0;
-        let mut right = self.keys.len();
-
-        // Range is [left, right) - left inclusive, right exclusive 
-        while left < right {
-            let mid = left + (right - left) / 2;
-
-            if self.keys[mid] == *value {
-                return (true, mid);
-            }
-            
-            if self.keys[mid] < *value {
-                left = mid + 1; // Search right half (exclusive of mid)
-            } else { // self.keys[mid] > value
-                right = mid; // Search left half (inclusive of mid -> mid could be idx of smallest value greater than value)
-            }
-        }
-
-        // If the value is not found, return false and idx of smallest value greater than value (insertion point)
-        (false, left)
+    fn search(&self, value: &T, node_search: NodeSearch, counters: &Counters) -> (bool, usize) {
+        node_search.search_counted(&self.keys, value, counters)
     }
 
     /// Inserts a value as a new key into a leaf node (called recursively)
     ///
-    /// It assumes that the node must be non-full when the function is called
-    fn insert_non_full(&mut self, value: T) {
+    /// It assumes that the node must be non-full when the function is called (InsertStrategy::TopDown)
+    ///
+    /// Takes the level (depth) of this node, which is passed on to the hooks of structural changes
+    fn insert_non_full(&mut self, value: T, level: usize, hooks: &mut Hooks<T>) {
         // Find index of where value should be placed
-        let (_, mut idx) = self.search(&value);
+        let (_, mut idx) = self.search(&value, hooks.node_search, hooks.counters);
+        hooks.visit(level, self);
 
         if self.leaf {
             // If the node is a leaf node, insert value into key (base case)
             self.keys.insert(idx, value);
+            hooks.insert_key(level, idx, self);
         } else {
             // Else, recursively call insert_non_full on child the value should go into
-            // For order 3, allow child to have 3 keys temporarily before splitting
-            if self.children[idx].order == 3 && self.children[idx].keys.len() == 2 {
-                // Insert into child first
-                self.children[idx].insert_non_full(value);
-
-                // Now check if child has 3 keys and needs splitting
-                if self.children[idx].keys.len() == 3 {
-                    self.split_child(idx);
-                }
-            } else if self.children[idx].keys.len() == (self.children[idx].order - 1) {
-                // Standard split for full children (non-order-3 case)
-                self.split_child(idx);
+            if self.children[idx].keys.len() == self.max_keys() {
+                // Split full children first
+                self.split_child(idx, level, hooks);
                 // Choose left or right child depending on new middle key (from child) at idx
                 if value > self.keys[idx] {
                     idx += 1;
                 }
                 // Insert into non-full child
-                self.children[idx].insert_non_full(value);
+                self.child_mut(idx).insert_non_full(value, level + 1, hooks);
             } else {
                 // Child not full, just insert
-                self.children[idx].insert_non_full(value);
+                self.child_mut(idx).insert_non_full(value, level + 1, hooks);
             }
         }
     }
 
     /// Splits a full child node into 2 nodes and moves the middle key up into current node
     /// 
-    /// Takes a child_idx that represents the index of the child to be split
-    fn split_child(&mut self, child_idx: usize) {
+    /// Takes a child_idx that represents the index of the child to be split and the level of the current node
+    fn split_child(&mut self, child_idx: usize, level: usize, hooks: &mut Hooks<T>) {
         // Get child node and calculate midpoint with integer division (in even cases, midpoint is skewed to right)
-        let child = &mut self.children[child_idx];
+        let child = self.child_mut(child_idx);
         let mid = child.keys.len() / 2;
 
         // Split keys: right half starts at mid+1
@@ -250,7 +346,7 @@ impl<T: PartialOrd + Debug + Clone> Node<T> {
         let middle_key = child.keys.pop().expect("Middle key missing in split_child");
 
         // Split children
-        let right_children: Vec<Box<Node<T>>> = if child.leaf {
+        let right_children: Vec<Child<T>> = if child.leaf {
             vec![]
         } else {
             child.children.split_off(mid + 1)
@@ -265,68 +361,81 @@ impl<T: PartialOrd + Debug + Clone> Node<T> {
         self.keys.insert(child_idx, middle_key);
 
         // Insert new child node to right of old child node (old child node borrowing is done)
-        self.children.insert(child_idx + 1, Box::new(new_node));
+        self.children.insert(child_idx + 1, Child::new(new_node));
+        hooks.split(level + 1, &self.keys[child_idx], self);
     }
 
     /// Deletes a value from the node (recursively) with several different cases 
-    fn delete(&mut self, value: &T) {
+    ///
+    /// Takes the level (depth) of this node, which is passed on to the hooks of structural changes
+    fn delete(&mut self, value: &T, level: usize, hooks: &mut Hooks<T>) {
         // Find index of smallest key greater than value == index of child value belongs in
-        let (found, idx) = self.search(value);
+        let (found, idx) = self.search(value, hooks.node_search, hooks.counters);
+        hooks.visit(level, self);
         if found {
             if self.leaf {
                 // Case 1: The value is in a leaf node (assumes has enough keys)
+                hooks.delete_case(level, DeleteCase::Case1, self);
                 self.keys.remove(idx);
-                return;
             } else {
                 // Case 2: The value is in an internal node
-                if self.children[idx].keys.len() >= (self.order - 1) / 2 + 1 {
+                if self.children[idx].keys.len() > self.min_keys() {
                     // Case 2a: Left subtree has at least floor(K/2) + 1 keys if case 3 (merging => lose 1 key) is called on it
+                    hooks.delete_case(level, DeleteCase::Case2a, self);
                     // Get predecessor
                     let pred = self.children[idx].get_rightmost().clone();
                     // Delete predecessor
-                    self.children[idx].delete(&pred);
+                    self.child_mut(idx).delete(&pred, level + 1, hooks);
                     // Replace current value with predecessor
                     self.keys[idx] = pred;
 
-                } else if self.children[idx + 1].keys.len() >= (self.order - 1) / 2 + 1 {
+                } else if self.children[idx + 1].keys.len() > self.min_keys() {
                     // Case 2b: Right subtree has at least floor(K/2) + 1 keys if case 3 (merging => lose 1 key) is called on it
+                    hooks.delete_case(level, DeleteCase::Case2b, self);
                     // Get successor
                     let succ = self.children[idx + 1].get_leftmost().clone();
                     // Delete successor
-                    self.children[idx + 1].delete(&succ);
+                    self.child_mut(idx + 1).delete(&succ, level + 1, hooks);
                     // Replace current value with successor
                     self.keys[idx] = succ;
 
                 } else {
                     // Case 2c: Both left and right do not have enough keys, so we merge them
-                    self.merge(idx);
-                    self.children[idx].delete(value);
+                    hooks.delete_case(level, DeleteCase::Case2c, self);
+                    self.merge(idx, level, hooks);
+                    self.child_mut(idx).delete(value, level + 1, hooks);
+                    self.split_overflowing(idx, level, hooks);
                 }
             }
         } else {
             if !self.leaf {
                 // Case 3: Not found and in internal node (need to make sure subtree we call on has enough keys)
-                if self.children[idx].keys.len() < (self.order - 1) / 2 + 1 {
-                    if idx > 0 && self.children[idx - 1].keys.len() >= (self.order - 1) / 2 + 1  {
+                if self.children[idx].keys.len() < self.min_keys() + 1 {
+                    if idx > 0 && self.children[idx - 1].keys.len() > self.min_keys() {
                         // Case 3a: Left subtree has at least floor(K/2) + 1 keys -> rotate to right
-                        self.rotate_right(idx);
-                    } else if idx < (self.children.len() - 1) && self.children[idx + 1].keys.len() >= (self.order - 1) / 2 + 1 {
+                        hooks.delete_case(level, DeleteCase::Case3a, self);
+                        self.rotate_right(idx, level, hooks);
+                    } else if idx < (self.children.len() - 1) && self.children[idx + 1].keys.len() > self.min_keys() {
                         // Case 3b: Right subtree has at least floor(K/2) + 1 keys -> rotate to left
-                        self.rotate_left(idx);
+                        hooks.delete_case(level, DeleteCase::Case3b, self);
+                        self.rotate_left(idx, level, hooks);
                     } else {
                         // Case 3c: Both left and right do not have enough keys, so we merge them
+                        hooks.delete_case(level, DeleteCase::Case3c, self);
                         if idx == (self.children.len() - 1) {
-                            self.merge(idx - 1);
+                            self.merge(idx - 1, level, hooks);
                             // Call delete on idx - 1
-                            self.children[idx - 1].delete(value);
+                            self.child_mut(idx - 1).delete(value, level + 1, hooks);
+                            self.split_overflowing(idx - 1, level, hooks);
                             return;
                         } else {
-                            self.merge(idx);
+                            self.merge(idx, level, hooks);
                         }
                     }
                 }
                 // Recursively call on child subtree that value belongs in
-                self.children[idx].delete(value);
+                self.child_mut(idx).delete(value, level + 1, hooks);
+                self.split_overflowing(idx, level, hooks);
             } else {
                 // Case 4: Not found at all (reached leaf node)
                 panic!("Non-existant value cannot be deleted from BTree")
@@ -334,6 +443,15 @@ impl<T: PartialOrd + Debug + Clone> Node<T> {
         }
     }
 
+    /// Splits the child at child_idx if it is still overfull after deleting from it
+    ///
+    /// Only happens for odd orders, where merging two children with floor(K/2) keys gives K+1 keys
+    fn split_overflowing(&mut self, child_idx: usize, level: usize, hooks: &mut Hooks<T>) {
+        if self.children[child_idx].keys.len() > self.max_keys() {
+            self.split_child(child_idx, level, hooks);
+        }
+    }
+
     /// Helper to get a ref of the rightmost value in a subtree
     fn get_rightmost(&self) -> &T {
         let mut node = self;
@@ -358,54 +476,58 @@ impl<T: PartialOrd + Debug + Clone> Node<T> {
 
     /// Helper that moves last key from left child to parent and parent key to right child's first key
     /// 
-    /// Takes a child_idx that represents the right child's index
-    fn rotate_right(&mut self, child_idx: usize) {
+    /// Takes a child_idx that represents the right child's index and the level of the current node
+    fn rotate_right(&mut self, child_idx: usize, level: usize, hooks: &mut Hooks<T>) {
         // Remove the middle key from parent
         let middle_key = self.keys.remove(child_idx - 1);
 
         // Insert middle key into right child's first key
-        self.children[child_idx].keys.insert(0, middle_key);
+        self.child_mut(child_idx).keys.insert(0, middle_key);
 
         // Remove last key from left child 
-        let last_key = self.children[child_idx - 1].keys.pop().expect("Left child has no keys");
+        let last_key = self.child_mut(child_idx - 1).keys.pop().expect("Left child has no keys");
 
         // Insert last key into parent at child_idx
         self.keys.insert(child_idx - 1, last_key);
 
         // Move left child's last child to right child's first child
         if !self.children[child_idx - 1].leaf {
-            let last_child = self.children[child_idx - 1].children.pop().expect("Left child has no children");
-            self.children[child_idx].children.insert(0, last_child);
+            let last_child = self.child_mut(child_idx - 1).children.pop().expect("Left child has no children");
+            self.child_mut(child_idx).children.insert(0, last_child);
         }
+
+        hooks.rotate_right(level + 1, &self.keys[child_idx - 1], self);
     }
 
     /// Helper that moves first key from right child to parent and parent key to left child's last key
     /// 
-    /// Takes a child_idx that represents the left child's index
-    fn rotate_left(&mut self, child_idx: usize) {
+    /// Takes a child_idx that represents the left child's index and the level of the current node
+    fn rotate_left(&mut self, child_idx: usize, level: usize, hooks: &mut Hooks<T>) {
         // Remove the middle key from parent
         let middle_key = self.keys.remove(child_idx);
 
         // Insert middle key into left child's last key
-        self.children[child_idx].keys.push(middle_key);
+        self.child_mut(child_idx).keys.push(middle_key);
 
         // Remove first key from right child 
-        let first_key = self.children[child_idx + 1].keys.remove(0);
+        let first_key = self.child_mut(child_idx + 1).keys.remove(0);
 
         // Insert first key into parent at child_idx
         self.keys.insert(child_idx, first_key);
 
         // Move right child's first child to left child's last child
         if !self.children[child_idx + 1].leaf {
-            let first_child = self.children[child_idx + 1].children.remove(0);
-            self.children[child_idx].children.push(first_child);
+            let first_child = self.child_mut(child_idx + 1).children.remove(0);
+            self.child_mut(child_idx).children.push(first_child);
         }
+
+        hooks.rotate_left(level + 1, &self.keys[child_idx], self);
     }
 
     /// Helper that merges two children nodes and inserts middle key into new child
     /// 
-    /// Takes a child_idx that represents the left child
-    fn merge(&mut self, child_idx: usize) {
+    /// Takes a child_idx that represents the left child and the level of the current node
+    fn merge(&mut self, child_idx: usize, level: usize, hooks: &mut Hooks<T>) {
         // Check if child index is greater than number of children
         if child_idx >= self.children.len() - 1 {
             panic!("Child index is greater than number of children");
@@ -414,11 +536,13 @@ impl<T: PartialOrd + Debug + Clone> Node<T> {
         // Remove the middle key from parent
         let middle_key = self.keys.remove(child_idx);
 
-        // Remove right child (transfers ownership)
-        let mut right_child = self.children.remove(child_idx + 1);
+        // Remove right child (transfers ownership, copying it first if it is shared)
+        let mut right = self.children.remove(child_idx + 1);
+        let right_child = make_mut(&mut right);
 
         // Get mutable ref of left child and then merge
-        let left_child = &mut self.children[child_idx];
+        let left_child = self.child_mut(child_idx);
+        let middle_idx = left_child.keys.len();
         left_child.keys.push(middle_key);
         left_child.keys.append(&mut right_child.keys);
 
@@ -427,525 +551,533 @@ impl<T: PartialOrd + Debug + Clone> Node<T> {
             left_child.children.append(&mut right_child.children);
         }
 
-        // Right child gets automatically deallocated here (out of scope)
-    }
+        hooks.merge(level + 1, &self.children[child_idx].keys[middle_idx], self);
 
-    /// Helper (test) function for printing b-tree node structure
-    #[cfg(test)]
-    fn print_structure(&self, level: usize) {
-        let indent = "  ".repeat(level);
-        println!("{}Node (leaf={}): {:?}", indent, self.leaf, self.keys);
-        
-        for child in &self.children {
-            child.print_structure(level + 1);
-        }
+        // Right child gets automatically deallocated here (out of scope)
     }
 }
 
+/// The test suite shared by BTree and ConstBTree (see const_order.rs)
+///
+/// Takes the name of a macro that creates an empty tree of the given order (and optionally key type)
 #[cfg(test)]
-mod tests {
-    use super::*;
+macro_rules! btree_test_suite {
+    ($new:ident) => {
+        #[test]
+        fn test_new_btree() {
+            let btree = $new!(3, i32);
+            assert!(!btree.search(5));
+        }
 
-    #[test]
-    fn test_new_btree() {
-        let btree: BTree<i32> = BTree::new(3);
-        assert!(!btree.search(5));
-    }
+        #[test]
+        fn test_insert_causes_root_split() {
+            let mut btree = $new!(3);
+            // Insert 3 values to force a split (max 2 keys for order 3)
+            btree.insert(10);
+            btree.insert(20);
+            btree.insert(30);
+
+            assert!(btree.search(10));
+            assert!(btree.search(20));
+            assert!(btree.search(30));
+        }
 
-    #[test]
-    fn test_insert_causes_root_split() {
-        let mut btree = BTree::new(3);
-        // Insert 3 values to force a split (max 2 keys for order 3)
-        btree.insert(10);
-        btree.insert(20);
-        btree.insert(30);
-        
-        assert!(btree.search(10));
-        assert!(btree.search(20));
-        assert!(btree.search(30));
-    }
+        #[test]
+        fn test_insert_ascending_order() {
+            let mut btree = $new!(3);
+            for i in 1..=10 {
+                btree.insert(i);
+            }
 
-    #[test]
-    fn test_insert_ascending_order() {
-        let mut btree = BTree::new(3);
-        for i in 1..=10 {
-            btree.insert(i);
-        }
-        
-        for i in 1..=10 {
-            assert!(btree.search(i));
+            for i in 1..=10 {
+                assert!(btree.search(i));
+            }
+            assert!(!btree.search(11));
         }
-        assert!(!btree.search(11));
-    }
 
-    #[test]
-    fn test_insert_descending_order() {
-        let mut btree = BTree::new(3);
-        for i in (1..=10).rev() {
-            btree.insert(i);
-        }
-        
-        for i in 1..=10 {
-            assert!(btree.search(i));
-        }
-    }
+        #[test]
+        fn test_insert_descending_order() {
+            let mut btree = $new!(3);
+            for i in (1..=10).rev() {
+                btree.insert(i);
+            }
 
-    #[test]
-    fn test_insert_random_order() {
-        let mut btree = BTree::new(5);
-        let values = vec![50, 30, 70, 20, 40, 60, 80, 10, 90];
-        
-        for val in &values {
-            btree.insert(*val);
-        }
-        
-        for val in &values {
-            assert!(btree.search(*val));
+            for i in 1..=10 {
+                assert!(btree.search(i));
+            }
         }
-        assert!(!btree.search(25));
-    }
 
-    #[test]
-    fn test_traverse_order() {
-        let mut btree = BTree::new(3);
-        btree.insert(5);
-        btree.insert(3);
-        btree.insert(7);
-        btree.insert(1);
-        btree.insert(9);
-        
-        // This will print to stdout - you can visually verify order
-        println!("\nTraversal output:");
-        btree.traverse();
-    }
+        #[test]
+        fn test_insert_random_order() {
+            let mut btree = $new!(5);
+            let values = vec![50, 30, 70, 20, 40, 60, 80, 10, 90];
 
-    #[test]
-    fn test_search_empty_tree() {
-        let btree: BTree<i32> = BTree::new(3);
-        assert!(!btree.search(10));
-    }
+            for val in &values {
+                btree.insert(*val);
+            }
 
-    #[test]
-    fn test_larger_order() {
-        let mut btree = BTree::new(5);
-        // Order 5 means max 4 keys before split
-        for i in 1..=20 {
-            btree.insert(i);
-        }
-        
-        for i in 1..=20 {
-            assert!(btree.search(i));
+            for val in &values {
+                assert!(btree.search(*val));
+            }
+            assert!(!btree.search(25));
         }
-    }
 
-    #[test]
-    fn test_duplicate_search() {
-        let mut btree = BTree::new(3);
-        btree.insert(10);
-        btree.insert(20);
-        
-        // Search multiple times for same value
-        assert!(btree.search(10));
-        assert!(btree.search(10));
-        assert!(btree.search(20));
-    }
+        #[test]
+        fn test_traverse_order() {
+            let mut btree = $new!(3);
+            btree.insert(5);
+            btree.insert(3);
+            btree.insert(7);
+            btree.insert(1);
+            btree.insert(9);
+
+            // This will print to stdout - you can visually verify order
+            println!("\nTraversal output:");
+            btree.traverse();
+        }
 
-    #[test]
-    fn test_string_btree() {
-        let mut btree = BTree::new(3);
-        btree.insert("apple".to_string());
-        btree.insert("banana".to_string());
-        btree.insert("cherry".to_string());
-        
-        assert!(btree.search("apple".to_string()));
-        assert!(btree.search("banana".to_string()));
-        assert!(!btree.search("date".to_string()));
-    }
+        #[test]
+        fn test_search_empty_tree() {
+            let btree = $new!(3, i32);
+            assert!(!btree.search(10));
+        }
 
-    #[test]
-    #[should_panic(expected = "BTree order must be at least 3")]
-    fn test_invalid_order() {
-        let _btree: BTree<i32> = BTree::new(2);
-    }
+        #[test]
+        fn test_larger_order() {
+            let mut btree = $new!(5);
+            // Order 5 means max 4 keys before split
+            for i in 1..=20 {
+                btree.insert(i);
+            }
 
-    #[test]
-    fn test_visualize_structure() {
-        let mut btree = BTree::new(3);
-        for i in 1..=7 {
-            btree.insert(i);
+            for i in 1..=20 {
+                assert!(btree.search(i));
+            }
         }
-        btree.print_structure();
-    }
 
-    #[test]
-    fn test_delete_from_leaf_simple() {
-        let mut btree = BTree::new(3);
-        btree.insert(10);
-        btree.insert(20);
+        #[test]
+        fn test_duplicate_search() {
+            let mut btree = $new!(3);
+            btree.insert(10);
+            btree.insert(20);
 
-        btree.delete(10);
-        assert!(!btree.search(10));
-        assert!(btree.search(20));
-    }
+            // Search multiple times for same value
+            assert!(btree.search(10));
+            assert!(btree.search(10));
+            assert!(btree.search(20));
+        }
 
-    #[test]
-    fn test_delete_single_element() {
-        let mut btree = BTree::new(3);
-        btree.insert(10);
+        #[test]
+        fn test_string_btree() {
+            let mut btree = $new!(3);
+            btree.insert("apple".to_string());
+            btree.insert("banana".to_string());
+            btree.insert("cherry".to_string());
 
-        btree.delete(10);
-        assert!(!btree.search(10));
-    }
+            assert!(btree.search("apple".to_string()));
+            assert!(btree.search("banana".to_string()));
+            assert!(!btree.search("date".to_string()));
+        }
 
-    #[test]
-    fn test_delete_from_leaf_with_sufficient_keys() {
-        let mut btree = BTree::new(5);
-        for i in 1..=10 {
-            btree.insert(i);
+        #[test]
+        #[should_panic(expected = "BTree order must be at least 3")]
+        fn test_invalid_order() {
+            let _btree = $new!(2, i32);
         }
 
-        btree.delete(5);
-        assert!(!btree.search(5));
-        for i in 1..=10 {
-            if i != 5 {
-                assert!(btree.search(i));
+        #[test]
+        fn test_visualize_structure() {
+            let mut btree = $new!(3);
+            for i in 1..=7 {
+                btree.insert(i);
             }
+            btree.print_structure();
         }
-    }
 
-    #[test]
-    fn test_delete_causes_rotation_left() {
-        let mut btree = BTree::new(3);
-        // Build a tree that will require left rotation
-        for i in 1..=7 {
-            btree.insert(i);
+        #[test]
+        fn test_delete_from_leaf_simple() {
+            let mut btree = $new!(3);
+            btree.insert(10);
+            btree.insert(20);
+
+            btree.delete(10);
+            assert!(!btree.search(10));
+            assert!(btree.search(20));
         }
 
-        println!("\nBefore delete:");
-        btree.print_structure();
+        #[test]
+        fn test_delete_single_element() {
+            let mut btree = $new!(3);
+            btree.insert(10);
 
-        // Delete to trigger rotation
-        btree.delete(1);
+            btree.delete(10);
+            assert!(!btree.search(10));
+        }
 
-        println!("\nAfter delete:");
-        btree.print_structure();
+        #[test]
+        fn test_delete_from_leaf_with_sufficient_keys() {
+            let mut btree = $new!(5);
+            for i in 1..=10 {
+                btree.insert(i);
+            }
 
-        assert!(!btree.search(1));
-        for i in 2..=7 {
-            assert!(btree.search(i));
+            btree.delete(5);
+            assert!(!btree.search(5));
+            for i in 1..=10 {
+                if i != 5 {
+                    assert!(btree.search(i));
+                }
+            }
         }
-    }
 
-    #[test]
-    fn test_delete_causes_rotation_right() {
-        let mut btree = BTree::new(3);
-        // Build a tree that will require right rotation
-        for i in (1..=7).rev() {
-            btree.insert(i);
-        }
+        #[test]
+        fn test_delete_causes_rotation_left() {
+            let mut btree = $new!(3);
+            // Build a tree that will require left rotation
+            for i in 1..=7 {
+                btree.insert(i);
+            }
 
-        println!("\nBefore delete:");
-        btree.print_structure();
+            println!("\nBefore delete:");
+            btree.print_structure();
 
-        // Delete to trigger rotation
-        btree.delete(7);
+            // Delete to trigger rotation
+            btree.delete(1);
 
-        println!("\nAfter delete:");
-        btree.print_structure();
+            println!("\nAfter delete:");
+            btree.print_structure();
 
-        assert!(!btree.search(7));
-        for i in 1..=6 {
-            assert!(btree.search(i));
+            assert!(!btree.search(1));
+            for i in 2..=7 {
+                assert!(btree.search(i));
+            }
         }
-    }
 
-    #[test]
-    fn test_delete_causes_merge() {
-        let mut btree = BTree::new(3);
-        // Build specific structure to test merge
-        for i in 1..=6 {
-            btree.insert(i);
-        }
+        #[test]
+        fn test_delete_causes_rotation_right() {
+            let mut btree = $new!(3);
+            // Build a tree that will require right rotation
+            for i in (1..=7).rev() {
+                btree.insert(i);
+            }
 
-        println!("\nBefore delete (merge test):");
-        btree.print_structure();
+            println!("\nBefore delete:");
+            btree.print_structure();
 
-        btree.delete(6);
-        btree.delete(5);
+            // Delete to trigger rotation
+            btree.delete(7);
 
-        println!("\nAfter deletes:");
-        btree.print_structure();
+            println!("\nAfter delete:");
+            btree.print_structure();
 
-        assert!(!btree.search(6));
-        assert!(!btree.search(5));
-        for i in 1..=4 {
-            assert!(btree.search(i));
+            assert!(!btree.search(7));
+            for i in 1..=6 {
+                assert!(btree.search(i));
+            }
         }
-    }
 
-    #[test]
-    fn test_delete_from_internal_node_case_2a() {
-        let mut btree = BTree::new(3);
-        // Insert values to create an internal node
-        for i in 1..=10 {
-            btree.insert(i);
-        }
+        #[test]
+        fn test_delete_causes_merge() {
+            let mut btree = $new!(3);
+            // Build specific structure to test merge
+            for i in 1..=6 {
+                btree.insert(i);
+            }
 
-        println!("\nBefore delete from internal:");
-        btree.print_structure();
+            println!("\nBefore delete (merge test):");
+            btree.print_structure();
 
-        // Delete a value that's likely in an internal node
-        btree.delete(4);
+            btree.delete(6);
+            btree.delete(5);
 
-        println!("\nAfter delete from internal:");
-        btree.print_structure();
+            println!("\nAfter deletes:");
+            btree.print_structure();
 
-        assert!(!btree.search(4));
-        for i in 1..=10 {
-            if i != 4 {
+            assert!(!btree.search(6));
+            assert!(!btree.search(5));
+            for i in 1..=4 {
                 assert!(btree.search(i));
             }
         }
-    }
 
-    #[test]
-    fn test_delete_from_internal_node_case_2b() {
-        let mut btree = BTree::new(3);
-        for i in 1..=10 {
-            btree.insert(i);
-        }
+        #[test]
+        fn test_delete_from_internal_node_case_2a() {
+            let mut btree = $new!(3);
+            // Insert values to create an internal node
+            for i in 1..=10 {
+                btree.insert(i);
+            }
 
-        // Delete value that will trigger case 2b (successor replacement)
-        btree.delete(7);
+            println!("\nBefore delete from internal:");
+            btree.print_structure();
 
-        assert!(!btree.search(7));
-        for i in 1..=10 {
-            if i != 7 {
-                assert!(btree.search(i));
+            // Delete a value that's likely in an internal node
+            btree.delete(4);
+
+            println!("\nAfter delete from internal:");
+            btree.print_structure();
+
+            assert!(!btree.search(4));
+            for i in 1..=10 {
+                if i != 4 {
+                    assert!(btree.search(i));
+                }
             }
         }
-    }
 
-    #[test]
-    fn test_delete_from_internal_node_case_2c() {
-        let mut btree = BTree::new(3);
-        // Build tree and delete to trigger case 2c (merge children)
-        for i in 1..=7 {
-            btree.insert(i);
-        }
+        #[test]
+        fn test_delete_from_internal_node_case_2b() {
+            let mut btree = $new!(3);
+            for i in 1..=10 {
+                btree.insert(i);
+            }
 
-        println!("\nBefore case 2c:");
-        btree.print_structure();
+            // Delete value that will trigger case 2b (successor replacement)
+            btree.delete(7);
 
-        btree.delete(4);
+            assert!(!btree.search(7));
+            for i in 1..=10 {
+                if i != 7 {
+                    assert!(btree.search(i));
+                }
+            }
+        }
 
-        println!("\nAfter case 2c:");
-        btree.print_structure();
+        #[test]
+        fn test_delete_from_internal_node_case_2c() {
+            let mut btree = $new!(3);
+            // Build tree and delete to trigger case 2c (merge children)
+            for i in 1..=7 {
+                btree.insert(i);
+            }
 
-        assert!(!btree.search(4));
-    }
+            println!("\nBefore case 2c:");
+            btree.print_structure();
 
-    #[test]
-    fn test_delete_multiple_sequential() {
-        let mut btree = BTree::new(3);
-        for i in 1..=10 {
-            btree.insert(i);
-        }
+            btree.delete(4);
 
-        // Delete multiple values
-        for i in 1..=5 {
-            println!("\nDeleting {}", i);
-            btree.delete(i);
+            println!("\nAfter case 2c:");
             btree.print_structure();
-        }
 
-        for i in 1..=5 {
-            assert!(!btree.search(i));
-        }
-        for i in 6..=10 {
-            assert!(btree.search(i));
+            assert!(!btree.search(4));
         }
-    }
 
-    #[test]
-    fn test_delete_all_elements() {
-        let mut btree = BTree::new(3);
-        let values = vec![1, 2, 3, 4, 5, 6, 7];
+        #[test]
+        fn test_delete_multiple_sequential() {
+            let mut btree = $new!(3);
+            for i in 1..=10 {
+                btree.insert(i);
+            }
 
-        for val in &values {
-            btree.insert(*val);
-        }
+            // Delete multiple values
+            for i in 1..=5 {
+                println!("\nDeleting {}", i);
+                btree.delete(i);
+                btree.print_structure();
+            }
 
-        for val in &values {
-            btree.delete(*val);
-            assert!(!btree.search(*val));
+            for i in 1..=5 {
+                assert!(!btree.search(i));
+            }
+            for i in 6..=10 {
+                assert!(btree.search(i));
+            }
         }
-    }
 
-    #[test]
-    fn test_delete_descending_order() {
-        let mut btree = BTree::new(3);
-        for i in 1..=10 {
-            btree.insert(i);
-        }
+        #[test]
+        fn test_delete_all_elements() {
+            let mut btree = $new!(3);
+            let values = vec![1, 2, 3, 4, 5, 6, 7];
 
-        // Delete in descending order
-        for i in (1..=10).rev() {
-            btree.delete(i);
-        }
+            for val in &values {
+                btree.insert(*val);
+            }
 
-        for i in 1..=10 {
-            assert!(!btree.search(i));
+            for val in &values {
+                btree.delete(*val);
+                assert!(!btree.search(*val));
+            }
         }
-    }
 
-    #[test]
-    fn test_delete_random_order() {
-        let mut btree = BTree::new(5);
-        let values = vec![50, 30, 70, 20, 40, 60, 80, 10, 90];
+        #[test]
+        fn test_delete_descending_order() {
+            let mut btree = $new!(3);
+            for i in 1..=10 {
+                btree.insert(i);
+            }
 
-        for val in &values {
-            btree.insert(*val);
-        }
+            // Delete in descending order
+            for i in (1..=10).rev() {
+                btree.delete(i);
+            }
 
-        let delete_order = vec![30, 70, 10, 90, 50];
-        for val in &delete_order {
-            btree.delete(*val);
-            assert!(!btree.search(*val));
+            for i in 1..=10 {
+                assert!(!btree.search(i));
+            }
         }
 
-        // Check remaining values
-        for val in &values {
-            if delete_order.contains(val) {
+        #[test]
+        fn test_delete_random_order() {
+            let mut btree = $new!(5);
+            let values = vec![50, 30, 70, 20, 40, 60, 80, 10, 90];
+
+            for val in &values {
+                btree.insert(*val);
+            }
+
+            let delete_order = vec![30, 70, 10, 90, 50];
+            for val in &delete_order {
+                btree.delete(*val);
                 assert!(!btree.search(*val));
-            } else {
-                assert!(btree.search(*val));
             }
-        }
-    }
 
-    #[test]
-    fn test_delete_root_shrinkage() {
-        let mut btree = BTree::new(3);
-        // Build a tree that will shrink when root becomes empty
-        for i in 1..=3 {
-            btree.insert(i);
+            // Check remaining values
+            for val in &values {
+                if delete_order.contains(val) {
+                    assert!(!btree.search(*val));
+                } else {
+                    assert!(btree.search(*val));
+                }
+            }
         }
 
-        println!("\nBefore root shrinkage:");
-        btree.print_structure();
+        #[test]
+        fn test_delete_root_shrinkage() {
+            let mut btree = $new!(3);
+            // Build a tree that will shrink when root becomes empty
+            for i in 1..=3 {
+                btree.insert(i);
+            }
 
-        btree.delete(1);
-        btree.delete(2);
+            println!("\nBefore root shrinkage:");
+            btree.print_structure();
 
-        println!("\nAfter root shrinkage:");
-        btree.print_structure();
+            btree.delete(1);
+            btree.delete(2);
 
-        assert!(btree.search(3));
-    }
-
-    #[test]
-    #[should_panic(expected = "Cannot delete from empty BTree")]
-    fn test_delete_from_empty_tree() {
-        let mut btree: BTree<i32> = BTree::new(3);
-        btree.delete(10);
-    }
+            println!("\nAfter root shrinkage:");
+            btree.print_structure();
 
-    #[test]
-    #[should_panic(expected = "Non-existant value cannot be deleted from BTree")]
-    fn test_delete_nonexistent_value() {
-        let mut btree = BTree::new(3);
-        btree.insert(10);
-        btree.insert(20);
-        btree.delete(15); // Should panic
-    }
+            assert!(btree.search(3));
+        }
 
-    #[test]
-    fn test_delete_with_larger_order() {
-        let mut btree = BTree::new(7);
-        for i in 1..=50 {
-            btree.insert(i);
+        #[test]
+        #[should_panic(expected = "Cannot delete from empty BTree")]
+        fn test_delete_from_empty_tree() {
+            let mut btree = $new!(3, i32);
+            btree.delete(10);
         }
 
-        // Delete every third element
-        for i in (1..=50).step_by(3) {
-            btree.delete(i);
+        #[test]
+        #[should_panic(expected = "Non-existant value cannot be deleted from BTree")]
+        fn test_delete_nonexistent_value() {
+            let mut btree = $new!(3);
+            btree.insert(10);
+            btree.insert(20);
+            btree.delete(15); // Should panic
         }
 
-        // Verify deletions
-        for i in 1..=50 {
-            if i % 3 == 1 {
-                assert!(!btree.search(i));
-            } else {
-                assert!(btree.search(i));
+        #[test]
+        fn test_delete_with_larger_order() {
+            let mut btree = $new!(7);
+            for i in 1..=50 {
+                btree.insert(i);
             }
-        }
-    }
 
-    #[test]
-    fn test_delete_insert_interleaved() {
-        let mut btree = BTree::new(3);
+            // Delete every third element
+            for i in (1..=50).step_by(3) {
+                btree.delete(i);
+            }
 
-        // Insert some values
-        for i in 1..=10 {
-            btree.insert(i);
+            // Verify deletions
+            for i in 1..=50 {
+                if i % 3 == 1 {
+                    assert!(!btree.search(i));
+                } else {
+                    assert!(btree.search(i));
+                }
+            }
         }
 
-        // Delete and insert interleaved
-        btree.delete(5);
-        btree.insert(15);
-        btree.delete(3);
-        btree.insert(13);
+        #[test]
+        fn test_delete_insert_interleaved() {
+            let mut btree = $new!(3);
 
-        assert!(!btree.search(5));
-        assert!(!btree.search(3));
-        assert!(btree.search(15));
-        assert!(btree.search(13));
-    }
+            // Insert some values
+            for i in 1..=10 {
+                btree.insert(i);
+            }
 
-    #[test]
-    fn test_delete_string_values() {
-        let mut btree = BTree::new(3);
-        let values = vec!["apple", "banana", "cherry", "date", "elderberry"];
+            // Delete and insert interleaved
+            btree.delete(5);
+            btree.insert(15);
+            btree.delete(3);
+            btree.insert(13);
 
-        for val in &values {
-            btree.insert(val.to_string());
+            assert!(!btree.search(5));
+            assert!(!btree.search(3));
+            assert!(btree.search(15));
+            assert!(btree.search(13));
         }
 
-        btree.delete("banana".to_string());
-        btree.delete("date".to_string());
+        #[test]
+        fn test_delete_string_values() {
+            let mut btree = $new!(3);
+            let values = vec!["apple", "banana", "cherry", "date", "elderberry"];
 
-        assert!(!btree.search("banana".to_string()));
-        assert!(!btree.search("date".to_string()));
-        assert!(btree.search("apple".to_string()));
-        assert!(btree.search("cherry".to_string()));
-        assert!(btree.search("elderberry".to_string()));
-    }
+            for val in &values {
+                btree.insert(val.to_string());
+            }
 
-    #[test]
-    fn test_delete_maintains_btree_properties() {
-        let mut btree = BTree::new(5);
+            btree.delete("banana".to_string());
+            btree.delete("date".to_string());
 
-        // Insert many values
-        for i in 1..=100 {
-            btree.insert(i);
+            assert!(!btree.search("banana".to_string()));
+            assert!(!btree.search("date".to_string()));
+            assert!(btree.search("apple".to_string()));
+            assert!(btree.search("cherry".to_string()));
+            assert!(btree.search("elderberry".to_string()));
         }
 
-        // Delete half of them
-        for i in (1..=100).step_by(2) {
-            btree.delete(i);
-        }
+        #[test]
+        fn test_delete_maintains_btree_properties() {
+            let mut btree = $new!(5);
 
-        println!("\nAfter deleting half:");
-        btree.print_structure();
+            // Insert many values
+            for i in 1..=100 {
+                btree.insert(i);
+            }
 
-        // Verify remaining values are searchable
-        for i in 1..=100 {
-            if i % 2 == 1 {
-                assert!(!btree.search(i));
-            } else {
-                assert!(btree.search(i));
+            // Delete half of them
+            for i in (1..=100).step_by(2) {
+                btree.delete(i);
+            }
+
+            println!("\nAfter deleting half:");
+            btree.print_structure();
+
+            // Verify remaining values are searchable
+            for i in 1..=100 {
+                if i % 2 == 1 {
+                    assert!(!btree.search(i));
+                } else {
+                    assert!(btree.search(i));
+                }
             }
         }
+    };
+}
+#[cfg(test)]
+use btree_test_suite;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! new_btree {
+        ($m:literal) => { BTree::new($m) };
+        ($m:literal, $t:ty) => { BTree::<$t>::new($m) };
     }
-}
\ No newline at end of file
+
+    btree_test_suite!(new_btree);
+}
@@ -0,0 +1,575 @@
+// BTree with its nodes stored in an arena
+//
+// ArenaBTree keeps every node in a single Vec (a slab) and refers to children by u32 node IDs
+// (indices into the slab) instead of Box pointers. Nodes are allocated next to each other, the tree
+// can be cloned with a plain Vec clone, and a node ID can later double as a page number on disk.
+//
+// Nodes freed by merge and root shrinkage are put on a free list, and their slots are reused by the
+// next nodes allocated (by splits and root growth), so the slab only grows when no slot is free.
+//
+// Insertion and deletion are the same algorithms as BTree::new (see b_tree.rs), with each node helper
+// taking the ID of the node it works on, so both build exactly the same tree.
+
+use std::fmt::Debug;
+
+use super::InsertStrategy;
+
+/// Index of a node in the arena
+type NodeId = u32;
+
+/// A B-tree whose nodes live in a Vec and refer to their children by index
+#[derive(Clone)]
+pub struct ArenaBTree<T: PartialOrd + Debug + Clone> {
+    nodes: Vec<Node<T>>,
+    free: Vec<NodeId>,
+    root: Option<NodeId>,
+    order: usize,
+}
+
+#[derive(Clone)]
+struct Node<T: PartialOrd + Debug + Clone> {
+    keys: Vec<T>,
+    children: Vec<NodeId>,
+    leaf: bool,
+}
+
+impl<T: PartialOrd + Debug + Clone> ArenaBTree<T> {
+    /// Constructor method for ArenaBTree
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    pub fn new(m: usize) -> Self {
+        assert!(m >= 3, "BTree order must be at least 3");
+        ArenaBTree { nodes: Vec::new(), free: Vec::new(), root: None, order: m }
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the number of nodes in the tree
+    pub fn node_count(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
+    /// Returns the number of node slots in the arena (nodes in the tree plus free slots)
+    pub fn capacity(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Traverse method for ArenaBTree
+    ///
+    /// Traverses through all keys for all nodes in order and prints them out
+    pub fn traverse(&self) {
+        match self.root {
+            Some(r) => self.traverse_node(r),
+            None => println!("=== EMPTY BTREE ==="),
+        }
+    }
+
+    /// Search method for ArenaBTree
+    ///
+    /// Returns true if value is present, false otherwise
+    pub fn search(&self, value: T) -> bool {
+        let mut id = match self.root {
+            Some(r) => r,
+            None => return false,
+        };
+
+        loop {
+            let (found, idx) = self.search_node(id, &value);
+            if found {
+                return true;
+            }
+            if self.node(id).leaf {
+                return false;
+            }
+            id = self.node(id).children[idx];
+        }
+    }
+
+    /// Inserts a value into the b-tree
+    pub fn insert(&mut self, value: T) {
+        let root = match self.root {
+            Some(r) => r,
+            None => {
+                // If root is empty, create a new root leaf node and insert value
+                let id = self.alloc(Node { keys: vec![value], children: vec![], leaf: true });
+                self.root = Some(id);
+                return;
+            },
+        };
+
+        if InsertStrategy::default_for(self.order) == InsertStrategy::TopDown {
+            // Split a full root before descending, as with every other full node on the way down
+            if self.node(root).keys.len() == self.max_keys() {
+                let new_root = self.split_root(root);
+                self.insert_non_full(new_root, value);
+            } else {
+                self.insert_non_full(root, value);
+            }
+        } else {
+            // Bottom-up, so the root is only split once it overflows
+            self.insert_bottom_up(root, value);
+            if self.node(root).keys.len() > self.max_keys() {
+                self.split_root(root);
+            }
+        }
+    }
+
+    /// Deletes a value from the b-tree
+    pub fn delete(&mut self, value: T) {
+        let root = match self.root {
+            Some(r) => r,
+            None => panic!("Cannot delete from empty BTree"),
+        };
+
+        self.delete_from(root, &value);
+
+        // Shrink tree if root is empty but has children, freeing the old root
+        let node = self.node(root);
+        if node.keys.is_empty() && !node.children.is_empty() {
+            self.root = Some(node.children[0]);
+            self.free(root);
+        }
+    }
+
+    /// Helper (test) function for printing b-tree structure
+    #[cfg(test)]
+    pub fn print_structure(&self) {
+        match self.root {
+            Some(r) => {
+                println!("=== ArenaBTree Structure (Order {}) ===", self.order);
+                println!();
+                self.print_node(r, 0);
+            },
+            None => println!("Empty tree"),
+        }
+    }
+
+    fn node(&self, id: NodeId) -> &Node<T> {
+        &self.nodes[id as usize]
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut Node<T> {
+        &mut self.nodes[id as usize]
+    }
+
+    /// Max number of keys K = m-1 a node holds (outside of a temporary overflow)
+    fn max_keys(&self) -> usize {
+        self.order - 1
+    }
+
+    /// Min number of keys floor(K/2) a non-root node holds
+    fn min_keys(&self) -> usize {
+        (self.order - 1) / 2
+    }
+
+    /// Puts a node into a free slot (or a new one) and returns its ID
+    fn alloc(&mut self, node: Node<T>) -> NodeId {
+        match self.free.pop() {
+            Some(id) => {
+                *self.node_mut(id) = node;
+                id
+            },
+            None => {
+                let id = NodeId::try_from(self.nodes.len()).expect("ArenaBTree is limited to u32::MAX nodes");
+                self.nodes.push(node);
+                id
+            },
+        }
+    }
+
+    /// Clears a node that is no longer in the tree and puts its slot on the free list
+    fn free(&mut self, id: NodeId) {
+        let node = self.node_mut(id);
+        node.keys = Vec::new();
+        node.children = Vec::new();
+        self.free.push(id);
+    }
+
+    /// Traverses and prints out all of the keys of a subtree recursively
+    fn traverse_node(&self, id: NodeId) {
+        let node = self.node(id);
+        for i in 0..node.keys.len() {
+            if !node.leaf {
+                self.traverse_node(node.children[i]);
+            }
+            print!("{:?} ", node.keys[i]);
+        }
+        if !node.leaf {
+            self.traverse_node(node.children[node.keys.len()]);
+        }
+    }
+
+    /// Binary search for value in the keys of a node
+    ///
+    /// Returns true and the idx of value if found, otherwise false and the idx of the smallest key greater than value
+    fn search_node(&self, id: NodeId, value: &T) -> (bool, usize) {
+        let keys = &self.node(id).keys;
+        let mut left = 0;
+        let mut right = keys.len();
+
+        while left < right {
+            let mid = left + (right - left) / 2;
+            if keys[mid] == *value {
+                return (true, mid);
+            }
+            if keys[mid] < *value {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+        (false, left)
+    }
+
+    /// Inserts a value into a non-full subtree, splitting full children on the way down (called recursively)
+    fn insert_non_full(&mut self, id: NodeId, value: T) {
+        let (_, mut idx) = self.search_node(id, &value);
+
+        if self.node(id).leaf {
+            self.node_mut(id).keys.insert(idx, value);
+            return;
+        }
+
+        let child = self.node(id).children[idx];
+        if self.node(child).keys.len() == self.max_keys() {
+            self.split_child(id, idx);
+            // Choose left or right child depending on new middle key (from child) at idx
+            if value > self.node(id).keys[idx] {
+                idx += 1;
+            }
+        }
+        let child = self.node(id).children[idx];
+        self.insert_non_full(child, value);
+    }
+
+    /// Inserts a value into a subtree, splitting nodes once they overflow (called recursively)
+    fn insert_bottom_up(&mut self, id: NodeId, value: T) {
+        let (_, idx) = self.search_node(id, &value);
+
+        if self.node(id).leaf {
+            self.node_mut(id).keys.insert(idx, value);
+            return;
+        }
+
+        let child = self.node(id).children[idx];
+        self.insert_bottom_up(child, value);
+        if self.node(child).keys.len() > self.max_keys() {
+            self.split_child(id, idx);
+        }
+    }
+
+    /// Makes the root the only child of a new root and splits it
+    ///
+    /// Returns the ID of the new root
+    fn split_root(&mut self, root: NodeId) -> NodeId {
+        let new_root = self.alloc(Node { keys: vec![], children: vec![root], leaf: false });
+        self.split_child(new_root, 0);
+        self.root = Some(new_root);
+        new_root
+    }
+
+    /// Splits the child at child_idx of a node in two and moves its middle key up into the node
+    fn split_child(&mut self, id: NodeId, child_idx: usize) {
+        let child_id = self.node(id).children[child_idx];
+        let child = self.node_mut(child_id);
+        let mid = child.keys.len() / 2;
+
+        let right_keys = child.keys.split_off(mid + 1);
+        let middle_key = child.keys.pop().expect("Middle key missing in split_child");
+        let right_children = if child.leaf { vec![] } else { child.children.split_off(mid + 1) };
+        let leaf = child.leaf;
+
+        let new_id = self.alloc(Node { keys: right_keys, children: right_children, leaf });
+        let node = self.node_mut(id);
+        node.keys.insert(child_idx, middle_key);
+        node.children.insert(child_idx + 1, new_id);
+    }
+
+    /// Deletes a value from a subtree (recursively) with the same cases as BTree (see Node::delete in b_tree.rs)
+    fn delete_from(&mut self, id: NodeId, value: &T) {
+        let (found, idx) = self.search_node(id, value);
+        let min = self.min_keys();
+
+        if self.node(id).leaf {
+            if !found {
+                // Case 4: Not found at all (reached leaf node)
+                panic!("Non-existant value cannot be deleted from BTree")
+            }
+            // Case 1: The value is in a leaf node
+            self.node_mut(id).keys.remove(idx);
+            return;
+        }
+
+        let children = &self.node(id).children;
+        let (left, right) = (children[idx], children.get(idx + 1).copied());
+        if found {
+            let right = right.expect("Internal node missing right child");
+            if self.node(left).keys.len() > min {
+                // Case 2a: Replace the value with its predecessor
+                let pred = self.rightmost(left);
+                self.delete_from(left, &pred);
+                self.node_mut(id).keys[idx] = pred;
+            } else if self.node(right).keys.len() > min {
+                // Case 2b: Replace the value with its successor
+                let succ = self.leftmost(right);
+                self.delete_from(right, &succ);
+                self.node_mut(id).keys[idx] = succ;
+            } else {
+                // Case 2c: Merge both children around the value, then delete it from the merged child
+                self.merge(id, idx);
+                self.delete_from(left, value);
+                self.split_overflowing(id, idx);
+            }
+            return;
+        }
+
+        // Case 3: Make sure the child the value belongs in has more than floor(K/2) keys
+        let mut idx = idx;
+        if self.node(left).keys.len() < min + 1 {
+            let children = &self.node(id).children;
+            if idx > 0 && self.node(children[idx - 1]).keys.len() > min {
+                // Case 3a: Borrow from the left sibling
+                self.rotate_right(id, idx);
+            } else if idx + 1 < children.len() && self.node(children[idx + 1]).keys.len() > min {
+                // Case 3b: Borrow from the right sibling
+                self.rotate_left(id, idx);
+            } else if idx + 1 == children.len() {
+                // Case 3c: Merge with a sibling (the left one for the last child)
+                self.merge(id, idx - 1);
+                idx -= 1;
+            } else {
+                self.merge(id, idx);
+            }
+        }
+        let child = self.node(id).children[idx];
+        self.delete_from(child, value);
+        self.split_overflowing(id, idx);
+    }
+
+    /// Splits the child at child_idx if it is still overfull after deleting from it (odd orders only)
+    fn split_overflowing(&mut self, id: NodeId, child_idx: usize) {
+        let child = self.node(id).children[child_idx];
+        if self.node(child).keys.len() > self.max_keys() {
+            self.split_child(id, child_idx);
+        }
+    }
+
+    /// Returns a clone of the rightmost key in a subtree
+    fn rightmost(&self, mut id: NodeId) -> T {
+        while !self.node(id).leaf {
+            id = *self.node(id).children.last().expect("Node missing children");
+        }
+        self.node(id).keys.last().expect("Leaf node missing keys").clone()
+    }
+
+    /// Returns a clone of the leftmost key in a subtree
+    fn leftmost(&self, mut id: NodeId) -> T {
+        while !self.node(id).leaf {
+            id = self.node(id).children[0];
+        }
+        self.node(id).keys.first().expect("Leaf node missing keys").clone()
+    }
+
+    /// Moves the last key of the left sibling up into the node and the separator down into the child at child_idx
+    fn rotate_right(&mut self, id: NodeId, child_idx: usize) {
+        let (left, right) = (self.node(id).children[child_idx - 1], self.node(id).children[child_idx]);
+
+        let last_key = self.node_mut(left).keys.pop().expect("Left child has no keys");
+        let last_child = self.node_mut(left).children.pop();
+        let middle_key = std::mem::replace(&mut self.node_mut(id).keys[child_idx - 1], last_key);
+
+        let right = self.node_mut(right);
+        right.keys.insert(0, middle_key);
+        if let Some(last_child) = last_child {
+            right.children.insert(0, last_child);
+        }
+    }
+
+    /// Moves the first key of the right sibling up into the node and the separator down into the child at child_idx
+    fn rotate_left(&mut self, id: NodeId, child_idx: usize) {
+        let (left, right) = (self.node(id).children[child_idx], self.node(id).children[child_idx + 1]);
+
+        let right_node = self.node_mut(right);
+        let first_key = right_node.keys.remove(0);
+        let first_child = if right_node.leaf { None } else { Some(right_node.children.remove(0)) };
+        let middle_key = std::mem::replace(&mut self.node_mut(id).keys[child_idx], first_key);
+
+        let left = self.node_mut(left);
+        left.keys.push(middle_key);
+        if let Some(first_child) = first_child {
+            left.children.push(first_child);
+        }
+    }
+
+    /// Merges the children at child_idx and child_idx + 1 around their separator, freeing the right child
+    fn merge(&mut self, id: NodeId, child_idx: usize) {
+        let node = self.node_mut(id);
+        let middle_key = node.keys.remove(child_idx);
+        let right = node.children.remove(child_idx + 1);
+        let left = node.children[child_idx];
+
+        let mut right_keys = std::mem::take(&mut self.node_mut(right).keys);
+        let mut right_children = std::mem::take(&mut self.node_mut(right).children);
+        let left = self.node_mut(left);
+        left.keys.push(middle_key);
+        left.keys.append(&mut right_keys);
+        left.children.append(&mut right_children);
+
+        self.free(right);
+    }
+
+    /// Prints a node and its subtree, indenting each level by two spaces (called recursively)
+    #[cfg(test)]
+    fn print_node(&self, id: NodeId, depth: usize) {
+        let node = self.node(id);
+        println!("{}Node (leaf={}): {:?}", "  ".repeat(depth), node.leaf, node.keys);
+        for &child in &node.children {
+            self.print_node(child, depth + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_tree::{btree_test_suite, NodeSnapshot};
+    use crate::BTree;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    macro_rules! new_arena_btree {
+        ($m:literal) => { ArenaBTree::new($m) };
+        ($m:literal, $t:ty) => { ArenaBTree::<$t>::new($m) };
+    }
+
+    btree_test_suite!(new_arena_btree);
+
+    impl<T: PartialOrd + Debug + Clone> ArenaBTree<T> {
+        fn snapshot(&self) -> Option<NodeSnapshot<T>> {
+            fn walk<T: PartialOrd + Debug + Clone>(btree: &ArenaBTree<T>, id: NodeId) -> NodeSnapshot<T> {
+                let node = btree.node(id);
+                NodeSnapshot { keys: node.keys.clone(), children: node.children.iter().map(|&c| walk(btree, c)).collect() }
+            }
+            self.root.map(|r| walk(self, r))
+        }
+
+        /// Returns the IDs of every node reachable from the root
+        fn reachable(&self) -> Vec<NodeId> {
+            let mut ids = vec![];
+            let mut stack: Vec<NodeId> = self.root.into_iter().collect();
+            while let Some(id) = stack.pop() {
+                ids.push(id);
+                stack.extend(&self.node(id).children);
+            }
+            ids
+        }
+    }
+
+    fn btree_snapshot(btree: &BTree<i32>) -> Option<NodeSnapshot<i32>> {
+        btree.root.as_ref().map(|r| r.snapshot())
+    }
+
+    #[test]
+    fn test_same_shape_as_btree() {
+        for order in 3..=8 {
+            let mut values: Vec<i32> = (0..300).collect();
+            values.shuffle(&mut StdRng::seed_from_u64(order as u64));
+
+            let mut arena = ArenaBTree::new(order);
+            let mut btree = BTree::new(order);
+            for &val in &values {
+                arena.insert(val);
+                btree.insert(val);
+            }
+            assert_eq!(arena.snapshot(), btree_snapshot(&btree));
+
+            values.shuffle(&mut StdRng::seed_from_u64(order as u64 + 100));
+            for &val in &values[..250] {
+                arena.delete(val);
+                btree.delete(val);
+                assert_eq!(arena.snapshot(), btree_snapshot(&btree), "order {} after deleting {}", order, val);
+            }
+        }
+    }
+
+    #[test]
+    fn test_free_list_tracks_unreachable_nodes() {
+        let mut values: Vec<i32> = (0..500).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(1));
+
+        let mut btree = ArenaBTree::new(4);
+        for &val in &values {
+            btree.insert(val);
+        }
+        for &val in &values[..450] {
+            btree.delete(val);
+
+            // Every slot is either reachable from the root or on the free list, never both
+            let mut reachable = btree.reachable();
+            assert_eq!(reachable.len(), btree.node_count());
+            reachable.extend(&btree.free);
+            reachable.sort();
+            let all: Vec<NodeId> = (0..btree.capacity() as NodeId).collect();
+            assert_eq!(reachable, all);
+        }
+    }
+
+    #[test]
+    fn test_merge_and_root_shrink_free_nodes() {
+        let mut btree = ArenaBTree::new(3);
+        for i in 1..=3 {
+            btree.insert(i);
+        }
+        // Root [2] with leaves [1] and [3]
+        assert_eq!(btree.node_count(), 3);
+
+        // The leaves merge, and then the empty root is replaced by the merged leaf
+        btree.delete(1);
+        assert_eq!(btree.node_count(), 1);
+        assert_eq!(btree.free.len(), 2);
+        assert_eq!(btree.snapshot(), Some(NodeSnapshot { keys: vec![2, 3], children: vec![] }));
+    }
+
+    #[test]
+    fn test_freed_slots_are_reused() {
+        let mut btree = ArenaBTree::new(5);
+        for i in 0..1000 {
+            btree.insert(i);
+        }
+        let capacity = btree.capacity();
+
+        // Shrink the tree right down, then grow it back: the slab does not grow
+        for i in 0..990 {
+            btree.delete(i);
+        }
+        assert!(btree.node_count() < capacity / 10);
+        for i in 0..990 {
+            btree.insert(i);
+        }
+        assert_eq!(btree.capacity(), capacity);
+        for i in 0..1000 {
+            assert!(btree.search(i));
+        }
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        let mut btree = ArenaBTree::new(4);
+        for i in 0..100 {
+            btree.insert(i);
+        }
+        let copy = btree.clone();
+        for i in 0..50 {
+            btree.delete(i);
+        }
+
+        for i in 0..100 {
+            assert!(copy.search(i));
+            assert_eq!(btree.search(i), i >= 50);
+        }
+    }
+}
@@ -0,0 +1,604 @@
+// Lehman-Yao B-link tree (https://www.csd.uoc.gr/~hy460/pdf/p650-lehman.pdf)
+//
+// Every node carries a high key (an upper bound on the keys in its subtree, None for the last node of
+// its level) and a right-link to the next node on its level. A split moves the upper half of a node
+// into a new right sibling, which takes over the old high key and right-link, and the node's high key
+// becomes the separator. So between a split and the insertion of the separator into the parent, the
+// moved keys are still reachable: anything looking for a key at or above a node's high key just
+// follows its right-link. Keys only ever move right, and nodes are never merged or freed, so a thread
+// that arrives at a node after it was split (having read its parent before the split) always recovers
+// by moving right.
+//
+// That means no operation needs lock coupling: each node has a latch, but a thread only ever holds one
+// at a time. A search holds each node's shared latch just long enough to pick the next node (a child,
+// or the right sibling), so readers never block each other and never wait at the root for a writer
+// further down the tree. An insert descends the same way, remembering the last node it visited on each
+// level, then latches the leaf exclusively (moving right if it has been split since), and inserts. An
+// overflowing node is split, its latch released, and the separator inserted into the remembered
+// parent (again moving right as needed) the same way (Sagiv's variant of Lehman-Yao, which never
+// holds two latches). If the node had no parent because it was on the top level, the tree grows a new
+// root holding every node on that level.
+//
+// Keys are only stored in the leaves (as in a B+ tree), since a key that moves up into the parent on a
+// split would briefly be in neither. Leaves split into halves of floor(m/2) and ceiling(m/2) keys, with
+// the first key of the right half copied up as the separator, and internal nodes split exactly as
+// Node::split_child does (the median moves up, the left half keeps the keys before it).
+//
+// Deletion is not supported: Lehman-Yao has no merges (which would break the right-link invariants),
+// and deleting without them leaves under-full nodes behind.
+//
+// The latches and atomics come from loom when built with --cfg loom, so the loom tests at the end
+// explore every interleaving of the operations (RUSTFLAGS="--cfg loom" cargo test --release b_link).
+
+use std::fmt::Debug;
+
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(loom)]
+use loom::sync::{Arc, RwLock};
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(not(loom))]
+use std::sync::{Arc, RwLock};
+
+use super::NodeSearch;
+
+type NodeRef<T> = Arc<RwLock<Node<T>>>;
+
+/// A concurrent B+ tree whose nodes link to their right siblings, so no operation holds more than one latch
+pub struct BLinkTree<T: PartialOrd + Debug + Clone> {
+    root: RwLock<NodeRef<T>>,
+    order: usize,
+    len: AtomicUsize,
+}
+
+struct Node<T: PartialOrd + Debug + Clone> {
+    /// Keys in a leaf, separators in an internal node (children[i] holds keys below keys[i])
+    keys: Vec<T>,
+    children: Vec<NodeRef<T>>,
+    /// 0 for leaves, counting up to the root
+    level: usize,
+    /// Every key in the subtree is below the high key (None for the last node of its level)
+    high_key: Option<T>,
+    /// Next node on the same level
+    right: Option<NodeRef<T>>,
+}
+
+/// Where to go from a node while descending
+enum Step<T: PartialOrd + Debug + Clone> {
+    Right(NodeRef<T>),
+    Down(NodeRef<T>),
+    Here,
+}
+
+impl<T: PartialOrd + Debug + Clone> BLinkTree<T> {
+    /// Constructor method for BLinkTree
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    pub fn new(m: usize) -> Self {
+        assert!(m >= 3, "BTree order must be at least 3");
+        let root = Node { keys: vec![], children: vec![], level: 0, high_key: None, right: None };
+        BLinkTree { root: RwLock::new(Arc::new(RwLock::new(root))), order: m, len: AtomicUsize::new(0) }
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the number of keys in the tree
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the tree holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Search method for BLinkTree
+    ///
+    /// Returns true if value is present, false otherwise
+    pub fn search(&self, value: T) -> bool {
+        let leaf = self.descend(&value, 0, &mut vec![]);
+        search_from(leaf, &value)
+    }
+
+    /// Returns all keys in order, reading one leaf at a time along the right-links
+    pub fn to_vec(&self) -> Vec<T> {
+        // Find the leftmost leaf
+        let mut node = Arc::clone(&self.root.read().expect("Root latch poisoned"));
+        loop {
+            let child = {
+                let n = node.read().expect("Node latch poisoned");
+                if n.level == 0 {
+                    break;
+                }
+                Arc::clone(&n.children[0])
+            };
+            node = child;
+        }
+
+        let mut keys = vec![];
+        loop {
+            let right = {
+                let n = node.read().expect("Node latch poisoned");
+                keys.extend(n.keys.iter().cloned());
+                n.right.clone()
+            };
+            match right {
+                Some(right) => node = right,
+                None => return keys,
+            }
+        }
+    }
+
+    /// Inserts a value into the b-tree
+    ///
+    /// Returns false (leaving the tree unchanged) if value is already present
+    pub fn insert(&self, value: T) -> bool {
+        let mut stack = vec![];
+        let mut node = self.descend(&value, 0, &mut stack);
+        let mut key = value;
+        let mut child = None;
+
+        loop {
+            let mut n = node.write().expect("Node latch poisoned");
+            if n.must_move_right(&key) {
+                // Split since it was read, so the key now belongs further right
+                let right = n.right.clone().expect("Node with a high key has a right-link");
+                drop(n);
+                node = right;
+                continue;
+            }
+
+            if !n.insert_entry(key, child.take()) {
+                // A duplicate in a leaf, or a separator another thread already inserted (see grow_root)
+                return n.level > 0;
+            }
+            if n.level == 0 {
+                self.len.fetch_add(1, Ordering::Relaxed);
+            }
+            if n.keys.len() < self.order {
+                return true;
+            }
+
+            // Overflowed: split, release the latch, and insert the separator into the parent
+            let level = n.level;
+            let (separator, right) = n.split();
+            drop(n);
+
+            match stack.pop() {
+                Some(parent) => node = parent,
+                // The new root holds every node on the level, the new right half included
+                None if self.grow_root(level) => return true,
+                // Another thread grew the tree first, so find the parent from the new root
+                None => node = self.descend(&separator, level + 1, &mut stack),
+            }
+            key = separator;
+            child = Some(right);
+        }
+    }
+
+    /// Descends from the root to the node on level that value belongs in, following right-links past
+    /// concurrent splits, and pushing the last node visited on each level above it onto stack
+    fn descend(&self, value: &T, level: usize, stack: &mut Vec<NodeRef<T>>) -> NodeRef<T> {
+        let mut node = Arc::clone(&self.root.read().expect("Root latch poisoned"));
+        loop {
+            let step = {
+                let n = node.read().expect("Node latch poisoned");
+                if n.must_move_right(value) {
+                    Step::Right(n.right.clone().expect("Node with a high key has a right-link"))
+                } else if n.level == level {
+                    Step::Here
+                } else {
+                    Step::Down(Arc::clone(&n.children[n.child_idx(value)]))
+                }
+            };
+            match step {
+                Step::Right(right) => node = right,
+                Step::Down(child) => {
+                    stack.push(node);
+                    node = child;
+                },
+                Step::Here => return node,
+            }
+        }
+    }
+
+    /// Makes a new root above the top level, after a node on level has split
+    ///
+    /// The new root holds every node on the level, so it also covers any other node split on the
+    /// level in the meantime (and is itself split if that is too many). Returns false if another
+    /// thread already grew the tree
+    fn grow_root(&self, level: usize) -> bool {
+        let mut root = self.root.write().expect("Root latch poisoned");
+        if root.read().expect("Node latch poisoned").level > level {
+            return false;
+        }
+
+        // The root pointer is the first node of the top level, so walk its right-links
+        let mut keys = vec![];
+        let mut children = vec![Arc::clone(&root)];
+        loop {
+            let next = {
+                let n = children.last().expect("Level has a node").read().expect("Node latch poisoned");
+                match (&n.high_key, &n.right) {
+                    (Some(high_key), Some(right)) => {
+                        keys.push(high_key.clone());
+                        Arc::clone(right)
+                    },
+                    _ => break,
+                }
+            };
+            children.push(next);
+        }
+
+        // Nothing else can see the new root until it is published, so it can be split without latches
+        let mut top = Node { keys, children, level: level + 1, high_key: None, right: None };
+        while top.keys.len() >= self.order {
+            let (separator, right) = top.split();
+            let level = top.level + 1;
+            let left = Arc::new(RwLock::new(top));
+            top = Node { keys: vec![separator], children: vec![left, right], level, high_key: None, right: None };
+        }
+        *root = Arc::new(RwLock::new(top));
+        true
+    }
+
+    /// Helper (test) function that checks keys are in order, every node is within its high key and
+    /// holds at most K keys, every internal node's children are bounded by its separators, and the
+    /// right-links of every level visit the children of the level above in order
+    #[cfg(test)]
+    fn check_invariants(&self) {
+        let mut first = Some(Arc::clone(&self.root.read().unwrap()));
+        let mut expected_level = first.as_ref().unwrap().read().unwrap().level;
+        while let Some(start) = first {
+            let mut below = vec![];
+            let mut node = Some(start);
+            while let Some(current) = node {
+                let n = current.read().unwrap();
+                assert_eq!(n.level, expected_level);
+                assert!(n.keys.len() < self.order, "Node overfull: {:?}", n.keys);
+                assert!(n.keys.windows(2).all(|w| w[0] < w[1]), "Keys out of order: {:?}", n.keys);
+                if let Some(high_key) = &n.high_key {
+                    assert!(n.keys.iter().all(|k| k < high_key), "Key above high key {:?}: {:?}", high_key, n.keys);
+                }
+                if n.level > 0 {
+                    assert_eq!(n.children.len(), n.keys.len() + 1);
+                    for (i, child) in n.children.iter().enumerate() {
+                        let c = child.read().unwrap();
+                        let bound = if i < n.keys.len() { Some(&n.keys[i]) } else { n.high_key.as_ref() };
+                        assert!(c.high_key.as_ref() == bound, "Child high key {:?} is not {:?}", c.high_key, bound);
+                        if i > 0 {
+                            assert!(c.keys.iter().all(|k| *k >= n.keys[i - 1]));
+                        }
+                    }
+                    below.extend(n.children.iter().cloned());
+                }
+                node = n.right.clone();
+            }
+
+            // The level below is exactly the children of this level, in order
+            first = below.first().cloned();
+            if let Some(first) = &first {
+                let mut node = Some(Arc::clone(first));
+                for child in &below {
+                    let current = node.expect("Level below ends early");
+                    assert!(Arc::ptr_eq(&current, child));
+                    node = current.read().unwrap().right.clone();
+                }
+                assert!(node.is_none());
+            }
+            expected_level = expected_level.saturating_sub(1);
+        }
+        let keys = self.to_vec();
+        assert_eq!(keys.len(), self.len());
+        assert!(keys.windows(2).all(|w| w[0] < w[1]), "Keys out of order: {:?}", keys);
+    }
+}
+
+/// Searches the leaf level from node (which may have split since it was found) for value
+fn search_from<T: PartialOrd + Debug + Clone>(mut node: NodeRef<T>, value: &T) -> bool {
+    loop {
+        let right = {
+            let n = node.read().expect("Node latch poisoned");
+            if !n.must_move_right(value) {
+                return NodeSearch::Binary.search(&n.keys, value).0;
+            }
+            n.right.clone().expect("Node with a high key has a right-link")
+        };
+        node = right;
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    /// Returns true if value is at or above the high key, so belongs in a node further right
+    fn must_move_right(&self, value: &T) -> bool {
+        self.high_key.as_ref().is_some_and(|high_key| *value >= *high_key)
+    }
+
+    /// Returns the index of the child value belongs in (the number of separators at or below it)
+    fn child_idx(&self, value: &T) -> usize {
+        self.keys.partition_point(|key| *key <= *value)
+    }
+
+    /// Inserts a key into a leaf, or a separator and the child to its right into an internal node
+    ///
+    /// Returns false if the key (or separator) is already there
+    fn insert_entry(&mut self, key: T, child: Option<NodeRef<T>>) -> bool {
+        if self.level == 0 {
+            let (found, idx) = NodeSearch::Binary.search(&self.keys, &key);
+            if found {
+                return false;
+            }
+            self.keys.insert(idx, key);
+        } else {
+            let child = child.expect("Separator inserted without a child");
+            let idx = self.child_idx(&key);
+            // The child is already in place if grow_root put it there, which is checked by identity
+            // rather than by the separator: a split may since have moved the separator up as its
+            // median, leaving the child first in the right half (where the separator leads)
+            if Arc::ptr_eq(&self.children[idx], &child) {
+                return false;
+            }
+            self.keys.insert(idx, key);
+            self.children.insert(idx + 1, child);
+        }
+        true
+    }
+
+    /// Splits this overflowing node, moving its upper half into a new right sibling
+    ///
+    /// Returns the separator (the new high key of this node) and the new sibling
+    fn split(&mut self) -> (T, NodeRef<T>) {
+        let mid = self.keys.len() / 2;
+        let (separator, right_keys, right_children) = if self.level == 0 {
+            // Leaves keep every key, so the first key of the right half is copied up
+            let right_keys = self.keys.split_off(mid);
+            (right_keys[0].clone(), right_keys, vec![])
+        } else {
+            // Same as Node::split_child: the median moves up, and the right half gets the keys after it
+            let right_keys = self.keys.split_off(mid + 1);
+            let median = self.keys.pop().expect("Middle key missing in split");
+            (median, right_keys, self.children.split_off(mid + 1))
+        };
+
+        let right = Node {
+            keys: right_keys,
+            children: right_children,
+            level: self.level,
+            high_key: self.high_key.take(),
+            right: self.right.take(),
+        };
+        let right = Arc::new(RwLock::new(right));
+        self.high_key = Some(separator.clone());
+        self.right = Some(Arc::clone(&right));
+        (separator, right)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+    use std::thread;
+
+    #[test]
+    fn test_against_btreeset() {
+        for order in 3..=10 {
+            let mut rng = StdRng::seed_from_u64(order as u64);
+            let btree = BLinkTree::new(order);
+            let mut set = BTreeSet::new();
+            for _ in 0..2000 {
+                let val: i32 = rng.random_range(0..1000);
+                assert_eq!(btree.insert(val), set.insert(val));
+            }
+            btree.check_invariants();
+            assert_eq!(btree.len(), set.len());
+            assert_eq!(btree.to_vec(), set.iter().copied().collect::<Vec<_>>());
+            for val in 0..1000 {
+                assert_eq!(btree.search(val), set.contains(&val));
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let btree: BLinkTree<i32> = BLinkTree::new(3);
+        assert!(btree.is_empty());
+        assert!(!btree.search(1));
+        assert!(btree.to_vec().is_empty());
+        assert!(btree.insert(1));
+        assert!(!btree.insert(1));
+        assert_eq!(btree.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "BTree order must be at least 3")]
+    fn test_invalid_order() {
+        let _btree: BLinkTree<i32> = BLinkTree::new(2);
+    }
+
+    #[test]
+    fn test_separator_already_inserted() {
+        let leaf = |keys: Vec<i32>| Arc::new(RwLock::new(Node { keys, children: vec![], level: 0, high_key: None, right: None }));
+        let (first, left, right, last) = (leaf(vec![1]), leaf(vec![3]), leaf(vec![5]), leaf(vec![9]));
+        let mut parent = Node { keys: vec![3, 5, 9], children: vec![first, left, Arc::clone(&right), last], level: 1, high_key: None, right: None };
+        assert!(!parent.insert_entry(5, Some(Arc::clone(&right))));
+
+        // After the parent split on 5 (the median), the right half leads with the child but not the separator
+        let (separator, half) = parent.split();
+        assert_eq!(separator, 5);
+        let mut half = half.write().unwrap();
+        assert!(Arc::ptr_eq(&half.children[0], &right));
+        assert!(!half.insert_entry(5, Some(right)));
+        assert_eq!(half.keys, vec![9]);
+        assert_eq!(half.children.len(), 2);
+    }
+
+    #[test]
+    fn test_leaf_and_internal_splits() {
+        // Order 3: the leaf [1, 2, 3] splits into [1] and [2, 3], with 2 copied up
+        let btree = BLinkTree::new(3);
+        for i in 1..=3 {
+            btree.insert(i);
+        }
+        let root = Arc::clone(&btree.root.read().unwrap());
+        let root = root.read().unwrap();
+        assert_eq!(root.level, 1);
+        assert_eq!(root.keys, vec![2]);
+        let left = root.children[0].read().unwrap();
+        assert_eq!(left.keys, vec![1]);
+        assert_eq!(left.high_key, Some(2));
+        assert!(Arc::ptr_eq(left.right.as_ref().unwrap(), &root.children[1]));
+        assert_eq!(root.children[1].read().unwrap().keys, vec![2, 3]);
+        drop(left);
+        drop(root);
+
+        // Separators 2, 3 and 4 overflow the root, which splits around its median (3) like split_child
+        for i in 4..=5 {
+            btree.insert(i);
+        }
+        let root = Arc::clone(&btree.root.read().unwrap());
+        let root = root.read().unwrap();
+        assert_eq!(root.level, 2);
+        assert_eq!(root.keys, vec![3]);
+        assert_eq!(root.children[0].read().unwrap().keys, vec![2]);
+        assert_eq!(root.children[1].read().unwrap().keys, vec![4]);
+        drop(root);
+        btree.check_invariants();
+    }
+
+    #[test]
+    fn test_search_recovers_from_split() {
+        // Find the leaf for 100 (the only leaf), then split it many times over behind the search's back
+        let btree = BLinkTree::new(4);
+        btree.insert(0);
+        let stale = btree.descend(&100, 0, &mut vec![]);
+        for i in 1..=200 {
+            btree.insert(i);
+        }
+        assert!(stale.read().unwrap().keys.len() < 4);
+
+        // The stale leaf only holds the smallest keys now, and the search moves right to the rest
+        for i in [0, 1, 100, 150, 200] {
+            assert!(search_from(Arc::clone(&stale), &i));
+        }
+        assert!(!search_from(Arc::clone(&stale), &201));
+        btree.check_invariants();
+    }
+
+    #[test]
+    fn test_stress() {
+        // Each writer owns the keys equal to its id mod THREADS, and readers search for keys once
+        // they are known to be inserted
+        const THREADS: usize = 8;
+        const KEYS: usize = 3000;
+        for order in [3, 4, 7, 32] {
+            let btree = BLinkTree::new(order);
+            thread::scope(|s| {
+                for t in 0..THREADS {
+                    let btree = &btree;
+                    s.spawn(move || {
+                        let mut keys: Vec<usize> = (0..KEYS / THREADS).map(|i| i * THREADS + t).collect();
+                        keys.shuffle(&mut StdRng::seed_from_u64(t as u64));
+                        for (i, &key) in keys.iter().enumerate() {
+                            assert!(btree.insert(key));
+                            assert!(!btree.insert(key));
+                            // Everything this writer inserted so far is still found, whatever has split since
+                            let earlier = keys[i / 2];
+                            assert!(btree.search(earlier), "{} missing", earlier);
+                        }
+                    });
+                }
+                for t in 0..2 {
+                    let btree = &btree;
+                    s.spawn(move || {
+                        let mut rng = StdRng::seed_from_u64(100 + t);
+                        for _ in 0..200 {
+                            let keys = btree.to_vec();
+                            assert!(keys.windows(2).all(|w| w[0] < w[1]));
+                            btree.search(rng.random_range(0..KEYS));
+                        }
+                    });
+                }
+            });
+
+            btree.check_invariants();
+            assert_eq!(btree.to_vec(), (0..KEYS).collect::<Vec<_>>());
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::*;
+    use loom::thread;
+
+    /// Checks every interleaving of f with up to 3 preemptions
+    fn model<F: Fn() + Sync + Send + 'static>(f: F) {
+        let mut builder = loom::model::Builder::new();
+        builder.preemption_bound = Some(3);
+        builder.check(f);
+    }
+
+    #[test]
+    fn loom_concurrent_inserts_split_root() {
+        model(|| {
+            // Order 3: the root leaf [1, 3] is full, so either insert splits it
+            let btree = Arc::new(BLinkTree::new(3));
+            btree.insert(1);
+            btree.insert(3);
+
+            let other = Arc::clone(&btree);
+            let handle = thread::spawn(move || assert!(other.insert(2)));
+            assert!(btree.insert(4));
+            handle.join().unwrap();
+
+            btree.check_invariants();
+            assert_eq!(btree.to_vec(), vec![1, 2, 3, 4]);
+        });
+    }
+
+    #[test]
+    fn loom_search_during_split() {
+        model(|| {
+            // Inserting 2 splits [1, 3] and moves 3 into a new right sibling
+            let btree = Arc::new(BLinkTree::new(3));
+            btree.insert(1);
+            btree.insert(3);
+
+            let other = Arc::clone(&btree);
+            let handle = thread::spawn(move || other.insert(2));
+            assert!(btree.search(3));
+            assert!(btree.search(1));
+            handle.join().unwrap();
+            assert!(btree.search(2));
+        });
+    }
+
+    #[test]
+    fn loom_racing_root_growth() {
+        model(|| {
+            // Both threads may split a node of the top level before either has grown a new root
+            let btree = Arc::new(BLinkTree::new(3));
+            btree.insert(1);
+            btree.insert(3);
+
+            let other = Arc::clone(&btree);
+            let handle = thread::spawn(move || {
+                other.insert(4);
+                other.insert(5);
+            });
+            btree.insert(2);
+            handle.join().unwrap();
+
+            btree.check_invariants();
+            assert_eq!(btree.to_vec(), vec![1, 2, 3, 4, 5]);
+        });
+    }
+}
@@ -0,0 +1,312 @@
+// B* tree insertion (https://en.wikipedia.org/wiki/B-tree#Variants)
+//
+// Instead of splitting a full node in two (leaving both halves about half full), a B* tree first
+// shifts a key into an adjacent sibling that has room. Only when that sibling is full too are the
+// two nodes split into three, each of which is then at least two-thirds full.
+//
+// Insertion here is bottom-up: the value is inserted into its leaf, and a node that overflows
+// (holds m keys, like the temporary third key of the order 3 case) is fixed by its parent on the
+// way back up, either by rotating a key into a sibling or by a 2-to-3 split with the sibling.
+//
+// With Knuth order m, an overflowing node (m keys), a full sibling (m-1 keys) and their separator
+// give 2m keys, two of which become separators in the parent. The remaining 2m-2 keys are spread
+// over three nodes, so each holds at least floor((2m-2)/3) keys.
+//
+// The root has no siblings, so it can only be split in two. Following Knuth, the root may hold up
+// to 2 * floor((2m-2)/3) keys (which can be more than m-1), so that when it overflows both halves
+// are two-thirds full as well.
+//
+// Deletion is unchanged (the usual B-tree minimum of floor(K/2) keys applies).
+
+use std::fmt::Debug;
+
+use super::cow::{make_mut, Child};
+use super::observer::Hooks;
+use super::{BTree, InsertStrategy, Node};
+
+/// Returns the maximum number of keys the root of a B* tree of the given order may hold
+pub(super) fn root_max_keys(order: usize) -> usize {
+    2 * ((2 * order - 2) / 3)
+}
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Constructor method for a BTree that inserts with the B* tree strategy
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    pub fn new_b_star(m: usize) -> Self {
+        BTree::with_strategy(m, InsertStrategy::BStar)
+    }
+
+    /// Returns true if the tree inserts with the B* tree strategy
+    pub fn is_b_star(&self) -> bool {
+        self.strategy == InsertStrategy::BStar
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    /// Inserts a value into the subtree using the B* strategy (called recursively)
+    ///
+    /// The node may be left holding m keys, which the caller then fixes
+    pub(super) fn insert_b_star(&mut self, value: T, level: usize, hooks: &mut Hooks<T>) {
+        let (_, idx) = self.search(&value, hooks.node_search, hooks.counters);
+        hooks.visit(level, self);
+
+        if self.leaf {
+            // Base case: insert into the leaf even if it overflows
+            self.keys.insert(idx, value);
+            hooks.insert_key(level, idx, self);
+            return;
+        }
+
+        self.child_mut(idx).insert_b_star(value, level + 1, hooks);
+        if self.children[idx].keys.len() == self.order {
+            self.fix_overflow(idx, level, hooks);
+        }
+    }
+
+    /// Fixes the overflowing child at child_idx by shifting a key into a sibling with room,
+    /// or splitting it and a full sibling into three nodes
+    fn fix_overflow(&mut self, child_idx: usize, level: usize, hooks: &mut Hooks<T>) {
+        let max_keys = self.order - 1;
+        let has_left = child_idx > 0;
+        let has_right = child_idx + 1 < self.children.len();
+
+        if has_left && self.children[child_idx - 1].keys.len() < max_keys {
+            // Shift the child's first key up and the separator down into the left sibling
+            self.rotate_left(child_idx - 1, level, hooks);
+        } else if has_right && self.children[child_idx + 1].keys.len() < max_keys {
+            // Shift the child's last key up and the separator down into the right sibling
+            self.rotate_right(child_idx + 1, level, hooks);
+        } else if has_right {
+            self.split_three(child_idx, level, hooks);
+        } else {
+            self.split_three(child_idx - 1, level, hooks);
+        }
+    }
+
+    /// Splits the children at child_idx and child_idx + 1 (and their separator) into three nodes
+    ///
+    /// Takes a child_idx that represents the left child and the level of the current node
+    fn split_three(&mut self, child_idx: usize, level: usize, hooks: &mut Hooks<T>) {
+        // Gather the keys and children of both nodes, with the separator between them
+        let separator = self.keys.remove(child_idx);
+        let mut right = self.children.remove(child_idx + 1);
+        let right_node = make_mut(&mut right);
+        let left = self.child_mut(child_idx);
+
+        let mut keys = std::mem::take(&mut left.keys);
+        keys.push(separator);
+        keys.append(&mut right_node.keys);
+        let mut children = std::mem::take(&mut left.children);
+        children.append(&mut right_node.children);
+
+        // Two keys become separators, the rest are spread evenly (any remainder goes to the right)
+        let n = keys.len() - 2;
+        let first = n / 3;
+        let second = (n - first) / 2;
+
+        let third_keys = keys.split_off(first + 1 + second + 1);
+        let second_separator = keys.pop().expect("Second separator missing in split_three");
+        let second_keys = keys.split_off(first + 1);
+        let first_separator = keys.pop().expect("First separator missing in split_three");
+        let first_keys = keys;
+
+        let (first_children, second_children, third_children) = if left.leaf {
+            (vec![], vec![], vec![])
+        } else {
+            let third_children = children.split_off(first + 1 + second + 1);
+            let second_children = children.split_off(first + 1);
+            (children, second_children, third_children)
+        };
+
+        // Reuse the two existing nodes for the first two thirds and create a new node for the last
+        left.keys = first_keys;
+        left.children = first_children;
+        right_node.keys = second_keys;
+        right_node.children = second_children;
+        let third = Node { keys: third_keys, children: third_children, leaf: left.leaf, order: left.order };
+
+        self.keys.insert(child_idx, first_separator);
+        self.keys.insert(child_idx + 1, second_separator);
+        self.children.insert(child_idx + 1, right);
+        self.children.insert(child_idx + 2, Child::new(third));
+
+        // One new node was created, with the second separator added to this node
+        hooks.split(level + 1, &self.keys[child_idx + 1], self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_tree::{Counters, NodeSearch};
+
+    /// Returns (min, max) keys over every non-root node
+    fn key_range(btree: &BTree<i32>) -> (usize, usize) {
+        fn walk(node: &Node<i32>, is_root: bool, range: &mut (usize, usize)) {
+            if !is_root {
+                range.0 = range.0.min(node.keys.len());
+                range.1 = range.1.max(node.keys.len());
+            }
+            for child in &node.children {
+                walk(child, false, range);
+            }
+        }
+        let mut range = (usize::MAX, 0);
+        walk(btree.root.as_ref().unwrap(), true, &mut range);
+        range
+    }
+
+    /// Checks keys are in order and every leaf is at the same depth
+    fn check_shape(btree: &BTree<i32>) {
+        fn walk(node: &Node<i32>, depth: usize, leaf_depth: &mut Option<usize>, keys: &mut Vec<i32>) {
+            if node.leaf {
+                assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+                keys.extend(&node.keys);
+                return;
+            }
+            assert_eq!(node.children.len(), node.keys.len() + 1);
+            for (i, child) in node.children.iter().enumerate() {
+                walk(child, depth + 1, leaf_depth, keys);
+                if i < node.keys.len() {
+                    keys.push(node.keys[i]);
+                }
+            }
+        }
+        let mut keys = vec![];
+        walk(btree.root.as_ref().unwrap(), 0, &mut None, &mut keys);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]), "Keys out of order: {:?}", keys);
+    }
+
+    #[test]
+    fn test_b_star_constructor() {
+        let btree: BTree<i32> = BTree::new_b_star(5);
+        assert!(btree.is_b_star());
+        assert!(!BTree::<i32>::new(5).is_b_star());
+    }
+
+    #[test]
+    fn test_b_star_insert_and_search() {
+        for order in [3, 4, 5, 6, 9] {
+            let mut btree = BTree::new_b_star(order);
+            for i in 1..=200 {
+                btree.insert(i);
+                check_shape(&btree);
+            }
+            for i in 1..=200 {
+                assert!(btree.search(i));
+            }
+            assert!(!btree.search(0));
+            assert!(!btree.search(201));
+        }
+    }
+
+    #[test]
+    fn test_b_star_random_order() {
+        let values = [50, 30, 70, 20, 40, 60, 80, 10, 90, 25, 35, 45, 55, 65, 75, 85, 5, 15, 95, 1];
+        let mut btree = BTree::new_b_star(4);
+        for val in values {
+            btree.insert(val);
+            check_shape(&btree);
+        }
+        for val in values {
+            assert!(btree.search(val));
+        }
+    }
+
+    #[test]
+    fn test_b_star_redistributes_before_splitting() {
+        let mut btree = BTree::new_b_star(5);
+        for i in 1..=5 {
+            btree.insert(i);
+        }
+        // The root split normally: [3] with [1, 2] and [4, 5]
+        btree.insert(6);
+        btree.insert(7);
+        // [4, 5, 6, 7] is full, so 8 overflows it and a key is shifted into [1, 2]
+        btree.insert(8);
+        btree.print_structure();
+
+        let root = btree.root.as_ref().unwrap();
+        assert_eq!(root.keys, vec![4]);
+        assert_eq!(root.children[0].keys, vec![1, 2, 3]);
+        assert_eq!(root.children[1].keys, vec![5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_b_star_two_to_three_split() {
+        let mut btree = BTree::new_b_star(5);
+        for i in 1..=10 {
+            btree.insert(i);
+        }
+        btree.print_structure();
+
+        // Both leaves full ([1, 2, 3, 4] and [6, 7, 8, 9]), so 10 splits them into three
+        let root = btree.root.as_ref().unwrap();
+        assert_eq!(root.children.len(), 3);
+        check_shape(&btree);
+        let (min, _) = key_range(&btree);
+        assert!(min >= 2);
+    }
+
+    #[test]
+    fn test_b_star_nodes_at_least_two_thirds_full() {
+        for order in [4, 5, 7, 10] {
+            let mut btree = BTree::new_b_star(order);
+            for i in 1..=1000 {
+                btree.insert(i);
+            }
+            check_shape(&btree);
+
+            // Every non-root node holds at least floor((2m-2)/3) keys and at most m-1
+            let (min, max) = key_range(&btree);
+            assert!(min >= (2 * order - 2) / 3, "order {}: min {} keys", order, min);
+            assert!(max < order);
+        }
+    }
+
+    #[test]
+    fn test_b_star_denser_than_standard() {
+        let mut standard = BTree::new(6);
+        let mut b_star = BTree::new_b_star(6);
+        for i in 1..=1000 {
+            standard.insert(i);
+            b_star.insert(i);
+        }
+
+        // Ascending inserts leave standard nodes about half full
+        assert!(key_range(&b_star).0 > key_range(&standard).0);
+    }
+
+    #[test]
+    fn test_b_star_delete() {
+        let mut btree = BTree::new_b_star(5);
+        for i in 1..=100 {
+            btree.insert(i);
+        }
+        for i in (1..=100).step_by(2) {
+            btree.delete(i);
+        }
+        for i in 1..=100 {
+            assert_eq!(btree.search(i), i % 2 == 0);
+        }
+        check_shape(&btree);
+    }
+
+    #[test]
+    fn test_split_three_order_3() {
+        // Root [4] with an overflowing leaf [1, 2, 3] and a full sibling [5, 6]
+        let counters = Counters::new();
+        let mut observer = None;
+        let mut hooks = Hooks::new(&counters, NodeSearch::Binary, &mut observer, None);
+        let mut node = Node { keys: vec![4], children: vec![], leaf: false, order: 3 };
+        node.children.push(Child::new(Node { keys: vec![1, 2, 3], children: vec![], leaf: true, order: 3 }));
+        node.children.push(Child::new(Node { keys: vec![5, 6], children: vec![], leaf: true, order: 3 }));
+
+        node.fix_overflow(0, 0, &mut hooks);
+
+        assert_eq!(node.keys, vec![2, 4]);
+        let children: Vec<Vec<i32>> = node.children.iter().map(|c| c.keys.clone()).collect();
+        assert_eq!(children, vec![vec![1], vec![3], vec![5, 6]]);
+    }
+}
@@ -0,0 +1,261 @@
+// Constructing a BTree from either definition of its size
+//
+// BTree::new takes the Knuth order m (max m children per node), while CLRS uses the minimum degree
+// t (every non-root node has between t-1 and 2t-1 keys). A tree of minimum degree t is the same as
+// a tree of order 2t, so with_min_degree and the builder just convert t to an order.
+
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use super::{BTree, InsertStrategy, NodeSearch};
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Constructor method for a BTree using the CLRS definition
+    ///
+    /// Takes in a usize parameter t representing the minimum degree of the tree (equivalent to knuth order 2t)
+    pub fn with_min_degree(t: usize) -> Self {
+        assert!(t >= 2, "BTree minimum degree must be at least 2");
+        BTree::new(2 * t)
+    }
+
+    /// Returns a builder for configuring a BTree
+    pub fn builder() -> BTreeBuilder<T> {
+        BTreeBuilder { order: None, strategy: None, node_search: NodeSearch::Binary, _marker: PhantomData }
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the CLRS minimum degree of the tree, if it has one (only even orders do)
+    pub fn min_degree(&self) -> Option<usize> {
+        self.order.is_multiple_of(2).then_some(self.order / 2)
+    }
+}
+
+/// Builder for a BTree, created with BTree::builder
+///
+/// The size is given with either order (Knuth) or min_degree (CLRS), whichever was set last.
+/// Without an insert strategy, the default for the order is used (see InsertStrategy::default_for)
+pub struct BTreeBuilder<T> {
+    order: Option<usize>,
+    strategy: Option<InsertStrategy>,
+    node_search: NodeSearch,
+    _marker: PhantomData<T>,
+}
+
+impl<T: PartialOrd + Debug + Clone> BTreeBuilder<T> {
+    /// Sets the knuth order m (max number of children per node)
+    pub fn order(mut self, m: usize) -> Self {
+        assert!(m >= 3, "BTree order must be at least 3");
+        self.order = Some(m);
+        self
+    }
+
+    /// Sets the CLRS minimum degree t (same as order 2t)
+    pub fn min_degree(mut self, t: usize) -> Self {
+        assert!(t >= 2, "BTree minimum degree must be at least 2");
+        self.order = Some(2 * t);
+        self
+    }
+
+    /// Sets the insert strategy
+    pub fn insert_strategy(mut self, strategy: InsertStrategy) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    /// Sets how keys are searched within each node (see NodeSearch)
+    pub fn node_search(mut self, node_search: NodeSearch) -> Self {
+        self.node_search = node_search;
+        self
+    }
+
+    /// Sets whether the tree inserts with the B* tree strategy (see BTree::new_b_star)
+    pub fn b_star(mut self, b_star: bool) -> Self {
+        if b_star {
+            self.strategy = Some(InsertStrategy::BStar);
+        } else if self.strategy == Some(InsertStrategy::BStar) {
+            self.strategy = None;
+        }
+        self
+    }
+
+    /// Builds the empty BTree
+    ///
+    /// Panics if no size was set, or the strategy is TopDown with an odd order
+    pub fn build(self) -> BTree<T> {
+        let order = self.order.expect("BTree order or minimum degree must be set");
+        let strategy = self.strategy.unwrap_or(InsertStrategy::default_for(order));
+        let mut btree = BTree::with_strategy(order, strategy);
+        btree.node_search = self.node_search;
+        btree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_tree::Node;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_with_min_degree() {
+        for t in 2..=6 {
+            let btree: BTree<i32> = BTree::with_min_degree(t);
+            assert_eq!(btree.order(), 2 * t);
+            assert_eq!(btree.min_degree(), Some(t));
+        }
+        assert_eq!(BTree::<i32>::new(5).min_degree(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "BTree minimum degree must be at least 2")]
+    fn test_invalid_min_degree() {
+        let _btree: BTree<i32> = BTree::with_min_degree(1);
+    }
+
+    #[test]
+    fn test_builder() {
+        let btree: BTree<i32> = BTree::builder().order(7).build();
+        assert_eq!(btree.order(), 7);
+        assert!(!btree.is_b_star());
+
+        let btree: BTree<i32> = BTree::builder().min_degree(3).b_star(true).build();
+        assert_eq!(btree.order(), 6);
+        assert!(btree.is_b_star());
+
+        // Whichever size was set last wins
+        let btree: BTree<i32> = BTree::builder().order(9).min_degree(2).build();
+        assert_eq!(btree.order(), 4);
+
+        let btree: BTree<i32> = BTree::builder().min_degree(2).insert_strategy(InsertStrategy::BottomUp).build();
+        assert_eq!(btree.insert_strategy(), InsertStrategy::BottomUp);
+
+        let btree: BTree<i32> = BTree::builder().order(5).b_star(true).b_star(false).build();
+        assert_eq!(btree.insert_strategy(), InsertStrategy::BottomUp);
+        assert_eq!(btree.node_search(), NodeSearch::Binary);
+
+        let btree: BTree<u32> = BTree::builder().order(64).node_search(NodeSearch::for_key_type::<u32>()).build();
+        assert!(matches!(btree.node_search(), NodeSearch::Threshold(_)));
+    }
+
+    #[test]
+    #[should_panic(expected = "BTree order or minimum degree must be set")]
+    fn test_builder_without_size() {
+        let _btree: BTree<i32> = BTree::builder().build();
+    }
+
+    #[test]
+    fn test_min_degree_thresholds() {
+        // CLRS: between t-1 and 2t-1 keys per non-root node
+        for t in 2..=6 {
+            let btree: BTree<i32> = BTree::with_min_degree(t);
+            let root = Node::<i32> { keys: vec![], children: vec![], leaf: true, order: btree.order };
+            assert_eq!(root.min_keys(), t - 1);
+            assert_eq!(root.max_keys(), 2 * t - 1);
+            assert_eq!(btree.insert_strategy(), InsertStrategy::TopDown);
+        }
+        // Knuth: between ceiling(m/2)-1 and m-1 keys per non-root node
+        for m in 3..=12 {
+            let node = Node::<i32> { keys: vec![], children: vec![], leaf: true, order: m };
+            assert_eq!(node.min_keys(), m.div_ceil(2) - 1);
+            assert_eq!(node.max_keys(), m - 1);
+        }
+    }
+
+    #[test]
+    fn test_order_4_split_and_merge() {
+        // t = 2 (2-3-4 tree): a full node of 3 keys splits into 1 + 1 around the median
+        let mut btree = BTree::with_min_degree(2);
+        for i in 1..=4 {
+            btree.insert(i);
+        }
+        let root = btree.root.as_ref().unwrap();
+        assert_eq!(root.keys, vec![2]);
+        assert_eq!(root.children[0].keys, vec![1]);
+        assert_eq!(root.children[1].keys, vec![3, 4]);
+
+        // Two nodes of 1 key merge with their separator into a full node of 3 keys
+        btree.delete(4);
+        btree.delete(1);
+        let root = btree.root.as_ref().unwrap();
+        assert!(root.leaf);
+        assert_eq!(root.keys, vec![2, 3]);
+        btree.check_invariants();
+    }
+
+    #[test]
+    fn test_even_orders_full_node_split_evenly() {
+        // Ascending inserts fill the rightmost leaf, so every left leaf is the half of a full node
+        for t in 2..=5 {
+            let mut btree = BTree::with_min_degree(t);
+            for i in 0..(2 * t as i32) {
+                btree.insert(i);
+            }
+            let root = btree.root.as_ref().unwrap();
+            assert_eq!(root.keys.len(), 1);
+            assert_eq!(root.children[0].keys.len(), t - 1);
+            assert_eq!(root.children[1].keys.len(), t);
+        }
+    }
+
+    #[test]
+    fn test_odd_orders_split_on_overflow() {
+        // Order 5 (K = 4): four keys cannot be split evenly, so the split waits for the fifth
+        let mut btree = BTree::new(5);
+        for i in 1..=4 {
+            btree.insert(i);
+        }
+        assert!(btree.root.as_ref().unwrap().leaf);
+        btree.insert(5);
+        let root = btree.root.as_ref().unwrap();
+        assert_eq!(root.keys, vec![3]);
+        assert_eq!(root.children[0].keys, vec![1, 2]);
+        assert_eq!(root.children[1].keys, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_odd_orders_merge_does_not_overflow() {
+        // Order 5: root [3, 6] has leaves of 2 keys either side of 3, so deleting 3 merges them into
+        // 5 keys (one more than K) before removing it. Merged internal nodes that are still overfull
+        // after the delete are split again (see test_invariants_across_orders)
+        let mut btree = BTree::new(5);
+        for i in [1, 2, 3, 4, 5, 6, 7, 8, 9] {
+            btree.insert(i);
+        }
+        btree.print_structure();
+        btree.check_invariants();
+        for i in [3, 9, 1] {
+            btree.delete(i);
+            btree.print_structure();
+            btree.check_invariants();
+        }
+    }
+
+    #[test]
+    fn test_invariants_across_orders() {
+        for order in 3..=10 {
+            let mut rng = StdRng::seed_from_u64(order as u64);
+            let mut keys: Vec<i32> = (0..500).collect();
+            keys.shuffle(&mut rng);
+
+            let mut btree = BTree::new(order);
+            for &k in &keys {
+                btree.insert(k);
+                btree.check_invariants();
+            }
+            keys.shuffle(&mut rng);
+            for &k in &keys[..450] {
+                btree.delete(k);
+                btree.check_invariants();
+            }
+            for &k in &keys[450..] {
+                assert!(btree.search(k));
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+// Building a BTree from sorted keys in O(n), without inserting them one by one
+//
+// The tree is built a level at a time, from the leaves up. The n keys of a level are packed into as
+// few nodes as hold them, L = ceiling((n+1)/m) (L nodes of K keys take L*K keys, plus the L-1
+// separators between them that move up to the next level). The keys left after taking the separators
+// are shared as evenly as possible, so every node gets floor or ceiling of (n-L+1)/L keys. Since L is
+// minimal, (L-1)*m < n+1, so each node gets at least floor((L-1)*K/L) >= floor(K/2) keys once there
+// are two or more nodes, and never more than K. The separators then become the keys of the level
+// above, where a node with k keys takes the next k+1 nodes of the level below as its children. This
+// stops at a level with a single node, which is the root.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+use super::cow::Child;
+use super::{BTree, Node};
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Replaces the contents of the tree with keys, which must be sorted (duplicates are kept)
+    ///
+    /// The nodes are built directly (see bulk.rs), so the observer is not told about them and
+    /// no metrics are counted
+    #[cfg_attr(not(any(feature = "rayon", feature = "serde")), allow(dead_code))]
+    pub(super) fn bulk_load(&mut self, keys: Vec<T>) {
+        debug_assert!(keys.windows(2).all(|w| w[0].partial_cmp(&w[1]) != Some(Ordering::Greater)), "Bulk loaded keys must be sorted");
+        self.root = build(self.order, keys);
+    }
+}
+
+/// Returns the root of a tree of the given order holding keys (None if there are none)
+fn build<T: PartialOrd + Debug + Clone>(order: usize, keys: Vec<T>) -> Option<Child<T>> {
+    if keys.is_empty() {
+        return None;
+    }
+
+    let mut items = keys;
+    let mut below: Option<Vec<Child<T>>> = None;
+    loop {
+        let n = items.len();
+        let nodes = (n + 1).div_ceil(order);
+        let total = n - (nodes - 1);
+        let (size, extra) = (total / nodes, total % nodes);
+
+        let mut items_iter = items.into_iter();
+        let mut below_iter = below.map(Vec::into_iter);
+        let mut level = Vec::with_capacity(nodes);
+        let mut separators = Vec::with_capacity(nodes - 1);
+        for i in 0..nodes {
+            // The first (total % nodes) nodes get one extra key
+            let len = size + usize::from(i < extra);
+            let keys: Vec<T> = items_iter.by_ref().take(len).collect();
+            let children: Vec<Child<T>> = match &mut below_iter {
+                Some(below_iter) => below_iter.by_ref().take(len + 1).collect(),
+                None => Vec::new(),
+            };
+            level.push(Child::new(Node { keys, children, leaf: below_iter.is_none(), order }));
+            if i + 1 < nodes {
+                separators.push(items_iter.next().expect("A separator follows every node but the last"));
+            }
+        }
+
+        if nodes == 1 {
+            return level.pop();
+        }
+        items = separators;
+        below = Some(level);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects all keys of a tree in order
+    fn keys(btree: &BTree<i32>) -> Vec<i32> {
+        fn walk(node: &Node<i32>, keys: &mut Vec<i32>) {
+            for i in 0..node.keys.len() {
+                if !node.leaf {
+                    walk(&node.children[i], keys);
+                }
+                keys.push(node.keys[i]);
+            }
+            if !node.leaf {
+                walk(&node.children[node.keys.len()], keys);
+            }
+        }
+        let mut keys = vec![];
+        if let Some(root) = &btree.root {
+            walk(root, &mut keys);
+        }
+        keys
+    }
+
+    #[test]
+    fn test_bulk_load_sizes() {
+        for order in 3..=9 {
+            for n in (0..200).chain([1000, 4321]) {
+                let mut btree = BTree::new(order);
+                let values: Vec<i32> = (0..n).collect();
+                btree.bulk_load(values.clone());
+                btree.check_invariants();
+                assert_eq!(keys(&btree), values, "order {}, {} keys", order, n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_bulk_load_packs_nodes() {
+        // 7 full leaves of 3 keys, whose 6 separators go into 2 internal nodes (of 3 and 2 keys) and the root
+        let mut btree = BTree::new(4);
+        btree.bulk_load((0..27).collect());
+        let root = btree.root.as_ref().unwrap();
+        assert_eq!(root.keys.len(), 1);
+        assert!(root.children.iter().all(|child| child.keys.len() == 2 || child.keys.len() == 3));
+        assert_eq!(root.children.iter().map(|child| child.children.len()).sum::<usize>(), 7);
+    }
+
+    #[test]
+    fn test_bulk_loaded_tree_stays_valid() {
+        for order in [3, 4, 5, 8] {
+            let mut btree = BTree::new(order);
+            btree.bulk_load((0..500).map(|v| v * 2).collect());
+            for val in 0..300 {
+                btree.insert(val * 2 + 1);
+            }
+            for val in 0..400 {
+                btree.delete(val * 2);
+            }
+            btree.check_invariants();
+            let mut expected: Vec<i32> = (0..300).map(|v| v * 2 + 1).chain((400..500).map(|v| v * 2)).collect();
+            expected.sort();
+            assert_eq!(keys(&btree), expected);
+        }
+    }
+
+    #[test]
+    fn test_bulk_load_replaces_contents() {
+        let mut btree = BTree::new(5);
+        for val in 0..50 {
+            btree.insert(val);
+        }
+        btree.bulk_load(vec![100, 200]);
+        assert_eq!(keys(&btree), vec![100, 200]);
+        btree.bulk_load(vec![]);
+        assert!(btree.root.is_none());
+    }
+}
@@ -0,0 +1,537 @@
+// Thread-safe BTree with lock coupling (latch crabbing)
+//
+// Every node has its own RwLock (its latch), and so does the pointer to the root. Operations only go
+// down the tree, taking the latch of a child before releasing the latch of its parent (hand-over-hand),
+// so a thread never sees a node in the middle of a change, and latches are always taken in the same
+// (top-down) order.
+//
+// Readers (search and range) take read latches and only ever hold two at a time.
+//
+// Writers (insert and remove) take write latches and hold on to the latches of the path below the
+// last "safe" node: one that cannot split (insert: fewer than K keys) or merge (remove: more than
+// floor(K/2) keys) as a result of the change below it. Once a child is known to be safe, the latches
+// of all its ancestors (and of the root pointer) are released, since nothing above it will change.
+// The change is then made bottom-up (see InsertStrategy::BottomUp) on the latched path: a node that
+// overflows is split into its parent, and a node left with too few keys borrows a key from a sibling
+// (rotate) or is merged with one, taking the sibling's latch while holding the parent's. Only the
+// root can grow or shrink the tree, so the root pointer is only held while the root is unsafe.
+//
+// Keys live in internal nodes too, so a range scan cannot stay in the leaves. It instead collects one
+// leaf at a time: it descends to the leaf holding the next keys (remembering the closest ancestor key
+// after that leaf), takes its keys, then the ancestor key, and descends again from the root for the
+// keys after that. So a scan only holds latches for one descent at a time, and sees every key that is
+// in the tree for the whole scan (keys inserted or removed during the scan may or may not be seen).
+
+use std::fmt::Debug;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use parking_lot::{ArcRwLockWriteGuard, RawRwLock, RwLock};
+
+use super::NodeSearch;
+
+type NodeRef<T> = Arc<RwLock<Node<T>>>;
+type WriteGuard<T> = ArcRwLockWriteGuard<RawRwLock, Node<T>>;
+
+/// A B-tree that can be searched and changed from many threads at once, with a latch per node
+pub struct ConcurrentBTree<T: PartialOrd + Debug + Clone> {
+    root: RwLock<NodeRef<T>>,
+    order: usize,
+    len: AtomicUsize,
+}
+
+struct Node<T: PartialOrd + Debug + Clone> {
+    keys: Vec<T>,
+    children: Vec<NodeRef<T>>,
+    leaf: bool,
+}
+
+impl<T: PartialOrd + Debug + Clone> ConcurrentBTree<T> {
+    /// Constructor method for ConcurrentBTree
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    pub fn new(m: usize) -> Self {
+        assert!(m >= 3, "BTree order must be at least 3");
+        ConcurrentBTree { root: RwLock::new(Node::new_ref(vec![], vec![], true)), order: m, len: AtomicUsize::new(0) }
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the number of keys in the tree
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the tree holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Search method for ConcurrentBTree
+    ///
+    /// Returns true if value is present, false otherwise
+    pub fn search(&self, value: T) -> bool {
+        let pointer = self.root.read();
+        let mut node = pointer.read_arc();
+        drop(pointer);
+
+        loop {
+            let (found, idx) = NodeSearch::Binary.search(&node.keys, &value);
+            if found {
+                return true;
+            }
+            if node.leaf {
+                return false;
+            }
+            // Latch the child before releasing its parent
+            let child = node.children[idx].read_arc();
+            node = child;
+        }
+    }
+
+    /// Returns all keys within range, in order
+    pub fn range<R: RangeBounds<T>>(&self, range: R) -> Vec<T> {
+        let mut keys = vec![];
+        let mut lower = range.start_bound().cloned();
+
+        loop {
+            // Descend to the leaf holding the keys after lower, remembering the closest ancestor key after it
+            let pointer = self.root.read();
+            let mut node = pointer.read_arc();
+            drop(pointer);
+
+            let mut next = None;
+            loop {
+                let idx = node.keys.partition_point(|key| before(key, &lower));
+                if node.leaf {
+                    for key in &node.keys[idx..] {
+                        // Every key is after lower, so a key outside the range is past its end
+                        if !range.contains(key) {
+                            return keys;
+                        }
+                        keys.push(key.clone());
+                    }
+                    break;
+                }
+                if idx < node.keys.len() {
+                    next = Some(node.keys[idx].clone());
+                }
+                let child = node.children[idx].read_arc();
+                node = child;
+            }
+            drop(node);
+
+            match next {
+                Some(key) if range.contains(&key) => {
+                    keys.push(key.clone());
+                    lower = Bound::Excluded(key);
+                },
+                _ => return keys,
+            }
+        }
+    }
+
+    /// Inserts a value into the b-tree
+    ///
+    /// Returns false (leaving the tree unchanged) if value is already present
+    pub fn insert(&self, value: T) -> bool {
+        let max_keys = self.order - 1;
+
+        // Latch the root pointer, in case the root splits
+        let mut pointer = Some(self.root.write());
+        let mut path = vec![pointer.as_ref().expect("Root pointer is latched").write_arc()];
+        let mut idxs = vec![];
+        if path[0].keys.len() < max_keys {
+            pointer = None;
+        }
+
+        // Crab down to the leaf, releasing the latches above every safe node
+        loop {
+            let node = path.last().expect("Path is never empty");
+            let (found, idx) = NodeSearch::Binary.search(&node.keys, &value);
+            if found {
+                return false;
+            }
+            if node.leaf {
+                idxs.push(idx);
+                break;
+            }
+            let child = node.children[idx].write_arc();
+            idxs.push(idx);
+            if child.keys.len() < max_keys {
+                path.clear();
+                idxs.clear();
+                pointer = None;
+            }
+            path.push(child);
+        }
+
+        let mut level = path.len() - 1;
+        path[level].keys.insert(idxs[level], value);
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        // Split overflowing nodes on the way back up (every overflowing node still has its parent latched)
+        while path[level].keys.len() > max_keys {
+            let (median, right) = path[level].split();
+            if level == 0 {
+                // Only the root can overflow with nothing above it, and then the root pointer is still latched
+                let pointer = pointer.as_mut().expect("Root pointer is latched when the root splits");
+                let old_root = Arc::clone(ArcRwLockWriteGuard::rwlock(&path[0]));
+                **pointer = Node::new_ref(vec![median], vec![old_root, right], false);
+                break;
+            }
+            let idx = idxs[level - 1];
+            let parent = &mut path[level - 1];
+            parent.keys.insert(idx, median);
+            parent.children.insert(idx + 1, right);
+            level -= 1;
+        }
+        true
+    }
+
+    /// Removes a value from the b-tree
+    ///
+    /// Returns false (leaving the tree unchanged) if value is not present
+    pub fn remove(&self, value: T) -> bool {
+        let min_keys = (self.order - 1) / 2;
+
+        // Latch the root pointer, in case the root shrinks
+        let mut pointer = Some(self.root.write());
+        let mut path = vec![pointer.as_ref().expect("Root pointer is latched").write_arc()];
+        let mut idxs = vec![];
+        if path[0].leaf || path[0].keys.len() > 1 {
+            pointer = None;
+        }
+
+        // Crab down to the leaf holding value, or its predecessor if value is in an internal node (in
+        // which case that node stays latched, to take the predecessor's place)
+        let mut found_at = None;
+        loop {
+            let node = path.last().expect("Path is never empty");
+            let idx = if found_at.is_some() {
+                // Follow the rightmost path of the left subtree to the predecessor
+                node.keys.len()
+            } else {
+                let (found, idx) = NodeSearch::Binary.search(&node.keys, &value);
+                if found {
+                    if node.leaf {
+                        idxs.push(idx);
+                        break;
+                    }
+                    found_at = Some((path.len() - 1, idx));
+                } else if node.leaf {
+                    return false;
+                }
+                idx
+            };
+            if node.leaf {
+                idxs.push(idx);
+                break;
+            }
+
+            let child = node.children[idx].write_arc();
+            idxs.push(idx);
+            if child.keys.len() > min_keys {
+                // Release everything above the child, other than the node value was found in
+                let release = found_at.map_or(path.len(), |(level, _)| level);
+                path.drain(..release);
+                idxs.drain(..release);
+                if let Some((level, _)) = &mut found_at {
+                    *level -= release;
+                }
+                pointer = None;
+            }
+            path.push(child);
+        }
+
+        let mut level = path.len() - 1;
+        match found_at {
+            None => {
+                path[level].keys.remove(idxs[level]);
+            },
+            Some((found_level, idx)) => {
+                let pred = path[level].keys.pop().expect("Leaf node missing keys");
+                path[found_level].keys[idx] = pred;
+            },
+        }
+        self.len.fetch_sub(1, Ordering::Relaxed);
+
+        // Fix nodes left with too few keys on the way back up (each still has its parent latched)
+        while level > 0 && path[level].keys.len() < min_keys {
+            let idx = idxs[level - 1];
+            let (parents, children) = path.split_at_mut(level);
+            parents[level - 1].fix_underflow(idx, &mut children[0], min_keys);
+            level -= 1;
+        }
+
+        // Shrink tree if the root is empty but has children (the root pointer is still latched)
+        if level == 0 && path[0].keys.is_empty() && !path[0].leaf {
+            let pointer = pointer.as_mut().expect("Root pointer is latched when the root shrinks");
+            **pointer = Arc::clone(&path[0].children[0]);
+        }
+        true
+    }
+
+    /// Helper (test) function that checks every non-root node holds between floor(K/2) and K keys,
+    /// keys are in order and every leaf is at the same depth
+    #[cfg(test)]
+    fn check_invariants(&self) {
+        fn walk<T: PartialOrd + Debug + Clone>(node: &Node<T>, order: usize, depth: usize, leaf_depth: &mut Option<usize>, keys: &mut Vec<T>) {
+            assert!(node.keys.len() < order, "Node overfull: {:?}", node.keys);
+            if depth > 0 {
+                assert!(node.keys.len() >= (order - 1) / 2, "Node underfull: {:?}", node.keys);
+            }
+            if node.leaf {
+                assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+                keys.extend(node.keys.iter().cloned());
+                return;
+            }
+            assert_eq!(node.children.len(), node.keys.len() + 1);
+            for (i, child) in node.children.iter().enumerate() {
+                walk(&child.read(), order, depth + 1, leaf_depth, keys);
+                if i < node.keys.len() {
+                    keys.push(node.keys[i].clone());
+                }
+            }
+        }
+        let root = Arc::clone(&self.root.read());
+        let mut keys = vec![];
+        walk(&root.read(), self.order, 0, &mut None, &mut keys);
+        assert_eq!(keys.len(), self.len());
+        assert!(keys.windows(2).all(|w| w[0] < w[1]), "Keys out of order: {:?}", keys);
+    }
+}
+
+/// Returns true if key comes before the lower bound
+fn before<T: PartialOrd>(key: &T, lower: &Bound<T>) -> bool {
+    match lower {
+        Bound::Included(bound) => key < bound,
+        Bound::Excluded(bound) => key <= bound,
+        Bound::Unbounded => false,
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    fn new_ref(keys: Vec<T>, children: Vec<NodeRef<T>>, leaf: bool) -> NodeRef<T> {
+        Arc::new(RwLock::new(Node { keys, children, leaf }))
+    }
+
+    /// Splits this overflowing node around its median, keeping the left half
+    ///
+    /// Returns the median and the new right half
+    fn split(&mut self) -> (T, NodeRef<T>) {
+        let mid = self.keys.len() / 2;
+        let right_keys = self.keys.split_off(mid + 1);
+        let right_children = if self.leaf { vec![] } else { self.children.split_off(mid + 1) };
+        let median = self.keys.pop().expect("Middle key missing in split");
+        (median, Node::new_ref(right_keys, right_children, self.leaf))
+    }
+
+    /// Fixes the (latched) child at child_idx, which has too few keys, by rotating a key into it from
+    /// a sibling that can spare one, or else merging it with a sibling
+    fn fix_underflow(&mut self, child_idx: usize, child: &mut WriteGuard<T>, min_keys: usize) {
+        // Latch the siblings while holding this node (their parent), so nothing else can be changing them
+        let mut left = (child_idx > 0).then(|| self.children[child_idx - 1].write_arc());
+        if let Some(left) = left.as_mut().filter(|left| left.keys.len() > min_keys) {
+            // Rotate right: the left sibling's last key goes up, and the separator comes down
+            let last_key = left.keys.pop().expect("Left child has no keys");
+            let middle_key = std::mem::replace(&mut self.keys[child_idx - 1], last_key);
+            child.keys.insert(0, middle_key);
+            if !left.leaf {
+                child.children.insert(0, left.children.pop().expect("Left child has no children"));
+            }
+            return;
+        }
+
+        let mut right = (child_idx + 1 < self.children.len()).then(|| self.children[child_idx + 1].write_arc());
+        if let Some(right) = right.as_mut().filter(|right| right.keys.len() > min_keys) {
+            // Rotate left: the right sibling's first key goes up, and the separator comes down
+            let first_key = right.keys.remove(0);
+            let middle_key = std::mem::replace(&mut self.keys[child_idx], first_key);
+            child.keys.push(middle_key);
+            if !right.leaf {
+                child.children.push(right.children.remove(0));
+            }
+            return;
+        }
+
+        // Merge with a sibling, along with the separator between them
+        match (left, right) {
+            (Some(mut left), _) => {
+                let middle_key = self.keys.remove(child_idx - 1);
+                left.keys.push(middle_key);
+                left.keys.append(&mut child.keys);
+                left.children.append(&mut child.children);
+                self.children.remove(child_idx);
+            },
+            (None, Some(mut right)) => {
+                let middle_key = self.keys.remove(child_idx);
+                child.keys.push(middle_key);
+                child.keys.append(&mut right.keys);
+                child.children.append(&mut right.children);
+                self.children.remove(child_idx + 1);
+            },
+            (None, None) => panic!("Non-root node has no siblings"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+    use std::thread;
+
+    #[test]
+    fn test_single_threaded_against_btreeset() {
+        for order in 3..=10 {
+            let mut rng = StdRng::seed_from_u64(order as u64);
+            let btree = ConcurrentBTree::new(order);
+            let mut set = BTreeSet::new();
+            for _ in 0..3000 {
+                let val: i32 = rng.random_range(0..500);
+                if rng.random_bool(0.6) {
+                    assert_eq!(btree.insert(val), set.insert(val));
+                } else {
+                    assert_eq!(btree.remove(val), set.remove(&val));
+                }
+            }
+            btree.check_invariants();
+            assert_eq!(btree.len(), set.len());
+            assert_eq!(btree.range(..), set.iter().copied().collect::<Vec<_>>());
+            for val in 0..500 {
+                assert_eq!(btree.search(val), set.contains(&val));
+            }
+        }
+    }
+
+    #[test]
+    fn test_range() {
+        let btree = ConcurrentBTree::new(4);
+        let mut values: Vec<i32> = (0..200).map(|v| v * 2).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(1));
+        for &val in &values {
+            btree.insert(val);
+        }
+
+        assert_eq!(btree.range(10..20), vec![10, 12, 14, 16, 18]);
+        assert_eq!(btree.range(11..=20), vec![12, 14, 16, 18, 20]);
+        assert_eq!(btree.range((Bound::Excluded(10), Bound::Excluded(16))), vec![12, 14]);
+        assert_eq!(btree.range(390..), vec![390, 392, 394, 396, 398]);
+        assert_eq!(btree.range(..4), vec![0, 2]);
+        assert_eq!(btree.range(..).len(), 200);
+        assert!(btree.range(1000..).is_empty());
+        assert!(btree.range(5..5).is_empty());
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let btree: ConcurrentBTree<i32> = ConcurrentBTree::new(3);
+        assert!(btree.is_empty());
+        assert!(!btree.search(1));
+        assert!(!btree.remove(1));
+        assert!(btree.range(..).is_empty());
+        assert!(btree.insert(1));
+        assert!(!btree.insert(1));
+        assert!(btree.remove(1));
+        assert!(btree.is_empty());
+        btree.check_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "BTree order must be at least 3")]
+    fn test_invalid_order() {
+        let _btree: ConcurrentBTree<i32> = ConcurrentBTree::new(2);
+    }
+
+    #[test]
+    fn test_stress_disjoint_keys() {
+        // Each writer owns the keys equal to its id mod THREADS, so the final contents are known
+        const THREADS: usize = 8;
+        const OPS: usize = 4000;
+        for order in [3, 4, 7, 16] {
+            let btree = ConcurrentBTree::new(order);
+            let expected: Vec<BTreeSet<usize>> = thread::scope(|s| {
+                // Readers scan while the writers run, checking each scan is sorted and within its range
+                for t in 0..2 {
+                    let btree = &btree;
+                    s.spawn(move || {
+                        let mut rng = StdRng::seed_from_u64(100 + t);
+                        for _ in 0..200 {
+                            let start = rng.random_range(0..2000);
+                            let keys = btree.range(start..start + 300);
+                            assert!(keys.windows(2).all(|w| w[0] < w[1]), "Scan out of order: {:?}", keys);
+                            assert!(keys.iter().all(|k| (start..start + 300).contains(k)));
+                            btree.search(start);
+                        }
+                    });
+                }
+
+                let writers: Vec<_> = (0..THREADS)
+                    .map(|t| {
+                        let btree = &btree;
+                        s.spawn(move || {
+                            let mut rng = StdRng::seed_from_u64(t as u64);
+                            let mut set = BTreeSet::new();
+                            for _ in 0..OPS {
+                                let val = rng.random_range(0..2000 / THREADS) * THREADS + t;
+                                if rng.random_bool(0.6) {
+                                    assert_eq!(btree.insert(val), set.insert(val));
+                                } else {
+                                    assert_eq!(btree.remove(val), set.remove(&val));
+                                }
+                                assert_eq!(btree.search(val), set.contains(&val));
+                            }
+                            set
+                        })
+                    })
+                    .collect();
+                writers.into_iter().map(|w| w.join().unwrap()).collect()
+            });
+
+            btree.check_invariants();
+            let mut all: Vec<usize> = expected.into_iter().flatten().collect();
+            all.sort();
+            assert_eq!(btree.range(..), all);
+        }
+    }
+
+    #[test]
+    fn test_stress_shared_keys() {
+        // Every thread works on the same keys, so count the successful inserts and removes instead
+        const THREADS: usize = 8;
+        let btree = ConcurrentBTree::new(5);
+        let net: i64 = thread::scope(|s| {
+            let handles: Vec<_> = (0..THREADS)
+                .map(|t| {
+                    let btree = &btree;
+                    s.spawn(move || {
+                        let mut rng = StdRng::seed_from_u64(t as u64);
+                        let mut net = 0i64;
+                        for _ in 0..4000 {
+                            let val: u32 = rng.random_range(0..300);
+                            if rng.random_bool(0.5) {
+                                net += i64::from(btree.insert(val));
+                            } else {
+                                net -= i64::from(btree.remove(val));
+                            }
+                        }
+                        net
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+
+        btree.check_invariants();
+        assert_eq!(btree.len() as i64, net);
+        assert_eq!(btree.range(..).len() as i64, net);
+    }
+}
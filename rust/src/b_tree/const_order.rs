@@ -0,0 +1,504 @@
+// BTree with the order fixed at compile time
+//
+// ConstBTree<T, M> stores the keys and children of each node inline in ArrayVecs sized from M, so a
+// node is a single allocation (its Box) instead of three, and nodes no longer carry their order (or a
+// leaf flag, since a node is a leaf exactly when it has no children).
+//
+// Array lengths can only be a plain const parameter on stable Rust (not M - 1 or M + 1), so keys are
+// stored in an ArrayVec<T, M> and children in an ArrayVec<_, M>. There is no room for a temporary m-th
+// key and (m+1)-th child, so both operations work bottom-up without ever overfilling a node:
+//
+// Insert: a full node (K = m-1 keys) that receives another key is split around the median of the
+// m keys it would hold, with the new key placed directly into the correct half. m keys always split
+// into halves of at least floor(K/2), so this works for every order.
+//
+// Delete: the value is removed from its leaf (an internal key is replaced by its predecessor first),
+// and a child left with fewer than floor(K/2) keys is fixed by its parent on the way back up, by
+// rotating a key from a sibling or merging with a sibling. A merge gives at most
+// (floor(K/2) - 1) + 1 + floor(K/2) <= K keys, so merges never overflow either.
+
+use std::fmt::Debug;
+
+use arrayvec::ArrayVec;
+
+/// A B-tree whose knuth order M is a compile-time constant
+///
+/// Behaves like BTree::new(M), with nodes stored in fixed-capacity arrays
+pub struct ConstBTree<T: PartialOrd + Debug + Clone, const M: usize> {
+    root: Option<Box<Node<T, M>>>,
+}
+
+struct Node<T: PartialOrd + Debug + Clone, const M: usize> {
+    // At most M-1 keys and M children (the spare key slot is never used)
+    keys: ArrayVec<T, M>,
+    // Boxed, since a node cannot hold nodes of its own type inline
+    children: ArrayVec<Box<Node<T, M>>, M>,
+}
+
+impl<T: PartialOrd + Debug + Clone, const M: usize> ConstBTree<T, M> {
+    /// Constructor method for ConstBTree (the order is the const parameter M)
+    pub fn new() -> Self {
+        assert!(M >= 3, "BTree order must be at least 3");
+        ConstBTree { root: None }
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        M
+    }
+
+    /// Traverse method for ConstBTree
+    ///
+    /// Traverses through all keys for all nodes in order and prints them out
+    pub fn traverse(&self) {
+        match &self.root {
+            Some(r) => r.traverse(),
+            None => println!("=== EMPTY BTREE ==="),
+        }
+    }
+
+    /// Search method for ConstBTree
+    ///
+    /// Returns true if value is present, false otherwise
+    pub fn search(&self, value: T) -> bool {
+        let mut node = match &self.root {
+            Some(r) => r.as_ref(),
+            None => return false,
+        };
+
+        loop {
+            let (found, idx) = node.search(&value);
+            if found {
+                return true;
+            }
+            if node.is_leaf() {
+                return false;
+            }
+            node = &node.children[idx];
+        }
+    }
+
+    /// Inserts a value into the b-tree
+    pub fn insert(&mut self, value: T) {
+        match &mut self.root {
+            Some(r) => {
+                // If the root split, make a new root above the two halves
+                if let Some((median, right)) = r.insert(value) {
+                    let old_root = self.root.take().expect("Root must exist in Some branch");
+                    let mut new_root = Node::new();
+                    new_root.keys.push(median);
+                    new_root.children.push(old_root);
+                    new_root.children.push(right);
+                    self.root = Some(Box::new(new_root));
+                }
+            },
+            None => {
+                let mut new_node = Node::new();
+                new_node.keys.push(value);
+                self.root = Some(Box::new(new_node));
+            },
+        }
+    }
+
+    /// Deletes a value from the b-tree
+    pub fn delete(&mut self, value: T) {
+        let node = match &mut self.root {
+            Some(r) => r.as_mut(),
+            None => panic!("Cannot delete from empty BTree"),
+        };
+
+        node.delete(&value);
+
+        // Shrink tree if root is empty but has children
+        if let Some(root) = &mut self.root
+            && root.keys.is_empty() && !root.children.is_empty() {
+            let new_root = root.children.pop().expect("Root must have a child");
+            self.root = Some(new_root);
+        }
+    }
+
+    /// Helper (test) function for printing b-tree structure
+    #[cfg(test)]
+    pub fn print_structure(&self) {
+        match &self.root {
+            Some(r) => {
+                println!("=== ConstBTree Structure (Order {}) ===", M);
+                println!();
+                r.print_structure(0);
+            },
+            None => println!("Empty tree"),
+        }
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone, const M: usize> Default for ConstBTree<T, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone, const M: usize> Node<T, M> {
+    fn new() -> Self {
+        Node { keys: ArrayVec::new(), children: ArrayVec::new() }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Returns the min number of keys floor(K/2) a non-root node holds
+    fn min_keys() -> usize {
+        (M - 1) / 2
+    }
+
+    /// Traverses and prints out all of the keys recursively
+    fn traverse(&self) {
+        for i in 0..self.keys.len() {
+            if !self.is_leaf() {
+                self.children[i].traverse();
+            }
+            print!("{:?} ", self.keys[i]);
+        }
+        if !self.is_leaf() {
+            self.children[self.keys.len()].traverse();
+        }
+    }
+
+    /// Binary search for value in keys
+    ///
+    /// Returns true and the idx of value if found, otherwise false and the idx of the smallest key greater than value
+    fn search(&self, value: &T) -> (bool, usize) {
+        let mut left = 0;
+        let mut right = self.keys.len();
+
+        while left < right {
+            let mid = left + (right - left) / 2;
+            if self.keys[mid] == *value {
+                return (true, mid);
+            }
+            if self.keys[mid] < *value {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+        (false, left)
+    }
+
+    /// Inserts a value into the subtree (called recursively)
+    ///
+    /// Returns the median and the new right node if this node had to split
+    fn insert(&mut self, value: T) -> Option<(T, Box<Node<T, M>>)> {
+        let (_, idx) = self.search(&value);
+
+        if self.is_leaf() {
+            return self.insert_key(idx, value, None);
+        }
+
+        // If the child split, its median and new right half go into this node
+        let (median, right) = self.children[idx].insert(value)?;
+        self.insert_key(idx, median, Some(right))
+    }
+
+    /// Inserts key at idx (and child to its right, for internal nodes), splitting the node if it is full
+    ///
+    /// Returns the median and the new right node if this node had to split
+    fn insert_key(&mut self, idx: usize, key: T, child: Option<Box<Node<T, M>>>) -> Option<(T, Box<Node<T, M>>)> {
+        if self.keys.len() < M - 1 {
+            self.keys.insert(idx, key);
+            if let Some(child) = child {
+                self.children.insert(idx + 1, child);
+            }
+            return None;
+        }
+
+        // The node would hold M keys, so split around the median of those M keys (the same split
+        // BTree makes of an overflowing node): the first mid keys stay here, the rest move right
+        let mid = M / 2;
+        let mut right = Node::new();
+        let median = if idx < mid {
+            // The key belongs in this half, and the median is the last key that stays here now
+            right.keys.extend(self.keys.drain(mid..));
+            let median = self.keys.pop().expect("Median missing in insert_key");
+            self.keys.insert(idx, key);
+            if let Some(child) = child {
+                right.children.extend(self.children.drain(mid..));
+                self.children.insert(idx + 1, child);
+            }
+            median
+        } else if idx == mid {
+            // The key itself is the median, and its child becomes the first child of the right half
+            right.keys.extend(self.keys.drain(mid..));
+            if let Some(child) = child {
+                right.children.push(child);
+                right.children.extend(self.children.drain(mid + 1..));
+            }
+            key
+        } else {
+            // The key belongs in the right half
+            right.keys.extend(self.keys.drain(mid + 1..));
+            let median = self.keys.pop().expect("Median missing in insert_key");
+            right.keys.insert(idx - mid - 1, key);
+            if let Some(child) = child {
+                right.children.extend(self.children.drain(mid + 1..));
+                right.children.insert(idx - mid, child);
+            }
+            median
+        };
+        Some((median, Box::new(right)))
+    }
+
+    /// Deletes a value from the subtree (called recursively), leaving this node with one key
+    /// fewer than floor(K/2) at worst, which the caller then fixes
+    fn delete(&mut self, value: &T) {
+        let (found, idx) = self.search(value);
+
+        if self.is_leaf() {
+            if !found {
+                panic!("Non-existant value cannot be deleted from BTree")
+            }
+            self.keys.remove(idx);
+            return;
+        }
+
+        if found {
+            // Replace the value with its predecessor, removed from the left subtree
+            self.keys[idx] = self.children[idx].remove_max();
+        } else {
+            self.children[idx].delete(value);
+        }
+        self.fix_underflow(idx);
+    }
+
+    /// Removes and returns the largest key of the subtree
+    fn remove_max(&mut self) -> T {
+        if self.is_leaf() {
+            return self.keys.pop().expect("Leaf node missing keys");
+        }
+        let last = self.children.len() - 1;
+        let max = self.children[last].remove_max();
+        self.fix_underflow(last);
+        max
+    }
+
+    /// Refills the child at child_idx if it has fewer than floor(K/2) keys, by rotating a key from
+    /// a sibling with keys to spare, or else merging it with a sibling
+    fn fix_underflow(&mut self, child_idx: usize) {
+        let min = Self::min_keys();
+        if self.children[child_idx].keys.len() >= min {
+            return;
+        }
+
+        if child_idx > 0 && self.children[child_idx - 1].keys.len() > min {
+            self.rotate_right(child_idx);
+        } else if child_idx + 1 < self.children.len() && self.children[child_idx + 1].keys.len() > min {
+            self.rotate_left(child_idx);
+        } else if child_idx > 0 {
+            self.merge(child_idx - 1);
+        } else {
+            self.merge(child_idx);
+        }
+    }
+
+    /// Helper that moves last key from left child to parent and parent key to right child's first key
+    ///
+    /// Takes a child_idx that represents the right child's index
+    fn rotate_right(&mut self, child_idx: usize) {
+        let (left, right) = self.children.split_at_mut(child_idx);
+        let (left, right) = (&mut left[child_idx - 1], &mut right[0]);
+
+        let last_key = left.keys.pop().expect("Left child has no keys");
+        let middle_key = std::mem::replace(&mut self.keys[child_idx - 1], last_key);
+        right.keys.insert(0, middle_key);
+
+        if let Some(last_child) = left.children.pop() {
+            right.children.insert(0, last_child);
+        }
+    }
+
+    /// Helper that moves first key from right child to parent and parent key to left child's last key
+    ///
+    /// Takes a child_idx that represents the left child's index
+    fn rotate_left(&mut self, child_idx: usize) {
+        let (left, right) = self.children.split_at_mut(child_idx + 1);
+        let (left, right) = (&mut left[child_idx], &mut right[0]);
+
+        let first_key = right.keys.remove(0);
+        let middle_key = std::mem::replace(&mut self.keys[child_idx], first_key);
+        left.keys.push(middle_key);
+
+        if !right.is_leaf() {
+            left.children.push(right.children.remove(0));
+        }
+    }
+
+    /// Helper that merges two children nodes and inserts middle key into new child
+    ///
+    /// Takes a child_idx that represents the left child
+    fn merge(&mut self, child_idx: usize) {
+        let middle_key = self.keys.remove(child_idx);
+        let right = self.children.remove(child_idx + 1);
+        let left = &mut self.children[child_idx];
+
+        left.keys.push(middle_key);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+    }
+
+    /// Prints the node and its subtree, indenting each level by two spaces (called recursively)
+    #[cfg(test)]
+    fn print_structure(&self, depth: usize) {
+        println!("{}Node (leaf={}): {:?}", "  ".repeat(depth), self.is_leaf(), self.keys.as_slice());
+        for child in &self.children {
+            child.print_structure(depth + 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_tree::btree_test_suite;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    macro_rules! new_const_btree {
+        ($m:literal) => { ConstBTree::<_, $m>::new() };
+        ($m:literal, $t:ty) => { ConstBTree::<$t, $m>::new() };
+    }
+
+    btree_test_suite!(new_const_btree);
+
+    impl<T: PartialOrd + Debug + Clone, const M: usize> ConstBTree<T, M> {
+        /// Checks every non-root node holds between floor(K/2) and K keys, keys are in order
+        /// and every leaf is at the same depth
+        fn check_invariants(&self) {
+            fn walk<T: PartialOrd + Debug + Clone, const M: usize>(node: &Node<T, M>, depth: usize, leaf_depth: &mut Option<usize>, keys: &mut Vec<T>) {
+                assert!(node.keys.len() < M, "Node overfull: {:?}", node.keys);
+                if depth > 0 {
+                    assert!(node.keys.len() >= Node::<T, M>::min_keys(), "Node underfull: {:?}", node.keys);
+                }
+                if node.is_leaf() {
+                    assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+                    keys.extend(node.keys.iter().cloned());
+                    return;
+                }
+                assert_eq!(node.children.len(), node.keys.len() + 1);
+                for (i, child) in node.children.iter().enumerate() {
+                    walk(child, depth + 1, leaf_depth, keys);
+                    if i < node.keys.len() {
+                        keys.push(node.keys[i].clone());
+                    }
+                }
+            }
+            if let Some(root) = &self.root {
+                let mut keys = vec![];
+                walk(root, 0, &mut None, &mut keys);
+                assert!(keys.windows(2).all(|w| w[0] < w[1]), "Keys out of order: {:?}", keys);
+            }
+        }
+
+        /// Returns the keys in order
+        fn keys(&self) -> Vec<T> {
+            fn walk<T: PartialOrd + Debug + Clone, const M: usize>(node: &Node<T, M>, keys: &mut Vec<T>) {
+                for i in 0..node.keys.len() {
+                    if !node.is_leaf() {
+                        walk(&node.children[i], keys);
+                    }
+                    keys.push(node.keys[i].clone());
+                }
+                if !node.is_leaf() {
+                    walk(&node.children[node.keys.len()], keys);
+                }
+            }
+            let mut keys = vec![];
+            if let Some(root) = &self.root {
+                walk(root, &mut keys);
+            }
+            keys
+        }
+    }
+
+    /// Inserts and deletes shuffled values, checking the invariants after every operation
+    fn random_ops<const M: usize>() {
+        let mut rng = StdRng::seed_from_u64(M as u64);
+        let mut values: Vec<i32> = (0..400).collect();
+        values.shuffle(&mut rng);
+
+        let mut btree = ConstBTree::<i32, M>::new();
+        for &val in &values {
+            btree.insert(val);
+            btree.check_invariants();
+        }
+        let mut sorted = values.clone();
+        sorted.sort();
+        assert_eq!(btree.keys(), sorted);
+
+        values.shuffle(&mut rng);
+        for &val in &values[..350] {
+            btree.delete(val);
+            btree.check_invariants();
+        }
+        let mut remaining = values[350..].to_vec();
+        remaining.sort();
+        assert_eq!(btree.keys(), remaining);
+    }
+
+    #[test]
+    fn test_invariants_across_orders() {
+        random_ops::<3>();
+        random_ops::<4>();
+        random_ops::<5>();
+        random_ops::<6>();
+        random_ops::<7>();
+        random_ops::<8>();
+        random_ops::<16>();
+        random_ops::<33>();
+    }
+
+    #[test]
+    fn test_split_with_key_in_each_half() {
+        // Order 5: a full leaf [10, 20, 30, 40] splits around the median of the five keys
+        for (key, median, left, right) in [
+            (5, 20, vec![5, 10], vec![30, 40]),
+            (25, 25, vec![10, 20], vec![30, 40]),
+            (35, 30, vec![10, 20], vec![35, 40]),
+            (45, 30, vec![10, 20], vec![40, 45]),
+        ] {
+            let mut btree = ConstBTree::<i32, 5>::new();
+            for i in [10, 20, 30, 40, key] {
+                btree.insert(i);
+            }
+            let root = btree.root.as_ref().unwrap();
+            assert_eq!(root.keys.as_slice(), &[median]);
+            assert_eq!(root.children[0].keys.as_slice(), left.as_slice());
+            assert_eq!(root.children[1].keys.as_slice(), right.as_slice());
+        }
+    }
+
+    #[test]
+    fn test_same_shape_as_bottom_up_btree() {
+        let mut values: Vec<i32> = (0..200).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(3));
+
+        let mut const_btree = ConstBTree::<i32, 5>::new();
+        let mut btree = crate::BTree::new(5);
+        for &val in &values {
+            const_btree.insert(val);
+            btree.insert(val);
+        }
+
+        // Both split overflowing nodes around the same median, so they build the same tree
+        fn shape<const M: usize>(node: &Node<i32, M>) -> crate::b_tree::NodeSnapshot<i32> {
+            crate::b_tree::NodeSnapshot { keys: node.keys.to_vec(), children: node.children.iter().map(|c| shape(c)).collect() }
+        }
+        assert_eq!(shape(const_btree.root.as_ref().unwrap()), btree.root.as_ref().unwrap().snapshot());
+    }
+
+    #[test]
+    fn test_node_is_single_allocation() {
+        // Keys and children are inline, so a node holds no pointers besides its children
+        assert_eq!(std::mem::size_of::<Node<u64, 8>>(), std::mem::size_of::<ArrayVec<u64, 8>>() + std::mem::size_of::<ArrayVec<Box<Node<u64, 8>>, 8>>());
+    }
+}
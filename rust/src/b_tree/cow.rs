@@ -0,0 +1,212 @@
+// Copy-on-write nodes for BTree (enabled with the "cow" cargo feature)
+//
+// Without the feature, a node owns its children through Box, and cloning a BTree copies every node.
+// With it, the root and children are Arcs, so cloning a BTree only clones the root Arc, and the clone
+// shares every node with the original. Nodes are then changed through Arc::make_mut: a node that only
+// one tree points at is changed in place, while a shared node is first copied (its keys, and the Arcs
+// of its children). So an insert or delete only copies the shared nodes on the path it modifies (and
+// any sibling a split, rotation or merge changes), and every other node stays shared.
+//
+// Every change to a child goes through make_mut (Node::child_mut for a child of a node), so the
+// insert and delete code is the same either way.
+
+use std::fmt::Debug;
+#[cfg(feature = "cow")]
+use std::sync::Arc;
+
+use super::metrics::Counters;
+use super::{BTree, Node};
+
+/// Pointer from a node to one of its children (or from the tree to its root)
+///
+/// Children are moved between nodes on split/merge/rotate, so keep them boxed
+#[cfg(not(feature = "cow"))]
+pub(super) type Child<T> = Box<Node<T>>;
+
+/// Pointer from a node to one of its children (or from the tree to its root)
+#[cfg(feature = "cow")]
+pub(super) type Child<T> = Arc<Node<T>>;
+
+/// Returns a mutable ref to the node, copying it first if it is shared with another tree
+#[cfg(not(feature = "cow"))]
+#[inline]
+pub(super) fn make_mut<T: PartialOrd + Debug + Clone>(child: &mut Child<T>) -> &mut Node<T> {
+    child
+}
+
+/// Returns a mutable ref to the node, copying it first if it is shared with another tree
+#[cfg(feature = "cow")]
+#[inline]
+pub(super) fn make_mut<T: PartialOrd + Debug + Clone>(child: &mut Child<T>) -> &mut Node<T> {
+    Arc::make_mut(child)
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    /// Returns a mutable ref to the child at idx, copying it first if it is shared with another tree
+    #[inline]
+    pub(super) fn child_mut(&mut self, idx: usize) -> &mut Node<T> {
+        make_mut(&mut self.children[idx])
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Clone for BTree<T> {
+    /// Clones the tree (without its observer, and with its counters reset)
+    ///
+    /// With the cow feature this is O(1), and the clone shares every node until either tree changes
+    /// it. Otherwise every node is copied
+    fn clone(&self) -> Self {
+        BTree {
+            root: self.root.clone(),
+            order: self.order,
+            counters: Counters::new(),
+            observer: None,
+            strategy: self.strategy,
+            node_search: self.node_search,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+
+    /// Collects all keys of a tree in order
+    fn keys(btree: &BTree<i32>) -> Vec<i32> {
+        fn walk(node: &Node<i32>, keys: &mut Vec<i32>) {
+            for i in 0..node.keys.len() {
+                if !node.leaf {
+                    walk(&node.children[i], keys);
+                }
+                keys.push(node.keys[i]);
+            }
+            if !node.leaf {
+                walk(&node.children[node.keys.len()], keys);
+            }
+        }
+        let mut keys = vec![];
+        if let Some(root) = &btree.root {
+            walk(root, &mut keys);
+        }
+        keys
+    }
+
+    fn build(order: usize, values: &[i32]) -> BTree<i32> {
+        let mut btree = BTree::new(order);
+        for &val in values {
+            btree.insert(val);
+        }
+        btree
+    }
+
+    #[test]
+    fn test_clone_is_independent() {
+        for order in [3, 4, 5, 8] {
+            let mut values: Vec<i32> = (0..500).collect();
+            values.shuffle(&mut StdRng::seed_from_u64(order as u64));
+            let original = build(order, &values);
+
+            let mut clone = original.clone();
+            assert_eq!(clone.insert_strategy(), original.insert_strategy());
+            for &val in &values[..300] {
+                clone.delete(val);
+            }
+            for val in 500..700 {
+                clone.insert(val);
+            }
+            clone.check_invariants();
+            original.check_invariants();
+
+            assert_eq!(keys(&original), (0..500).collect::<Vec<_>>());
+            let mut expected: Vec<i32> = values[300..].iter().copied().chain(500..700).collect();
+            expected.sort();
+            assert_eq!(keys(&clone), expected);
+        }
+    }
+
+    #[cfg(feature = "cow")]
+    mod shared {
+        use super::*;
+        use std::collections::HashSet;
+
+        /// Collects the address of every node in a tree
+        fn node_ptrs(btree: &BTree<i32>) -> HashSet<*const Node<i32>> {
+            fn walk(node: &Child<i32>, ptrs: &mut HashSet<*const Node<i32>>) {
+                ptrs.insert(Arc::as_ptr(node));
+                for child in &node.children {
+                    walk(child, ptrs);
+                }
+            }
+            let mut ptrs = HashSet::new();
+            if let Some(root) = &btree.root {
+                walk(root, &mut ptrs);
+            }
+            ptrs
+        }
+
+        /// Returns the number of levels in a tree
+        fn height(btree: &BTree<i32>) -> usize {
+            let mut height = 0;
+            let mut node = btree.root.as_ref();
+            while let Some(n) = node {
+                height += 1;
+                node = n.children.first();
+            }
+            height
+        }
+
+        #[test]
+        fn test_clone_shares_every_node() {
+            let btree = build(4, &(0..1000).collect::<Vec<_>>());
+            let clone = btree.clone();
+            assert!(Arc::ptr_eq(btree.root.as_ref().unwrap(), clone.root.as_ref().unwrap()));
+            assert_eq!(node_ptrs(&btree), node_ptrs(&clone));
+        }
+
+        #[test]
+        fn test_only_shared_path_is_copied() {
+            let mut values: Vec<i32> = (0..5000).map(|v| v * 2).collect();
+            values.shuffle(&mut StdRng::seed_from_u64(3));
+            for order in [3, 4, 8, 32] {
+                let original = build(order, &values);
+                let before = node_ptrs(&original);
+
+                // Insert: one copy per level, plus a new node per split (including a new root)
+                let mut clone = original.clone();
+                clone.insert(4001);
+                let copied = node_ptrs(&clone).difference(&before).count();
+                assert!(copied <= 2 * height(&original) + 1, "order {}: {} nodes copied", order, copied);
+
+                // Delete: one copy per level, plus a copied sibling per rotation or merge
+                let mut clone = original.clone();
+                clone.delete(5000);
+                let copied = node_ptrs(&clone).difference(&before).count();
+                assert!(copied <= 2 * height(&original), "order {}: {} nodes copied", order, copied);
+
+                assert_eq!(node_ptrs(&original), before);
+                original.check_invariants();
+                clone.check_invariants();
+            }
+        }
+
+        #[test]
+        fn test_unshared_nodes_change_in_place() {
+            let mut btree = build(8, &(0..1000).collect::<Vec<_>>());
+            let before = node_ptrs(&btree);
+            btree.insert(1000);
+            btree.delete(0);
+            // Without a clone nothing is shared, so no node is copied (a split may still add one)
+            assert!(node_ptrs(&btree).difference(&before).count() <= 1);
+
+            // Once the clone is dropped, the nodes it copied are no longer shared either
+            let snapshot = btree.clone();
+            btree.insert(1001);
+            drop(snapshot);
+            let before = node_ptrs(&btree);
+            btree.insert(1002);
+            assert!(node_ptrs(&btree).difference(&before).count() <= 1);
+        }
+    }
+}
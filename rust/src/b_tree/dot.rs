@@ -0,0 +1,198 @@
+// Graphviz DOT export for BTree
+// https://graphviz.org/doc/info/shapes.html#record
+//
+// Each node is drawn as a record with a port in front of every key and one after the last key:
+//   node0 [label="<c0> |<k0> 10|<c1> |<k1> 20|<c2> "];
+// Edges leave from the child ports (c0, c1, ...) so they appear between the keys they separate.
+
+use std::fmt::Debug;
+use std::io::{self, Write};
+
+use super::{BTree, Counters, Node, NodeSearch};
+
+const HIGHLIGHT_COLOR: &str = "red";
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Renders the b-tree as a Graphviz DOT digraph
+    ///
+    /// Each node becomes a record-shaped node with a port per key, with edges to its children
+    pub fn to_dot(&self) -> String {
+        let mut buf = Vec::new();
+        self.write_dot(&mut buf).expect("Writing to a Vec cannot fail");
+        String::from_utf8(buf).expect("DOT output is valid UTF-8")
+    }
+
+    /// Writes the b-tree as a Graphviz DOT digraph to any writer
+    pub fn write_dot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.write_dot_inner(out, None)
+    }
+
+    /// Writes the b-tree as a Graphviz DOT digraph, highlighting the path a search for value takes
+    ///
+    /// Every node visited by the search and the edges between them are highlighted,
+    /// as is the matching key if the value is present
+    pub fn write_dot_search_path<W: Write>(&self, out: &mut W, value: &T) -> io::Result<()> {
+        self.write_dot_inner(out, Some(value))
+    }
+
+    fn write_dot_inner<W: Write>(&self, out: &mut W, highlight: Option<&T>) -> io::Result<()> {
+        writeln!(out, "digraph BTree {{")?;
+        writeln!(out, "    label=\"BTree (order {})\";", self.order)?;
+        writeln!(out, "    node [shape=record, height=0.1];")?;
+
+        if let Some(r) = &self.root {
+            let mut next_id = 0;
+            r.write_dot(out, &mut next_id, highlight)?;
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    /// Writes this node and its subtree in DOT format (called recursively)
+    ///
+    /// Node ids are assigned in pre-order from next_id, and the id of this node is returned
+    ///
+    /// highlight is the value being searched for if this node lies on the search path
+    fn write_dot<W: Write>(&self, out: &mut W, next_id: &mut usize, highlight: Option<&T>) -> io::Result<usize> {
+        let id = *next_id;
+        *next_id += 1;
+
+        // Work out where the search goes from this node (if this node is on the search path)
+        // Comparisons made while rendering are not counted towards the tree's metrics
+        let search = highlight.map(|value| self.search(value, NodeSearch::Binary, &Counters::new()));
+
+        // Build the record label: a child port before every key and one after the last key
+        let mut label = String::new();
+        for (i, key) in self.keys.iter().enumerate() {
+            label.push_str(&format!("<c{}> |<k{}> {}|", i, i, escape_record(&format!("{:?}", key))));
+        }
+        label.push_str(&format!("<c{}> ", self.keys.len()));
+
+        write!(out, "    node{} [label=\"{}\"", id, label)?;
+        if search.is_some() {
+            write!(out, ", color={}, penwidth=2", HIGHLIGHT_COLOR)?;
+        }
+        writeln!(out, "];")?;
+
+        // Mark the matching key (records cannot colour a single field, so attach an annotation to its port)
+        if let Some((true, idx)) = search {
+            writeln!(out, "    found{} [shape=plaintext, label=\"found\", fontcolor={}];", id, HIGHLIGHT_COLOR)?;
+            writeln!(out, "    found{} -> node{}:k{} [color={}, style=dashed];", id, id, idx, HIGHLIGHT_COLOR)?;
+        }
+
+        if !self.leaf {
+            for (i, child) in self.children.iter().enumerate() {
+                // The search only continues into child idx (and only if the value was not found here)
+                let on_path = matches!(search, Some((false, idx)) if idx == i);
+                let child_id = child.write_dot(out, next_id, if on_path { highlight } else { None })?;
+
+                write!(out, "    node{}:c{} -> node{}", id, i, child_id)?;
+                if on_path {
+                    write!(out, " [color={}, penwidth=2]", HIGHLIGHT_COLOR)?;
+                }
+                writeln!(out, ";")?;
+            }
+        }
+
+        Ok(id)
+    }
+}
+
+/// Escapes characters that have special meaning inside a DOT record label
+fn escape_record(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '"' | '{' | '}' | '|' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dot_empty_tree() {
+        let btree: BTree<i32> = BTree::new(3);
+        let dot = btree.to_dot();
+
+        assert!(dot.starts_with("digraph BTree {"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert!(!dot.contains("node0"));
+    }
+
+    #[test]
+    fn test_dot_single_node() {
+        let mut btree = BTree::new(5);
+        btree.insert(10);
+        btree.insert(20);
+
+        let dot = btree.to_dot();
+        assert!(dot.contains("node0 [label=\"<c0> |<k0> 10|<c1> |<k1> 20|<c2> \"];"));
+        assert!(!dot.contains("->"));
+    }
+
+    #[test]
+    fn test_dot_edges_to_children() {
+        let mut btree = BTree::new(3);
+        for i in 1..=7 {
+            btree.insert(i);
+        }
+
+        let dot = btree.to_dot();
+
+        // Root [4] with children [2] and [6], each with two leaves -> 7 nodes and 6 edges
+        assert!(dot.contains("node0 [label=\"<c0> |<k0> 4|<c1> \"];"));
+        assert_eq!(dot.matches(" [label=").count(), 7);
+        assert_eq!(dot.matches(" -> ").count(), 6);
+        assert!(dot.contains("node0:c0 -> node1;"));
+    }
+
+    #[test]
+    fn test_dot_search_path_highlight() {
+        let mut btree = BTree::new(3);
+        for i in 1..=7 {
+            btree.insert(i);
+        }
+
+        let mut buf = Vec::new();
+        btree.write_dot_search_path(&mut buf, &7).unwrap();
+        let dot = String::from_utf8(buf).unwrap();
+
+        // Root -> [6] -> [7], so three highlighted nodes, two highlighted edges, and the found annotation
+        assert_eq!(dot.matches(", color=red, penwidth=2];").count(), 3);
+        assert_eq!(dot.matches("[color=red, penwidth=2]").count(), 2);
+        assert!(dot.contains("-> node6:k0"));
+    }
+
+    #[test]
+    fn test_dot_search_path_missing_value() {
+        let mut btree = BTree::new(3);
+        for i in 1..=7 {
+            btree.insert(i * 10);
+        }
+
+        let mut buf = Vec::new();
+        btree.write_dot_search_path(&mut buf, &15).unwrap();
+        let dot = String::from_utf8(buf).unwrap();
+
+        assert!(dot.contains("color=red"));
+        assert!(!dot.contains("found"));
+    }
+
+    #[test]
+    fn test_dot_escapes_record_characters() {
+        let mut btree = BTree::new(3);
+        btree.insert("a|b".to_string());
+        btree.insert("<c>".to_string());
+
+        let dot = btree.to_dot();
+        assert!(dot.contains("\\\"a\\|b\\\""));
+        assert!(dot.contains("\\\"\\<c\\>\\\""));
+    }
+}
@@ -0,0 +1,244 @@
+// Opt-in operation counters for BTree (enabled with the "metrics" cargo feature)
+//
+// Every BTree owns a Counters value that the node helpers record into. With the feature
+// off, Counters is a zero-sized type whose methods are empty, so the calls compile away.
+// The counters are atomics (with relaxed ordering) rather than Cells so that counting
+// comparisons from &self searches does not stop a BTree from being shared across threads.
+
+#[cfg(feature = "metrics")]
+use std::fmt::Debug;
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "metrics")]
+use super::BTree;
+
+/// A snapshot of the operation counters of a BTree
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Key comparisons made by the in-node binary search
+    pub comparisons: u64,
+    /// Nodes split into two (including root splits)
+    pub splits: u64,
+    /// Sibling nodes merged into one
+    pub merges: u64,
+    /// Keys rotated from a right sibling through the parent into a left sibling
+    pub rotations_left: u64,
+    /// Keys rotated from a left sibling through the parent into a right sibling
+    pub rotations_right: u64,
+    /// Nodes allocated (new roots and the right halves of splits)
+    pub node_allocations: u64,
+    /// Times the tree grew a level (a new root was created above the old one)
+    pub root_grows: u64,
+    /// Times the tree lost a level (an empty root was replaced by its only child)
+    pub root_shrinks: u64,
+}
+
+#[cfg(feature = "metrics")]
+#[derive(Default)]
+pub(super) struct Counters {
+    comparisons: AtomicU64,
+    splits: AtomicU64,
+    merges: AtomicU64,
+    rotations_left: AtomicU64,
+    rotations_right: AtomicU64,
+    node_allocations: AtomicU64,
+    root_grows: AtomicU64,
+    root_shrinks: AtomicU64,
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(super) struct Counters;
+
+#[cfg(feature = "metrics")]
+impl Counters {
+    pub(super) fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    pub(super) fn comparison(&self) {
+        self.comparisons.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn comparisons(&self, n: usize) {
+        self.comparisons.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn split(&self) {
+        self.splits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn merge(&self) {
+        self.merges.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn rotate_left(&self) {
+        self.rotations_left.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn rotate_right(&self) {
+        self.rotations_right.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn allocation(&self) {
+        self.node_allocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn root_grow(&self) {
+        self.root_grows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(super) fn root_shrink(&self) {
+        self.root_shrinks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Metrics {
+        Metrics {
+            comparisons: self.comparisons.load(Ordering::Relaxed),
+            splits: self.splits.load(Ordering::Relaxed),
+            merges: self.merges.load(Ordering::Relaxed),
+            rotations_left: self.rotations_left.load(Ordering::Relaxed),
+            rotations_right: self.rotations_right.load(Ordering::Relaxed),
+            node_allocations: self.node_allocations.load(Ordering::Relaxed),
+            root_grows: self.root_grows.load(Ordering::Relaxed),
+            root_shrinks: self.root_shrinks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+impl Counters {
+    pub(super) fn new() -> Self {
+        Counters
+    }
+
+    #[inline(always)]
+    pub(super) fn comparison(&self) {}
+
+    #[inline(always)]
+    pub(super) fn comparisons(&self, _n: usize) {}
+
+    #[inline(always)]
+    pub(super) fn split(&self) {}
+
+    #[inline(always)]
+    pub(super) fn merge(&self) {}
+
+    #[inline(always)]
+    pub(super) fn rotate_left(&self) {}
+
+    #[inline(always)]
+    pub(super) fn rotate_right(&self) {}
+
+    #[inline(always)]
+    pub(super) fn allocation(&self) {}
+
+    #[inline(always)]
+    pub(super) fn root_grow(&self) {}
+
+    #[inline(always)]
+    pub(super) fn root_shrink(&self) {}
+}
+
+#[cfg(feature = "metrics")]
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Returns a snapshot of the operation counters since the tree was created or last reset
+    pub fn metrics(&self) -> Metrics {
+        self.counters.snapshot()
+    }
+
+    /// Resets all operation counters to zero
+    pub fn reset_metrics(&mut self) {
+        self.counters = Counters::new();
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_new_tree() {
+        let btree: BTree<i32> = BTree::new(3);
+        assert_eq!(btree.metrics(), Metrics::default());
+    }
+
+    #[test]
+    fn test_metrics_root_split() {
+        let mut btree = BTree::new(5);
+        for i in 1..=5 {
+            btree.insert(i);
+        }
+
+        // First insert allocates the root, the fifth splits it (new root + right half)
+        let metrics = btree.metrics();
+        assert_eq!(metrics.splits, 1);
+        assert_eq!(metrics.root_grows, 1);
+        assert_eq!(metrics.node_allocations, 3);
+        assert!(metrics.comparisons > 0);
+    }
+
+    #[test]
+    fn test_metrics_search_counts_comparisons() {
+        let mut btree = BTree::new(5);
+        for i in 1..=4 {
+            btree.insert(i);
+        }
+        btree.reset_metrics();
+
+        // [1, 2, 3, 4]: probing 3 costs == and <, then 2 is found at the next midpoint with ==
+        assert!(btree.search(2));
+        assert_eq!(btree.metrics().comparisons, 3);
+        assert_eq!(btree.metrics().splits, 0);
+    }
+
+    #[test]
+    fn test_metrics_rotations_and_merges() {
+        let mut btree = BTree::new(3);
+        for i in 1..=7 {
+            btree.insert(i);
+        }
+        btree.reset_metrics();
+
+        // Deleting from the full tree of 7 merges back down to a single level
+        for i in 1..=7 {
+            btree.delete(i);
+        }
+        let metrics = btree.metrics();
+        assert!(metrics.merges > 0);
+        assert!(metrics.root_shrinks > 0);
+        assert_eq!(metrics.splits, 0);
+
+        let mut btree = BTree::new(3);
+        for i in 1..=10 {
+            btree.insert(i);
+        }
+        btree.reset_metrics();
+        btree.delete(1);
+        btree.delete(10);
+        let metrics = btree.metrics();
+        assert!(metrics.rotations_left + metrics.rotations_right + metrics.merges > 0);
+    }
+
+    #[test]
+    fn test_metrics_reset() {
+        let mut btree = BTree::new(3);
+        for i in 1..=20 {
+            btree.insert(i);
+        }
+        assert_ne!(btree.metrics(), Metrics::default());
+
+        btree.reset_metrics();
+        assert_eq!(btree.metrics(), Metrics::default());
+    }
+}
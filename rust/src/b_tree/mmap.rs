@@ -0,0 +1,468 @@
+// Immutable B-tree file that is memory-mapped and searched in place (enabled with the "mmap" cargo feature)
+//
+// A file is built once from entries in sorted order (fixed-width keys of K bytes and values of V
+// bytes, compared as bytes, so e.g. integers should be written big-endian) and never changed. Like a
+// B+ tree, every entry is in a leaf, and since the tree is never changed the nodes are packed full
+// in key order, so no child pointers are needed: child c of node n at one level is node n * B + c at
+// the level below, where B is the keys per node. Layout (integers little-endian):
+//   header (32 bytes): magic "BTMM", version (u16), key width K (u32), value width V (u32), keys per
+//     node B (u32), number of entries n (u64), zero padding
+//   leaves: ceil(n/B) nodes of B entries (the last one may hold fewer), each its keys then its values
+//   index levels, from the one above the leaves up to the root: node i of a level holds the first keys
+//     of nodes i * B to i * B + B - 1 of the level below (so a level holds one key per node below it,
+//     and has ceil(keys/B) nodes, and the root is the first level with a single node)
+//
+// Opening a file maps it and checks that its length matches the header, after which every node is a
+// slice of the mapping: its keys are searched as a &[[u8; K]] with NodeSearch (the same search as
+// BTree's nodes), and lookups and range scans return references into the mapping without copying.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use super::NodeSearch;
+
+const MAGIC: [u8; 4] = *b"BTMM";
+const VERSION: u16 = 1;
+const HEADER: usize = 32;
+
+/// Returns an InvalidData error
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(message: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Byte offsets of the parts of a file
+struct Layout {
+    /// Offset and key count of each index level, root first
+    levels: Vec<(usize, usize)>,
+    file_len: usize,
+}
+
+impl Layout {
+    /// Returns the layout of n entries in nodes of node_keys, or None if it does not fit in memory
+    fn new(n: usize, node_keys: usize, key_width: usize, value_width: usize) -> Option<Layout> {
+        let mut end = n.checked_mul(key_width.checked_add(value_width)?)?.checked_add(HEADER)?;
+        let mut levels = vec![];
+        let mut keys = n.div_ceil(node_keys);
+        while keys > 1 {
+            levels.push((end, keys));
+            end = end.checked_add(keys.checked_mul(key_width)?)?;
+            keys = keys.div_ceil(node_keys);
+        }
+        levels.reverse();
+        Some(Layout { levels, file_len: end })
+    }
+}
+
+/// A read-only B-tree of fixed-width keys and values in a memory-mapped file, built by MmapBTree::build
+pub struct MmapBTree<const K: usize, const V: usize> {
+    map: Mmap,
+    len: usize,
+    node_keys: usize,
+    /// Offset and key count of each index level, root first
+    levels: Vec<(usize, usize)>,
+    node_search: NodeSearch,
+}
+
+impl<const K: usize, const V: usize> MmapBTree<K, V> {
+    /// Writes a file at path (replacing any file there) holding entries, which must be in strictly
+    /// increasing key order, in nodes of node_keys keys
+    ///
+    /// The entries are written as they come, so only the first key of every leaf is kept in memory.
+    /// Fails with InvalidInput if the keys are not in order (leaving a partial file)
+    pub fn build<P, I>(path: P, node_keys: usize, entries: I) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = ([u8; K], [u8; V])>,
+    {
+        assert!(K > 0, "MmapBTree keys must be at least 1 byte wide");
+        assert!(node_keys >= 2, "MmapBTree nodes must hold at least 2 keys");
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(&[0; HEADER])?;
+
+        // First key of each leaf, which make up the level above
+        let mut firsts: Vec<[u8; K]> = vec![];
+        let mut keys = Vec::with_capacity(node_keys * K);
+        let mut values = Vec::with_capacity(node_keys * V);
+        let mut last: Option<[u8; K]> = None;
+        let mut len = 0;
+        for (key, value) in entries {
+            if let Some(last) = last
+                && last >= key {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("MmapBTree keys must be in strictly increasing order, but entry {} is not", len),
+                ));
+            }
+            if keys.is_empty() {
+                firsts.push(key);
+            }
+            keys.extend_from_slice(&key);
+            values.extend_from_slice(&value);
+            if keys.len() == node_keys * K {
+                out.write_all(&keys)?;
+                out.write_all(&values)?;
+                keys.clear();
+                values.clear();
+            }
+            last = Some(key);
+            len += 1;
+        }
+        out.write_all(&keys)?;
+        out.write_all(&values)?;
+
+        // Each level holds the first key of every node of the level below, up to a single root
+        let mut level = firsts;
+        while level.len() > 1 {
+            for key in &level {
+                out.write_all(key)?;
+            }
+            level = level.into_iter().step_by(node_keys).collect();
+        }
+
+        out.seek(SeekFrom::Start(0))?;
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        for width in [K, V, node_keys] {
+            let width = u32::try_from(width).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "MmapBTree widths must fit in 32 bits"))?;
+            out.write_all(&width.to_le_bytes())?;
+        }
+        out.write_all(&(len as u64).to_le_bytes())?;
+        out.into_inner().map_err(|err| err.into_error())?.sync_all()
+    }
+
+    /// Maps a file written by build
+    ///
+    /// Fails with InvalidData if the header is wrong (including key or value widths other than K and
+    /// V) or the file's length does not match it. The file must not be changed while it is open (see
+    /// memmap2::Mmap::map)
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is only read, and files built by build are never written again; a file
+        // changed by another process while mapped is the caller's responsibility (as documented)
+        let map = unsafe { Mmap::map(&file)? };
+
+        let header = map.get(..HEADER).ok_or_else(|| invalid("file is shorter than an MmapBTree header"))?;
+        if header[..4] != MAGIC {
+            return Err(invalid("not an MmapBTree file"));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != VERSION {
+            return Err(invalid(format!("unsupported MmapBTree version {}", version)));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().expect("4 bytes")) as usize;
+        let (key_width, value_width, node_keys) = (u32_at(6), u32_at(10), u32_at(14));
+        if (key_width, value_width) != (K, V) {
+            return Err(invalid(format!("file holds {}-byte keys and {}-byte values, not {} and {}", key_width, value_width, K, V)));
+        }
+        if node_keys < 2 {
+            return Err(invalid(format!("nodes of {} keys", node_keys)));
+        }
+        let len = usize::try_from(u64::from_le_bytes(header[18..26].try_into().expect("8 bytes")))
+            .map_err(|_| invalid("too many entries for this platform"))?;
+
+        let layout = Layout::new(len, node_keys, K, V).ok_or_else(|| invalid(format!("{} entries do not fit in memory", len)))?;
+        if map.len() != layout.file_len {
+            return Err(invalid(format!("file is {} bytes, but its header says {}", map.len(), layout.file_len)));
+        }
+        Ok(MmapBTree { map, len, node_keys, levels: layout.levels, node_search: NodeSearch::Binary })
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of keys per node
+    pub fn node_keys(&self) -> usize {
+        self.node_keys
+    }
+
+    /// Returns the number of levels of nodes (0 if empty)
+    pub fn height(&self) -> usize {
+        if self.len == 0 { 0 } else { self.levels.len() + 1 }
+    }
+
+    /// Sets how keys are searched within each node (Binary by default)
+    pub fn set_node_search(&mut self, search: NodeSearch) {
+        self.node_search = search;
+    }
+
+    /// Returns the value of key, if it is present
+    pub fn get(&self, key: &[u8; K]) -> Option<&[u8; V]> {
+        let idx = self.lower_bound(key);
+        (idx < self.len && self.key(idx) == key).then(|| self.value(idx))
+    }
+
+    /// Returns true if key is present
+    pub fn contains_key(&self, key: &[u8; K]) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the entries in key order
+    pub fn iter(&self) -> MmapRange<'_, K, V> {
+        MmapRange { tree: self, idx: 0..self.len }
+    }
+
+    /// Returns the entries with keys in range, in key order
+    pub fn range<R: RangeBounds<[u8; K]>>(&self, range: R) -> MmapRange<'_, K, V> {
+        let start = match range.start_bound() {
+            Bound::Included(key) => self.lower_bound(key),
+            Bound::Excluded(key) => self.upper_bound(key),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => self.upper_bound(key),
+            Bound::Excluded(key) => self.lower_bound(key),
+            Bound::Unbounded => self.len,
+        };
+        MmapRange { tree: self, idx: start..end.max(start) }
+    }
+
+    /// Checks that the keys are in strictly increasing order and that every index key is the first key
+    /// of its child, returning a description of the first problem found
+    ///
+    /// open only checks the header, so this is for files that might be corrupt
+    pub fn validate(&self) -> Result<(), String> {
+        for idx in 1..self.len {
+            if self.key(idx - 1) >= self.key(idx) {
+                return Err(format!("entry {} is not after entry {}", idx, idx - 1));
+            }
+        }
+        // The first keys of the level below (starting from the leaves)
+        let mut below: Vec<&[u8; K]> = (0..self.len).step_by(self.node_keys).map(|idx| self.key(idx)).collect();
+        for (depth, &(offset, count)) in self.levels.iter().enumerate().rev() {
+            let keys = self.keys(offset, 0..count);
+            if keys.iter().ne(below.iter().copied()) {
+                return Err(format!("index level {} does not hold the first keys of the level below", depth));
+            }
+            below = keys.iter().step_by(self.node_keys).collect();
+        }
+        Ok(())
+    }
+
+    /// Returns the idx of the first entry with a key not less than key (len if there is none)
+    fn lower_bound(&self, key: &[u8; K]) -> usize {
+        // Go down through the index levels to the leaf the key belongs in
+        let mut node = 0;
+        for &(offset, count) in &self.levels {
+            let start = node * self.node_keys;
+            let keys = self.keys(offset, start..count.min(start + self.node_keys));
+            let (found, idx) = self.node_search.search(keys, key);
+            // The child whose first key is the last one not greater than key (or the first child, if
+            // key is before every key)
+            let child = if found { idx } else { idx.saturating_sub(1) };
+            node = start + child;
+        }
+
+        let start = node * self.node_keys;
+        let end = self.len.min(start + self.node_keys);
+        let (_, idx) = self.node_search.search(self.leaf_keys(node, end - start), key);
+        start + idx
+    }
+
+    /// Returns the idx of the first entry with a key greater than key (len if there is none)
+    fn upper_bound(&self, key: &[u8; K]) -> usize {
+        let idx = self.lower_bound(key);
+        if idx < self.len && self.key(idx) == key { idx + 1 } else { idx }
+    }
+
+    /// Returns the keys at idxs of the level at offset
+    fn keys(&self, offset: usize, idxs: Range<usize>) -> &[[u8; K]] {
+        self.map[offset + idxs.start * K..offset + idxs.end * K].as_chunks::<K>().0
+    }
+
+    /// Returns the first count keys of leaf node
+    fn leaf_keys(&self, node: usize, count: usize) -> &[[u8; K]] {
+        self.keys(HEADER + node * self.node_keys * (K + V), 0..count)
+    }
+
+    /// Returns the key of the entry at idx
+    fn key(&self, idx: usize) -> &[u8; K] {
+        let (node, slot) = (idx / self.node_keys, idx % self.node_keys);
+        let offset = HEADER + node * self.node_keys * (K + V) + slot * K;
+        self.map[offset..offset + K].try_into().expect("slice of K bytes")
+    }
+
+    /// Returns the value of the entry at idx
+    fn value(&self, idx: usize) -> &[u8; V] {
+        let (node, slot) = (idx / self.node_keys, idx % self.node_keys);
+        // The values of a node come after its keys, of which the last node may have fewer than node_keys
+        let count = self.node_keys.min(self.len - node * self.node_keys);
+        let offset = HEADER + node * self.node_keys * (K + V) + count * K + slot * V;
+        self.map[offset..offset + V].try_into().expect("slice of V bytes")
+    }
+}
+
+/// Iterator over entries of an MmapBTree in key order, created by MmapBTree::iter and MmapBTree::range
+pub struct MmapRange<'a, const K: usize, const V: usize> {
+    tree: &'a MmapBTree<K, V>,
+    idx: Range<usize>,
+}
+
+impl<'a, const K: usize, const V: usize> Iterator for MmapRange<'a, K, V> {
+    type Item = (&'a [u8; K], &'a [u8; V]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.idx.next()?;
+        Some((self.tree.key(idx), self.tree.value(idx)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.idx.size_hint()
+    }
+}
+
+impl<const K: usize, const V: usize> DoubleEndedIterator for MmapRange<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let idx = self.idx.next_back()?;
+        Some((self.tree.key(idx), self.tree.value(idx)))
+    }
+}
+
+impl<const K: usize, const V: usize> ExactSizeIterator for MmapRange<'_, K, V> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    type Tree = MmapBTree<4, 8>;
+
+    /// Returns random entries of u32 keys (big-endian, so byte order is numeric order)
+    fn entries(n: usize, seed: u64) -> BTreeMap<[u8; 4], [u8; 8]> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        (0..n).map(|_| (rng.random_range(0..10 * n as u32 + 1).to_be_bytes(), rng.random::<u64>().to_le_bytes())).collect()
+    }
+
+    fn build(entries: &BTreeMap<[u8; 4], [u8; 8]>, node_keys: usize) -> (tempfile::TempDir, Tree) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        Tree::build(&path, node_keys, entries.iter().map(|(&k, &v)| (k, v))).unwrap();
+        let tree = Tree::open(&path).unwrap();
+        (dir, tree)
+    }
+
+    #[test]
+    fn test_layout() {
+        // 10 entries in nodes of 3: 4 leaves, a level of 2 nodes above them, and the root
+        let layout = Layout::new(10, 3, 4, 8).unwrap();
+        assert_eq!(layout.levels, [(HEADER + 120 + 16, 2), (HEADER + 120, 4)]);
+        assert_eq!(layout.file_len, HEADER + 120 + 16 + 8);
+        assert!(Layout::new(3, 3, 4, 8).unwrap().levels.is_empty());
+        assert_eq!(Layout::new(0, 3, 4, 8).unwrap().file_len, HEADER);
+        assert!(Layout::new(usize::MAX, 3, 4, 8).is_none());
+    }
+
+    #[test]
+    fn test_get_matches_btreemap() {
+        for (n, node_keys) in [(0, 4), (1, 2), (3, 3), (100, 2), (1000, 7), (5000, 64), (4096, 16)] {
+            let map = entries(n, n as u64);
+            let (_dir, tree) = build(&map, node_keys);
+            tree.validate().unwrap();
+            assert_eq!(tree.len(), map.len());
+            assert_eq!(tree.height() == 0, map.is_empty());
+            for key in 0..=10 * n as u32 + 2 {
+                let key = key.to_be_bytes();
+                assert_eq!(tree.get(&key), map.get(&key), "key {:?} of {} in nodes of {}", key, n, node_keys);
+            }
+            assert!(tree.iter().eq(map.iter()));
+            assert!(tree.iter().rev().eq(map.iter().rev()));
+        }
+    }
+
+    #[test]
+    fn test_ranges_match_btreemap() {
+        let map = entries(2000, 5);
+        let (_dir, mut tree) = build(&map, 5);
+        let mut rng = StdRng::seed_from_u64(6);
+        for search in [NodeSearch::Binary, NodeSearch::Linear, NodeSearch::Simd] {
+            tree.set_node_search(search);
+            for _ in 0..300 {
+                let a = rng.random_range(0..20_010u32).to_be_bytes();
+                let b = rng.random_range(0..20_010u32).to_be_bytes();
+                let (lo, hi) = if a <= b { (a, b) } else { (b, a) };
+                assert!(tree.range(lo..hi).eq(map.range(lo..hi)));
+                assert!(tree.range(lo..=hi).eq(map.range(lo..=hi)));
+                assert!(tree.range(lo..).eq(map.range(lo..)));
+                assert!(tree.range(..hi).eq(map.range(..hi)));
+                let bounds = (Bound::Excluded(lo), Bound::Included(hi));
+                assert!(tree.range(bounds).eq(map.range(bounds)));
+                assert_eq!(tree.range(lo..hi).len(), map.range(lo..hi).count());
+            }
+            // A range that ends before it starts is empty
+            assert_eq!(tree.range((Bound::Excluded([0, 0, 0, 5]), Bound::Excluded([0, 0, 0, 5]))).count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_zero_copy() {
+        let map = entries(100, 1);
+        let (_dir, tree) = build(&map, 8);
+        let (key, value) = tree.iter().nth(50).unwrap();
+        let mapped = tree.map.as_ptr_range();
+        assert!(mapped.contains(&key.as_ptr()) && mapped.contains(&value.as_ptr()));
+        assert!(mapped.contains(&tree.get(key).unwrap().as_ptr()));
+    }
+
+    #[test]
+    fn test_set_without_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("set");
+        MmapBTree::<8, 0>::build(&path, 3, (0..50u64).map(|val| ((val * 2).to_be_bytes(), []))).unwrap();
+        let set = MmapBTree::<8, 0>::open(&path).unwrap();
+        set.validate().unwrap();
+        assert!((0..100u64).all(|val| set.contains_key(&val.to_be_bytes()) == (val % 2 == 0)));
+    }
+
+    #[test]
+    fn test_rejects_unsorted_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        for keys in [[1u32, 3, 2], [1, 2, 2]] {
+            let err = Tree::build(&path, 4, keys.map(|key| (key.to_be_bytes(), [0; 8]))).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains("entry 2"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        let map = entries(500, 2);
+        let (dir, _tree) = build(&map, 6);
+        let path = dir.path().join("tree");
+        let bytes = std::fs::read(&path).unwrap();
+        let other = dir.path().join("other");
+        let open_error = |bytes: &[u8]| {
+            std::fs::write(&other, bytes).unwrap();
+            Tree::open(&other).err().expect("invalid file was opened")
+        };
+
+        assert!(open_error(&bytes[..bytes.len() - 1]).to_string().contains("header says"));
+        assert!(open_error(&bytes[..10]).to_string().contains("shorter than"));
+        let mut wrong = bytes.clone();
+        wrong[0] = b'X';
+        assert!(open_error(&wrong).to_string().contains("not an MmapBTree"));
+        assert!(MmapBTree::<8, 4>::open(&path).is_err());
+
+        // A swapped pair of keys still opens, but validate finds it
+        let mut swapped = bytes.clone();
+        let (first, second) = (HEADER, HEADER + 4);
+        let key: [u8; 4] = swapped[first..second].try_into().unwrap();
+        swapped.copy_within(second..second + 4, first);
+        swapped[second..second + 4].copy_from_slice(&key);
+        std::fs::write(&other, &swapped).unwrap();
+        assert!(Tree::open(&other).unwrap().validate().unwrap_err().contains("entry 1"));
+    }
+}
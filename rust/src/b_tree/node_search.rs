@@ -0,0 +1,285 @@
+// Strategies for searching the keys of a single node
+//
+// Every strategy returns the same thing as the original binary search: whether the value is one of
+// the keys, and its index if so, or else the index of the smallest key greater than it (the child to
+// descend into). They differ in how they get there:
+//
+// Binary: halves the range each step, with two data-dependent branches per step. Fewest comparisons,
+// but the branches are unpredictable, which dominates for small nodes.
+//
+// Linear: scans from the left until the first key that is not less than the value. One predictable
+// branch per key, so it wins for small nodes and loses as nodes grow.
+//
+// BranchlessBinary: a lower-bound binary search whose only branch is the loop (which depends on the
+// node size, not the keys), so the compiler turns the step into a conditional move.
+//
+// Simd: counts the keys less than the value (in sorted keys that count is the lower bound), a fixed
+// number of lanes at a time, stopping after the first chunk that is not entirely less than the value.
+// std::simd is nightly-only, so this is the portable fallback: for integer keys the compiler
+// vectorises the comparisons within a chunk (SSE2/AVX2/NEON), and for any other key type it is still
+// correct, just a scan with one branch per chunk.
+//
+// Threshold: Simd for nodes with at most the given number of keys, and Binary above that.
+// NodeSearch::for_key_type picks it for 4-byte keys. On x86-64 (baseline SSE2) the node_search
+// benchmark puts the crossover for u32 keys at around 16 keys per node. Without a 64-bit vector
+// compare, scanning u64 keys does not beat binary search at any node size, so 8-byte keys stay Binary.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::mem;
+
+use super::metrics::Counters;
+use super::BTree;
+
+/// Number of keys compared per chunk by NodeSearch::Simd
+const LANES: usize = 8;
+
+/// Largest node searched with NodeSearch::Simd by the Threshold that for_key_type picks
+const SCAN_MAX_KEYS: usize = 16;
+
+/// How keys are searched within a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeSearch {
+    /// Binary search with early exit on a match (the default)
+    Binary,
+    /// Left-to-right scan to the first key not less than the value
+    Linear,
+    /// Binary search for the lower bound without data-dependent branches
+    BranchlessBinary,
+    /// Count of the keys less than the value, in fixed-size chunks the compiler can vectorise
+    Simd,
+    /// Simd for nodes with at most this many keys, Binary for larger nodes
+    Threshold(usize),
+}
+
+impl NodeSearch {
+    /// Returns a strategy suited to the key type
+    ///
+    /// Keys of up to 4 bytes without drop glue (u32, i32, f32, char, ...) are compared several to a
+    /// vector register, so scanning small nodes beats binary search and they get Threshold.
+    /// Everything else (wider integers, or e.g. String, whose comparisons chase pointers) gets Binary.
+    pub fn for_key_type<T>() -> Self {
+        if mem::size_of::<T>() <= 4 && !mem::needs_drop::<T>() {
+            NodeSearch::Threshold(SCAN_MAX_KEYS)
+        } else {
+            NodeSearch::Binary
+        }
+    }
+
+    /// Searches sorted keys for value
+    ///
+    /// Returns true and the idx of value if found, otherwise false and the idx of the smallest key greater than value
+    pub fn search<T: PartialOrd>(self, keys: &[T], value: &T) -> (bool, usize) {
+        self.search_counted(keys, value, &Counters::new())
+    }
+
+    /// Same as search, counting the comparisons made
+    pub(super) fn search_counted<T: PartialOrd>(self, keys: &[T], value: &T, counters: &Counters) -> (bool, usize) {
+        match self {
+            NodeSearch::Binary => binary(keys, value, counters),
+            NodeSearch::Linear => found_at(keys, value, linear(keys, value, counters), counters),
+            NodeSearch::BranchlessBinary => found_at(keys, value, branchless_binary(keys, value, counters), counters),
+            NodeSearch::Simd => found_at(keys, value, simd(keys, value, counters), counters),
+            NodeSearch::Threshold(max) if keys.len() <= max => found_at(keys, value, simd(keys, value, counters), counters),
+            NodeSearch::Threshold(_) => binary(keys, value, counters),
+        }
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Sets how keys are searched within each node
+    pub fn set_node_search(&mut self, search: NodeSearch) {
+        self.node_search = search;
+    }
+
+    /// Returns how keys are searched within each node
+    pub fn node_search(&self) -> NodeSearch {
+        self.node_search
+    }
+}
+
+/// Checks whether the key at the lower bound idx is the value
+fn found_at<T: PartialOrd>(keys: &[T], value: &T, idx: usize, counters: &Counters) -> (bool, usize) {
+    if idx == keys.len() {
+        return (false, idx);
+    }
+    counters.comparison();
+    (keys[idx] == *value, idx)
+}
+
+/// Binary search with early exit
+fn binary<T: PartialOrd>(keys: &[T], value: &T, counters: &Counters) -> (bool, usize) {
+    let mut left = 0;
+    let mut right = keys.len();
+
+    // Range is [left, right) - left inclusive, right exclusive
+    while left < right {
+        let mid = left + (right - left) / 2;
+
+        counters.comparison();
+        if keys[mid] == *value {
+            return (true, mid);
+        }
+
+        counters.comparison();
+        if keys[mid] < *value {
+            left = mid + 1; // Search right half (exclusive of mid)
+        } else { // keys[mid] > value
+            right = mid; // Search left half (inclusive of mid -> mid could be idx of smallest value greater than value)
+        }
+    }
+
+    // If the value is not found, return false and idx of smallest value greater than value (insertion point)
+    (false, left)
+}
+
+/// Returns the idx of the first key not less than value, scanning from the left
+fn linear<T: PartialOrd>(keys: &[T], value: &T, counters: &Counters) -> usize {
+    for (i, key) in keys.iter().enumerate() {
+        counters.comparison();
+        if key.partial_cmp(value) != Some(Ordering::Less) {
+            return i;
+        }
+    }
+    keys.len()
+}
+
+/// Returns the idx of the first key not less than value, with a fixed number of steps for the node size
+fn branchless_binary<T: PartialOrd>(keys: &[T], value: &T, counters: &Counters) -> usize {
+    if keys.is_empty() {
+        return 0;
+    }
+
+    // base stays at the last key known to be less than value (or 0)
+    let mut base = 0;
+    let mut size = keys.len();
+    while size > 1 {
+        let half = size / 2;
+        counters.comparison();
+        base = if keys[base + half] < *value { base + half } else { base };
+        size -= half;
+    }
+    counters.comparison();
+    base + usize::from(keys[base] < *value)
+}
+
+/// Returns the number of keys less than value (the idx of the first key not less than value)
+///
+/// Keys are sorted, so the scan stops at the first chunk that is not entirely less than value
+fn simd<T: PartialOrd>(keys: &[T], value: &T, counters: &Counters) -> usize {
+    let mut count = 0;
+    let mut chunks = keys.chunks_exact(LANES);
+    for chunk in &mut chunks {
+        // A fixed-size array, so every lane is compared without branches (counted in u32 rather than
+        // usize, so narrow keys are not widened and more of them fit in a vector register)
+        let chunk: &[T; LANES] = chunk.try_into().expect("chunks_exact yields LANES keys");
+        let mut less: u32 = 0;
+        for key in chunk {
+            less += u32::from(*key < *value);
+        }
+        let less = less as usize;
+        count += less;
+        if less < LANES {
+            counters.comparisons(count - count % LANES + LANES);
+            return count;
+        }
+    }
+
+    let remainder = chunks.remainder();
+    count += remainder.iter().map(|key| usize::from(*key < *value)).sum::<usize>();
+    counters.comparisons(keys.len());
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    const STRATEGIES: [NodeSearch; 6] = [
+        NodeSearch::Binary,
+        NodeSearch::Linear,
+        NodeSearch::BranchlessBinary,
+        NodeSearch::Simd,
+        NodeSearch::Threshold(4),
+        NodeSearch::Threshold(64),
+    ];
+
+    /// The lower bound, and whether it is a match
+    fn expected(keys: &[u32], value: u32) -> (bool, usize) {
+        let idx = keys.partition_point(|&k| k < value);
+        (keys.get(idx) == Some(&value), idx)
+    }
+
+    #[test]
+    fn test_strategies_agree() {
+        let mut rng = StdRng::seed_from_u64(5);
+        for len in 0..=70 {
+            // Even keys, so odd values fall between them
+            let keys: Vec<u32> = (0..len).map(|i| 2 * i + 2).collect();
+            for value in 0..=(2 * len + 3) {
+                for strategy in STRATEGIES {
+                    assert_eq!(strategy.search(&keys, &value), expected(&keys, value), "{:?} len {} value {}", strategy, len, value);
+                }
+            }
+
+            // Random keys too
+            let mut keys: Vec<u32> = (0..len).map(|_| rng.random()).collect();
+            keys.sort();
+            keys.dedup();
+            let mut values = keys.clone();
+            values.extend((0..20).map(|_| rng.random::<u32>()));
+            values.shuffle(&mut rng);
+            for value in values {
+                for strategy in STRATEGIES {
+                    assert_eq!(strategy.search(&keys, &value), expected(&keys, value), "{:?}", strategy);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_strategies_with_non_copy_keys() {
+        let keys: Vec<String> = ["apple", "banana", "cherry", "date"].iter().map(|s| s.to_string()).collect();
+        for strategy in STRATEGIES {
+            assert_eq!(strategy.search(&keys, &"banana".to_string()), (true, 1));
+            assert_eq!(strategy.search(&keys, &"blueberry".to_string()), (false, 2));
+            assert_eq!(strategy.search(&keys, &"zucchini".to_string()), (false, 4));
+        }
+    }
+
+    #[test]
+    fn test_for_key_type() {
+        assert_eq!(NodeSearch::for_key_type::<u32>(), NodeSearch::Threshold(SCAN_MAX_KEYS));
+        assert_eq!(NodeSearch::for_key_type::<char>(), NodeSearch::Threshold(SCAN_MAX_KEYS));
+        assert_eq!(NodeSearch::for_key_type::<u64>(), NodeSearch::Binary);
+        assert_eq!(NodeSearch::for_key_type::<String>(), NodeSearch::Binary);
+        assert_eq!(NodeSearch::for_key_type::<u128>(), NodeSearch::Binary);
+    }
+
+    #[test]
+    fn test_btree_with_each_strategy() {
+        let mut values: Vec<u64> = (0..2000).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(9));
+
+        for order in [3, 8, 64] {
+            for strategy in STRATEGIES {
+                let mut btree = BTree::new(order);
+                btree.set_node_search(strategy);
+                assert_eq!(btree.node_search(), strategy);
+                for &val in &values {
+                    btree.insert(val);
+                }
+                for &val in &values[..1500] {
+                    btree.delete(val);
+                }
+                btree.check_invariants();
+                for &val in &values {
+                    assert_eq!(btree.search(val), !values[..1500].contains(&val));
+                }
+            }
+        }
+    }
+}
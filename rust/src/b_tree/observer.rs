@@ -0,0 +1,284 @@
+// Observer hooks for structural changes to a BTree
+//
+// The node helpers (split_child, merge, rotate_left/right) and the root handling in
+// BTree::insert/delete report each structural change through Hooks, which bumps the
+// metrics counters, forwards the event to the installed observer (if any), and records
+// a trace step if the operation is being traced.
+
+use std::fmt::Debug;
+
+use super::metrics::Counters;
+use super::node_search::NodeSearch;
+use super::trace::{DeleteCase, TraceAction, TraceStep};
+use super::{BTree, Node};
+
+/// Callbacks for structural changes to a BTree, fired as they happen
+///
+/// level is the depth of the node(s) being changed, counted from the root (depth 0) at the time
+/// of the callback. When the root splits, on_root_grow fires first and the old root is reported
+/// as splitting at level 1.
+///
+/// All methods have empty default implementations, so observers only implement what they need.
+pub trait BTreeObserver<T>: Send + Sync {
+    /// A full node at level was split in two, and median moved up into its parent
+    fn on_split(&mut self, _level: usize, _median: &T) {}
+
+    /// Two sibling nodes at level were merged, pulling separator down from their parent
+    fn on_merge(&mut self, _level: usize, _separator: &T) {}
+
+    /// A key moved from a right sibling at level through the parent into the left sibling,
+    /// and separator is the key that replaced it in the parent
+    fn on_rotate_left(&mut self, _level: usize, _separator: &T) {}
+
+    /// A key moved from a left sibling at level through the parent into the right sibling,
+    /// and separator is the key that replaced it in the parent
+    fn on_rotate_right(&mut self, _level: usize, _separator: &T) {}
+
+    /// A new root was created above the old one (the tree grew a level)
+    fn on_root_grow(&mut self) {}
+
+    /// An empty root was replaced by its only child (the tree lost a level)
+    fn on_root_shrink(&mut self) {}
+}
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Installs an observer that is notified of every structural change, replacing any previous one
+    pub fn set_observer<O: BTreeObserver<T> + 'static>(&mut self, observer: O) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Removes and returns the installed observer
+    pub fn take_observer(&mut self) -> Option<Box<dyn BTreeObserver<T>>> {
+        self.observer.take()
+    }
+}
+
+/// Where the node helpers report what they do: the metrics counters, the optional observer,
+/// and the step list when the operation is being traced (see insert_traced/delete_traced)
+///
+/// Also carries the tree's in-node search strategy down to the node helpers
+pub(super) struct Hooks<'a, T: PartialOrd + Debug + Clone> {
+    pub(super) counters: &'a Counters,
+    pub(super) node_search: NodeSearch,
+    observer: Option<&'a mut dyn BTreeObserver<T>>,
+    trace: Option<&'a mut Vec<TraceStep<T>>>,
+}
+
+impl<'a, T: PartialOrd + Debug + Clone> Hooks<'a, T> {
+    pub(super) fn new(
+        counters: &'a Counters,
+        node_search: NodeSearch,
+        observer: &'a mut Option<Box<dyn BTreeObserver<T>>>,
+        trace: Option<&'a mut Vec<TraceStep<T>>>,
+    ) -> Self {
+        let observer = observer.as_deref_mut().map(|o| o as &mut dyn BTreeObserver<T>);
+        Hooks { counters, node_search, observer, trace }
+    }
+
+    /// Records a trace step (snapshotting node only if the operation is being traced)
+    fn record(&mut self, level: usize, action: impl FnOnce() -> TraceAction<T>, node: &Node<T>) {
+        if let Some(trace) = self.trace.as_deref_mut() {
+            trace.push(TraceStep { level, action: action(), snapshot: node.snapshot() });
+        }
+    }
+
+    pub(super) fn visit(&mut self, level: usize, node: &Node<T>) {
+        self.record(level, || TraceAction::Visit, node);
+    }
+
+    pub(super) fn insert_key(&mut self, level: usize, index: usize, node: &Node<T>) {
+        self.record(level, || TraceAction::InsertKey { index }, node);
+    }
+
+    pub(super) fn delete_case(&mut self, level: usize, case: DeleteCase, node: &Node<T>) {
+        self.record(level, || TraceAction::DeleteCase { case }, node);
+    }
+
+    pub(super) fn split(&mut self, level: usize, median: &T, parent: &Node<T>) {
+        self.counters.allocation();
+        self.counters.split();
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.on_split(level, median);
+        }
+        self.record(level, || TraceAction::Split { median: median.clone() }, parent);
+    }
+
+    pub(super) fn merge(&mut self, level: usize, separator: &T, parent: &Node<T>) {
+        self.counters.merge();
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.on_merge(level, separator);
+        }
+        self.record(level, || TraceAction::Merge { separator: separator.clone() }, parent);
+    }
+
+    pub(super) fn rotate_left(&mut self, level: usize, separator: &T, parent: &Node<T>) {
+        self.counters.rotate_left();
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.on_rotate_left(level, separator);
+        }
+        self.record(level, || TraceAction::RotateLeft { separator: separator.clone() }, parent);
+    }
+
+    pub(super) fn rotate_right(&mut self, level: usize, separator: &T, parent: &Node<T>) {
+        self.counters.rotate_right();
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.on_rotate_right(level, separator);
+        }
+        self.record(level, || TraceAction::RotateRight { separator: separator.clone() }, parent);
+    }
+
+    pub(super) fn root_grow(&mut self, new_root: &Node<T>) {
+        self.counters.allocation();
+        self.counters.root_grow();
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.on_root_grow();
+        }
+        self.record(0, || TraceAction::RootGrow, new_root);
+    }
+
+    pub(super) fn root_shrink(&mut self, new_root: &Node<T>) {
+        self.counters.root_shrink();
+        if let Some(observer) = self.observer.as_deref_mut() {
+            observer.on_root_shrink();
+        }
+        self.record(0, || TraceAction::RootShrink, new_root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records every event as a string so tests can check the exact sequence
+    struct Recorder {
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl BTreeObserver<i32> for Recorder {
+        fn on_split(&mut self, level: usize, median: &i32) {
+            self.events.lock().unwrap().push(format!("split({}, {})", level, median));
+        }
+
+        fn on_merge(&mut self, level: usize, separator: &i32) {
+            self.events.lock().unwrap().push(format!("merge({}, {})", level, separator));
+        }
+
+        fn on_rotate_left(&mut self, level: usize, separator: &i32) {
+            self.events.lock().unwrap().push(format!("rotate_left({}, {})", level, separator));
+        }
+
+        fn on_rotate_right(&mut self, level: usize, separator: &i32) {
+            self.events.lock().unwrap().push(format!("rotate_right({}, {})", level, separator));
+        }
+
+        fn on_root_grow(&mut self) {
+            self.events.lock().unwrap().push("root_grow".to_string());
+        }
+
+        fn on_root_shrink(&mut self) {
+            self.events.lock().unwrap().push("root_shrink".to_string());
+        }
+    }
+
+    fn recorded_tree(order: usize) -> (BTree<i32>, Arc<Mutex<Vec<String>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut btree = BTree::new(order);
+        btree.set_observer(Recorder { events: Arc::clone(&events) });
+        (btree, events)
+    }
+
+    #[test]
+    fn test_observer_root_split() {
+        let (mut btree, events) = recorded_tree(5);
+        for i in 1..=5 {
+            btree.insert(i);
+        }
+
+        assert_eq!(*events.lock().unwrap(), vec!["root_grow", "split(1, 3)"]);
+    }
+
+    #[test]
+    fn test_observer_child_split_level() {
+        let (mut btree, events) = recorded_tree(3);
+        for i in 1..=5 {
+            btree.insert(i);
+        }
+
+        // [1,2,3] splits the root, then [3,4,5] splits as a child of the new root
+        assert_eq!(*events.lock().unwrap(), vec!["root_grow", "split(1, 2)", "split(1, 4)"]);
+    }
+
+    #[test]
+    fn test_observer_merge_and_root_shrink() {
+        let (mut btree, events) = recorded_tree(3);
+        for i in 1..=3 {
+            btree.insert(i);
+        }
+        events.lock().unwrap().clear();
+
+        btree.delete(1);
+
+        assert_eq!(*events.lock().unwrap(), vec!["merge(1, 2)", "root_shrink"]);
+    }
+
+    #[test]
+    fn test_observer_rotations() {
+        let (mut btree, events) = recorded_tree(5);
+        for i in 1..=6 {
+            btree.insert(i);
+        }
+        // Root [3] with leaves [1, 2] and [4, 5, 6]
+        events.lock().unwrap().clear();
+
+        btree.delete(1);
+        assert_eq!(*events.lock().unwrap(), vec!["rotate_left(1, 4)"]);
+
+        let (mut btree, events) = recorded_tree(5);
+        for i in [1, 2, 3, 4, 5, 0] {
+            btree.insert(i);
+        }
+        // Root [3] with leaves [0, 1, 2] and [4, 5]
+        events.lock().unwrap().clear();
+
+        btree.delete(5);
+        assert_eq!(*events.lock().unwrap(), vec!["rotate_right(1, 2)"]);
+    }
+
+    #[test]
+    fn test_take_observer_stops_events() {
+        let (mut btree, events) = recorded_tree(3);
+        btree.insert(1);
+        btree.insert(2);
+
+        assert!(btree.take_observer().is_some());
+        assert!(btree.take_observer().is_none());
+
+        btree.insert(3);
+        assert!(events.lock().unwrap().is_empty());
+        assert!(btree.search(3));
+    }
+
+    #[test]
+    fn test_default_observer_methods() {
+        struct SplitsOnly(Arc<Mutex<usize>>);
+
+        impl BTreeObserver<i32> for SplitsOnly {
+            fn on_split(&mut self, _level: usize, _median: &i32) {
+                *self.0.lock().unwrap() += 1;
+            }
+        }
+
+        let splits = Arc::new(Mutex::new(0));
+        let mut btree = BTree::new(3);
+        btree.set_observer(SplitsOnly(Arc::clone(&splits)));
+        for i in 1..=20 {
+            btree.insert(i);
+        }
+        for i in 1..=20 {
+            btree.delete(i);
+        }
+
+        assert!(*splits.lock().unwrap() > 0);
+    }
+}
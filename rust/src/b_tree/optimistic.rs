@@ -0,0 +1,652 @@
+// Optimistic lock coupling (https://db.in.tum.de/~leis/papers/artsync.pdf, "The ART of Practical
+// Synchronization", Leis et al.)
+//
+// Every node has a version word instead of a latch. Bit 1 is set while a writer holds the node, and
+// unlocking adds another 2, so the version changes every time the node is written. A reader never
+// writes to shared memory: it loads the version (waiting while the node is locked), reads what it needs
+// from the node, and then checks the version is unchanged. If it changed, a writer got in, so what was
+// read may be inconsistent and the operation restarts from the root. Going down the tree, the child's
+// version is read before the parent's version is checked again, which is the optimistic version of
+// lock coupling: the child was reached through a parent that was consistent at the time.
+//
+// Writers descend the same way, then upgrade to an exclusive lock with a compare-exchange on the
+// version they read (restarting if that fails). Full nodes are split on the way down (like
+// InsertStrategy::TopDown), with the parent and the full node locked, so the parent always has room
+// for the separator and nothing above it has to change.
+//
+// A node can be read while it is being written, so everything in it is an atomic: the key count, the
+// keys (stored as u64 bits, see AtomicKey) and the child pointers. Relaxed loads are enough, since the
+// version check (after an acquire fence) decides whether they can be used, as with a seqlock. Nodes are
+// only freed when the tree is dropped, so a reader can always follow a child pointer it read, even
+// from a node that has changed since.
+//
+// Keys are only stored in the leaves (as in a B+ tree). Full leaves split into floor(K/2) keys and the
+// rest, with the first key of the right half copied up as the separator, and internal nodes split
+// around their median like Node::split_child. remove takes keys out of their leaf without merging
+// (so nodes may be left under-full), since merging would need nodes to be freed while readers may
+// still be in them.
+
+use std::fmt::Debug;
+use std::hint;
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+
+/// Set in a node's version while a writer holds it
+const LOCKED: u64 = 0b10;
+
+/// A key type that fits in (and can be read racily as) a u64
+///
+/// from_bits(to_bits(key)) must give the key back
+pub trait AtomicKey: Copy + PartialOrd + Debug {
+    fn to_bits(self) -> u64;
+    fn from_bits(bits: u64) -> Self;
+}
+
+macro_rules! impl_atomic_key {
+    ($($t:ty),*) => {
+        $(
+            impl AtomicKey for $t {
+                fn to_bits(self) -> u64 {
+                    self as u64
+                }
+
+                fn from_bits(bits: u64) -> Self {
+                    bits as $t
+                }
+            }
+        )*
+    };
+}
+
+impl_atomic_key!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+impl AtomicKey for char {
+    fn to_bits(self) -> u64 {
+        u64::from(self)
+    }
+
+    fn from_bits(bits: u64) -> Self {
+        char::from_u32(bits as u32).unwrap_or_default()
+    }
+}
+
+/// A B+ tree whose readers validate node versions instead of taking latches
+pub struct OptimisticBTree<K: AtomicKey> {
+    root: AtomicPtr<Node>,
+    order: usize,
+    len: AtomicUsize,
+    _marker: PhantomData<K>,
+}
+
+struct Node {
+    version: AtomicU64,
+    leaf: bool,
+    count: AtomicUsize,
+    /// Keys in a leaf, separators in an internal node (children[i] holds keys below keys[i])
+    keys: Box<[AtomicU64]>,
+    children: Box<[AtomicPtr<Node>]>,
+}
+
+impl<K: AtomicKey> OptimisticBTree<K> {
+    /// Constructor method for OptimisticBTree
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    pub fn new(m: usize) -> Self {
+        assert!(m >= 3, "BTree order must be at least 3");
+        let root = Box::into_raw(Node::new(m, true));
+        OptimisticBTree { root: AtomicPtr::new(root), order: m, len: AtomicUsize::new(0), _marker: PhantomData }
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the number of keys in the tree
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if the tree holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Search method for OptimisticBTree
+    ///
+    /// Returns true if value is present, false otherwise
+    pub fn search(&self, value: K) -> bool {
+        'restart: loop {
+            let (leaf, version) = match self.find_leaf(value) {
+                Some(found) => found,
+                None => continue 'restart,
+            };
+            let (found, _) = leaf.search::<K>(value);
+            if leaf.validate(version) {
+                return found;
+            }
+        }
+    }
+
+    /// Inserts a value into the b-tree
+    ///
+    /// Returns false (leaving the tree unchanged) if value is already present
+    pub fn insert(&self, value: K) -> bool {
+        'restart: loop {
+            let mut node = self.root();
+            let Some(mut version) = node.read_lock() else { continue 'restart };
+            if !ptr::eq(node, self.root.load(Ordering::Acquire)) {
+                continue 'restart;
+            }
+            let mut parent: Option<(&Node, u64)> = None;
+
+            loop {
+                if node.is_full(self.order) {
+                    // Split eagerly with the parent (which has room) and the full node locked
+                    if let Some((p, p_version)) = parent
+                        && !p.upgrade(p_version) {
+                        continue 'restart;
+                    }
+                    if !node.upgrade(version) {
+                        if let Some((p, _)) = parent {
+                            p.unlock();
+                        }
+                        continue 'restart;
+                    }
+                    // Check the root has not changed between the read and the upgrade
+                    if parent.is_none() && !ptr::eq(node, self.root.load(Ordering::Acquire)) {
+                        node.unlock();
+                        continue 'restart;
+                    }
+
+                    let (separator, right) = node.split(self.order);
+                    match parent {
+                        Some((p, _)) => {
+                            p.insert_child::<K>(separator, right);
+                            p.unlock();
+                        },
+                        None => {
+                            let new_root = Node::new(self.order, false);
+                            new_root.keys[0].store(separator, Ordering::Relaxed);
+                            new_root.children[0].store(ptr::from_ref(node).cast_mut(), Ordering::Relaxed);
+                            new_root.children[1].store(right, Ordering::Relaxed);
+                            new_root.count.store(1, Ordering::Relaxed);
+                            self.root.store(Box::into_raw(new_root), Ordering::Release);
+                        },
+                    }
+                    node.unlock();
+                    continue 'restart;
+                }
+
+                if node.leaf {
+                    if !node.upgrade(version) {
+                        continue 'restart;
+                    }
+                    let (found, idx) = node.search::<K>(value);
+                    if !found {
+                        node.insert_key(idx, value.to_bits());
+                        self.len.fetch_add(1, Ordering::Relaxed);
+                    }
+                    node.unlock();
+                    return !found;
+                }
+
+                // Read the child, then check this node was consistent before going down
+                let child = node.child(node.child_idx::<K>(value));
+                if !node.validate(version) {
+                    continue 'restart;
+                }
+                let Some(child) = child else { continue 'restart };
+                let Some(child_version) = child.read_lock() else { continue 'restart };
+                if !node.validate(version) {
+                    continue 'restart;
+                }
+                parent = Some((node, version));
+                node = child;
+                version = child_version;
+            }
+        }
+    }
+
+    /// Removes a value from its leaf (without merging under-full nodes)
+    ///
+    /// Returns false (leaving the tree unchanged) if value is not present
+    pub fn remove(&self, value: K) -> bool {
+        'restart: loop {
+            let (leaf, version) = match self.find_leaf(value) {
+                Some(found) => found,
+                None => continue 'restart,
+            };
+            let (found, idx) = leaf.search::<K>(value);
+            if !found {
+                if leaf.validate(version) {
+                    return false;
+                }
+                continue 'restart;
+            }
+            if !leaf.upgrade(version) {
+                continue 'restart;
+            }
+            leaf.remove_key(idx);
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            leaf.unlock();
+            return true;
+        }
+    }
+
+    fn root(&self) -> &Node {
+        // SAFETY: the root is always a valid node, and nodes are only freed when the tree is dropped
+        unsafe { &*self.root.load(Ordering::Acquire) }
+    }
+
+    /// Descends to the leaf value belongs in, validating every node on the way
+    ///
+    /// Returns the leaf and the version it was read at, or None if the descent has to restart
+    fn find_leaf(&self, value: K) -> Option<(&Node, u64)> {
+        let mut node = self.root();
+        let mut version = node.read_lock()?;
+        if !ptr::eq(node, self.root.load(Ordering::Acquire)) {
+            return None;
+        }
+
+        while !node.leaf {
+            let child = node.child(node.child_idx::<K>(value));
+            if !node.validate(version) {
+                return None;
+            }
+            let child = child?;
+            let child_version = child.read_lock()?;
+            if !node.validate(version) {
+                return None;
+            }
+            node = child;
+            version = child_version;
+        }
+        Some((node, version))
+    }
+
+    /// Helper (test) function that returns all keys in order (the tree must not be changing)
+    #[cfg(test)]
+    fn to_vec(&self) -> Vec<K> {
+        fn walk<K: AtomicKey>(node: &Node, keys: &mut Vec<K>) {
+            let count = node.count.load(Ordering::Relaxed);
+            if node.leaf {
+                keys.extend(node.keys[..count].iter().map(|k| K::from_bits(k.load(Ordering::Relaxed))));
+            } else {
+                for i in 0..=count {
+                    walk(node.child(i).expect("Internal node missing child"), keys);
+                }
+            }
+        }
+        let mut keys = vec![];
+        walk(self.root(), &mut keys);
+        keys
+    }
+
+    /// Helper (test) function that checks no node is locked or overfull, keys are in order within
+    /// their separators, and every leaf is at the same depth (the tree must not be changing)
+    #[cfg(test)]
+    fn check_invariants(&self) {
+        fn walk<K: AtomicKey>(node: &Node, lower: Option<K>, upper: Option<K>, depth: usize, leaf_depth: &mut Option<usize>) {
+            assert_eq!(node.version.load(Ordering::Relaxed) & LOCKED, 0, "Node left locked");
+            let count = node.count.load(Ordering::Relaxed);
+            let keys: Vec<K> = node.keys[..count].iter().map(|k| K::from_bits(k.load(Ordering::Relaxed))).collect();
+            assert!(keys.windows(2).all(|w| w[0] < w[1]), "Keys out of order: {:?}", keys);
+            assert!(keys.iter().all(|k| lower.is_none_or(|l| *k >= l) && upper.is_none_or(|u| *k < u)), "Keys outside {:?}..{:?}: {:?}", lower, upper, keys);
+            if node.leaf {
+                assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+                return;
+            }
+            for i in 0..=count {
+                let lower = if i == 0 { lower } else { Some(keys[i - 1]) };
+                let upper = if i == count { upper } else { Some(keys[i]) };
+                walk(node.child(i).expect("Internal node missing child"), lower, upper, depth + 1, leaf_depth);
+            }
+        }
+        walk::<K>(self.root(), None, None, 0, &mut None);
+        assert_eq!(self.to_vec().len(), self.len());
+    }
+}
+
+impl<K: AtomicKey> Drop for OptimisticBTree<K> {
+    fn drop(&mut self) {
+        fn free(node: *mut Node) {
+            // SAFETY: every node is reachable from the root exactly once, and nothing else can be
+            // using the tree while it is dropped
+            let node = unsafe { Box::from_raw(node) };
+            if !node.leaf {
+                for i in 0..=node.count.load(Ordering::Relaxed) {
+                    free(node.children[i].load(Ordering::Relaxed));
+                }
+            }
+        }
+        free(*self.root.get_mut());
+    }
+}
+
+impl Node {
+    /// Returns a new empty node with room for the m-1 keys (and m children) of a full node
+    fn new(order: usize, leaf: bool) -> Box<Node> {
+        Box::new(Node {
+            version: AtomicU64::new(0),
+            leaf,
+            count: AtomicUsize::new(0),
+            keys: (0..order - 1).map(|_| AtomicU64::new(0)).collect(),
+            children: if leaf { Box::new([]) } else { (0..order).map(|_| AtomicPtr::new(ptr::null_mut())).collect() },
+        })
+    }
+
+    /// Returns the version of an unlocked node, waiting briefly while it is locked
+    ///
+    /// Returns None if it stayed locked, so the operation should restart
+    fn read_lock(&self) -> Option<u64> {
+        for _ in 0..64 {
+            let version = self.version.load(Ordering::Acquire);
+            if version & LOCKED == 0 {
+                return Some(version);
+            }
+            hint::spin_loop();
+        }
+        std::thread::yield_now();
+        None
+    }
+
+    /// Returns true if the node has not been written since version was read
+    fn validate(&self, version: u64) -> bool {
+        // Order the (relaxed) reads of the node before the version check
+        fence(Ordering::Acquire);
+        self.version.load(Ordering::Relaxed) == version
+    }
+
+    /// Locks the node for writing, if it has not been written since version was read
+    fn upgrade(&self, version: u64) -> bool {
+        if self.version.compare_exchange(version, version + LOCKED, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            return false;
+        }
+        // Order the locked version before the writes to the node (as for the writer of a seqlock)
+        fence(Ordering::Release);
+        true
+    }
+
+    /// Unlocks a node locked with upgrade, giving it a new version
+    fn unlock(&self) {
+        self.version.fetch_add(LOCKED, Ordering::Release);
+    }
+
+    /// Returns the number of keys, capped at capacity (a racing writer can only make it wrong, not out of bounds)
+    fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed).min(self.keys.len())
+    }
+
+    fn key<K: AtomicKey>(&self, idx: usize) -> K {
+        K::from_bits(self.keys[idx].load(Ordering::Relaxed))
+    }
+
+    /// Returns the child at idx, or None if the slot has not been filled (only possible while a writer
+    /// is changing the node, which the caller's validation then catches)
+    fn child(&self, idx: usize) -> Option<&Node> {
+        let child = self.children[idx].load(Ordering::Relaxed);
+        // SAFETY: child slots only ever hold null or pointers to live nodes, since nodes are only freed
+        // when the tree is dropped
+        unsafe { child.as_ref() }
+    }
+
+    fn is_full(&self, order: usize) -> bool {
+        self.count() == order - 1
+    }
+
+    /// Binary search of the keys
+    ///
+    /// Returns true and the idx of value if found, otherwise false and the idx of the smallest key greater than value
+    fn search<K: AtomicKey>(&self, value: K) -> (bool, usize) {
+        let (mut left, mut right) = (0, self.count());
+        while left < right {
+            let mid = left + (right - left) / 2;
+            let key = self.key::<K>(mid);
+            if key == value {
+                return (true, mid);
+            }
+            if key < value {
+                left = mid + 1;
+            } else {
+                right = mid;
+            }
+        }
+        (false, left)
+    }
+
+    /// Returns the index of the child value belongs in (the number of separators at or below it)
+    fn child_idx<K: AtomicKey>(&self, value: K) -> usize {
+        match self.search(value) {
+            (true, idx) => idx + 1,
+            (false, idx) => idx,
+        }
+    }
+
+    /// Inserts a key at idx (the node must be locked and not full)
+    fn insert_key(&self, idx: usize, key: u64) {
+        let count = self.count();
+        for i in (idx..count).rev() {
+            self.keys[i + 1].store(self.keys[i].load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        self.keys[idx].store(key, Ordering::Relaxed);
+        self.count.store(count + 1, Ordering::Relaxed);
+    }
+
+    /// Removes the key at idx (the node must be a locked leaf)
+    fn remove_key(&self, idx: usize) {
+        let count = self.count();
+        for i in idx + 1..count {
+            self.keys[i - 1].store(self.keys[i].load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        self.count.store(count - 1, Ordering::Relaxed);
+    }
+
+    /// Inserts a separator and the child to its right (the node must be locked and not full)
+    fn insert_child<K: AtomicKey>(&self, separator: u64, child: *mut Node) {
+        let count = self.count();
+        // Compare as keys, since the bits of signed keys are not in order
+        let (_, idx) = self.search(K::from_bits(separator));
+        for i in (idx + 1..=count).rev() {
+            self.children[i + 1].store(self.children[i].load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        self.children[idx + 1].store(child, Ordering::Relaxed);
+        self.insert_key(idx, separator);
+    }
+
+    /// Splits this full (locked) node, moving its upper half into a new right sibling
+    ///
+    /// Returns the separator and the new sibling
+    fn split(&self, order: usize) -> (u64, *mut Node) {
+        let count = self.count();
+        let mid = count / 2;
+        let right = Node::new(order, self.leaf);
+
+        let (separator, first) = if self.leaf {
+            // Leaves keep every key, so the first key of the right half is copied up
+            (self.keys[mid].load(Ordering::Relaxed), mid)
+        } else {
+            // Same as Node::split_child: the median moves up, and the right half gets the keys after it
+            for i in mid + 1..=count {
+                right.children[i - mid - 1].store(self.children[i].load(Ordering::Relaxed), Ordering::Relaxed);
+            }
+            (self.keys[mid].load(Ordering::Relaxed), mid + 1)
+        };
+        for i in first..count {
+            right.keys[i - first].store(self.keys[i].load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        right.count.store(count - first, Ordering::Relaxed);
+        self.count.store(mid, Ordering::Relaxed);
+        (separator, Box::into_raw(right))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+    use std::thread;
+
+    #[test]
+    fn test_against_btreeset() {
+        for order in 3..=10 {
+            let mut rng = StdRng::seed_from_u64(order as u64);
+            let btree = OptimisticBTree::new(order);
+            let mut set = BTreeSet::new();
+            for _ in 0..3000 {
+                let val: i32 = rng.random_range(-500..500);
+                if rng.random_bool(0.7) {
+                    assert_eq!(btree.insert(val), set.insert(val));
+                } else {
+                    assert_eq!(btree.remove(val), set.remove(&val));
+                }
+            }
+            btree.check_invariants();
+            assert_eq!(btree.len(), set.len());
+            assert_eq!(btree.to_vec(), set.iter().copied().collect::<Vec<_>>());
+            for val in -500..500 {
+                assert_eq!(btree.search(val), set.contains(&val));
+            }
+        }
+    }
+
+    #[test]
+    fn test_key_types() {
+        let btree = OptimisticBTree::new(4);
+        for c in "the quick brown fox".chars() {
+            btree.insert(c);
+        }
+        assert!(btree.search('q'));
+        assert!(!btree.search('z'));
+        assert_eq!(btree.to_vec().into_iter().collect::<String>(), " bcefhiknoqrtuwx");
+
+        let btree = OptimisticBTree::new(4);
+        for val in [u64::MAX, 0, 1 << 63, 7] {
+            btree.insert(val);
+        }
+        assert_eq!(btree.to_vec(), vec![0, 7, 1 << 63, u64::MAX]);
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let btree: OptimisticBTree<u32> = OptimisticBTree::new(3);
+        assert!(btree.is_empty());
+        assert!(!btree.search(1));
+        assert!(!btree.remove(1));
+        assert!(btree.insert(1));
+        assert!(!btree.insert(1));
+        assert!(btree.remove(1));
+        assert!(btree.is_empty());
+        btree.check_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "BTree order must be at least 3")]
+    fn test_invalid_order() {
+        let _btree: OptimisticBTree<u32> = OptimisticBTree::new(2);
+    }
+
+    #[test]
+    fn test_versions() {
+        let node = Node::new(4, true);
+        let version = node.read_lock().unwrap();
+        assert!(node.validate(version));
+
+        // A locked node cannot be read, and a second writer cannot upgrade
+        assert!(node.upgrade(version));
+        assert!(node.read_lock().is_none());
+        assert!(!node.upgrade(version));
+        node.unlock();
+
+        // Unlocking gives a new version, so older reads fail validation
+        let new_version = node.read_lock().unwrap();
+        assert_ne!(new_version, version);
+        assert!(!node.validate(version));
+        assert!(!node.upgrade(version));
+        assert!(node.validate(new_version));
+    }
+
+    #[test]
+    fn test_readers_do_not_write() {
+        let btree = OptimisticBTree::new(8);
+        for val in 0..1000u64 {
+            btree.insert(val);
+        }
+        let versions = |btree: &OptimisticBTree<u64>| {
+            let mut versions = vec![];
+            let mut stack = vec![btree.root()];
+            while let Some(node) = stack.pop() {
+                versions.push(node.version.load(Ordering::Relaxed));
+                if !node.leaf {
+                    stack.extend((0..=node.count()).map(|i| node.child(i).unwrap()));
+                }
+            }
+            versions
+        };
+        let before = versions(&btree);
+        for val in 0..2000 {
+            assert_eq!(btree.search(val), val < 1000);
+        }
+        assert_eq!(versions(&btree), before);
+    }
+
+    #[test]
+    fn test_stress() {
+        // Writers insert and remove the keys they own (their id mod THREADS) while readers check that
+        // keys inserted before they started, and never removed, are always found
+        const THREADS: u64 = 6;
+        const KEYS: u64 = 3000;
+        for order in [3, 4, 8, 32] {
+            let btree = OptimisticBTree::new(order);
+            for val in (0..KEYS).step_by(2) {
+                btree.insert(val);
+            }
+
+            let expected: Vec<BTreeSet<u64>> = thread::scope(|s| {
+                for t in 0..3 {
+                    let btree = &btree;
+                    s.spawn(move || {
+                        let mut rng = StdRng::seed_from_u64(100 + t);
+                        for _ in 0..20_000 {
+                            // No writer owns the multiples of THREADS, which are even, so were inserted up front
+                            let val = rng.random_range(0..KEYS / THREADS) * THREADS;
+                            assert!(btree.search(val), "{} missing", val);
+                        }
+                    });
+                }
+
+                let writers: Vec<_> = (1..THREADS)
+                    .map(|t| {
+                        let btree = &btree;
+                        s.spawn(move || {
+                            let mut rng = StdRng::seed_from_u64(t);
+                            let mut set: BTreeSet<u64> = (0..KEYS).filter(|v| v % THREADS == t && v % 2 == 0).collect();
+                            let mut keys: Vec<u64> = (0..KEYS).filter(|v| v % THREADS == t).collect();
+                            for _ in 0..4 {
+                                keys.shuffle(&mut rng);
+                                for &val in &keys {
+                                    if rng.random_bool(0.6) {
+                                        assert_eq!(btree.insert(val), set.insert(val));
+                                    } else {
+                                        assert_eq!(btree.remove(val), set.remove(&val));
+                                    }
+                                }
+                            }
+                            set
+                        })
+                    })
+                    .collect();
+                writers.into_iter().map(|w| w.join().unwrap()).collect()
+            });
+
+            btree.check_invariants();
+            let mut all: Vec<u64> = expected.into_iter().flatten().chain((0..KEYS).step_by(THREADS as usize)).collect();
+            all.sort();
+            assert_eq!(btree.to_vec(), all);
+        }
+    }
+}
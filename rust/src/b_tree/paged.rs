@@ -0,0 +1,1314 @@
+// Disk-backed B-tree whose nodes are fixed-size pages of a file (see paged/page.rs for the layout)
+//
+// Keys and values are byte strings, turned into and out of bytes by an Encoding, and keys are ordered
+// by their bytes (so an Encoding of keys must preserve the order of the items). Every node lives in a
+// page and is only reached through the buffer pool (see paged/buffer_pool.rs), so the tree can be far
+// bigger than the memory it uses: a node is read into a frame when it is visited, decoded, and written
+// back when it is changed. The order is derived from the page size and the max key and value lengths,
+// so that a full node of the longest keys and values fits in a page.
+//
+// Inserts and deletes are the same as BTree's with InsertStrategy::TopDown (full nodes are split on the
+// way down, and deletes rotate or merge on the way down, with the same cases), so the order is always
+// even, and a PagedBTree has exactly the nodes a BTree of the same order would have after the same
+// operations. The differences are that the keys map to values, so inserting a key that is already
+// there replaces its value, and that removing a key that is not there returns None instead of
+// panicking. Pages of merged nodes are put on a free list and reused before the file grows.
+//
+// Changed pages are only written when they are evicted from the pool or flushed (with the meta page,
+// which holds the root, the free list and the length), so without a write-ahead log a file is only
+// consistent after flush, which dropping the tree also does. With one (see PagedOptions::wal), each
+// insert and remove is a commit whose changed pages are logged before they go into the pool, and
+// opening the tree replays the log, so it survives a crash at any point (see paged/wal.rs).
+
+mod buffer_pool;
+mod page;
+mod wal;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use buffer_pool::BufferPool;
+use page::{Entry, Meta, PageId, PageNode, invalid};
+use wal::Wal;
+
+pub use buffer_pool::PoolStats;
+pub use wal::WalSync;
+
+/// Turns keys or values of a PagedBTree into bytes and back
+///
+/// Keys are ordered by their encoded bytes, so an encoding of keys must keep a < b if and only if
+/// encode(a) < encode(b)
+pub trait Encoding {
+    type Item;
+
+    /// Appends the bytes of item to out
+    fn encode(item: &Self::Item, out: &mut Vec<u8>);
+
+    /// Reads an item from its bytes, failing with InvalidData if they are not an encoded item
+    fn decode(bytes: &[u8]) -> io::Result<Self::Item>;
+}
+
+/// Byte strings as they are
+pub struct Bytes;
+
+impl Encoding for Bytes {
+    type Item = Vec<u8>;
+
+    fn encode(item: &Vec<u8>, out: &mut Vec<u8>) {
+        out.extend_from_slice(item);
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// Strings as their UTF-8 bytes (whose order is the order of the strings)
+pub struct Utf8;
+
+impl Encoding for Utf8 {
+    type Item = String;
+
+    fn encode(item: &String, out: &mut Vec<u8>) {
+        out.extend_from_slice(item.as_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> io::Result<String> {
+        String::from_utf8(bytes.to_vec()).map_err(invalid)
+    }
+}
+
+/// Integers as their big-endian bytes, with the sign bit of signed integers flipped so negative numbers
+/// come first
+pub struct BigEndian<T>(PhantomData<T>);
+
+macro_rules! big_endian {
+    ($($t:ty => $flip:expr),* $(,)?) => {
+        $(
+            impl Encoding for BigEndian<$t> {
+                type Item = $t;
+
+                fn encode(item: &$t, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&(item ^ $flip).to_be_bytes());
+                }
+
+                fn decode(bytes: &[u8]) -> io::Result<$t> {
+                    let bytes = bytes.try_into().map_err(|_| {
+                        invalid(format!("{} bytes are not a {}", bytes.len(), stringify!($t)))
+                    })?;
+                    Ok(<$t>::from_be_bytes(bytes) ^ $flip)
+                }
+            }
+        )*
+    };
+}
+
+big_endian!(u8 => 0, u16 => 0, u32 => 0, u64 => 0, u128 => 0);
+big_endian!(i8 => i8::MIN, i16 => i16::MIN, i32 => i32::MIN, i64 => i64::MIN, i128 => i128::MIN);
+
+/// Settings of a PagedBTree file, passed to PagedBTree::create (or PagedBTree::open_with, which only
+/// uses the pool and write-ahead log settings)
+///
+/// The order is the largest even order whose full nodes of max length keys and values fit in a page,
+/// unless it is set (to a smaller even order)
+#[derive(Debug, Clone)]
+pub struct PagedOptions {
+    page_size: usize,
+    pool_pages: usize,
+    max_key_len: usize,
+    max_value_len: usize,
+    order: Option<usize>,
+    wal: Option<WalSync>,
+    checkpoint_bytes: u64,
+}
+
+impl Default for PagedOptions {
+    fn default() -> Self {
+        PagedOptions {
+            page_size: 4096,
+            pool_pages: 256,
+            max_key_len: 64,
+            max_value_len: 64,
+            order: None,
+            wal: None,
+            checkpoint_bytes: 16 << 20,
+        }
+    }
+}
+
+impl PagedOptions {
+    /// Sets the bytes per page (from 128 to 65536, 4096 by default)
+    pub fn page_size(mut self, bytes: usize) -> Self {
+        self.page_size = bytes;
+        self
+    }
+
+    /// Sets the number of pages the buffer pool keeps in memory (256 by default)
+    pub fn pool_pages(mut self, pages: usize) -> Self {
+        assert!(pages > 0, "Buffer pool needs at least one page");
+        self.pool_pages = pages;
+        self
+    }
+
+    /// Sets the max bytes of an encoded key (64 by default)
+    pub fn max_key_len(mut self, bytes: usize) -> Self {
+        self.max_key_len = bytes;
+        self
+    }
+
+    /// Sets the max bytes of an encoded value (64 by default)
+    pub fn max_value_len(mut self, bytes: usize) -> Self {
+        self.max_value_len = bytes;
+        self
+    }
+
+    /// Sets the knuth order m instead of deriving it from the page size (it must be even, at least 4,
+    /// and its full nodes must fit in a page)
+    pub fn order(mut self, m: usize) -> Self {
+        assert!(m >= 4 && m.is_multiple_of(2), "PagedBTree order must be even and at least 4");
+        self.order = Some(m);
+        self
+    }
+
+    /// Logs every insert and remove to a write-ahead log synced with the given policy, so the tree
+    /// survives a crash (no log by default)
+    pub fn wal(mut self, sync: WalSync) -> Self {
+        self.wal = Some(sync);
+        self
+    }
+
+    /// Sets how big the write-ahead log grows before a checkpoint empties it (16 MiB by default)
+    pub fn checkpoint_bytes(mut self, bytes: u64) -> Self {
+        self.checkpoint_bytes = bytes;
+        self
+    }
+}
+
+/// Returns the path of the write-ahead log of the tree at path
+fn wal_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".wal");
+    PathBuf::from(name)
+}
+
+/// Reads the meta page of a tree file
+fn read_meta(file: &mut File) -> io::Result<Meta> {
+    let mut header = [0; page::META_LEN];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    let meta = Meta::decode(&header)?;
+    check_page_size(meta.page_size)?;
+    Ok(meta)
+}
+
+/// Fails with InvalidData unless page_size is from 128 to 65536 bytes
+fn check_page_size(page_size: usize) -> io::Result<()> {
+    if !(128..=65536).contains(&page_size) {
+        return Err(invalid(format!("page size {} is not from 128 to 65536 bytes", page_size)));
+    }
+    Ok(())
+}
+
+/// A B-tree map stored in pages of a file, with keys and values encoded by K and V
+pub struct PagedBTree<K: Encoding = Bytes, V: Encoding = Bytes> {
+    pool: BufferPool,
+    meta: Meta,
+    /// Pages changed by the operation in progress, which only go into the pool once it is logged
+    /// (with a write-ahead log)
+    pending: BTreeMap<PageId, Box<[u8]>>,
+    checkpoint_bytes: u64,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K: Encoding, V: Encoding> PagedBTree<K, V> {
+    /// Creates an empty tree in a new file at path (replacing any file there, and its write-ahead log)
+    ///
+    /// Fails with InvalidInput if the options do not fit together (see PagedOptions)
+    pub fn create<P: AsRef<Path>>(path: P, options: PagedOptions) -> io::Result<Self> {
+        let PagedOptions { page_size, pool_pages, max_key_len, max_value_len, order, wal, checkpoint_bytes } = options;
+        let invalid_input = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+        check_page_size(page_size).map_err(|err| invalid_input(err.to_string()))?;
+        if max_key_len > u16::MAX as usize || max_value_len > u16::MAX as usize {
+            return Err(invalid_input(format!("max key and value lengths must be at most {} bytes", u16::MAX)));
+        }
+        // Rounded down to an even order, which can be split top-down (see b_tree.rs)
+        let max_order = page::max_order(page_size, max_key_len, max_value_len) & !1;
+        let order = order.unwrap_or(max_order);
+        if order < 4 || order > max_order {
+            return Err(invalid_input(format!(
+                "a node of order {} with keys of {} bytes and values of {} bytes does not fit in a page of {} bytes",
+                order.max(4),
+                max_key_len,
+                max_value_len,
+                page_size
+            )));
+        }
+
+        let path = path.as_ref();
+        let file = File::options().read(true).write(true).create(true).truncate(true).open(path)?;
+        // A log left from an old tree at path must not be replayed onto this one
+        let log = match wal {
+            Some(sync) => Some(Wal::new(File::options().read(true).write(true).create(true).truncate(true).open(wal_path(path))?, sync)),
+            None => {
+                if let Err(err) = fs::remove_file(wal_path(path))
+                    && err.kind() != io::ErrorKind::NotFound {
+                    return Err(err);
+                }
+                None
+            },
+        };
+        let meta = Meta { page_size, order, max_key_len, max_value_len, root: 0, page_count: 1, free_head: 0, len: 0 };
+        let pool = BufferPool::new(file, page_size, pool_pages, log);
+        let mut btree = PagedBTree { pool, meta, pending: BTreeMap::new(), checkpoint_bytes, _marker: PhantomData };
+        btree.flush()?;
+        Ok(btree)
+    }
+
+    /// Opens a tree in a file made by create, keeping up to pool_pages of its pages in memory (without
+    /// a write-ahead log, see open_with)
+    ///
+    /// Fails with InvalidData if the file is not a PagedBTree file
+    pub fn open<P: AsRef<Path>>(path: P, pool_pages: usize) -> io::Result<Self> {
+        PagedBTree::open_with(path, PagedOptions::default().pool_pages(pool_pages))
+    }
+
+    /// Opens a tree in a file made by create, with the pool and write-ahead log settings of options
+    /// (its page size, order and max lengths are the ones it was created with)
+    ///
+    /// If the tree has a write-ahead log, the commits in it are replayed first (whether or not options
+    /// has one), recovering from a crash of the last process that changed it
+    pub fn open_with<P: AsRef<Path>>(path: P, options: PagedOptions) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::options().read(true).write(true).open(path)?;
+        let page_size = read_meta(&mut file)?.page_size;
+
+        let log_path = wal_path(path);
+        let log = match File::options().read(true).write(true).create(options.wal.is_some()).open(&log_path) {
+            Ok(mut log) => {
+                wal::recover(&mut file, &mut log, page_size)?;
+                Some(log)
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        let log = match (log, options.wal) {
+            (Some(log), Some(sync)) => Some(Wal::new(log, sync)),
+            (Some(log), None) => {
+                drop(log);
+                fs::remove_file(&log_path)?;
+                None
+            },
+            (None, _) => None,
+        };
+
+        let meta = read_meta(&mut file)?;
+        if meta.page_size != page_size {
+            return Err(invalid("write-ahead log changed the page size"));
+        }
+        if file.metadata()?.len() < meta.page_count.saturating_mul(meta.page_size as u64) {
+            return Err(invalid(format!("file is shorter than its {} pages", meta.page_count)));
+        }
+        let pool = BufferPool::new(file, meta.page_size, options.pool_pages, log);
+        Ok(PagedBTree { pool, meta, pending: BTreeMap::new(), checkpoint_bytes: options.checkpoint_bytes, _marker: PhantomData })
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        self.meta.order
+    }
+
+    /// Returns the bytes per page of the file
+    pub fn page_size(&self) -> usize {
+        self.meta.page_size
+    }
+
+    /// Returns the number of pages in the file (including the meta page and free pages)
+    pub fn page_count(&self) -> u64 {
+        self.meta.page_count
+    }
+
+    /// Returns the number of keys in the tree
+    pub fn len(&self) -> u64 {
+        self.meta.len
+    }
+
+    /// Returns true if the tree has no keys
+    pub fn is_empty(&self) -> bool {
+        self.meta.len == 0
+    }
+
+    /// Returns the counts of page hits, misses, evictions and writes of the buffer pool
+    pub fn pool_stats(&self) -> PoolStats {
+        self.pool.stats()
+    }
+
+    /// Returns the value of key, if it is present
+    pub fn get(&mut self, key: &K::Item) -> io::Result<Option<V::Item>> {
+        let key = encode::<K>(key);
+        self.get_bytes(&key)?.map(|value| V::decode(&value)).transpose()
+    }
+
+    /// Returns true if key is present
+    pub fn contains_key(&mut self, key: &K::Item) -> io::Result<bool> {
+        Ok(self.get_bytes(&encode::<K>(key))?.is_some())
+    }
+
+    /// Inserts key with value, returning the value it replaced if key was already present
+    ///
+    /// Fails with InvalidInput if the encoded key or value is longer than the max set at create
+    pub fn insert(&mut self, key: &K::Item, value: &V::Item) -> io::Result<Option<V::Item>> {
+        let (key, value) = (encode::<K>(key), encode::<V>(value));
+        for (kind, bytes, max) in [("key", &key, self.meta.max_key_len), ("value", &value, self.meta.max_value_len)] {
+            if bytes.len() > max {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} of {} bytes is longer than the max of {} bytes", kind, bytes.len(), max),
+                ));
+            }
+        }
+        self.commit(|btree| btree.insert_bytes(key, value))?.map(|old| V::decode(&old)).transpose()
+    }
+
+    /// Removes key, returning its value, or None (leaving the tree as it was) if it is not present
+    pub fn remove(&mut self, key: &K::Item) -> io::Result<Option<V::Item>> {
+        let key = encode::<K>(key);
+        self.commit(|btree| btree.remove_bytes(&key))?.map(|old| V::decode(&old)).transpose()
+    }
+
+    /// Writes the meta page and every changed page to the file, and syncs it
+    ///
+    /// With a write-ahead log this is a checkpoint, which then empties the log
+    pub fn flush(&mut self) -> io::Result<()> {
+        // Pages a commit logged but could not put into the pool have to reach the file before the
+        // meta page that refers to them, and before the log (their only other copy) is emptied
+        if let Some(lsn) = self.pool.wal().map(Wal::len) {
+            self.install_pending(lsn)?;
+        }
+        let frame = self.pool.create(0)?;
+        self.meta.encode(self.pool.data_mut(frame));
+        self.pool.unpin(frame, true);
+        self.pool.flush()?;
+        if let Some(wal) = self.pool.wal_mut() {
+            wal.reset()?;
+        }
+        Ok(())
+    }
+
+    /// Checks the structure of the tree, returning a description of the first problem found
+    ///
+    /// Every page of the file other than the meta page must be either a node of the tree (reached
+    /// once) or on the free list. A non-root node holds between floor(K/2) and K entries and the root
+    /// between 1 and K, every leaf must be at the same depth, the keys must be in strictly increasing
+    /// order, and their number must be the tree's len
+    pub fn validate(&mut self) -> Result<(), String> {
+        let mut walk = Walk { seen: HashSet::new(), leaf_depth: None, last_key: None, entries: 0 };
+        if self.meta.root != 0 {
+            self.validate_node(self.meta.root, 0, &mut walk)?;
+        }
+        if walk.entries != self.meta.len {
+            return Err(format!("tree holds {} entries but its len is {}", walk.entries, self.meta.len));
+        }
+
+        let mut page = self.meta.free_head;
+        while page != 0 {
+            self.check_page(page, &mut walk.seen)?;
+            page = self
+                .read_page(page, page::decode_free)
+                .and_then(|next| next)
+                .map_err(|err| format!("free page {}: {}", page, err))?;
+        }
+        if walk.seen.len() as u64 != self.meta.page_count - 1 {
+            return Err(format!(
+                "{} of the {} pages are neither in the tree nor on the free list",
+                self.meta.page_count - 1 - walk.seen.len() as u64,
+                self.meta.page_count
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks the subtree in a page (called recursively)
+    fn validate_node(&mut self, page: PageId, depth: usize, walk: &mut Walk) -> Result<(), String> {
+        self.check_page(page, &mut walk.seen)?;
+        let node = self.read(page).map_err(|err| format!("page {}: {}", page, err))?;
+        let (min, max) = (if depth == 0 { 1 } else { self.min_keys() }, self.max_keys());
+        if node.entries.len() < min || node.entries.len() > max {
+            return Err(format!("page {} holds {} entries (min {}, max {})", page, node.entries.len(), min, max));
+        }
+        if node.leaf {
+            match walk.leaf_depth {
+                Some(leaf_depth) if leaf_depth != depth => {
+                    return Err(format!("leaf in page {} is at depth {}, others at {}", page, depth, leaf_depth));
+                },
+                _ => walk.leaf_depth = Some(depth),
+            }
+        }
+
+        for (i, entry) in node.entries.iter().enumerate() {
+            if !node.leaf {
+                self.validate_node(node.children[i], depth + 1, walk)?;
+            }
+            if entry.key.len() > self.meta.max_key_len || entry.value.len() > self.meta.max_value_len {
+                return Err(format!("page {} holds an entry longer than the max", page));
+            }
+            if let Some(last) = &walk.last_key
+                && *last >= entry.key {
+                return Err(format!("keys out of order in page {}: {:?} after {:?}", page, entry.key, last));
+            }
+            walk.last_key = Some(entry.key.clone());
+            walk.entries += 1;
+        }
+        if !node.leaf {
+            self.validate_node(node.children[node.entries.len()], depth + 1, walk)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a page is in the file and not reached before
+    fn check_page(&self, page: PageId, seen: &mut HashSet<PageId>) -> Result<(), String> {
+        if page == 0 || page >= self.meta.page_count {
+            return Err(format!("page {} is not in the file of {} pages", page, self.meta.page_count));
+        }
+        if !seen.insert(page) {
+            return Err(format!("page {} is reached twice", page));
+        }
+        Ok(())
+    }
+
+    /// Runs an insert or remove, as one commit if the tree has a write-ahead log
+    ///
+    /// With a log, an operation that fails leaves the tree as it was, as its pages are only in pending
+    fn commit<R>(&mut self, op: impl FnOnce(&mut Self) -> io::Result<R>) -> io::Result<R> {
+        if self.pool.wal().is_none() {
+            return op(self);
+        }
+        // Pages already logged by a commit that could not put them all into the pool stay pending (see
+        // below), so an operation that fails only drops what it changed itself
+        let (meta, logged) = (self.meta, self.pending.clone());
+        let result = match op(self) {
+            Ok(result) => result,
+            Err(err) => {
+                self.meta = meta;
+                self.pending = logged;
+                return Err(err);
+            },
+        };
+        if self.pending.is_empty() {
+            return Ok(result);
+        }
+
+        let mut meta_page = [0; page::META_LEN];
+        self.meta.encode(&mut meta_page);
+        let wal = self.pool.wal_mut().expect("Tree has a write-ahead log");
+        let lsn = wal.log_commit(self.pending.iter().map(|(&page, image)| (page, &image[..])), &meta_page);
+        self.install_pending(lsn)?;
+        let wal = self.pool.wal_mut().expect("Tree has a write-ahead log");
+        wal.sync_commit(lsn)?;
+        if wal.len() >= self.checkpoint_bytes {
+            self.flush()?;
+        }
+        Ok(result)
+    }
+
+    /// Puts the pending pages into the pool, as changed by the commit logged up to lsn
+    ///
+    /// A page that fails to go in is still logged, so it stays pending for the next commit or flush
+    fn install_pending(&mut self, lsn: u64) -> io::Result<()> {
+        while let Some((page, image)) = self.pending.pop_first() {
+            if let Err(err) = self.pool.install(page, &image, lsn) {
+                self.pending.insert(page, image);
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    fn get_bytes(&mut self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let mut page = self.meta.root;
+        while page != 0 {
+            let mut node = self.read(page)?;
+            let (found, idx) = node.search(key);
+            if found {
+                return Ok(Some(node.entries.swap_remove(idx).value));
+            }
+            if node.leaf {
+                break;
+            }
+            page = node.children[idx];
+        }
+        Ok(None)
+    }
+
+    fn insert_bytes(&mut self, key: Vec<u8>, value: Vec<u8>) -> io::Result<Option<Vec<u8>>> {
+        // Replace the value of a key that is already present, leaving the nodes as they are
+        let mut page = self.meta.root;
+        while page != 0 {
+            let mut node = self.read(page)?;
+            let (found, idx) = node.search(&key);
+            if found {
+                let old = std::mem::replace(&mut node.entries[idx].value, value);
+                self.write(page, &node)?;
+                return Ok(Some(old));
+            }
+            if node.leaf {
+                break;
+            }
+            page = node.children[idx];
+        }
+
+        let entry = Entry { key, value };
+        if self.meta.root == 0 {
+            // If root is empty, create a new root leaf node with the entry
+            let root = self.allocate()?;
+            self.write(root, &PageNode { leaf: true, entries: vec![entry], children: vec![] })?;
+            self.meta.root = root;
+        } else {
+            let mut page = self.meta.root;
+            let mut node = self.read(page)?;
+            if node.entries.len() == self.max_keys() {
+                // Root is full, so make a new root with the old root as its only child and split it
+                let old_root = node;
+                page = self.allocate()?;
+                node = PageNode { leaf: false, entries: vec![], children: vec![self.meta.root] };
+                self.split_child(&mut node, 0, old_root)?;
+                self.write(page, &node)?;
+                self.meta.root = page;
+            }
+            self.insert_non_full(page, node, entry)?;
+        }
+        self.meta.len += 1;
+        Ok(None)
+    }
+
+    /// Inserts an entry below a node that is not full, splitting full children on the way down
+    fn insert_non_full(&mut self, mut page: PageId, mut node: PageNode, entry: Entry) -> io::Result<()> {
+        loop {
+            let (_, mut idx) = node.search(&entry.key);
+            if node.leaf {
+                node.entries.insert(idx, entry);
+                return self.write(page, &node);
+            }
+
+            let mut child = self.read(node.children[idx])?;
+            if child.entries.len() == self.max_keys() {
+                // Split full children first, then go to the half the entry belongs in
+                let right = self.split_child(&mut node, idx, child)?;
+                self.write(page, &node)?;
+                if entry.key > node.entries[idx].key {
+                    idx += 1;
+                    child = right;
+                } else {
+                    child = self.read(node.children[idx])?;
+                }
+            }
+            page = node.children[idx];
+            node = child;
+        }
+    }
+
+    /// Splits the full child at child_idx of node (read into child) into 2 nodes and moves the middle
+    /// entry up into node, writing both halves and returning the new right half
+    ///
+    /// node is changed but not written
+    fn split_child(&mut self, node: &mut PageNode, child_idx: usize, mut child: PageNode) -> io::Result<PageNode> {
+        let mid = child.entries.len() / 2;
+        let right_entries = child.entries.split_off(mid + 1);
+        let middle = child.entries.pop().expect("Middle entry missing in split_child");
+        let right_children = if child.leaf { vec![] } else { child.children.split_off(mid + 1) };
+        let right = PageNode { leaf: child.leaf, entries: right_entries, children: right_children };
+
+        let right_page = self.allocate()?;
+        self.write(node.children[child_idx], &child)?;
+        self.write(right_page, &right)?;
+        node.entries.insert(child_idx, middle);
+        node.children.insert(child_idx + 1, right_page);
+        Ok(right)
+    }
+
+    fn remove_bytes(&mut self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        let Some(old) = self.get_bytes(key)? else {
+            return Ok(None);
+        };
+        let root = self.meta.root;
+        let node = self.read(root)?;
+        self.delete(root, node, key)?;
+        self.meta.len -= 1;
+
+        // Shrink the tree if the root is empty but has a child, or drop an empty root leaf
+        let node = self.read(root)?;
+        if node.entries.is_empty() {
+            self.meta.root = node.children.first().copied().unwrap_or(0);
+            self.free(root)?;
+        }
+        Ok(Some(old))
+    }
+
+    /// Deletes key (which must be present) from below a node, with the same cases as BTree's delete
+    fn delete(&mut self, page: PageId, mut node: PageNode, key: &[u8]) -> io::Result<()> {
+        let min_keys = self.min_keys();
+        let (found, mut idx) = node.search(key);
+        if found {
+            if node.leaf {
+                // Case 1: The key is in a leaf node
+                node.entries.remove(idx);
+                return self.write(page, &node);
+            }
+
+            // Case 2: The key is in an internal node
+            let left = self.read(node.children[idx])?;
+            if left.entries.len() > min_keys {
+                // Case 2a: Replace the key with its predecessor, deleted from the left subtree
+                let pred = self.rightmost(&left)?;
+                self.delete(node.children[idx], left, &pred.key)?;
+                node.entries[idx] = pred;
+                return self.write(page, &node);
+            }
+            let right = self.read(node.children[idx + 1])?;
+            if right.entries.len() > min_keys {
+                // Case 2b: Replace the key with its successor, deleted from the right subtree
+                let succ = self.leftmost(&right)?;
+                self.delete(node.children[idx + 1], right, &succ.key)?;
+                node.entries[idx] = succ;
+                return self.write(page, &node);
+            }
+            // Case 2c: Both children have min keys, so merge them around the key and delete it from there
+            let merged = self.merge(&mut node, idx, left, right)?;
+            self.write(page, &node)?;
+            return self.delete(node.children[idx], merged, key);
+        }
+
+        if node.leaf {
+            unreachable!("Key to delete must be present");
+        }
+
+        // Case 3: Not found in an internal node, so make sure the child it is below has more than min keys
+        let mut child = self.read(node.children[idx])?;
+        if child.entries.len() <= min_keys {
+            let mut left = None;
+            if idx > 0 {
+                let mut sibling = self.read(node.children[idx - 1])?;
+                if sibling.entries.len() > min_keys {
+                    // Case 3a: Left sibling has keys to spare -> rotate to right
+                    rotate_right(&mut node, idx, &mut sibling, &mut child);
+                    self.write(node.children[idx - 1], &sibling)?;
+                    self.write(node.children[idx], &child)?;
+                    self.write(page, &node)?;
+                    return self.delete(node.children[idx], child, key);
+                }
+                left = Some(sibling);
+            }
+            if idx + 1 < node.children.len() {
+                let mut sibling = self.read(node.children[idx + 1])?;
+                if sibling.entries.len() > min_keys {
+                    // Case 3b: Right sibling has keys to spare -> rotate to left
+                    rotate_left(&mut node, idx, &mut child, &mut sibling);
+                    self.write(node.children[idx + 1], &sibling)?;
+                    self.write(node.children[idx], &child)?;
+                    self.write(page, &node)?;
+                    return self.delete(node.children[idx], child, key);
+                }
+                // Case 3c: Neither sibling has keys to spare, so merge with the right one
+                child = self.merge(&mut node, idx, child, sibling)?;
+            } else {
+                // Case 3c: The last child merges into its left sibling
+                let sibling = left.expect("Last child must have a left sibling");
+                idx -= 1;
+                child = self.merge(&mut node, idx, sibling, child)?;
+            }
+            self.write(page, &node)?;
+        }
+        self.delete(node.children[idx], child, key)
+    }
+
+    /// Merges the child at child_idx of node (left) with the one after it (right) around the entry
+    /// between them, writing the merged node into left's page and freeing right's page
+    ///
+    /// node is changed but not written
+    fn merge(&mut self, node: &mut PageNode, child_idx: usize, mut left: PageNode, mut right: PageNode) -> io::Result<PageNode> {
+        let middle = node.entries.remove(child_idx);
+        let right_page = node.children.remove(child_idx + 1);
+        left.entries.push(middle);
+        left.entries.append(&mut right.entries);
+        left.children.append(&mut right.children);
+        self.write(node.children[child_idx], &left)?;
+        self.free(right_page)?;
+        Ok(left)
+    }
+
+    /// Returns the last entry of the subtree under node
+    fn rightmost(&mut self, node: &PageNode) -> io::Result<Entry> {
+        let mut page = match node.leaf {
+            true => return Ok(node.entries.last().expect("Leaf node missing entries").clone()),
+            false => *node.children.last().expect("Node missing children"),
+        };
+        loop {
+            let mut node = self.read(page)?;
+            if node.leaf {
+                return Ok(node.entries.pop().expect("Leaf node missing entries"));
+            }
+            page = *node.children.last().expect("Node missing children");
+        }
+    }
+
+    /// Returns the first entry of the subtree under node
+    fn leftmost(&mut self, node: &PageNode) -> io::Result<Entry> {
+        let mut page = match node.leaf {
+            true => return Ok(node.entries.first().expect("Leaf node missing entries").clone()),
+            false => node.children[0],
+        };
+        loop {
+            let mut node = self.read(page)?;
+            if node.leaf {
+                return Ok(node.entries.swap_remove(0));
+            }
+            page = node.children[0];
+        }
+    }
+
+    /// Returns the max number of entries K = m-1 in a node
+    fn max_keys(&self) -> usize {
+        self.meta.order - 1
+    }
+
+    /// Returns the min number of entries floor(K/2) in a non-root node
+    fn min_keys(&self) -> usize {
+        (self.meta.order - 1) / 2
+    }
+
+    /// Reads the node in a page
+    fn read(&mut self, page: PageId) -> io::Result<PageNode> {
+        self.read_page(page, PageNode::decode)?
+    }
+
+    /// Writes a node into a page
+    fn write(&mut self, page: PageId, node: &PageNode) -> io::Result<()> {
+        self.write_page(page, |data| node.encode(data))
+    }
+
+    /// Calls f with the bytes of a page (as changed by the operation in progress)
+    fn read_page<R>(&mut self, page: PageId, f: impl FnOnce(&[u8]) -> R) -> io::Result<R> {
+        if let Some(image) = self.pending.get(&page) {
+            return Ok(f(image));
+        }
+        let frame = self.pool.fetch(page)?;
+        let result = f(self.pool.data(frame));
+        self.pool.unpin(frame, false);
+        Ok(result)
+    }
+
+    /// Replaces the bytes of a page with what f writes into a zeroed page
+    fn write_page(&mut self, page: PageId, f: impl FnOnce(&mut [u8])) -> io::Result<()> {
+        if self.pool.wal().is_some() {
+            let page_size = self.meta.page_size;
+            let image = self.pending.entry(page).or_insert_with(|| vec![0; page_size].into_boxed_slice());
+            image.fill(0);
+            f(image);
+            return Ok(());
+        }
+        // The whole page is replaced, so it does not need to be read first
+        let frame = self.pool.create(page)?;
+        f(self.pool.data_mut(frame));
+        self.pool.unpin(frame, true);
+        Ok(())
+    }
+
+    /// Returns a page for a new node, from the free list if it is not empty
+    fn allocate(&mut self) -> io::Result<PageId> {
+        if self.meta.free_head == 0 {
+            self.meta.page_count += 1;
+            return Ok(self.meta.page_count - 1);
+        }
+        let page = self.meta.free_head;
+        self.meta.free_head = self.read_page(page, page::decode_free)??;
+        Ok(page)
+    }
+
+    /// Puts a page that is no longer used on the free list
+    fn free(&mut self, page: PageId) -> io::Result<()> {
+        let next = self.meta.free_head;
+        self.write_page(page, |data| page::encode_free(data, next))?;
+        self.meta.free_head = page;
+        Ok(())
+    }
+}
+
+/// What PagedBTree::validate has seen so far
+struct Walk {
+    seen: HashSet<PageId>,
+    leaf_depth: Option<usize>,
+    last_key: Option<Vec<u8>>,
+    entries: u64,
+}
+
+impl<K: Encoding, V: Encoding> Drop for PagedBTree<K, V> {
+    fn drop(&mut self) {
+        // Errors cannot be returned from drop, so call flush first to see them
+        let _ = self.flush();
+    }
+}
+
+/// Returns the bytes of an item
+fn encode<E: Encoding>(item: &E::Item) -> Vec<u8> {
+    let mut out = vec![];
+    E::encode(item, &mut out);
+    out
+}
+
+/// Moves the last entry of left up into node and the entry of node between left and child down into
+/// child (with left's last child), where child is at child_idx of node and left is before it
+fn rotate_right(node: &mut PageNode, child_idx: usize, left: &mut PageNode, child: &mut PageNode) {
+    let last = left.entries.pop().expect("Left sibling has no entries");
+    let middle = std::mem::replace(&mut node.entries[child_idx - 1], last);
+    child.entries.insert(0, middle);
+    if !left.leaf {
+        child.children.insert(0, left.children.pop().expect("Left sibling has no children"));
+    }
+}
+
+/// Moves the first entry of right up into node and the entry of node between child and right down into
+/// child (with right's first child), where child is at child_idx of node and right is after it
+fn rotate_left(node: &mut PageNode, child_idx: usize, child: &mut PageNode, right: &mut PageNode) {
+    let middle = std::mem::replace(&mut node.entries[child_idx], right.entries.remove(0));
+    child.entries.push(middle);
+    if !right.leaf {
+        child.children.push(right.children.remove(0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_tree::{BTree, Node};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeMap;
+
+    type IntTree = PagedBTree<BigEndian<i64>, BigEndian<i64>>;
+
+    fn options(order: usize) -> PagedOptions {
+        PagedOptions::default().page_size(512).max_key_len(8).max_value_len(8).order(order)
+    }
+
+    /// Returns the keys of each node in pre-order, with its depth
+    fn paged_nodes(tree: &mut IntTree) -> Vec<(usize, Vec<i64>)> {
+        fn walk(tree: &mut IntTree, page: PageId, depth: usize, nodes: &mut Vec<(usize, Vec<i64>)>) {
+            let node = tree.read(page).unwrap();
+            let keys = node.entries.iter().map(|entry| BigEndian::<i64>::decode(&entry.key).unwrap()).collect();
+            nodes.push((depth, keys));
+            for child in node.children {
+                walk(tree, child, depth + 1, nodes);
+            }
+        }
+        let mut nodes = vec![];
+        if tree.meta.root != 0 {
+            walk(tree, tree.meta.root, 0, &mut nodes);
+        }
+        nodes
+    }
+
+    /// Returns the keys of each node of a BTree in pre-order, with its depth (an empty root leaf is left out)
+    fn btree_nodes(btree: &BTree<i64>) -> Vec<(usize, Vec<i64>)> {
+        fn walk(node: &Node<i64>, depth: usize, nodes: &mut Vec<(usize, Vec<i64>)>) {
+            nodes.push((depth, node.keys.clone()));
+            for child in &node.children {
+                walk(child, depth + 1, nodes);
+            }
+        }
+        let mut nodes = vec![];
+        if let Some(root) = &btree.root
+            && !root.keys.is_empty() {
+            walk(root, 0, &mut nodes);
+        }
+        nodes
+    }
+
+    #[test]
+    fn test_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        // (8 + 4 + 64 + 64) bytes per child and entry in a 4 KiB page
+        let tree = PagedBTree::<Bytes, Bytes>::create(&path, PagedOptions::default()).unwrap();
+        assert_eq!((tree.order(), tree.page_size(), tree.page_count()), (30, 4096, 1));
+        let tree = PagedBTree::<Bytes, Bytes>::create(&path, PagedOptions::default().max_key_len(8).max_value_len(0)).unwrap();
+        assert_eq!(tree.order(), 204);
+
+        for options in [
+            PagedOptions::default().page_size(64),
+            PagedOptions::default().page_size(1 << 20),
+            PagedOptions::default().page_size(128),
+            PagedOptions::default().max_key_len(100_000),
+            PagedOptions::default().order(32),
+        ] {
+            let err = PagedBTree::<Bytes, Bytes>::create(&path, options).err().expect("invalid options were accepted");
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_same_nodes_as_btree() {
+        for (order, seed) in [(4, 1), (6, 2), (8, 3), (10, 4)] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut tree: IntTree = PagedBTree::create(dir.path().join("tree"), options(order)).unwrap();
+            let mut btree = BTree::new(order);
+            let mut map = BTreeMap::new();
+            let mut rng = StdRng::seed_from_u64(seed);
+            for step in 0..3000 {
+                let key = rng.random_range(-500..500);
+                if rng.random_bool(0.6) {
+                    let old = tree.insert(&key, &(key * 2 + step)).unwrap();
+                    assert_eq!(old, map.insert(key, key * 2 + step));
+                    if old.is_none() {
+                        btree.insert(key);
+                    }
+                } else {
+                    let old = tree.remove(&key).unwrap();
+                    assert_eq!(old, map.remove(&key));
+                    if old.is_some() {
+                        btree.delete(key);
+                    }
+                }
+                assert_eq!(paged_nodes(&mut tree), btree_nodes(&btree), "order {} step {}", order, step);
+            }
+            assert_eq!(tree.len(), map.len() as u64);
+            tree.validate().unwrap();
+            for key in -500..500 {
+                assert_eq!(tree.get(&key).unwrap(), map.get(&key).copied());
+            }
+        }
+    }
+
+    #[test]
+    fn test_delete_all() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tree: IntTree = PagedBTree::create(dir.path().join("tree"), options(4)).unwrap();
+        assert_eq!(tree.remove(&1).unwrap(), None);
+        for key in 0..200 {
+            assert_eq!(tree.insert(&key, &-key).unwrap(), None);
+        }
+        for key in (0..200).rev() {
+            assert_eq!(tree.remove(&key).unwrap(), Some(-key));
+            assert_eq!(tree.remove(&key).unwrap(), None);
+        }
+        assert!(tree.is_empty());
+        assert_eq!(tree.meta.root, 0);
+        assert_eq!(tree.get(&5).unwrap(), None);
+    }
+
+    #[test]
+    fn test_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        {
+            let mut tree: PagedBTree<Utf8, BigEndian<u32>> = PagedBTree::create(&path, PagedOptions::default()).unwrap();
+            for i in 0..2000u32 {
+                tree.insert(&format!("key{}", i), &i).unwrap();
+            }
+        }
+
+        let mut tree: PagedBTree<Utf8, BigEndian<u32>> = PagedBTree::open(&path, 16).unwrap();
+        assert_eq!(tree.len(), 2000);
+        for i in 0..2000u32 {
+            assert_eq!(tree.get(&format!("key{}", i)).unwrap(), Some(i));
+        }
+        for i in (0..2000u32).step_by(2) {
+            assert_eq!(tree.remove(&format!("key{}", i)).unwrap(), Some(i));
+        }
+        tree.flush().unwrap();
+        drop(tree);
+
+        let mut tree: PagedBTree<Utf8, BigEndian<u32>> = PagedBTree::open(&path, 16).unwrap();
+        assert_eq!(tree.len(), 1000);
+        for i in 0..2000u32 {
+            let expected = (i % 2 == 1).then_some(i);
+            assert_eq!(tree.get(&format!("key{}", i)).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_small_pool() {
+        // Every operation only pins one page at a time, so even a single frame is enough
+        let dir = tempfile::tempdir().unwrap();
+        let mut tree: IntTree = PagedBTree::create(dir.path().join("tree"), options(6).pool_pages(1)).unwrap();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut map = BTreeMap::new();
+        for _ in 0..2000 {
+            let key = rng.random_range(0..400);
+            if rng.random_bool(0.7) {
+                assert_eq!(tree.insert(&key, &key).unwrap(), map.insert(key, key));
+            } else {
+                assert_eq!(tree.remove(&key).unwrap(), map.remove(&key));
+            }
+        }
+        let stats = tree.pool_stats();
+        assert!(stats.evictions > 0 && stats.writes > 0, "{:?}", stats);
+        for key in 0..400 {
+            assert_eq!(tree.get(&key).unwrap(), map.get(&key).copied());
+        }
+    }
+
+    #[test]
+    fn test_free_pages_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tree: IntTree = PagedBTree::create(dir.path().join("tree"), options(4)).unwrap();
+        for key in 0..500 {
+            tree.insert(&key, &key).unwrap();
+        }
+        let page_count = tree.page_count();
+        for key in 0..500 {
+            tree.remove(&key).unwrap();
+        }
+        for key in 0..500 {
+            tree.insert(&(key * 7 % 500), &key).unwrap();
+        }
+        // The pages of the first tree are all free again, so the second one fits in them
+        assert!(tree.page_count() <= page_count + 1, "{} pages grew to {}", page_count, tree.page_count());
+    }
+
+    #[test]
+    fn test_encodings() {
+        let mut bytes = vec![];
+        for val in [i64::MIN, -300, -1, 0, 1, 255, 256, i64::MAX] {
+            let encoded = encode::<BigEndian<i64>>(&val);
+            assert!(bytes < encoded);
+            assert_eq!(BigEndian::<i64>::decode(&encoded).unwrap(), val);
+            bytes = encoded;
+        }
+        assert_eq!(encode::<BigEndian<u16>>(&0x0102), [1, 2]);
+        assert!(BigEndian::<u32>::decode(&[1, 2]).is_err());
+        assert_eq!(Utf8::decode("héllo".as_bytes()).unwrap(), "héllo");
+        assert_eq!(Utf8::decode(&[0xff]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_rejects_long_keys_and_invalid_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        let mut tree = PagedBTree::<Bytes, Bytes>::create(&path, PagedOptions::default().max_key_len(4)).unwrap();
+        assert_eq!(tree.insert(&b"long key".to_vec(), &vec![]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(tree.insert(&vec![], &vec![0; 65]).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(tree.insert(&b"key".to_vec(), &b"value".to_vec()).unwrap(), None);
+        assert_eq!(tree.len(), 1);
+        drop(tree);
+
+        let other = dir.path().join("other");
+        std::fs::write(&other, [0; 4096]).unwrap();
+        assert_eq!(PagedBTree::<Bytes, Bytes>::open(&other, 4).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        // A file cut short is missing pages
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&other, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(PagedBTree::<Bytes, Bytes>::open(&other, 4).is_err());
+    }
+
+    /// Returns the entries of a tree of int keys from 0 to 100
+    fn contents(tree: &mut IntTree) -> BTreeMap<i64, i64> {
+        (0..100).filter_map(|key| tree.get(&key).unwrap().map(|value| (key, value))).collect()
+    }
+
+    /// Runs random inserts and removes of keys from 0 to 100 on tree and map
+    fn random_ops(tree: &mut IntTree, map: &mut BTreeMap<i64, i64>, rng: &mut StdRng) {
+        let key = rng.random_range(0..100);
+        if rng.random_bool(0.6) {
+            tree.insert(&key, &rng.random()).map(|_| ()).unwrap();
+            map.insert(key, tree.get(&key).unwrap().unwrap());
+        } else {
+            assert_eq!(tree.remove(&key).unwrap(), map.remove(&key));
+        }
+    }
+
+    #[test]
+    fn test_recovers_from_log_cut_anywhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        let mut tree: IntTree = PagedBTree::create(&path, options(4).wal(WalSync::Never)).unwrap();
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut map = BTreeMap::new();
+        for _ in 0..100 {
+            random_ops(&mut tree, &mut map, &mut rng);
+        }
+        // A checkpoint, so the log starts from a tree that is not empty
+        tree.flush().unwrap();
+        let checkpoint = std::fs::read(&path).unwrap();
+
+        // The state after each commit, and where its commit record ends in the log
+        let mut states = vec![(0, map.clone())];
+        for _ in 0..150 {
+            random_ops(&mut tree, &mut map, &mut rng);
+            states.push((tree.pool.wal().unwrap().len(), map.clone()));
+        }
+        tree.pool.wal_mut().unwrap().sync_to(u64::MAX).unwrap();
+        // Nothing was evicted (the pool holds every page), so the file is as it was at the checkpoint
+        assert_eq!(std::fs::read(&path).unwrap(), checkpoint);
+        let log = std::fs::read(wal_path(&path)).unwrap();
+        assert_eq!(log.len() as u64, states.last().unwrap().0);
+        std::mem::forget(tree);
+
+        // Cut the log at every commit boundary and the bytes next to it, and at random offsets
+        let mut cuts: Vec<usize> = states.iter().flat_map(|&(end, _)| [end as usize, end as usize + 1, (end as usize).saturating_sub(1)]).collect();
+        cuts.extend((0..200).map(|_| rng.random_range(0..=log.len())));
+        let crashed = dir.path().join("crashed");
+        for cut in cuts.into_iter().filter(|&cut| cut <= log.len()) {
+            std::fs::write(&crashed, &checkpoint).unwrap();
+            std::fs::write(wal_path(&crashed), &log[..cut]).unwrap();
+            let mut recovered: IntTree = PagedBTree::open_with(&crashed, options(4).wal(WalSync::Never)).unwrap();
+            recovered.validate().unwrap_or_else(|problem| panic!("cut at {}: {}", cut, problem));
+
+            // The commits that are whole in the log are recovered, and none after them
+            let (_, expected) = states.iter().rev().find(|&&(end, _)| end as usize <= cut).unwrap();
+            assert_eq!(&contents(&mut recovered), expected, "cut at {}", cut);
+            assert_eq!(recovered.len(), expected.len() as u64);
+            assert_eq!(std::fs::metadata(wal_path(&crashed)).unwrap().len(), 0);
+        }
+    }
+
+    #[test]
+    fn test_crash_with_evictions() {
+        // With a small pool pages are written between checkpoints, and only after their commits are in the log
+        for sync in [WalSync::Always, WalSync::EveryN(7), WalSync::Never] {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("tree");
+            let mut tree: IntTree = PagedBTree::create(&path, options(4).pool_pages(3).wal(sync)).unwrap();
+            let mut rng = StdRng::seed_from_u64(12);
+            let mut map = BTreeMap::new();
+            let mut states = vec![];
+            for _ in 0..300 {
+                random_ops(&mut tree, &mut map, &mut rng);
+                states.push(map.clone());
+            }
+            assert!(tree.pool_stats().evictions > 0);
+            std::mem::forget(tree);
+
+            let mut recovered: IntTree = PagedBTree::open(&path, 8).unwrap();
+            recovered.validate().unwrap();
+            let contents = contents(&mut recovered);
+            match sync {
+                WalSync::Always => assert_eq!(&contents, states.last().unwrap()),
+                WalSync::EveryN(n) => assert!(states[states.len() - n..].contains(&contents), "{:?}", sync),
+                WalSync::Never => assert!(contents.is_empty() || states.contains(&contents)),
+            }
+            // Opened without a log, so the recovered one is gone
+            assert!(!wal_path(&path).exists());
+        }
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        let mut tree: IntTree = PagedBTree::create(&path, options(6).wal(WalSync::EveryN(10)).checkpoint_bytes(20_000)).unwrap();
+        let mut rng = StdRng::seed_from_u64(13);
+        let mut map = BTreeMap::new();
+        for _ in 0..1000 {
+            random_ops(&mut tree, &mut map, &mut rng);
+            // A commit changes at most a few pages of 512 bytes per level
+            assert!(tree.pool.wal().unwrap().len() < 30_000);
+        }
+        drop(tree);
+        // Dropping the tree is a checkpoint, so it is all in the file
+        assert_eq!(std::fs::metadata(wal_path(&path)).unwrap().len(), 0);
+
+        let mut tree: IntTree = PagedBTree::open_with(&path, options(6).wal(WalSync::Always)).unwrap();
+        tree.validate().unwrap();
+        assert_eq!(contents(&mut tree), map);
+    }
+
+    #[test]
+    fn test_failed_operation_keeps_logged_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tree: IntTree = PagedBTree::create(dir.path().join("tree"), options(6).wal(WalSync::Always)).unwrap();
+        for key in 0..50 {
+            tree.insert(&key, &key).unwrap();
+        }
+        // As left by a commit whose pages were logged, but which failed to put the root into the pool
+        let root = tree.meta.root;
+        let logged = tree.read_page(root, |data| data.to_vec().into_boxed_slice()).unwrap();
+        tree.pending.insert(root, logged.clone());
+
+        let err = tree
+            .commit(|btree| {
+                btree.write_page(root, |data| data.fill(0xff))?;
+                btree.write_page(1000, |data| data.fill(0xff))?;
+                Err::<(), _>(io::Error::other("failed"))
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "failed");
+        assert_eq!(tree.pending.keys().copied().collect::<Vec<_>>(), vec![root]);
+        assert_eq!(tree.pending[&root], logged);
+
+        // The next commit puts it into the pool
+        tree.insert(&50, &50).unwrap();
+        assert!(tree.pending.is_empty());
+        tree.validate().unwrap();
+        assert_eq!(contents(&mut tree), (0..=50).map(|key| (key, key)).collect());
+    }
+
+    #[test]
+    fn test_flush_installs_logged_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        let mut tree: IntTree = PagedBTree::create(&path, options(6).wal(WalSync::Always)).unwrap();
+        for key in 0..50 {
+            tree.insert(&key, &key).unwrap();
+        }
+        // As left by a commit that logged its pages (and moved the meta on), but failed to put them into the pool
+        tree.insert_bytes(encode::<BigEndian<i64>>(&50), encode::<BigEndian<i64>>(&50)).unwrap();
+        let mut meta_page = [0; page::META_LEN];
+        tree.meta.encode(&mut meta_page);
+        let pending = tree.pending.iter().map(|(&page, image)| (page, &image[..]));
+        tree.pool.wal_mut().unwrap().log_commit(pending, &meta_page);
+
+        tree.flush().unwrap();
+        assert!(tree.pending.is_empty());
+        assert_eq!(tree.pool.wal().unwrap().len(), 0);
+        drop(tree);
+
+        let mut tree: IntTree = PagedBTree::open_with(&path, options(6).wal(WalSync::Always)).unwrap();
+        tree.validate().unwrap();
+        assert_eq!(contents(&mut tree), (0..=50).map(|key| (key, key)).collect());
+    }
+
+    #[test]
+    fn test_create_drops_old_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tree");
+        let mut tree: IntTree = PagedBTree::create(&path, options(4).wal(WalSync::Always)).unwrap();
+        tree.insert(&1, &1).unwrap();
+        std::mem::forget(tree);
+
+        let mut tree: IntTree = PagedBTree::create(&path, options(4)).unwrap();
+        assert!(!wal_path(&path).exists());
+        drop(tree);
+        tree = PagedBTree::open(&path, 4).unwrap();
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_validate_finds_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut tree: IntTree = PagedBTree::create(dir.path().join("tree"), options(8)).unwrap();
+        for key in 0..20 {
+            tree.insert(&key, &key).unwrap();
+        }
+        tree.validate().unwrap();
+
+        let root = tree.meta.root;
+        let leaf = tree.read(root).unwrap().children[0];
+        let mut node = tree.read(leaf).unwrap();
+        node.entries.swap(0, 1);
+        tree.write(leaf, &node).unwrap();
+        assert!(tree.validate().unwrap_err().contains("out of order"));
+        node.entries.swap(0, 1);
+        tree.write(leaf, &node).unwrap();
+
+        tree.meta.len += 1;
+        assert!(tree.validate().unwrap_err().contains("len"));
+        tree.meta.len -= 1;
+        tree.meta.page_count += 1;
+        assert!(tree.validate().unwrap_err().contains("neither in the tree nor on the free list"));
+        tree.meta.page_count -= 1;
+        tree.validate().unwrap();
+    }
+}
@@ -0,0 +1,361 @@
+// Buffer pool: a fixed number of in-memory frames caching pages of a file
+//
+// A page is used by fetching it, which pins its frame (reading the page in first if it is not
+// resident), and then unpinning it, saying whether it was changed. A pinned frame is never evicted. A
+// frame whose pin count drops to 0 joins the LRU list, keyed by a tick that increases with every
+// unpin, and when a page has to be read into a full pool the frame unpinned longest ago is evicted
+// (written back first if it is dirty). So pages are only written when they are evicted or flushed.
+//
+// With a write-ahead log, each dirty frame remembers the log sequence number of the commit that last
+// changed it, and the log is synced up to it before the page is written (see wal.rs).
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::page::PageId;
+use super::wal::Wal;
+
+/// Index of a frame in the pool
+pub(super) type FrameId = usize;
+
+/// Counts of what the buffer pool has done since it was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Fetches of a page that was resident
+    pub hits: u64,
+    /// Fetches that had to read the page from the file
+    pub misses: u64,
+    /// Pages dropped from the pool to make room for another
+    pub evictions: u64,
+    /// Pages written to the file (on eviction or flush)
+    pub writes: u64,
+}
+
+struct Frame {
+    page: PageId,
+    data: Box<[u8]>,
+    pins: usize,
+    dirty: bool,
+    /// Position in the LRU list, while unpinned
+    lru_tick: Option<u64>,
+    /// Log sequence number of the commit that last changed the page (0 without a log)
+    lsn: u64,
+}
+
+pub(super) struct BufferPool {
+    file: File,
+    page_size: usize,
+    capacity: usize,
+    frames: Vec<Frame>,
+    page_table: HashMap<PageId, FrameId>,
+    /// Unpinned frames, oldest unpin first
+    lru: BTreeMap<u64, FrameId>,
+    tick: u64,
+    stats: PoolStats,
+    wal: Option<Wal>,
+}
+
+impl BufferPool {
+    /// Creates a pool of capacity frames over the pages of file, logging to wal if given
+    pub fn new(file: File, page_size: usize, capacity: usize, wal: Option<Wal>) -> Self {
+        assert!(capacity > 0, "Buffer pool needs at least one frame");
+        BufferPool {
+            file,
+            page_size,
+            capacity,
+            frames: Vec::with_capacity(capacity),
+            page_table: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            stats: PoolStats::default(),
+            wal,
+        }
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.stats
+    }
+
+    /// Pins the frame holding page, reading the page from the file if it is not resident
+    pub fn fetch(&mut self, page: PageId) -> io::Result<FrameId> {
+        if let Some(&frame) = self.page_table.get(&page) {
+            self.stats.hits += 1;
+            self.pin(frame);
+            return Ok(frame);
+        }
+
+        self.stats.misses += 1;
+        let frame = self.victim(page)?;
+        self.file.seek(SeekFrom::Start(page * self.page_size as u64))?;
+        if let Err(err) = self.file.read_exact(&mut self.frames[frame].data) {
+            self.release(frame);
+            return Err(err);
+        }
+        Ok(frame)
+    }
+
+    /// Pins a frame for a page that is new (past the end of the file, or reused from the free list),
+    /// without reading it. The frame is zeroed and dirty, so the page is written out later
+    pub fn create(&mut self, page: PageId) -> io::Result<FrameId> {
+        let frame = match self.page_table.get(&page) {
+            Some(&frame) => {
+                self.pin(frame);
+                frame
+            },
+            None => self.victim(page)?,
+        };
+        self.frames[frame].data.fill(0);
+        self.frames[frame].dirty = true;
+        Ok(frame)
+    }
+
+    /// Puts the image of a page changed by a commit into its frame, which is not written to the file
+    /// before the log is synced up to lsn
+    pub fn install(&mut self, page: PageId, image: &[u8], lsn: u64) -> io::Result<()> {
+        let frame = self.create(page)?;
+        self.frames[frame].data.copy_from_slice(image);
+        self.frames[frame].lsn = lsn;
+        self.unpin(frame, true);
+        Ok(())
+    }
+
+    /// Unpins a frame, marking it dirty if it was changed
+    pub fn unpin(&mut self, frame: FrameId, dirty: bool) {
+        let f = &mut self.frames[frame];
+        assert!(f.pins > 0, "Unpinned page {} more times than it was pinned", f.page);
+        f.pins -= 1;
+        f.dirty |= dirty;
+        if f.pins == 0 {
+            self.tick += 1;
+            f.lru_tick = Some(self.tick);
+            self.lru.insert(self.tick, frame);
+        }
+    }
+
+    pub fn data(&self, frame: FrameId) -> &[u8] {
+        &self.frames[frame].data
+    }
+
+    /// Returns the page in a pinned frame to change (unpin it with dirty = true afterwards)
+    pub fn data_mut(&mut self, frame: FrameId) -> &mut [u8] {
+        &mut self.frames[frame].data
+    }
+
+    /// Writes every dirty page to the file and syncs it
+    pub fn flush(&mut self) -> io::Result<()> {
+        let mut dirty: Vec<FrameId> = (0..self.frames.len()).filter(|&f| self.frames[f].dirty).collect();
+        // In page order, so the writes go through the file front to back
+        dirty.sort_by_key(|&f| self.frames[f].page);
+        for frame in dirty {
+            self.write_back(frame)?;
+        }
+        self.file.sync_data()
+    }
+
+    pub fn wal(&self) -> Option<&Wal> {
+        self.wal.as_ref()
+    }
+
+    pub fn wal_mut(&mut self) -> Option<&mut Wal> {
+        self.wal.as_mut()
+    }
+
+    /// Returns true if page is resident
+    #[cfg(test)]
+    pub fn is_resident(&self, page: PageId) -> bool {
+        self.page_table.contains_key(&page)
+    }
+
+    fn pin(&mut self, frame: FrameId) {
+        let f = &mut self.frames[frame];
+        if let Some(tick) = f.lru_tick.take() {
+            self.lru.remove(&tick);
+        }
+        f.pins += 1;
+    }
+
+    /// Returns a pinned frame now holding page (with stale data), evicting the least recently used
+    /// unpinned frame if every frame is taken
+    fn victim(&mut self, page: PageId) -> io::Result<FrameId> {
+        let frame = if self.frames.len() < self.capacity {
+            self.frames.push(Frame { page, data: vec![0; self.page_size].into_boxed_slice(), pins: 0, dirty: false, lru_tick: None, lsn: 0 });
+            self.frames.len() - 1
+        } else {
+            let Some((_, frame)) = self.lru.pop_first() else {
+                return Err(io::Error::other(format!("buffer pool exhausted: all {} frames are pinned", self.capacity)));
+            };
+            self.frames[frame].lru_tick = None;
+            if self.frames[frame].dirty
+                && let Err(err) = self.write_back(frame) {
+                // The frame keeps its page, so it goes back on the LRU list (last, so the next
+                // eviction tries another frame first)
+                self.tick += 1;
+                self.frames[frame].lru_tick = Some(self.tick);
+                self.lru.insert(self.tick, frame);
+                return Err(err);
+            }
+            self.stats.evictions += 1;
+            self.page_table.remove(&self.frames[frame].page);
+            frame
+        };
+        let f = &mut self.frames[frame];
+        f.page = page;
+        f.pins = 1;
+        f.dirty = false;
+        f.lsn = 0;
+        self.page_table.insert(page, frame);
+        Ok(frame)
+    }
+
+    /// Drops a pinned frame that never got its page (after a failed read)
+    fn release(&mut self, frame: FrameId) {
+        self.page_table.remove(&self.frames[frame].page);
+        self.frames[frame].pins = 0;
+        self.tick += 1;
+        self.frames[frame].lru_tick = Some(self.tick);
+        self.lru.insert(self.tick, frame);
+    }
+
+    fn write_back(&mut self, frame: FrameId) -> io::Result<()> {
+        let f = &mut self.frames[frame];
+        // The write-ahead rule: the commit that changed the page must be in the log on disk first
+        if let Some(wal) = &mut self.wal {
+            wal.sync_to(f.lsn)?;
+        }
+        self.file.seek(SeekFrom::Start(f.page * self.page_size as u64))?;
+        self.file.write_all(&f.data)?;
+        f.dirty = false;
+        self.stats.writes += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: usize = 64;
+
+    /// Returns a pool over a file of pages filled with their own page number
+    fn pool(pages: u64, capacity: usize) -> BufferPool {
+        let mut file = tempfile::tempfile().unwrap();
+        for page in 0..pages {
+            file.write_all(&[page as u8; PAGE]).unwrap();
+        }
+        BufferPool::new(file, PAGE, capacity, None)
+    }
+
+    #[test]
+    fn test_fetch_reads_pages() {
+        let mut pool = pool(4, 2);
+        for page in [0, 3, 1, 3] {
+            let frame = pool.fetch(page).unwrap();
+            assert!(pool.data(frame).iter().all(|&b| b == page as u8));
+            pool.unpin(frame, false);
+        }
+        assert_eq!(pool.stats(), PoolStats { hits: 1, misses: 3, evictions: 1, writes: 0 });
+        assert!(pool.fetch(4).is_err());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut pool = pool(5, 3);
+        for page in [0, 1, 2] {
+            let frame = pool.fetch(page).unwrap();
+            pool.unpin(frame, false);
+        }
+        // Using 0 again makes 1 the least recently used
+        let frame = pool.fetch(0).unwrap();
+        pool.unpin(frame, false);
+        let frame = pool.fetch(3).unwrap();
+        pool.unpin(frame, false);
+        assert!(!pool.is_resident(1));
+        assert!(pool.is_resident(0) && pool.is_resident(2) && pool.is_resident(3));
+        let frame = pool.fetch(4).unwrap();
+        pool.unpin(frame, false);
+        assert!(!pool.is_resident(2));
+    }
+
+    #[test]
+    fn test_pinned_pages_stay() {
+        let mut pool = pool(5, 2);
+        let pinned = pool.fetch(0).unwrap();
+        for page in 1..5 {
+            let frame = pool.fetch(page).unwrap();
+            pool.unpin(frame, false);
+            assert!(pool.is_resident(0));
+        }
+
+        // With both frames pinned there is nothing to evict
+        let other = pool.fetch(1).unwrap();
+        let err = pool.fetch(2).unwrap_err();
+        assert!(err.to_string().contains("all 2 frames are pinned"), "{}", err);
+        pool.unpin(other, false);
+        pool.unpin(pinned, false);
+        assert!(pool.fetch(2).is_ok());
+    }
+
+    #[test]
+    fn test_dirty_pages_written_back() {
+        let mut pool = pool(3, 1);
+        let frame = pool.fetch(1).unwrap();
+        pool.data_mut(frame).fill(0xaa);
+        pool.unpin(frame, true);
+
+        // Evicting page 1 writes it, and it reads back changed
+        let frame = pool.fetch(2).unwrap();
+        pool.unpin(frame, false);
+        assert_eq!(pool.stats().writes, 1);
+        let frame = pool.fetch(1).unwrap();
+        assert!(pool.data(frame).iter().all(|&b| b == 0xaa));
+        pool.unpin(frame, false);
+
+        // Clean pages are not written again
+        let frame = pool.fetch(0).unwrap();
+        pool.unpin(frame, false);
+        assert_eq!(pool.stats().writes, 1);
+    }
+
+    #[test]
+    fn test_failed_write_back_keeps_frame() {
+        // Writes to a file opened read-only fail
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0; 3 * PAGE]).unwrap();
+        let mut pool = BufferPool::new(File::open(file.path()).unwrap(), PAGE, 2, None);
+        for page in [0, 1] {
+            let frame = pool.fetch(page).unwrap();
+            pool.unpin(frame, page == 0);
+        }
+
+        // Evicting dirty page 0 fails, but its frame can still be evicted (or used) afterwards
+        assert!(pool.fetch(2).is_err());
+        assert!(pool.is_resident(0));
+        assert_eq!(pool.lru.len(), 2);
+        let frame = pool.fetch(2).unwrap();
+        pool.unpin(frame, false);
+        assert!(pool.is_resident(0) && !pool.is_resident(1));
+        let frame = pool.fetch(0).unwrap();
+        pool.unpin(frame, false);
+        assert_eq!(pool.stats().writes, 0);
+    }
+
+    #[test]
+    fn test_create_and_flush() {
+        let mut pool = pool(2, 4);
+        let frame = pool.create(2).unwrap();
+        assert!(pool.data(frame).iter().all(|&b| b == 0));
+        pool.data_mut(frame)[0] = 7;
+        pool.unpin(frame, true);
+        pool.flush().unwrap();
+        assert_eq!(pool.stats().writes, 1);
+        pool.flush().unwrap();
+        assert_eq!(pool.stats().writes, 1);
+
+        let mut bytes = vec![];
+        pool.file.seek(SeekFrom::Start(0)).unwrap();
+        pool.file.read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 3 * PAGE);
+        assert_eq!(bytes[2 * PAGE], 7);
+    }
+}
@@ -0,0 +1,307 @@
+// Page layouts of a PagedBTree file (every integer little-endian)
+//
+// Page 0 is the meta page:
+//   magic "BTPG" (4 bytes), version (u16), page size (u32), order (u32), max key length (u16),
+//   max value length (u16), root page (u64, 0 for an empty tree), page count (u64), head of the
+//   free page list (u64, 0 if empty), number of entries (u64)
+//
+// Every other page is a node or a free page:
+//   kind (u8): 1 leaf, 2 internal node, 3 free page
+//   leaf / internal node: entry count n (u16), then for an internal node its n+1 child pages (u64
+//     each), then the n entries, each a key length (u16), the key, a value length (u16) and the value
+//   free page: the next free page (u64, 0 at the end of the list)
+//
+// A node of order m holds at most m-1 entries of at most the max key and value lengths, so the order
+// is the largest (even) m for which m children and m-1 such entries fit in a page (see max_order).
+
+use std::io;
+
+/// Index of a page in the file (its offset is index * page size)
+pub(super) type PageId = u64;
+
+const MAGIC: [u8; 4] = *b"BTPG";
+const VERSION: u16 = 1;
+
+const LEAF: u8 = 1;
+const INTERNAL: u8 = 2;
+const FREE: u8 = 3;
+
+/// Bytes before the children of a node (kind and entry count)
+const NODE_HEADER: usize = 3;
+/// Bytes of an entry besides its key and value (the two lengths)
+const ENTRY_HEADER: usize = 4;
+const CHILD: usize = 8;
+
+/// Bytes of the meta page that are used
+pub(super) const META_LEN: usize = 50;
+
+/// Returns an InvalidData error
+pub(super) fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(message: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The contents of the meta page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Meta {
+    pub page_size: usize,
+    pub order: usize,
+    pub max_key_len: usize,
+    pub max_value_len: usize,
+    pub root: PageId,
+    pub page_count: u64,
+    pub free_head: PageId,
+    pub len: u64,
+}
+
+impl Meta {
+    pub fn encode(&self, page: &mut [u8]) {
+        let mut out = Cursor::new(page);
+        out.put(&MAGIC);
+        out.put(&VERSION.to_le_bytes());
+        out.put(&(self.page_size as u32).to_le_bytes());
+        out.put(&(self.order as u32).to_le_bytes());
+        out.put(&(self.max_key_len as u16).to_le_bytes());
+        out.put(&(self.max_value_len as u16).to_le_bytes());
+        out.put(&self.root.to_le_bytes());
+        out.put(&self.page_count.to_le_bytes());
+        out.put(&self.free_head.to_le_bytes());
+        out.put(&self.len.to_le_bytes());
+    }
+
+    /// Reads the meta page (only its first META_LEN bytes are needed)
+    pub fn decode(page: &[u8]) -> io::Result<Meta> {
+        let mut input = Reader::new(page);
+        if input.take(4)? != MAGIC {
+            return Err(invalid("not a PagedBTree file"));
+        }
+        let version = input.u16()?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported PagedBTree file version {}", version)));
+        }
+        let meta = Meta {
+            page_size: input.u32()? as usize,
+            order: input.u32()? as usize,
+            max_key_len: input.u16()? as usize,
+            max_value_len: input.u16()? as usize,
+            root: input.u64()?,
+            page_count: input.u64()?,
+            free_head: input.u64()?,
+            len: input.u64()?,
+        };
+        if meta.order < 3 || max_order(meta.page_size, meta.max_key_len, meta.max_value_len) < meta.order {
+            return Err(invalid(format!("order {} does not fit in pages of {} bytes", meta.order, meta.page_size)));
+        }
+        Ok(meta)
+    }
+}
+
+/// Returns the largest order whose full nodes fit in a page
+pub(super) fn max_order(page_size: usize, max_key_len: usize, max_value_len: usize) -> usize {
+    // m children and m-1 entries: NODE_HEADER + m * CHILD + (m-1) * entry <= page_size
+    let entry = ENTRY_HEADER + max_key_len + max_value_len;
+    (page_size + entry).saturating_sub(NODE_HEADER) / (CHILD + entry)
+}
+
+/// A key and its value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Entry {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+}
+
+/// A node read out of its page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct PageNode {
+    pub leaf: bool,
+    pub entries: Vec<Entry>,
+    pub children: Vec<PageId>,
+}
+
+impl PageNode {
+    /// Binary search of the keys
+    ///
+    /// Returns true and the idx of key if found, otherwise false and the idx of the smallest key greater than key
+    pub fn search(&self, key: &[u8]) -> (bool, usize) {
+        match self.entries.binary_search_by(|entry| entry.key.as_slice().cmp(key)) {
+            Ok(idx) => (true, idx),
+            Err(idx) => (false, idx),
+        }
+    }
+
+    /// Writes the node into a page (which it must fit in, see max_order)
+    pub fn encode(&self, page: &mut [u8]) {
+        let mut out = Cursor::new(page);
+        out.put(&[if self.leaf { LEAF } else { INTERNAL }]);
+        out.put(&(self.entries.len() as u16).to_le_bytes());
+        for child in &self.children {
+            out.put(&child.to_le_bytes());
+        }
+        for entry in &self.entries {
+            out.put(&(entry.key.len() as u16).to_le_bytes());
+            out.put(&entry.key);
+            out.put(&(entry.value.len() as u16).to_le_bytes());
+            out.put(&entry.value);
+        }
+    }
+
+    /// Reads a node from its page
+    pub fn decode(page: &[u8]) -> io::Result<PageNode> {
+        let mut input = Reader::new(page);
+        let leaf = match input.u8()? {
+            LEAF => true,
+            INTERNAL => false,
+            FREE => return Err(invalid("node page is on the free list")),
+            kind => return Err(invalid(format!("unknown page kind {}", kind))),
+        };
+        let count = input.u16()? as usize;
+        let mut children = Vec::new();
+        if !leaf {
+            children.reserve(count + 1);
+            for _ in 0..=count {
+                children.push(input.u64()?);
+            }
+        }
+        let mut entries = Vec::with_capacity(count);
+        for _ in 0..count {
+            let key_len = input.u16()? as usize;
+            let key = input.take(key_len)?.to_vec();
+            let value_len = input.u16()? as usize;
+            let value = input.take(value_len)?.to_vec();
+            entries.push(Entry { key, value });
+        }
+        Ok(PageNode { leaf, entries, children })
+    }
+}
+
+/// Marks a page as free, linking it to the next free page
+pub(super) fn encode_free(page: &mut [u8], next: PageId) {
+    page.fill(0);
+    let mut out = Cursor::new(page);
+    out.put(&[FREE]);
+    out.put(&next.to_le_bytes());
+}
+
+/// Returns the next free page after a free page
+pub(super) fn decode_free(page: &[u8]) -> io::Result<PageId> {
+    let mut input = Reader::new(page);
+    if input.u8()? != FREE {
+        return Err(invalid("page on the free list is not free"));
+    }
+    input.u64()
+}
+
+/// Writes bytes one after another into a page
+struct Cursor<'a> {
+    page: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(page: &'a mut [u8]) -> Self {
+        Cursor { page, pos: 0 }
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.page[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+}
+
+/// Reads bytes one after another from a page, failing if the page runs out
+struct Reader<'a> {
+    page: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(page: &'a [u8]) -> Self {
+        Reader { page, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self.page.get(self.pos..self.pos + len).ok_or_else(|| invalid("page overflows"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().expect("took 2 bytes")))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("took 4 bytes")))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("took 8 bytes")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, value: &str) -> Entry {
+        Entry { key: key.as_bytes().to_vec(), value: value.as_bytes().to_vec() }
+    }
+
+    #[test]
+    fn test_node_round_trip() {
+        let leaf = PageNode { leaf: true, entries: vec![entry("a", "1"), entry("bb", ""), entry("", "333")], children: vec![] };
+        let internal = PageNode { leaf: false, entries: vec![entry("m", "x")], children: vec![7, 9] };
+        for node in [leaf, internal, PageNode { leaf: true, entries: vec![], children: vec![] }] {
+            let mut page = vec![0; 128];
+            node.encode(&mut page);
+            assert_eq!(PageNode::decode(&page).unwrap(), node);
+        }
+    }
+
+    #[test]
+    fn test_meta_round_trip() {
+        let meta = Meta { page_size: 4096, order: 20, max_key_len: 64, max_value_len: 128, root: 3, page_count: 10, free_head: 5, len: 99 };
+        let mut page = vec![0; 4096];
+        meta.encode(&mut page);
+        assert_eq!(Meta::decode(&page[..META_LEN]).unwrap(), meta);
+
+        page[0] = b'X';
+        assert!(Meta::decode(&page).is_err());
+    }
+
+    #[test]
+    fn test_max_order_fills_page() {
+        for (page_size, key, value) in [(4096, 64, 128), (4096, 8, 8), (512, 16, 0), (65536, 255, 1024)] {
+            let order = max_order(page_size, key, value);
+            // A full node of the largest entries fits, and one more child and entry would not
+            let full = PageNode {
+                leaf: false,
+                entries: vec![Entry { key: vec![0; key], value: vec![0; value] }; order - 1],
+                children: vec![0; order],
+            };
+            let len = NODE_HEADER + order * CHILD + (order - 1) * (ENTRY_HEADER + key + value);
+            assert!(len <= page_size);
+            assert!(len + CHILD + ENTRY_HEADER + key + value > page_size);
+            full.encode(&mut vec![0; page_size]);
+        }
+    }
+
+    #[test]
+    fn test_free_pages() {
+        let mut page = vec![0xff; 64];
+        encode_free(&mut page, 12);
+        assert_eq!(decode_free(&page).unwrap(), 12);
+        assert!(PageNode::decode(&page).is_err());
+        assert!(decode_free(&[LEAF, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_truncated_page() {
+        let node = PageNode { leaf: true, entries: vec![entry("key", "value")], children: vec![] };
+        let mut page = vec![0; 64];
+        node.encode(&mut page);
+        assert!(PageNode::decode(&page[..8]).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+// Write-ahead log of a PagedBTree (the file next to it, with ".wal" added to its name)
+//
+// The log is redo only and page level: every insert or remove of the tree is one commit, logged as an
+// image of each page it changed (so a split logs both halves and the parent, and a merge the merged
+// node, the parent and the freed page), then a commit record with the meta page after it. Records:
+//   checksum (u32, CRC-32 of the rest of the record), body length (u32), kind (u8), body
+//   page image (kind 1): page (u64), then the page
+//   commit (kind 2): the meta page (see page.rs)
+//
+// Following the write-ahead rule, the pages of a commit only reach the buffer pool once the commit is
+// in the log, and the pool syncs the log up to the commit that last changed a page before writing that
+// page to the file (see BufferPool::write_back). So every page in the file is from a commit that is
+// in the log, or from before the last checkpoint. A checkpoint writes every changed page and the meta
+// page to the file, syncs it and empties the log.
+//
+// Recovery (when the tree is opened) reads the log up to the first record that is cut short or fails
+// its checksum, and writes the page images of every complete commit to the file in order, ending with
+// the meta page of the last one. Page images after the last commit record are from an operation that
+// never committed and are dropped. Replaying a commit twice leaves the same pages, so a crash during
+// recovery is recovered from the same way.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::page::{PageId, META_LEN};
+
+const PAGE_IMAGE: u8 = 1;
+const COMMIT: u8 = 2;
+
+/// Bytes of a record before its body (checksum, body length and kind)
+const RECORD_HEADER: usize = 9;
+
+/// When the write-ahead log of a PagedBTree is synced to disk
+///
+/// A commit that was synced survives a crash. Whatever the policy, the log is also synced at every
+/// checkpoint and whenever the buffer pool has to write a page from a commit that is not synced yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalSync {
+    /// At every commit, so every insert and remove that returned survives a crash
+    Always,
+    /// At every nth commit, so a crash loses at most the last n-1 commits
+    EveryN(usize),
+    /// Only when it has to be, so a crash loses the commits since the last sync
+    Never,
+}
+
+pub(super) struct Wal {
+    file: File,
+    /// Records not written to the file yet
+    buf: Vec<u8>,
+    /// Bytes of records written to the file
+    written: u64,
+    /// Bytes of records synced to disk
+    synced: u64,
+    sync: WalSync,
+    unsynced_commits: usize,
+}
+
+impl Wal {
+    /// Starts logging to an empty log file (after recovery)
+    pub fn new(file: File, sync: WalSync) -> Self {
+        if let WalSync::EveryN(n) = sync {
+            assert!(n > 0, "WalSync::EveryN needs at least 1 commit");
+        }
+        Wal { file, buf: vec![], written: 0, synced: 0, sync, unsynced_commits: 0 }
+    }
+
+    /// Returns the bytes of records logged since the last checkpoint
+    pub fn len(&self) -> u64 {
+        self.written + self.buf.len() as u64
+    }
+
+    /// Logs a commit of the given page images and meta page (without syncing it)
+    ///
+    /// Returns its log sequence number (the end of its commit record), which the log has to be synced
+    /// up to before any of its pages are written to the tree's file
+    pub fn log_commit<'a>(&mut self, pages: impl IntoIterator<Item = (PageId, &'a [u8])>, meta: &[u8]) -> u64 {
+        for (page, data) in pages {
+            self.record(PAGE_IMAGE, &[&page.to_le_bytes(), data]);
+        }
+        self.record(COMMIT, &[meta]);
+        self.unsynced_commits += 1;
+        self.len()
+    }
+
+    /// Syncs the log up to a commit just logged, if the policy says so
+    pub fn sync_commit(&mut self, lsn: u64) -> io::Result<()> {
+        match self.sync {
+            WalSync::Always => self.sync_to(lsn),
+            WalSync::EveryN(n) if self.unsynced_commits >= n => self.sync_to(lsn),
+            _ => Ok(()),
+        }
+    }
+
+    /// Makes sure every record up to lsn is synced to disk
+    pub fn sync_to(&mut self, lsn: u64) -> io::Result<()> {
+        if self.synced >= lsn {
+            return Ok(());
+        }
+        self.file.write_all(&self.buf)?;
+        self.written += self.buf.len() as u64;
+        self.buf.clear();
+        self.file.sync_data()?;
+        self.synced = self.written;
+        self.unsynced_commits = 0;
+        Ok(())
+    }
+
+    /// Empties the log (at a checkpoint, once every page it holds has been written to the tree's file)
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.buf.clear();
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.sync_data()?;
+        self.written = 0;
+        self.synced = 0;
+        self.unsynced_commits = 0;
+        Ok(())
+    }
+
+    fn record(&mut self, kind: u8, body: &[&[u8]]) {
+        let len: usize = body.iter().map(|part| part.len()).sum();
+        let start = self.buf.len();
+        self.buf.extend_from_slice(&[0; 4]);
+        self.buf.extend_from_slice(&(len as u32).to_le_bytes());
+        self.buf.push(kind);
+        for part in body {
+            self.buf.extend_from_slice(part);
+        }
+        let checksum = crc32fast::hash(&self.buf[start + 4..]);
+        self.buf[start..start + 4].copy_from_slice(&checksum.to_le_bytes());
+    }
+}
+
+/// Replays the complete commits in log onto data (a tree file with pages of page_size bytes), then
+/// syncs data and empties log
+///
+/// Returns the number of commits replayed
+pub(super) fn recover(data: &mut File, log: &mut File, page_size: usize) -> io::Result<usize> {
+    let mut bytes = vec![];
+    log.seek(SeekFrom::Start(0))?;
+    log.read_to_end(&mut bytes)?;
+
+    let mut commits = 0;
+    let mut pages: Vec<(PageId, &[u8])> = vec![];
+    let mut rest = bytes.as_slice();
+    while let Some((kind, body, next)) = next_record(rest) {
+        rest = next;
+        match kind {
+            PAGE_IMAGE if body.len() == 8 + page_size => {
+                let page = PageId::from_le_bytes(body[..8].try_into().expect("body holds a page id"));
+                pages.push((page, &body[8..]));
+            },
+            COMMIT if body.len() == META_LEN => {
+                for (page, image) in pages.drain(..) {
+                    data.seek(SeekFrom::Start(page * page_size as u64))?;
+                    data.write_all(image)?;
+                }
+                data.seek(SeekFrom::Start(0))?;
+                data.write_all(body)?;
+                commits += 1;
+            },
+            // A record that does not fit the tree cannot have been written by it, so the log ends here
+            _ => break,
+        }
+    }
+
+    data.sync_data()?;
+    log.set_len(0)?;
+    log.seek(SeekFrom::Start(0))?;
+    log.sync_data()?;
+    Ok(commits)
+}
+
+/// Returns the kind and body of the first record in bytes and the bytes after it, or None if it is cut
+/// short or fails its checksum
+fn next_record(bytes: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let header = bytes.get(..RECORD_HEADER)?;
+    let checksum = u32::from_le_bytes(header[..4].try_into().expect("header holds a checksum"));
+    let len = u32::from_le_bytes(header[4..8].try_into().expect("header holds a length")) as usize;
+    let end = RECORD_HEADER.checked_add(len)?;
+    let record = bytes.get(..end)?;
+    if crc32fast::hash(&record[4..]) != checksum {
+        return None;
+    }
+    Some((header[8], &record[RECORD_HEADER..], &bytes[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: usize = 64;
+
+    fn log_bytes(wal: &mut Wal) -> Vec<u8> {
+        wal.sync_to(u64::MAX).unwrap();
+        let mut bytes = vec![];
+        wal.file.seek(SeekFrom::Start(0)).unwrap();
+        wal.file.read_to_end(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_sync_policies() {
+        for (sync, synced) in [(WalSync::Always, [true, true, true]), (WalSync::EveryN(2), [false, true, false]), (WalSync::Never, [false; 3])] {
+            let mut wal = Wal::new(tempfile::tempfile().unwrap(), sync);
+            for expected in synced {
+                let lsn = wal.log_commit([(1, [0; PAGE].as_slice())], &[0; META_LEN]);
+                wal.sync_commit(lsn).unwrap();
+                assert_eq!(wal.synced == lsn, expected, "{:?}", sync);
+            }
+            assert_eq!(wal.len(), 3 * (2 * RECORD_HEADER + 8 + PAGE + META_LEN) as u64);
+            wal.reset().unwrap();
+            assert_eq!(wal.len(), 0);
+            assert!(log_bytes(&mut wal).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_recover_complete_commits() {
+        let mut wal = Wal::new(tempfile::tempfile().unwrap(), WalSync::Never);
+        let meta = |n: u8| [n; META_LEN];
+        wal.log_commit([(1, [1; PAGE].as_slice()), (2, [2; PAGE].as_slice())], &meta(1));
+        wal.log_commit([(1, [3; PAGE].as_slice())], &meta(2));
+        let complete = log_bytes(&mut wal);
+        // Page images without their commit are dropped
+        wal.record(PAGE_IMAGE, &[&2u64.to_le_bytes(), &[4; PAGE]]);
+        let bytes = log_bytes(&mut wal);
+
+        // Each cut of the log replays the commits that are whole in it
+        let commit_ends = [2 * RECORD_HEADER + 2 * (8 + PAGE) + META_LEN + RECORD_HEADER, complete.len()];
+        for cut in 0..=bytes.len() {
+            let mut data = tempfile::tempfile().unwrap();
+            data.write_all(&[0; 3 * PAGE]).unwrap();
+            let mut log = tempfile::tempfile().unwrap();
+            log.write_all(&bytes[..cut]).unwrap();
+
+            let commits = recover(&mut data, &mut log, PAGE).unwrap();
+            assert_eq!(commits, commit_ends.iter().filter(|&&end| end <= cut).count());
+            assert_eq!(log.metadata().unwrap().len(), 0);
+
+            let mut file = vec![];
+            data.seek(SeekFrom::Start(0)).unwrap();
+            data.read_to_end(&mut file).unwrap();
+            let (meta, page1, page2) = match commits {
+                0 => (0, 0, 0),
+                1 => (1, 1, 2),
+                _ => (2, 3, 2),
+            };
+            assert!(file[..META_LEN].iter().all(|&b| b == meta), "cut {}", cut);
+            assert!(file[PAGE..2 * PAGE].iter().all(|&b| b == page1), "cut {}", cut);
+            assert!(file[2 * PAGE..].iter().all(|&b| b == page2), "cut {}", cut);
+        }
+    }
+
+    #[test]
+    fn test_recover_stops_at_corruption() {
+        let mut wal = Wal::new(tempfile::tempfile().unwrap(), WalSync::Never);
+        wal.log_commit([(1, [1; PAGE].as_slice())], &[1; META_LEN]);
+        wal.log_commit([(1, [2; PAGE].as_slice())], &[2; META_LEN]);
+        let mut bytes = log_bytes(&mut wal);
+        let second = bytes.len() / 2;
+        bytes[second + RECORD_HEADER + 3] ^= 1;
+
+        let mut data = tempfile::tempfile().unwrap();
+        let mut log = tempfile::tempfile().unwrap();
+        log.write_all(&bytes).unwrap();
+        assert_eq!(recover(&mut data, &mut log, PAGE).unwrap(), 1);
+    }
+}
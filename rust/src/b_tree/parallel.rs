@@ -0,0 +1,373 @@
+// Parallel iteration and bulk construction with rayon (enabled with the "rayon" cargo feature)
+//
+// par_iter and par_range hand out the keys of the tree (in order) to rayon's thread pool. The work is
+// split at subtree boundaries: a piece of work is a run of whole subtrees and single keys, in order.
+// A run of several pieces splits into two halves, and a run of one internal subtree splits by
+// replacing the subtree with its children and the keys between them. So each thread ends up walking
+// whole subtrees on its own, and nodes are never copied. For par_range, subtrees whose separators put
+// them entirely outside the range are dropped when they are split off, and skipped while walking.
+//
+// Collecting (FromParallelIterator) and extending (ParallelExtend) sort the new keys in parallel,
+// merge them with any keys already in the tree, and then build the tree from the sorted keys in one
+// pass (see bulk.rs). Duplicates are kept, as with BTree::insert.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::ops::{Bound, RangeBounds};
+
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::iter::{FromParallelIterator, IntoParallelIterator, ParallelExtend, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+
+use super::{BTree, Node};
+
+/// The order of a tree made with from_par_iter (or collect). For another order, make the tree with
+/// BTree::new and par_extend it
+const FROM_PAR_ITER_ORDER: usize = 16;
+
+/// Parallel iterator over (a range of) the keys of a BTree, in order
+///
+/// Created with BTree::par_iter or BTree::par_range
+pub struct ParIter<'a, T: PartialOrd + Debug + Clone> {
+    root: Option<&'a Node<T>>,
+    range: (Bound<T>, Bound<T>),
+}
+
+/// One piece of the work a producer holds
+enum Piece<'a, T: PartialOrd + Debug + Clone> {
+    Subtree(&'a Node<T>),
+    Key(&'a T),
+}
+
+/// Producer over a run of pieces, which it splits at subtree boundaries
+struct TreeProducer<'a, 'r, T: PartialOrd + Debug + Clone> {
+    pieces: Vec<Piece<'a, T>>,
+    range: &'r (Bound<T>, Bound<T>),
+}
+
+impl<T: PartialOrd + Debug + Clone + Send + Sync> BTree<T> {
+    /// Returns a parallel iterator over all the keys of the tree, in order
+    pub fn par_iter(&self) -> ParIter<'_, T> {
+        self.par_range(..)
+    }
+
+    /// Returns a parallel iterator over the keys of the tree within range, in order
+    ///
+    /// Subtrees outside the range are skipped, so e.g. par_range(a..b).count() only visits the
+    /// nodes on the paths to a and b and the subtrees in between
+    pub fn par_range<R: RangeBounds<T>>(&self, range: R) -> ParIter<'_, T> {
+        ParIter {
+            root: self.root.as_deref(),
+            range: (range.start_bound().cloned(), range.end_bound().cloned()),
+        }
+    }
+}
+
+impl<'a, T: PartialOrd + Debug + Clone + Send + Sync> ParallelIterator for ParIter<'a, T> {
+    type Item = &'a T;
+
+    fn drive_unindexed<C: UnindexedConsumer<Self::Item>>(self, consumer: C) -> C::Result {
+        let producer = TreeProducer { pieces: self.root.map(Piece::Subtree).into_iter().collect(), range: &self.range };
+        bridge_unindexed(producer, consumer)
+    }
+}
+
+impl<'a, T: PartialOrd + Debug + Clone + Send + Sync> IntoParallelIterator for &'a BTree<T> {
+    type Iter = ParIter<'a, T>;
+    type Item = &'a T;
+
+    fn into_par_iter(self) -> Self::Iter {
+        self.par_iter()
+    }
+}
+
+impl<'a, 'r, T: PartialOrd + Debug + Clone + Send + Sync> UnindexedProducer for TreeProducer<'a, 'r, T> {
+    type Item = &'a T;
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        if let [Piece::Subtree(node)] = self.pieces[..]
+            && !node.leaf
+        {
+            self.pieces = expand(node, self.range);
+        }
+        if self.pieces.len() < 2 {
+            return (self, None);
+        }
+        let right = self.pieces.split_off(self.pieces.len() / 2);
+        let range = self.range;
+        (self, Some(TreeProducer { pieces: right, range }))
+    }
+
+    fn fold_with<F: Folder<Self::Item>>(self, mut folder: F) -> F {
+        for piece in self.pieces {
+            if folder.full() {
+                break;
+            }
+            folder = match piece {
+                Piece::Subtree(node) => fold_node(node, self.range, folder),
+                Piece::Key(key) => folder.consume(key),
+            };
+        }
+        folder
+    }
+}
+
+/// Returns true if every value up to bound is below the range
+///
+/// Duplicates of a separator can sit on both sides of it, so a child bounded by the start itself
+/// may still hold keys within the range
+fn below_range<T: PartialOrd>(range: &(Bound<T>, Bound<T>), bound: &T) -> bool {
+    match &range.0 {
+        Bound::Included(start) => bound < start,
+        Bound::Excluded(start) => bound <= start,
+        Bound::Unbounded => false,
+    }
+}
+
+/// Returns true if every value from bound on is above the range
+fn above_range<T: PartialOrd>(range: &(Bound<T>, Bound<T>), bound: &T) -> bool {
+    match &range.1 {
+        Bound::Included(end) => bound > end,
+        Bound::Excluded(end) => bound >= end,
+        Bound::Unbounded => false,
+    }
+}
+
+/// Returns true if the child at idx (between keys idx-1 and idx) may hold keys within the range
+fn child_in_range<T: PartialOrd + Debug + Clone>(node: &Node<T>, idx: usize, range: &(Bound<T>, Bound<T>)) -> bool {
+    let below = node.keys.get(idx).is_some_and(|upper| below_range(range, upper));
+    let above = idx > 0 && above_range(range, &node.keys[idx - 1]);
+    !below && !above
+}
+
+/// Splits an internal node into its children and the keys between them, leaving out any outside the range
+fn expand<'a, T: PartialOrd + Debug + Clone>(node: &'a Node<T>, range: &(Bound<T>, Bound<T>)) -> Vec<Piece<'a, T>> {
+    let mut pieces = Vec::with_capacity(2 * node.keys.len() + 1);
+    for (i, child) in node.children.iter().enumerate() {
+        if child_in_range(node, i, range) {
+            pieces.push(Piece::Subtree(child));
+        }
+        if let Some(key) = node.keys.get(i)
+            && range.contains(key)
+        {
+            pieces.push(Piece::Key(key));
+        }
+    }
+    pieces
+}
+
+/// Feeds the keys of a subtree within the range to folder in order, until it is full
+fn fold_node<'a, T: PartialOrd + Debug + Clone, F: Folder<&'a T>>(node: &'a Node<T>, range: &(Bound<T>, Bound<T>), mut folder: F) -> F {
+    for i in 0..=node.keys.len() {
+        // Everything from here on is at least keys[i-1]
+        if folder.full() || (i > 0 && above_range(range, &node.keys[i - 1])) {
+            break;
+        }
+        if !node.leaf && child_in_range(node, i, range) {
+            folder = fold_node(&node.children[i], range, folder);
+        }
+        if let Some(key) = node.keys.get(i)
+            && range.contains(key)
+        {
+            folder = folder.consume(key);
+        }
+    }
+    folder
+}
+
+/// Sorts keys in parallel
+fn sort_keys<T: PartialOrd + Send>(keys: &mut [T]) {
+    keys.par_sort_unstable_by(|a, b| a.partial_cmp(b).expect("BTree keys must be comparable"));
+}
+
+impl<T: PartialOrd + Debug + Clone + Send + Sync> FromParallelIterator<T> for BTree<T> {
+    /// Builds a tree of order 16 from the values (see FROM_PAR_ITER_ORDER)
+    fn from_par_iter<I: IntoParallelIterator<Item = T>>(par_iter: I) -> Self {
+        let mut btree = BTree::new(FROM_PAR_ITER_ORDER);
+        btree.par_extend(par_iter);
+        btree
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone + Send + Sync> ParallelExtend<T> for BTree<T> {
+    /// Inserts the values (keeping duplicates, as insert does)
+    ///
+    /// This rebuilds the whole tree from its keys and the new values (see bulk.rs), so it is meant
+    /// for batches that are large next to the tree
+    fn par_extend<I: IntoParallelIterator<Item = T>>(&mut self, par_iter: I) {
+        let mut values: Vec<T> = par_iter.into_par_iter().collect();
+        if values.is_empty() {
+            return;
+        }
+        sort_keys(&mut values);
+
+        let existing: Vec<T> = self.par_iter().cloned().collect();
+        let keys = if existing.is_empty() { values } else { merge(existing, values) };
+        self.bulk_load(keys);
+    }
+}
+
+/// Merges two sorted runs of keys into one, keeping every copy of keys in both
+fn merge<T: PartialOrd>(left: Vec<T>, right: Vec<T>) -> Vec<T> {
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        match l.partial_cmp(r).expect("BTree keys must be comparable") {
+            Ordering::Greater => merged.extend(right.next()),
+            _ => merged.extend(left.next()),
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    /// Returns a tree (built by inserting) and a set holding the same random values
+    fn random_tree(order: usize, n: usize, seed: u64) -> (BTree<i64>, BTreeSet<i64>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut btree = BTree::new(order);
+        let mut set = BTreeSet::new();
+        for _ in 0..n {
+            let val = rng.random_range(-10_000..10_000);
+            if set.insert(val) {
+                btree.insert(val);
+            }
+        }
+        (btree, set)
+    }
+
+    #[test]
+    fn test_par_iter_in_order() {
+        for order in [3, 4, 5, 8, 32] {
+            let (btree, set) = random_tree(order, 5000, order as u64);
+            let keys: Vec<i64> = btree.par_iter().copied().collect();
+            assert_eq!(keys, set.iter().copied().collect::<Vec<_>>());
+            assert_eq!(btree.par_iter().count(), set.len());
+            assert_eq!((&btree).into_par_iter().copied().sum::<i64>(), set.iter().sum::<i64>());
+        }
+    }
+
+    #[test]
+    fn test_par_iter_empty_tree() {
+        let btree: BTree<i64> = BTree::new(4);
+        assert_eq!(btree.par_iter().count(), 0);
+        assert_eq!(btree.par_range(0..10).count(), 0);
+    }
+
+    #[test]
+    fn test_par_range_aggregations() {
+        let (btree, set) = random_tree(6, 5000, 1);
+        let ranges: [(Bound<i64>, Bound<i64>); 8] = [
+            (Bound::Included(-500), Bound::Excluded(500)),
+            (Bound::Excluded(-500), Bound::Included(500)),
+            (Bound::Unbounded, Bound::Excluded(0)),
+            (Bound::Included(0), Bound::Unbounded),
+            (Bound::Included(3), Bound::Included(3)),
+            (Bound::Included(20_000), Bound::Unbounded),
+            (Bound::Unbounded, Bound::Excluded(-20_000)),
+            (Bound::Unbounded, Bound::Unbounded),
+        ];
+        for range in ranges {
+            let expected: Vec<i64> = set.range(range).copied().collect();
+            assert_eq!(btree.par_range(range).copied().collect::<Vec<_>>(), expected, "{:?}", range);
+            assert_eq!(btree.par_range(range).count(), expected.len());
+            assert_eq!(btree.par_range(range).copied().sum::<i64>(), expected.iter().sum::<i64>());
+            assert_eq!(btree.par_range(range).filter(|v| *v % 3 == 0).count(), expected.iter().filter(|v| *v % 3 == 0).count());
+            assert_eq!(btree.par_range(range).max(), expected.iter().max());
+        }
+
+        // Copies of a separator sit on both sides of it, so neither child can be pruned
+        for order in 3..=6 {
+            let mut btree = BTree::new(order);
+            for i in 0..200 {
+                btree.insert(i % 10);
+            }
+            assert_eq!(btree.par_range(5..=5).count(), 20);
+            assert_eq!(btree.par_range(..=5).count(), 120);
+            assert_eq!(btree.par_range(5..).copied().sum::<i32>(), 20 * (5 + 6 + 7 + 8 + 9));
+            assert_eq!(btree.par_range((Bound::Excluded(4), Bound::Excluded(6))).count(), 20);
+            assert_eq!(btree.par_range(4..6).filter(|v| **v == 5).count(), 20);
+        }
+    }
+
+    #[test]
+    fn test_par_range_short_circuits() {
+        let btree: BTree<i64> = (0..100_000).into_par_iter().collect();
+        assert_eq!(btree.par_range(5000..).find_first(|v| *v % 1000 == 999), Some(&5999));
+        assert!(btree.par_iter().any(|v| *v == 99_999));
+        assert!(!btree.par_range(..50_000).any(|v| *v == 99_999));
+    }
+
+    #[test]
+    fn test_from_par_iter() {
+        let mut values: Vec<i64> = (0..20_000).chain(0..5000).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(2));
+        let btree: BTree<i64> = values.clone().into_par_iter().collect();
+        btree.check_invariants();
+        assert_eq!(btree.order(), FROM_PAR_ITER_ORDER);
+        values.sort();
+        assert_eq!(btree.par_iter().copied().collect::<Vec<_>>(), values);
+    }
+
+    #[test]
+    fn test_par_extend() {
+        for order in [3, 4, 7] {
+            let (mut btree, mut set) = random_tree(order, 2000, 3);
+            let mut rng = StdRng::seed_from_u64(4);
+            // New values only, so the tree keeps matching the set (see test_par_extend_keeps_duplicates)
+            let batch: BTreeSet<i64> = (0..5000).map(|_| rng.random_range(-15_000..15_000)).filter(|val| !set.contains(val)).collect();
+            set.extend(batch.iter().copied());
+            btree.par_extend(batch);
+            btree.par_extend(Vec::new());
+            btree.check_invariants();
+            assert_eq!(btree.order(), order);
+            assert_eq!(btree.par_iter().copied().collect::<Vec<_>>(), set.iter().copied().collect::<Vec<_>>());
+
+            // Still a normal tree afterwards
+            for val in -15_000..0 {
+                if set.remove(&val) {
+                    btree.delete(val);
+                }
+            }
+            btree.check_invariants();
+            assert_eq!(btree.par_iter().copied().collect::<Vec<_>>(), set.iter().copied().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_par_extend_keeps_duplicates() {
+        // The same multiset of keys as inserting the values one by one, into a tree already holding duplicates
+        for order in [3, 4, 7, 16] {
+            let mut rng = StdRng::seed_from_u64(order as u64);
+            let mut btree = BTree::new(order);
+            for _ in 0..500 {
+                btree.insert(rng.random_range(0..100i64));
+            }
+            let mut inserted = btree.clone();
+            let batch: Vec<i64> = (0..3000).map(|_| rng.random_range(-50..150)).collect();
+            for &val in &batch {
+                inserted.insert(val);
+            }
+
+            btree.par_extend(batch);
+            btree.check_invariants();
+            assert_eq!(btree.par_iter().copied().collect::<Vec<_>>(), inserted.par_iter().copied().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        assert_eq!(merge(vec![1, 3, 5, 7], vec![2, 3, 4, 8, 9]), vec![1, 2, 3, 3, 4, 5, 7, 8, 9]);
+        assert_eq!(merge(vec![], vec![1, 2]), vec![1, 2]);
+        assert_eq!(merge(vec![1, 2], vec![]), vec![1, 2]);
+    }
+}
@@ -0,0 +1,565 @@
+// Persistent (immutable) BTree
+//
+// Nodes are shared between versions of the tree through Arc, and are never changed once they are part
+// of a version. insert and remove copy the nodes on the path from the root down to the leaf they change
+// (path copying), along with any sibling that a split, rotation or merge changes, and return a new
+// version whose root points at the copies. Every other node is shared with the old version, so an
+// update allocates O(log n) nodes, and the old version stays valid and unchanged. Cloning a version
+// (taking a snapshot) only clones the root Arc.
+//
+// Insertion is bottom-up (see InsertStrategy::BottomUp), so it works for every order: the value is
+// inserted into a copy of its leaf, and a copied node that overflows is split by its parent's copy on
+// the way back up. Deletion is bottom-up as well: the value is removed from the copied path, and a
+// copied child left with too few keys borrows a key from a sibling (rotate) or is merged with one on
+// the way back up. Either way only copies are changed.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::NodeSearch;
+
+/// A B-tree whose updates return a new version that shares unchanged nodes with the old one
+#[derive(Clone)]
+pub struct PersistentBTree<T: PartialOrd + Debug + Clone> {
+    root: Option<Arc<Node<T>>>,
+    order: usize,
+    len: usize,
+}
+
+#[derive(Clone)]
+struct Node<T: PartialOrd + Debug + Clone> {
+    keys: Vec<T>,
+    children: Vec<Arc<Node<T>>>,
+    leaf: bool,
+}
+
+impl<T: PartialOrd + Debug + Clone> PersistentBTree<T> {
+    /// Constructor method for PersistentBTree
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    pub fn new(m: usize) -> Self {
+        assert!(m >= 3, "BTree order must be at least 3");
+        PersistentBTree { root: None, order: m, len: 0 }
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the number of keys in this version
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if this version holds no keys
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Traverse method for PersistentBTree
+    ///
+    /// Traverses through all keys for all nodes in order and prints them out
+    pub fn traverse(&self) {
+        match &self.root {
+            Some(r) => r.traverse(),
+            None => println!("=== EMPTY BTREE ==="),
+        }
+    }
+
+    /// Returns all keys of this version in order
+    pub fn to_vec(&self) -> Vec<T> {
+        let mut keys = Vec::with_capacity(self.len);
+        if let Some(root) = &self.root {
+            root.collect(&mut keys);
+        }
+        keys
+    }
+
+    /// Search method for PersistentBTree
+    ///
+    /// Returns true if value is present, false otherwise
+    pub fn search(&self, value: T) -> bool {
+        let mut node = match &self.root {
+            Some(r) => r,
+            None => return false,
+        };
+
+        loop {
+            let (found, idx) = NodeSearch::Binary.search(&node.keys, &value);
+            if found {
+                return true;
+            }
+            if node.leaf {
+                return false;
+            }
+            node = &node.children[idx];
+        }
+    }
+
+    /// Returns a new version with value inserted, sharing every node off the insert path with this one
+    pub fn insert(&self, value: T) -> Self {
+        let root = match &self.root {
+            Some(r) => {
+                let root = r.inserted(value, self.order);
+                if root.keys.len() > self.order - 1 {
+                    // The copied root overflowed, so it becomes the only child of a new root and is split
+                    let mut new_root = Node { keys: vec![], children: vec![], leaf: false };
+                    new_root.put_split(0, root);
+                    new_root
+                } else {
+                    root
+                }
+            },
+            None => Node { keys: vec![value], children: vec![], leaf: true },
+        };
+        PersistentBTree { root: Some(Arc::new(root)), order: self.order, len: self.len + 1 }
+    }
+
+    /// Returns a new version with value removed, sharing every node off the delete path with this one
+    ///
+    /// If value is not present, the new version is a clone of this one
+    pub fn remove(&self, value: T) -> Self {
+        let root = match self.root.as_ref().and_then(|r| r.removed(&value, self.order)) {
+            Some(root) => root,
+            None => return self.clone(),
+        };
+
+        // Shrink tree if root is empty, reusing its only child (shared) as the new root
+        let root = if !root.keys.is_empty() {
+            Some(Arc::new(root))
+        } else if root.leaf {
+            None
+        } else {
+            Some(Arc::clone(&root.children[0]))
+        };
+        PersistentBTree { root, order: self.order, len: self.len - 1 }
+    }
+
+    /// Helper (test) function that checks every non-root node holds between floor(K/2) and K keys,
+    /// keys are in order and every leaf is at the same depth
+    #[cfg(test)]
+    fn check_invariants(&self) {
+        fn walk<T: PartialOrd + Debug + Clone>(node: &Node<T>, order: usize, depth: usize, leaf_depth: &mut Option<usize>) {
+            assert!(node.keys.len() < order, "Node overfull: {:?}", node.keys);
+            if depth > 0 {
+                assert!(node.keys.len() >= (order - 1) / 2, "Node underfull: {:?}", node.keys);
+            }
+            if node.leaf {
+                assert_eq!(*leaf_depth.get_or_insert(depth), depth);
+                return;
+            }
+            assert_eq!(node.children.len(), node.keys.len() + 1);
+            for child in &node.children {
+                walk(child, order, depth + 1, leaf_depth);
+            }
+        }
+        if let Some(root) = &self.root {
+            walk(root, self.order, 0, &mut None);
+        }
+        let keys = self.to_vec();
+        assert_eq!(keys.len(), self.len);
+        assert!(keys.windows(2).all(|w| w[0] < w[1]), "Keys out of order: {:?}", keys);
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    /// Traverses and prints out all of the keys recursively
+    fn traverse(&self) {
+        for i in 0..self.keys.len() {
+            if !self.leaf {
+                self.children[i].traverse();
+            }
+            print!("{:?} ", self.keys[i]);
+        }
+        if !self.leaf {
+            self.children[self.keys.len()].traverse();
+        }
+    }
+
+    /// Pushes clones of all of the keys in order
+    fn collect(&self, keys: &mut Vec<T>) {
+        for i in 0..self.keys.len() {
+            if !self.leaf {
+                self.children[i].collect(keys);
+            }
+            keys.push(self.keys[i].clone());
+        }
+        if !self.leaf {
+            self.children[self.keys.len()].collect(keys);
+        }
+    }
+
+    /// Returns a copy of this node with value inserted into its subtree (called recursively)
+    ///
+    /// The copy may hold m keys, which the caller then splits
+    fn inserted(&self, value: T, order: usize) -> Node<T> {
+        let (_, idx) = NodeSearch::Binary.search(&self.keys, &value);
+
+        // Copy this node (the keys, and the Arcs of its children)
+        let mut node = self.clone();
+        if node.leaf {
+            // Base case: insert into the copied leaf even if it overflows
+            node.keys.insert(idx, value);
+            return node;
+        }
+
+        let child = self.children[idx].inserted(value, order);
+        if child.keys.len() > order - 1 {
+            node.put_split(idx, child);
+        } else {
+            node.children[idx] = Arc::new(child);
+        }
+        node
+    }
+
+    /// Splits an overflowing copy of the child at child_idx around its median, and puts both halves in
+    /// its place with the median between them
+    fn put_split(&mut self, child_idx: usize, mut child: Node<T>) {
+        let mid = child.keys.len() / 2;
+
+        // Right half gets the keys (and children) after the median
+        let right_keys = child.keys.split_off(mid + 1);
+        let right_children = if child.leaf { vec![] } else { child.children.split_off(mid + 1) };
+        let median = child.keys.pop().expect("Child has no median key");
+        let right = Node { keys: right_keys, children: right_children, leaf: child.leaf };
+
+        // Left half stays at child_idx, with the median and right half after it
+        if child_idx < self.children.len() {
+            self.children[child_idx] = Arc::new(child);
+        } else {
+            self.children.push(Arc::new(child));
+        }
+        self.keys.insert(child_idx, median);
+        self.children.insert(child_idx + 1, Arc::new(right));
+    }
+
+    /// Returns a copy of this node with value removed from its subtree, or None if it is not there
+    /// (called recursively)
+    ///
+    /// The copy may be left with too few keys, which the caller then fixes
+    fn removed(&self, value: &T, order: usize) -> Option<Node<T>> {
+        let (found, idx) = NodeSearch::Binary.search(&self.keys, value);
+        if self.leaf {
+            if !found {
+                return None;
+            }
+            let mut node = self.clone();
+            node.keys.remove(idx);
+            return Some(node);
+        }
+
+        if found {
+            // Internal node: replace the value with its predecessor, removed from the left subtree
+            let (pred, child) = self.children[idx].removed_max(order);
+            let mut node = self.clone();
+            node.keys[idx] = pred;
+            node.put_child(idx, child, order);
+            Some(node)
+        } else {
+            // Not found here, so remove from the subtree it belongs in (copying this node only if it is there)
+            let child = self.children[idx].removed(value, order)?;
+            let mut node = self.clone();
+            node.put_child(idx, child, order);
+            Some(node)
+        }
+    }
+
+    /// Returns the max key of the subtree, and a copy of this node with it removed (called recursively)
+    fn removed_max(&self, order: usize) -> (T, Node<T>) {
+        let mut node = self.clone();
+        if node.leaf {
+            let max = node.keys.pop().expect("Leaf node missing keys");
+            return (max, node);
+        }
+
+        let last = node.children.len() - 1;
+        let (max, child) = self.children[last].removed_max(order);
+        node.put_child(last, child, order);
+        (max, node)
+    }
+
+    /// Puts a copy of the child at child_idx in its place, first borrowing a key from a sibling or
+    /// merging it with one if it has too few keys
+    fn put_child(&mut self, child_idx: usize, child: Node<T>, order: usize) {
+        let min_keys = (order - 1) / 2;
+        if child.keys.len() >= min_keys {
+            self.children[child_idx] = Arc::new(child);
+        } else if child_idx > 0 && self.children[child_idx - 1].keys.len() > min_keys {
+            // Left sibling can spare a key -> rotate to right
+            self.rotate_right(child_idx, child);
+        } else if child_idx + 1 < self.children.len() && self.children[child_idx + 1].keys.len() > min_keys {
+            // Right sibling can spare a key -> rotate to left
+            self.rotate_left(child_idx, child);
+        } else if child_idx > 0 {
+            // Merge into a copy of the left sibling
+            let left = Node::clone(&self.children[child_idx - 1]);
+            self.merge(child_idx - 1, left, child);
+        } else {
+            // Merge a copy of the right sibling into the child
+            let right = Node::clone(&self.children[child_idx + 1]);
+            self.merge(child_idx, child, right);
+        }
+    }
+
+    /// Helper that moves last key from a copy of the left sibling to parent and parent key to the child's first key
+    ///
+    /// Takes a child_idx that represents the (right) child's index, and the copy of the child
+    fn rotate_right(&mut self, child_idx: usize, mut child: Node<T>) {
+        let mut left = Node::clone(&self.children[child_idx - 1]);
+
+        // Swap the left sibling's last key in for the middle key, which becomes the child's first key
+        let last_key = left.keys.pop().expect("Left child has no keys");
+        let middle_key = std::mem::replace(&mut self.keys[child_idx - 1], last_key);
+        child.keys.insert(0, middle_key);
+
+        // Move left sibling's last child to the child's first child
+        if !left.leaf {
+            let last_child = left.children.pop().expect("Left child has no children");
+            child.children.insert(0, last_child);
+        }
+
+        self.children[child_idx - 1] = Arc::new(left);
+        self.children[child_idx] = Arc::new(child);
+    }
+
+    /// Helper that moves first key from a copy of the right sibling to parent and parent key to the child's last key
+    ///
+    /// Takes a child_idx that represents the (left) child's index, and the copy of the child
+    fn rotate_left(&mut self, child_idx: usize, mut child: Node<T>) {
+        let mut right = Node::clone(&self.children[child_idx + 1]);
+
+        // Swap the right sibling's first key in for the middle key, which becomes the child's last key
+        let first_key = right.keys.remove(0);
+        let middle_key = std::mem::replace(&mut self.keys[child_idx], first_key);
+        child.keys.push(middle_key);
+
+        // Move right sibling's first child to the child's last child
+        if !right.leaf {
+            let first_child = right.children.remove(0);
+            child.children.push(first_child);
+        }
+
+        self.children[child_idx] = Arc::new(child);
+        self.children[child_idx + 1] = Arc::new(right);
+    }
+
+    /// Helper that merges copies of two children and the middle key between them into one node
+    ///
+    /// Takes a child_idx that represents the left child, and the copies of the left and right children
+    fn merge(&mut self, child_idx: usize, mut left: Node<T>, right: Node<T>) {
+        let middle_key = self.keys.remove(child_idx);
+        self.children.remove(child_idx + 1);
+
+        left.keys.push(middle_key);
+        left.keys.extend(right.keys);
+        left.children.extend(right.children);
+        self.children[child_idx] = Arc::new(left);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use std::collections::{BTreeSet, HashSet};
+
+    /// Collects the address of every node in a version
+    fn node_ptrs<T: PartialOrd + Debug + Clone>(btree: &PersistentBTree<T>) -> HashSet<*const Node<T>> {
+        fn walk<T: PartialOrd + Debug + Clone>(node: &Arc<Node<T>>, ptrs: &mut HashSet<*const Node<T>>) {
+            ptrs.insert(Arc::as_ptr(node));
+            for child in &node.children {
+                walk(child, ptrs);
+            }
+        }
+        let mut ptrs = HashSet::new();
+        if let Some(root) = &btree.root {
+            walk(root, &mut ptrs);
+        }
+        ptrs
+    }
+
+    /// Returns the number of levels in a version
+    fn height<T: PartialOrd + Debug + Clone>(btree: &PersistentBTree<T>) -> usize {
+        let mut height = 0;
+        let mut node = btree.root.as_ref();
+        while let Some(n) = node {
+            height += 1;
+            node = n.children.first();
+        }
+        height
+    }
+
+    fn build(order: usize, values: &[i32]) -> PersistentBTree<i32> {
+        values.iter().fold(PersistentBTree::new(order), |btree, &val| btree.insert(val))
+    }
+
+    #[test]
+    fn test_new_persistent_btree() {
+        let btree: PersistentBTree<i32> = PersistentBTree::new(4);
+        assert_eq!(btree.order(), 4);
+        assert!(btree.is_empty());
+        assert!(!btree.search(1));
+        assert_eq!(btree.to_vec(), Vec::<i32>::new());
+        btree.traverse();
+    }
+
+    #[test]
+    #[should_panic(expected = "BTree order must be at least 3")]
+    fn test_invalid_order() {
+        let _btree: PersistentBTree<i32> = PersistentBTree::new(2);
+    }
+
+    #[test]
+    fn test_old_versions_unchanged() {
+        let mut versions = vec![PersistentBTree::new(3)];
+        for i in 1..=20 {
+            let next = versions.last().unwrap().insert(i);
+            versions.push(next);
+        }
+        for i in (1..=20).step_by(2) {
+            let next = versions.last().unwrap().remove(i);
+            versions.push(next);
+        }
+
+        for (i, version) in versions.iter().enumerate() {
+            version.check_invariants();
+            let expected: Vec<i32> = if i <= 20 {
+                (1..=i as i32).collect()
+            } else {
+                // Odd values up to 2(i-20)-1 have been removed
+                (1..=20).filter(|v| v % 2 == 0 || *v > 2 * (i as i32 - 20)).collect()
+            };
+            assert_eq!(version.to_vec(), expected, "version {}", i);
+            assert_eq!(version.len(), expected.len());
+        }
+    }
+
+    #[test]
+    fn test_root_split_and_shrink() {
+        // Order 3: the root overflows on the third key and splits into [1] [2] [3]
+        let v2 = build(3, &[1, 2]);
+        let v3 = v2.insert(3);
+        assert!(v2.root.as_ref().unwrap().leaf);
+        let root = v3.root.as_ref().unwrap();
+        assert_eq!(root.keys, vec![2]);
+        assert_eq!(root.children[0].keys, vec![1]);
+        assert_eq!(root.children[1].keys, vec![3]);
+
+        // Removing 1 merges [2, 3] back into a single root leaf
+        let v4 = v3.remove(1);
+        let root = v4.root.as_ref().unwrap();
+        assert!(root.leaf);
+        assert_eq!(root.keys, vec![2, 3]);
+        assert_eq!(v3.to_vec(), vec![1, 2, 3]);
+
+        let empty = v4.remove(2).remove(3);
+        assert!(empty.is_empty());
+        assert!(empty.root.is_none());
+    }
+
+    #[test]
+    fn test_clone_shares_root() {
+        let btree = build(4, &(0..100).collect::<Vec<_>>());
+        let snapshot = btree.clone();
+        assert!(Arc::ptr_eq(btree.root.as_ref().unwrap(), snapshot.root.as_ref().unwrap()));
+    }
+
+    #[test]
+    fn test_remove_missing_value() {
+        let btree = build(5, &[10, 20, 30]);
+        let same = btree.remove(15);
+        assert_eq!(same.len(), 3);
+        assert!(Arc::ptr_eq(btree.root.as_ref().unwrap(), same.root.as_ref().unwrap()));
+        assert!(PersistentBTree::<i32>::new(5).remove(1).is_empty());
+    }
+
+    #[test]
+    fn test_insert_shares_untouched_nodes() {
+        let mut values: Vec<i32> = (0..5000).map(|v| v * 2).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(1));
+        for order in [3, 4, 8, 32] {
+            let btree = build(order, &values);
+            let old = node_ptrs(&btree);
+            for val in [1, 4001, 9999] {
+                let new = btree.insert(val);
+                let copied = node_ptrs(&new).difference(&old).count();
+                // One copy per level, plus a new node per split (including a new root)
+                assert!(copied <= 2 * height(&btree) + 1, "order {}: {} nodes copied", order, copied);
+                assert!(node_ptrs(&new).intersection(&old).count() >= old.len() - height(&btree));
+                assert!(!btree.search(val));
+                assert!(new.search(val));
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove_shares_untouched_nodes() {
+        let mut values: Vec<i32> = (0..5000).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(2));
+        for order in [3, 4, 8, 32] {
+            let btree = build(order, &values);
+            let old = node_ptrs(&btree);
+            for val in [0, 2500, 4999] {
+                let new = btree.remove(val);
+                let copied = node_ptrs(&new).difference(&old).count();
+                // One copy per level, plus a copied sibling per rotation or merge
+                assert!(copied <= 2 * height(&btree), "order {}: {} nodes copied", order, copied);
+                assert!(btree.search(val));
+                assert!(!new.search(val));
+                new.check_invariants();
+            }
+        }
+    }
+
+    #[test]
+    fn test_against_btreeset() {
+        for order in 3..=10 {
+            let mut rng = StdRng::seed_from_u64(order as u64);
+            let mut values: Vec<i32> = (0..400).collect();
+            values.shuffle(&mut rng);
+
+            let mut btree = PersistentBTree::new(order);
+            let mut set = BTreeSet::new();
+            let mut snapshots = vec![];
+            for (i, &val) in values.iter().enumerate() {
+                btree = btree.insert(val);
+                set.insert(val);
+                if i % 50 == 0 {
+                    snapshots.push((btree.clone(), set.clone()));
+                }
+            }
+            btree.check_invariants();
+
+            values.shuffle(&mut rng);
+            for (i, &val) in values.iter().enumerate() {
+                btree = btree.remove(val);
+                set.remove(&val);
+                if i % 50 == 0 {
+                    btree.check_invariants();
+                    snapshots.push((btree.clone(), set.clone()));
+                }
+            }
+            assert!(btree.is_empty());
+
+            // Every snapshot still holds exactly what it held when it was taken
+            for (snapshot, set) in &snapshots {
+                snapshot.check_invariants();
+                assert_eq!(snapshot.to_vec(), set.iter().copied().collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn test_string_keys() {
+        let words = ["pear", "apple", "fig", "kiwi", "banana", "cherry", "date"];
+        let btree = words.iter().fold(PersistentBTree::new(3), |btree, w| btree.insert(w.to_string()));
+        let without_fig = btree.remove("fig".to_string());
+        assert!(btree.search("fig".to_string()));
+        assert!(!without_fig.search("fig".to_string()));
+        assert_eq!(without_fig.to_vec(), vec!["apple", "banana", "cherry", "date", "kiwi", "pear"]);
+        without_fig.check_invariants();
+    }
+}
@@ -0,0 +1,598 @@
+// BTree of byte strings with prefix-compressed nodes
+//
+// Keys such as URLs or paths share long prefixes, and the keys of a node (which are close together in
+// key order) share even longer ones. So instead of storing each key in full, a node stores the longest
+// prefix common to all of its keys once, and each key as its suffix after that prefix.
+//
+// Searching a node first compares the key against the prefix: a key that does not start with it is
+// smaller or greater than every key in the node, depending on the first byte where the two differ (or
+// smaller, if the key is a proper prefix of it). Otherwise the rest of the key is searched for among
+// the suffixes, which are in the same order as the full keys, since they all follow the same prefix.
+//
+// The prefix is kept as long as it can be: it is the common prefix of the first and last keys (every
+// key in between shares it, as they are sorted). A key inserted that does not share all of it shortens
+// it, pushing the bytes it loses back onto the front of every suffix. Taking keys out can lengthen it,
+// which split_child does for both halves of a node, and merge shortens it to what the keys of both
+// nodes and their separator share.
+//
+// Insertion and deletion are the same algorithms as BTree::new (see b_tree.rs), so both build exactly
+// the same tree.
+
+use super::InsertStrategy;
+
+/// A B-tree of byte strings whose nodes store the prefix common to their keys once
+#[derive(Clone)]
+pub struct PrefixBTree {
+    root: Option<Box<Node>>,
+    order: usize,
+}
+
+#[derive(Clone)]
+struct Node {
+    /// Longest prefix common to every key of the node
+    prefix: Vec<u8>,
+    /// The keys with the prefix cut off, in order
+    suffixes: Vec<Box<[u8]>>,
+    children: Vec<Node>,
+    leaf: bool,
+}
+
+impl PrefixBTree {
+    /// Constructor method for PrefixBTree
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    pub fn new(m: usize) -> Self {
+        assert!(m >= 3, "BTree order must be at least 3");
+        PrefixBTree { root: None, order: m }
+    }
+
+    /// Returns the knuth order of the tree
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    /// Returns the number of key bytes stored (the prefixes and suffixes of every node), which is the
+    /// total length of the keys for a tree storing them in full
+    pub fn key_bytes(&self) -> usize {
+        fn walk(node: &Node) -> usize {
+            node.prefix.len() + node.suffixes.iter().map(|suffix| suffix.len()).sum::<usize>() + node.children.iter().map(walk).sum::<usize>()
+        }
+        self.root.as_deref().map_or(0, walk)
+    }
+
+    /// Traverse method for PrefixBTree
+    ///
+    /// Traverses through all keys for all nodes in order and prints them out (as lossy UTF-8)
+    pub fn traverse(&self) {
+        match &self.root {
+            Some(r) => r.traverse(),
+            None => println!("=== EMPTY BTREE ==="),
+        }
+    }
+
+    /// Search method for PrefixBTree
+    ///
+    /// Returns true if key is present, false otherwise
+    pub fn search(&self, key: &[u8]) -> bool {
+        let mut node = match &self.root {
+            Some(r) => r.as_ref(),
+            None => return false,
+        };
+
+        loop {
+            let (found, idx) = node.search(key);
+            if found {
+                return true;
+            }
+            if node.leaf {
+                return false;
+            }
+            node = &node.children[idx];
+        }
+    }
+
+    /// Inserts a key into the b-tree
+    pub fn insert(&mut self, key: &[u8]) {
+        let (max, top_down) = (self.max_keys(), InsertStrategy::default_for(self.order) == InsertStrategy::TopDown);
+        let root = match &mut self.root {
+            Some(r) => r,
+            None => {
+                // If root is empty, create a new root leaf node and insert key
+                self.root = Some(Box::new(Node { prefix: key.to_vec(), suffixes: vec![Box::default()], children: vec![], leaf: true }));
+                return;
+            },
+        };
+
+        if top_down {
+            // Split a full root before descending, as with every other full node on the way down
+            if root.suffixes.len() == max {
+                self.split_root();
+            }
+            self.root.as_mut().expect("Root exists").insert_non_full(key, max);
+        } else {
+            // Bottom-up, so the root is only split once it overflows
+            root.insert_bottom_up(key, max);
+            if root.suffixes.len() > max {
+                self.split_root();
+            }
+        }
+    }
+
+    /// Deletes a key from the b-tree
+    pub fn delete(&mut self, key: &[u8]) {
+        let (min, max) = (self.min_keys(), self.max_keys());
+        let root = match &mut self.root {
+            Some(r) => r,
+            None => panic!("Cannot delete from empty BTree"),
+        };
+
+        root.delete(key, min, max);
+
+        // Shrink tree if root is empty but has children
+        if root.suffixes.is_empty() && !root.children.is_empty() {
+            self.root = Some(Box::new(root.children.remove(0)));
+        }
+    }
+
+    /// Max number of keys K = m-1 a node holds (outside of a temporary overflow)
+    fn max_keys(&self) -> usize {
+        self.order - 1
+    }
+
+    /// Min number of keys floor(K/2) a non-root node holds
+    fn min_keys(&self) -> usize {
+        (self.order - 1) / 2
+    }
+
+    /// Makes the root the only child of a new root and splits it
+    fn split_root(&mut self) {
+        let old_root = self.root.take().expect("Root exists");
+        let mut new_root = Box::new(Node { prefix: vec![], suffixes: vec![], children: vec![*old_root], leaf: false });
+        new_root.split_child(0);
+        self.root = Some(new_root);
+    }
+}
+
+impl Node {
+    /// Returns the key at idx in full
+    fn key(&self, idx: usize) -> Vec<u8> {
+        [self.prefix.as_slice(), &self.suffixes[idx]].concat()
+    }
+
+    /// Traverses and prints out all of the keys recursively
+    fn traverse(&self) {
+        for i in 0..self.suffixes.len() {
+            if !self.leaf {
+                self.children[i].traverse();
+            }
+            print!("{:?} ", String::from_utf8_lossy(&self.key(i)));
+        }
+        if !self.leaf {
+            self.children[self.suffixes.len()].traverse();
+        }
+    }
+
+    /// Searches for key among the keys of the node, comparing it against the prefix first and then
+    /// against the suffixes
+    ///
+    /// Returns true and the idx of key if found, otherwise false and the idx of the smallest key greater than key
+    fn search(&self, key: &[u8]) -> (bool, usize) {
+        let Some(rest) = key.strip_prefix(self.prefix.as_slice()) else {
+            // The key is before or after every key of the node, as it is before or after the prefix
+            let idx = if key < self.prefix.as_slice() { 0 } else { self.suffixes.len() };
+            return (false, idx);
+        };
+        match self.suffixes.binary_search_by(|suffix| suffix.as_ref().cmp(rest)) {
+            Ok(idx) => (true, idx),
+            Err(idx) => (false, idx),
+        }
+    }
+
+    /// Inserts key at idx, first shortening the prefix to what key shares of it
+    fn insert_key(&mut self, idx: usize, key: &[u8]) {
+        let common = common_len(&self.prefix, key);
+        self.shorten_prefix(common);
+        self.suffixes.insert(idx, key[common..].into());
+        // A node that was empty has no prefix yet
+        self.lengthen_prefix();
+    }
+
+    /// Removes the key at idx, returning it in full
+    fn remove_key(&mut self, idx: usize) -> Vec<u8> {
+        let key = self.key(idx);
+        self.suffixes.remove(idx);
+        self.lengthen_prefix();
+        key
+    }
+
+    /// Replaces the key at idx (with one that belongs there), returning the old key in full
+    fn replace_key(&mut self, idx: usize, key: &[u8]) -> Vec<u8> {
+        let old = self.remove_key(idx);
+        self.insert_key(idx, key);
+        old
+    }
+
+    /// Cuts the prefix down to its first len bytes, putting the rest back in front of every suffix
+    fn shorten_prefix(&mut self, len: usize) {
+        if len == self.prefix.len() {
+            return;
+        }
+        let cut = &self.prefix[len..];
+        for suffix in &mut self.suffixes {
+            *suffix = [cut, suffix].concat().into_boxed_slice();
+        }
+        self.prefix.truncate(len);
+    }
+
+    /// Extends the prefix by whatever the suffixes of the first and last keys still have in common (so
+    /// every suffix does), taking it off the front of every suffix
+    fn lengthen_prefix(&mut self) {
+        let (Some(first), Some(last)) = (self.suffixes.first(), self.suffixes.last()) else {
+            self.prefix.clear();
+            return;
+        };
+        let common = common_len(first, last);
+        if common == 0 {
+            return;
+        }
+        self.prefix.extend_from_slice(&first[..common]);
+        for suffix in &mut self.suffixes {
+            *suffix = suffix[common..].into();
+        }
+    }
+
+    /// Inserts a key into a non-full subtree, splitting full children on the way down (called recursively)
+    fn insert_non_full(&mut self, key: &[u8], max: usize) {
+        let (_, mut idx) = self.search(key);
+
+        if self.leaf {
+            self.insert_key(idx, key);
+            return;
+        }
+
+        if self.children[idx].suffixes.len() == max {
+            self.split_child(idx);
+            // Choose left or right child depending on new middle key (from child) at idx
+            if self.search(key).1 > idx {
+                idx += 1;
+            }
+        }
+        self.children[idx].insert_non_full(key, max);
+    }
+
+    /// Inserts a key into a subtree, splitting nodes once they overflow (called recursively)
+    fn insert_bottom_up(&mut self, key: &[u8], max: usize) {
+        let (_, idx) = self.search(key);
+
+        if self.leaf {
+            self.insert_key(idx, key);
+            return;
+        }
+
+        self.children[idx].insert_bottom_up(key, max);
+        if self.children[idx].suffixes.len() > max {
+            self.split_child(idx);
+        }
+    }
+
+    /// Splits the child at child_idx in two and moves its middle key up into the node, recomputing the
+    /// prefixes of both halves (which can only get longer, as each holds fewer keys)
+    fn split_child(&mut self, child_idx: usize) {
+        let child = &mut self.children[child_idx];
+        let mid = child.suffixes.len() / 2;
+
+        let right_suffixes = child.suffixes.split_off(mid + 1);
+        let right_children = if child.leaf { vec![] } else { child.children.split_off(mid + 1) };
+        let mut right = Node { prefix: child.prefix.clone(), suffixes: right_suffixes, children: right_children, leaf: child.leaf };
+        right.lengthen_prefix();
+        let middle_key = child.remove_key(mid);
+
+        self.insert_key(child_idx, &middle_key);
+        self.children.insert(child_idx + 1, right);
+    }
+
+    /// Deletes a key from a subtree (recursively) with the same cases as BTree (see Node::delete in b_tree.rs)
+    fn delete(&mut self, key: &[u8], min: usize, max: usize) {
+        let (found, idx) = self.search(key);
+
+        if self.leaf {
+            if !found {
+                // Case 4: Not found at all (reached leaf node)
+                panic!("Non-existant value cannot be deleted from BTree")
+            }
+            // Case 1: The key is in a leaf node
+            self.remove_key(idx);
+            return;
+        }
+
+        if found {
+            if self.children[idx].suffixes.len() > min {
+                // Case 2a: Replace the key with its predecessor
+                let pred = self.children[idx].rightmost();
+                self.children[idx].delete(&pred, min, max);
+                self.replace_key(idx, &pred);
+            } else if self.children[idx + 1].suffixes.len() > min {
+                // Case 2b: Replace the key with its successor
+                let succ = self.children[idx + 1].leftmost();
+                self.children[idx + 1].delete(&succ, min, max);
+                self.replace_key(idx, &succ);
+            } else {
+                // Case 2c: Merge both children around the key, then delete it from the merged child
+                self.merge(idx);
+                self.children[idx].delete(key, min, max);
+                self.split_overflowing(idx, max);
+            }
+            return;
+        }
+
+        // Case 3: Make sure the child the key belongs in has more than floor(K/2) keys
+        let mut idx = idx;
+        if self.children[idx].suffixes.len() < min + 1 {
+            if idx > 0 && self.children[idx - 1].suffixes.len() > min {
+                // Case 3a: Borrow from the left sibling
+                self.rotate_right(idx);
+            } else if idx + 1 < self.children.len() && self.children[idx + 1].suffixes.len() > min {
+                // Case 3b: Borrow from the right sibling
+                self.rotate_left(idx);
+            } else if idx + 1 == self.children.len() {
+                // Case 3c: Merge with a sibling (the left one for the last child)
+                self.merge(idx - 1);
+                idx -= 1;
+            } else {
+                self.merge(idx);
+            }
+        }
+        self.children[idx].delete(key, min, max);
+        self.split_overflowing(idx, max);
+    }
+
+    /// Splits the child at child_idx if it is still overfull after deleting from it (odd orders only)
+    fn split_overflowing(&mut self, child_idx: usize, max: usize) {
+        if self.children[child_idx].suffixes.len() > max {
+            self.split_child(child_idx);
+        }
+    }
+
+    /// Returns the rightmost key in the subtree
+    fn rightmost(&self) -> Vec<u8> {
+        let mut node = self;
+        while !node.leaf {
+            node = node.children.last().expect("Node missing children");
+        }
+        node.key(node.suffixes.len() - 1)
+    }
+
+    /// Returns the leftmost key in the subtree
+    fn leftmost(&self) -> Vec<u8> {
+        let mut node = self;
+        while !node.leaf {
+            node = &node.children[0];
+        }
+        node.key(0)
+    }
+
+    /// Moves the last key of the left sibling up into the node and the separator down into the child at child_idx
+    fn rotate_right(&mut self, child_idx: usize) {
+        let left = &mut self.children[child_idx - 1];
+        let last_key = left.remove_key(left.suffixes.len() - 1);
+        let last_child = left.children.pop();
+        let middle_key = self.replace_key(child_idx - 1, &last_key);
+
+        let right = &mut self.children[child_idx];
+        right.insert_key(0, &middle_key);
+        if let Some(last_child) = last_child {
+            right.children.insert(0, last_child);
+        }
+    }
+
+    /// Moves the first key of the right sibling up into the node and the separator down into the child at child_idx
+    fn rotate_left(&mut self, child_idx: usize) {
+        let right = &mut self.children[child_idx + 1];
+        let first_key = right.remove_key(0);
+        let first_child = if right.leaf { None } else { Some(right.children.remove(0)) };
+        let middle_key = self.replace_key(child_idx, &first_key);
+
+        let left = &mut self.children[child_idx];
+        let len = left.suffixes.len();
+        left.insert_key(len, &middle_key);
+        if let Some(first_child) = first_child {
+            left.children.push(first_child);
+        }
+    }
+
+    /// Merges the children at child_idx and child_idx + 1 around their separator, recomputing the
+    /// prefix of the merged node (what the prefixes of both and the separator have in common, as each
+    /// prefix is the longest its own keys share)
+    fn merge(&mut self, child_idx: usize) {
+        let middle_key = self.remove_key(child_idx);
+        let right = self.children.remove(child_idx + 1);
+        let left = &mut self.children[child_idx];
+
+        let common = common_len(&left.prefix, &middle_key).min(common_len(&left.prefix, &right.prefix));
+        left.shorten_prefix(common);
+        left.suffixes.push(middle_key[common..].into());
+        let cut = &right.prefix[common..];
+        left.suffixes.extend(right.suffixes.into_iter().map(|suffix| [cut, &suffix].concat().into_boxed_slice()));
+        left.children.extend(right.children);
+    }
+}
+
+/// Returns the length of the longest common prefix of a and b
+fn common_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_tree::NodeSnapshot;
+    use crate::BTree;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+
+    impl PrefixBTree {
+        /// Copies the full keys of every node
+        fn snapshot(&self) -> Option<NodeSnapshot<Vec<u8>>> {
+            fn walk(node: &Node) -> NodeSnapshot<Vec<u8>> {
+                NodeSnapshot { keys: (0..node.suffixes.len()).map(|i| node.key(i)).collect(), children: node.children.iter().map(walk).collect() }
+            }
+            self.root.as_deref().map(walk)
+        }
+
+        /// Checks that the prefix of every node is the longest its keys have in common
+        fn check_prefixes(&self) {
+            fn walk(node: &Node) {
+                if node.suffixes.is_empty() {
+                    assert!(node.prefix.is_empty());
+                } else {
+                    assert_eq!(common_len(&node.suffixes[0], node.suffixes.last().unwrap()), 0, "Prefix {:?} too short", node.prefix);
+                    assert!(node.suffixes.windows(2).all(|w| w[0] <= w[1]), "Suffixes out of order");
+                }
+                node.children.iter().for_each(walk);
+            }
+            if let Some(root) = &self.root {
+                walk(root);
+            }
+        }
+    }
+
+    fn btree_snapshot(btree: &BTree<Vec<u8>>) -> Option<NodeSnapshot<Vec<u8>>> {
+        btree.root.as_ref().map(|r| r.snapshot())
+    }
+
+    /// Returns distinct URL-like keys, with long shared prefixes
+    fn urls(n: usize, rng: &mut StdRng) -> Vec<Vec<u8>> {
+        let hosts = ["https://www.example.com", "https://www.example.org", "https://docs.example.com", "http://example.net"];
+        let sections = ["/articles/2024/", "/articles/2025/", "/users/profile/", "/static/images/"];
+        let mut keys: Vec<Vec<u8>> = (0..n)
+            .map(|i| format!("{}{}{}-{}", hosts[rng.random_range(0..hosts.len())], sections[rng.random_range(0..sections.len())], rng.random_range(0..100), i).into_bytes())
+            .collect();
+        keys.shuffle(rng);
+        keys
+    }
+
+    #[test]
+    fn test_node_search_against_prefix() {
+        let mut node = Node { prefix: vec![], suffixes: vec![], children: vec![], leaf: true };
+        for (idx, key) in [b"abcx".as_slice(), b"abcy", b"abcyz"].into_iter().enumerate() {
+            node.insert_key(idx, key);
+        }
+        assert_eq!(node.prefix, b"abc");
+
+        assert_eq!(node.search(b"abcy"), (true, 1));
+        assert_eq!(node.search(b"abcw"), (false, 0));
+        assert_eq!(node.search(b"abcyy"), (false, 2));
+        // Keys not starting with the prefix are before or after every key
+        assert_eq!(node.search(b"ab"), (false, 0));
+        assert_eq!(node.search(b"aba"), (false, 0));
+        assert_eq!(node.search(b""), (false, 0));
+        assert_eq!(node.search(b"abd"), (false, 3));
+        assert_eq!(node.search(b"b"), (false, 3));
+
+        // The prefix shortens for a key that does not share it all, and lengthens back once it is gone
+        node.insert_key(0, b"ab");
+        assert_eq!(node.prefix, b"ab");
+        assert_eq!(node.search(b"abcy"), (true, 2));
+        assert_eq!(node.remove_key(0), b"ab");
+        assert_eq!(node.prefix, b"abc");
+        assert_eq!(node.replace_key(2, b"abcz"), b"abcyz");
+        assert_eq!(node.key(2), b"abcz");
+    }
+
+    #[test]
+    fn test_insert_and_search() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let keys = urls(500, &mut rng);
+        for order in [3, 4, 5, 8] {
+            let mut btree = PrefixBTree::new(order);
+            for key in &keys {
+                btree.insert(key);
+                btree.check_prefixes();
+            }
+            for key in &keys {
+                assert!(btree.search(key));
+                let mut missing = key.clone();
+                missing.push(b'!');
+                assert!(!btree.search(&missing));
+            }
+        }
+    }
+
+    #[test]
+    fn test_same_shape_as_btree() {
+        for order in 3..=8 {
+            let mut rng = StdRng::seed_from_u64(order as u64);
+            let mut keys = urls(300, &mut rng);
+
+            let mut prefix = PrefixBTree::new(order);
+            let mut btree = BTree::new(order);
+            for key in &keys {
+                prefix.insert(key);
+                btree.insert(key.clone());
+            }
+            assert_eq!(prefix.snapshot(), btree_snapshot(&btree));
+
+            keys.shuffle(&mut rng);
+            for key in &keys[..250] {
+                prefix.delete(key);
+                btree.delete(key.clone());
+                prefix.check_prefixes();
+                assert_eq!(prefix.snapshot(), btree_snapshot(&btree), "order {} after deleting {:?}", order, key);
+            }
+            for key in &keys[250..] {
+                assert!(prefix.search(key));
+            }
+        }
+    }
+
+    #[test]
+    fn test_keys_without_shared_prefix() {
+        // Empty keys, keys that are prefixes of each other and arbitrary bytes
+        let mut keys: Vec<Vec<u8>> = vec![vec![], vec![0], vec![0, 0], vec![255], vec![255, 255, 0]];
+        let mut rng = StdRng::seed_from_u64(3);
+        keys.extend((0..200).map(|i| (0..rng.random_range(0..6)).map(|_| rng.random_range(0..4)).chain([i as u8]).collect()));
+        keys.sort();
+        keys.dedup();
+        keys.shuffle(&mut rng);
+
+        let mut btree = PrefixBTree::new(4);
+        for key in &keys {
+            btree.insert(key);
+        }
+        btree.check_prefixes();
+        for key in &keys {
+            assert!(btree.search(key));
+            btree.delete(key);
+            assert!(!btree.search(key));
+            btree.check_prefixes();
+        }
+        assert!(btree.snapshot().unwrap().keys.is_empty());
+    }
+
+    #[test]
+    fn test_key_bytes_saved() {
+        let keys = urls(2000, &mut StdRng::seed_from_u64(11));
+        let mut btree = PrefixBTree::new(16);
+        for key in &keys {
+            btree.insert(key);
+        }
+        // Most of each key is its host and section, which the nodes store once
+        let total: usize = keys.iter().map(|key| key.len()).sum();
+        assert!(btree.key_bytes() < total / 2, "{} of {} bytes", btree.key_bytes(), total);
+    }
+
+    #[test]
+    #[should_panic(expected = "Non-existant value cannot be deleted from BTree")]
+    fn test_delete_missing_key() {
+        let mut btree = PrefixBTree::new(3);
+        btree.insert(b"https://example.com/a");
+        btree.delete(b"https://example.com/b");
+    }
+
+    #[test]
+    #[should_panic(expected = "BTree order must be at least 3")]
+    fn test_invalid_order() {
+        let _btree = PrefixBTree::new(2);
+    }
+}
@@ -0,0 +1,237 @@
+// Serialize/Deserialize for BTree (enabled with the "serde" cargo feature)
+//
+// A tree is written as its order and its keys in sorted order, e.g. {"order":4,"keys":[1,2,3]} in
+// JSON, and not as its nodes, so the format does not depend on the shape the inserts and deletes left
+// the tree in. Reading it back builds the nodes directly from the sorted keys (see bulk.rs) instead
+// of inserting them one by one. Input with keys out of order is rejected rather than sorted. A BTree
+// keeps duplicate keys (as insert does), so repeated keys are accepted, unless the tree is read with
+// BTree::deserialize_set for set semantics. The insert strategy and node search are not written, and
+// a deserialized tree uses the defaults for its order (as BTree::new does).
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{BTree, Node};
+
+/// What a BTree is written as
+#[derive(Serialize)]
+struct SortedKeys<'a, T> {
+    order: usize,
+    keys: Vec<&'a T>,
+}
+
+/// What a BTree is read from
+#[derive(Deserialize)]
+struct OwnedSortedKeys<T> {
+    order: usize,
+    keys: Vec<T>,
+}
+
+impl<T: PartialOrd + Debug + Clone + Serialize> Serialize for BTree<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        fn walk<'a, T: PartialOrd + Debug + Clone>(node: &'a Node<T>, keys: &mut Vec<&'a T>) {
+            for i in 0..node.keys.len() {
+                if !node.leaf {
+                    walk(&node.children[i], keys);
+                }
+                keys.push(&node.keys[i]);
+            }
+            if !node.leaf {
+                walk(&node.children[node.keys.len()], keys);
+            }
+        }
+        let mut keys = vec![];
+        if let Some(root) = &self.root {
+            walk(root, &mut keys);
+        }
+        SortedKeys { order: self.order, keys }.serialize(serializer)
+    }
+}
+
+impl<'de, T: PartialOrd + Debug + Clone + Deserialize<'de>> Deserialize<'de> for BTree<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        read_sorted_keys(deserializer, false)
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Deserializes a tree with set semantics, rejecting repeated keys as well as keys out of order
+    ///
+    /// For use with #[serde(deserialize_with = "BTree::deserialize_set")]
+    pub fn deserialize_set<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    where
+        T: Deserialize<'de>,
+    {
+        read_sorted_keys(deserializer, true)
+    }
+}
+
+/// Reads the order and sorted keys of a tree and bulk loads them, rejecting repeated keys if distinct
+fn read_sorted_keys<'de, T: PartialOrd + Debug + Clone + Deserialize<'de>, D: Deserializer<'de>>(deserializer: D, distinct: bool) -> Result<BTree<T>, D::Error> {
+    let OwnedSortedKeys { order, keys } = OwnedSortedKeys::<T>::deserialize(deserializer)?;
+    if order < 3 {
+        return Err(D::Error::custom("BTree order must be at least 3"));
+    }
+    let expected = if distinct { "sorted and distinct" } else { "sorted" };
+    let out_of_order = |w: &[T]| match w[0].partial_cmp(&w[1]) {
+        Some(Ordering::Less) => false,
+        Some(Ordering::Equal) => distinct,
+        _ => true,
+    };
+    if let Some(idx) = keys.windows(2).position(out_of_order) {
+        return Err(D::Error::custom(format!("BTree keys must be {}, but key {} ({:?}) is followed by {:?}", expected, idx, keys[idx], keys[idx + 1])));
+    }
+
+    let mut btree = BTree::new(order);
+    btree.bulk_load(keys);
+    Ok(btree)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    /// Returns a tree built by random inserts and deletes, and the keys left in it
+    fn random_tree(order: usize, seed: u64) -> (BTree<i32>, Vec<i32>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut btree = BTree::new(order);
+        let mut set = BTreeSet::new();
+        for _ in 0..2000 {
+            let val = rng.random_range(-1000..1000);
+            if rng.random_bool(0.7) {
+                if set.insert(val) {
+                    btree.insert(val);
+                }
+            } else if set.remove(&val) {
+                btree.delete(val);
+            }
+        }
+        (btree, set.into_iter().collect())
+    }
+
+    fn to_cbor<T: Serialize>(value: &T) -> Vec<u8> {
+        let mut bytes = vec![];
+        ciborium::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_json_format() {
+        let mut btree = BTree::new(4);
+        for val in [3, 1, 2] {
+            btree.insert(val);
+        }
+        assert_eq!(serde_json::to_string(&btree).unwrap(), r#"{"order":4,"keys":[1,2,3]}"#);
+        assert_eq!(serde_json::to_string(&BTree::<i32>::new(3)).unwrap(), r#"{"order":3,"keys":[]}"#);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        for order in [3, 4, 5, 8] {
+            let (btree, keys) = random_tree(order, order as u64);
+            let json = serde_json::to_string(&btree).unwrap();
+            let decoded: BTree<i32> = serde_json::from_str(&json).unwrap();
+            decoded.check_invariants();
+            assert_eq!(decoded.order(), order);
+            assert_eq!(decoded.insert_strategy(), btree.insert_strategy());
+            assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+            assert!(keys.iter().all(|&val| decoded.search(val)));
+        }
+    }
+
+    #[test]
+    fn test_cbor_round_trip() {
+        for order in [3, 6, 32] {
+            let (btree, keys) = random_tree(order, 10 + order as u64);
+            let bytes = to_cbor(&btree);
+            let mut decoded: BTree<i32> = ciborium::from_reader(bytes.as_slice()).unwrap();
+            decoded.check_invariants();
+            assert_eq!(to_cbor(&decoded), bytes);
+
+            // The decoded tree is an ordinary tree
+            for &val in &keys[..keys.len() / 2] {
+                decoded.delete(val);
+            }
+            decoded.insert(5000);
+            decoded.check_invariants();
+            assert!(keys[keys.len() / 2..].iter().all(|&val| decoded.search(val)));
+            assert!(decoded.search(5000));
+        }
+    }
+
+    #[test]
+    fn test_duplicates_round_trip() {
+        let mut btree = BTree::new(4);
+        for val in [1, 1, 2, 2, 2, 3, 1] {
+            btree.insert(val);
+        }
+        let json = serde_json::to_string(&btree).unwrap();
+        assert_eq!(json, r#"{"order":4,"keys":[1,1,1,2,2,2,3]}"#);
+        let decoded: BTree<i32> = serde_json::from_str(&json).unwrap();
+        decoded.check_invariants();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+
+        let bytes = to_cbor(&btree);
+        let mut decoded: BTree<i32> = ciborium::from_reader(bytes.as_slice()).unwrap();
+        decoded.check_invariants();
+        assert_eq!(to_cbor(&decoded), bytes);
+        decoded.delete(1);
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), r#"{"order":4,"keys":[1,1,2,2,2,3]}"#);
+    }
+
+    #[test]
+    fn test_deserialize_set_rejects_duplicates() {
+        #[derive(Deserialize)]
+        struct Config {
+            #[serde(deserialize_with = "BTree::deserialize_set")]
+            tree: BTree<i32>,
+        }
+
+        let config: Config = serde_json::from_str(r#"{"tree":{"order":4,"keys":[1,2,3]}}"#).unwrap();
+        assert!(config.tree.search(2));
+        let err = serde_json::from_str::<Config>(r#"{"tree":{"order":4,"keys":[1,2,2,3]}}"#).err().unwrap().to_string();
+        assert!(err.contains("sorted and distinct, but key 1 (2) is followed by 2"), "{}", err);
+        let err = serde_json::from_str::<Config>(r#"{"tree":{"order":4,"keys":[2,1]}}"#).err().unwrap().to_string();
+        assert!(err.contains("key 0 (2) is followed by 1"), "{}", err);
+    }
+
+    #[test]
+    fn test_string_round_trip() {
+        let mut btree = BTree::new(3);
+        for word in ["pear", "apple", "fig", "banana", "cherry"] {
+            btree.insert(word.to_string());
+        }
+        let json = serde_json::to_string(&btree).unwrap();
+        assert_eq!(json, r#"{"order":3,"keys":["apple","banana","cherry","fig","pear"]}"#);
+        let decoded: BTree<String> = ciborium::from_reader(to_cbor(&btree).as_slice()).unwrap();
+        assert_eq!(serde_json::to_string(&decoded).unwrap(), json);
+    }
+
+    /// Returns the error from reading a tree from json, which must be invalid
+    fn json_error(json: &str) -> String {
+        match serde_json::from_str::<BTree<i32>>(json) {
+            Ok(_) => panic!("{} was accepted", json),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_input() {
+        let err = json_error(r#"{"order":4,"keys":[1,3,2]}"#);
+        assert!(err.contains("key 1 (3) is followed by 2"), "{}", err);
+        let err = json_error(r#"{"order":4,"keys":[1,2,2,1]}"#);
+        assert!(err.contains("must be sorted, but key 2 (2) is followed by 1"), "{}", err);
+        let err = json_error(r#"{"order":2,"keys":[1]}"#);
+        assert!(err.contains("order must be at least 3"), "{}", err);
+        json_error(r#"{"keys":[1]}"#);
+
+        let unsorted = to_cbor(&SortedKeys { order: 5, keys: vec![&2, &1] });
+        assert!(ciborium::from_reader::<BTree<i32>, _>(unsorted.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,604 @@
+// Binary snapshots that keep the exact node layout of a BTree
+//
+// Serializing with serde (see serialize.rs) only keeps the keys, so the tree read back has the shape a
+// bulk load gives it. A snapshot stores every node as it is, so a tree left in an odd shape by some
+// sequence of inserts and deletes can be saved and loaded again to reproduce a bug.
+//
+// Format (version 1, every integer little-endian):
+//   magic          4 bytes  "BTRS"
+//   version        u16      1
+//   strategy       u8       0 TopDown, 1 BottomUp, 2 BStar
+//   node search    u8, u64  0 Binary, 1 Linear, 2 BranchlessBinary, 3 Simd, 4 Threshold (with its
+//                           max in the u64, which is 0 for the others)
+//   order          u64
+//   node count     u64
+//   node table     node count records in pre-order (a node, then the subtrees of its children from
+//                  left to right), each a leaf flag (u8, 0 or 1), a key count (u32) and the keys
+//                  (see SnapshotKey). An internal node is followed by the records of its key count
+//                  + 1 children, so the layout is implied by the order of the records
+//   checksum       u32      CRC-32 of every byte before it
+//
+// Loading checks the header, the node count and the checksum, and then validates the tree (see
+// BTree::validate), so a snapshot that loads is a tree every operation can be run on.
+
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::io::{self, Read, Write};
+use std::mem;
+
+use crc32fast::Hasher;
+
+use super::b_star::root_max_keys;
+use super::cow::Child;
+use super::metrics::Counters;
+use super::{BTree, InsertStrategy, Node, NodeSearch};
+
+const MAGIC: [u8; 4] = *b"BTRS";
+const VERSION: u16 = 1;
+
+/// Deepest tree a snapshot is read with. Every level at least doubles the number of leaves, so a
+/// deeper tree could not fit in memory, and the limit keeps corrupt input from overflowing the stack
+const MAX_DEPTH: usize = 64;
+
+/// Most keys (and children) reserved for a node before they are read. The order comes from the
+/// snapshot too, so a corrupt one could let a node claim billions of keys, which are only found to be
+/// missing once they are read
+const MAX_RESERVE: usize = 1024;
+
+/// A key type that can be written to (and read back from) a snapshot
+pub trait SnapshotKey: Sized {
+    fn write_key<W: Write>(&self, out: &mut W) -> io::Result<()>;
+    fn read_key<R: Read>(input: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! impl_snapshot_key {
+    ($($t:ty),*) => {
+        $(
+            impl SnapshotKey for $t {
+                fn write_key<W: Write>(&self, out: &mut W) -> io::Result<()> {
+                    out.write_all(&self.to_le_bytes())
+                }
+
+                fn read_key<R: Read>(input: &mut R) -> io::Result<Self> {
+                    let mut bytes = [0; mem::size_of::<$t>()];
+                    input.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_snapshot_key!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// Written as a u64, so snapshots are the same on 32 and 64 bit targets
+impl SnapshotKey for usize {
+    fn write_key<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        (*self as u64).write_key(out)
+    }
+
+    fn read_key<R: Read>(input: &mut R) -> io::Result<Self> {
+        usize::try_from(u64::read_key(input)?).map_err(|_| invalid("usize key out of range"))
+    }
+}
+
+/// Written as an i64, so snapshots are the same on 32 and 64 bit targets
+impl SnapshotKey for isize {
+    fn write_key<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        (*self as i64).write_key(out)
+    }
+
+    fn read_key<R: Read>(input: &mut R) -> io::Result<Self> {
+        isize::try_from(i64::read_key(input)?).map_err(|_| invalid("isize key out of range"))
+    }
+}
+
+impl SnapshotKey for char {
+    fn write_key<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        u32::from(*self).write_key(out)
+    }
+
+    fn read_key<R: Read>(input: &mut R) -> io::Result<Self> {
+        let code = u32::read_key(input)?;
+        char::from_u32(code).ok_or_else(|| invalid(format!("{:#x} is not a char", code)))
+    }
+}
+
+/// Written as its length in bytes (u64) followed by its UTF-8 bytes
+impl SnapshotKey for String {
+    fn write_key<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        (self.len() as u64).write_key(out)?;
+        out.write_all(self.as_bytes())
+    }
+
+    fn read_key<R: Read>(input: &mut R) -> io::Result<Self> {
+        let len = u64::read_key(input)?;
+        // Read through take rather than allocating len bytes up front, since len may be corrupt
+        let mut bytes = vec![];
+        input.take(len).read_to_end(&mut bytes)?;
+        if bytes.len() as u64 != len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "snapshot ends inside a string key"));
+        }
+        String::from_utf8(bytes).map_err(|_| invalid("string key is not UTF-8"))
+    }
+}
+
+/// Returns an InvalidData error
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(message: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reader or writer that passes every byte through a CRC-32 hasher
+struct Checksummed<S> {
+    inner: S,
+    hasher: Hasher,
+}
+
+impl<S> Checksummed<S> {
+    fn new(inner: S) -> Self {
+        Checksummed { inner, hasher: Hasher::new() }
+    }
+
+    /// Returns the checksum of every byte passed through so far
+    fn checksum(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+}
+
+impl<W: Write> Write for Checksummed<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for Checksummed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone + SnapshotKey> BTree<T> {
+    /// Writes the tree (its nodes exactly as they are, its order, insert strategy and node search)
+    /// as a binary snapshot (see snapshot.rs for the format)
+    ///
+    /// The snapshot is written in many small writes, so out should be buffered
+    pub fn write_snapshot<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut out = Checksummed::new(out);
+        out.write_all(&MAGIC)?;
+        VERSION.write_key(&mut out)?;
+        let strategy: u8 = match self.strategy {
+            InsertStrategy::TopDown => 0,
+            InsertStrategy::BottomUp => 1,
+            InsertStrategy::BStar => 2,
+        };
+        strategy.write_key(&mut out)?;
+        let (node_search, threshold): (u8, usize) = match self.node_search {
+            NodeSearch::Binary => (0, 0),
+            NodeSearch::Linear => (1, 0),
+            NodeSearch::BranchlessBinary => (2, 0),
+            NodeSearch::Simd => (3, 0),
+            NodeSearch::Threshold(max) => (4, max),
+        };
+        node_search.write_key(&mut out)?;
+        threshold.write_key(&mut out)?;
+        self.order.write_key(&mut out)?;
+
+        let node_count = self.root.as_ref().map_or(0, |root| root.node_count());
+        node_count.write_key(&mut out)?;
+        if let Some(root) = &self.root {
+            root.write_snapshot(&mut out)?;
+        }
+
+        let checksum = out.checksum();
+        checksum.write_key(&mut out.inner)?;
+        out.flush()
+    }
+
+    /// Reads a tree from a binary snapshot written by write_snapshot
+    ///
+    /// Fails with InvalidData if the header is wrong, the checksum does not match, or the tree in it
+    /// is not valid (see BTree::validate), and with UnexpectedEof if the snapshot is cut short
+    pub fn read_snapshot<R: Read>(input: R) -> io::Result<Self> {
+        let mut input = Checksummed::new(input);
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid("not a BTree snapshot"));
+        }
+        let version = u16::read_key(&mut input)?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported snapshot version {}", version)));
+        }
+        let strategy = match u8::read_key(&mut input)? {
+            0 => InsertStrategy::TopDown,
+            1 => InsertStrategy::BottomUp,
+            2 => InsertStrategy::BStar,
+            other => return Err(invalid(format!("unknown insert strategy {}", other))),
+        };
+        let (node_search, threshold) = (u8::read_key(&mut input)?, usize::read_key(&mut input)?);
+        let node_search = match node_search {
+            0 => NodeSearch::Binary,
+            1 => NodeSearch::Linear,
+            2 => NodeSearch::BranchlessBinary,
+            3 => NodeSearch::Simd,
+            4 => NodeSearch::Threshold(threshold),
+            other => return Err(invalid(format!("unknown node search {}", other))),
+        };
+        let order = usize::read_key(&mut input)?;
+        if order < 3 {
+            return Err(invalid("BTree order must be at least 3"));
+        }
+        if strategy == InsertStrategy::TopDown && !order.is_multiple_of(2) {
+            return Err(invalid("TopDown insertion needs an even order"));
+        }
+
+        let node_count = usize::read_key(&mut input)?;
+        let mut remaining = node_count;
+        let root = match node_count {
+            0 => None,
+            _ => Some(Child::new(Node::read_snapshot(&mut input, order, &mut remaining, 0)?)),
+        };
+        if remaining != 0 {
+            return Err(invalid(format!("header counts {} nodes, but the node table holds {}", node_count, node_count - remaining)));
+        }
+
+        let checksum = input.checksum();
+        let stored = u32::read_key(&mut input.inner)?;
+        if stored != checksum {
+            return Err(invalid(format!("checksum mismatch (stored {:#010x}, computed {:#010x})", stored, checksum)));
+        }
+
+        let btree = BTree { root, order, counters: Counters::new(), observer: None, strategy, node_search };
+        btree.validate().map_err(|problem| invalid(format!("invalid tree in snapshot: {}", problem)))?;
+        Ok(btree)
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Checks the structure of the tree, returning a description of the first problem found
+    ///
+    /// Every node must have the tree's order, an internal node one more child than keys, and a leaf no
+    /// children. A non-root node holds between floor(K/2) and K keys, and the root at most K (or
+    /// 2 * floor((2m-2)/3) for a B* tree), and at least 1 if it has children. Every leaf must be at the
+    /// same depth, and the keys must be in order (equal keys may follow each other, as BTree keeps
+    /// duplicates)
+    pub fn validate(&self) -> Result<(), String> {
+        let Some(root) = &self.root else { return Ok(()) };
+        if !root.leaf && root.keys.is_empty() {
+            return Err("internal root has no keys".to_string());
+        }
+        let root_max = if self.strategy == InsertStrategy::BStar { root_max_keys(self.order) } else { self.order - 1 };
+        if root.keys.len() > root_max {
+            return Err(format!("root holds {} keys (max {})", root.keys.len(), root_max));
+        }
+        root.validate(self.order, 0, &mut None, &mut None)
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    /// Returns the number of nodes in the subtree
+    fn node_count(&self) -> usize {
+        1 + self.children.iter().map(|child| child.node_count()).sum::<usize>()
+    }
+
+    /// Writes the records of the subtree to the node table, in pre-order
+    fn write_snapshot<W: Write>(&self, out: &mut W) -> io::Result<()>
+    where
+        T: SnapshotKey,
+    {
+        u8::from(self.leaf).write_key(out)?;
+        let count = u32::try_from(self.keys.len()).map_err(|_| invalid("node holds too many keys for a snapshot"))?;
+        count.write_key(out)?;
+        for key in &self.keys {
+            key.write_key(out)?;
+        }
+        for child in &self.children {
+            child.write_snapshot(out)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a subtree from the node table (called recursively), counting down remaining for each node
+    fn read_snapshot<R: Read>(input: &mut R, order: usize, remaining: &mut usize, depth: usize) -> io::Result<Node<T>>
+    where
+        T: SnapshotKey,
+    {
+        if *remaining == 0 {
+            return Err(invalid("node table holds more nodes than the header counts"));
+        }
+        if depth > MAX_DEPTH {
+            return Err(invalid(format!("tree deeper than {} levels", MAX_DEPTH)));
+        }
+        *remaining -= 1;
+
+        let leaf = match u8::read_key(input)? {
+            0 => false,
+            1 => true,
+            other => return Err(invalid(format!("leaf flag {} is not 0 or 1", other))),
+        };
+        // No node holds 2m keys (even a B* root)
+        let count = u32::read_key(input)? as usize;
+        if count >= order.saturating_mul(2) {
+            return Err(invalid(format!("node holds {} keys", count)));
+        }
+        let mut keys = Vec::with_capacity(count.min(MAX_RESERVE));
+        for _ in 0..count {
+            keys.push(T::read_key(input)?);
+        }
+        let mut children = Vec::new();
+        if !leaf {
+            children.reserve((count + 1).min(MAX_RESERVE));
+            for _ in 0..=count {
+                children.push(Child::new(Node::read_snapshot(input, order, remaining, depth + 1)?));
+            }
+        }
+        Ok(Node { keys, children, leaf, order })
+    }
+
+    /// Checks the subtree (called recursively, see BTree::validate)
+    ///
+    /// leaf_depth is the depth of the first leaf found, and last_key the last key visited in order
+    fn validate<'a>(&'a self, order: usize, depth: usize, leaf_depth: &mut Option<usize>, last_key: &mut Option<&'a T>) -> Result<(), String> {
+        if self.order != order {
+            return Err(format!("node {:?} has order {} in a tree of order {}", self.keys, self.order, order));
+        }
+        if depth > 0 && (self.keys.len() < self.min_keys() || self.keys.len() > self.max_keys()) {
+            return Err(format!("node {:?} at depth {} holds {} keys (min {}, max {})", self.keys, depth, self.keys.len(), self.min_keys(), self.max_keys()));
+        }
+
+        if self.leaf {
+            if !self.children.is_empty() {
+                return Err(format!("leaf {:?} has {} children", self.keys, self.children.len()));
+            }
+            if *leaf_depth.get_or_insert(depth) != depth {
+                return Err(format!("leaf {:?} at depth {}, but other leaves are at depth {}", self.keys, depth, leaf_depth.unwrap_or(depth)));
+            }
+        } else if self.children.len() != self.keys.len() + 1 {
+            return Err(format!("node {:?} has {} keys but {} children", self.keys, self.keys.len(), self.children.len()));
+        }
+
+        for i in 0..=self.keys.len() {
+            if !self.leaf {
+                self.children[i].validate(order, depth + 1, leaf_depth, last_key)?;
+            }
+            if let Some(key) = self.keys.get(i) {
+                if let Some(last) = *last_key
+                    && matches!(last.partial_cmp(key), Some(Ordering::Greater) | None)
+                {
+                    return Err(format!("key {:?} follows {:?}", key, last));
+                }
+                *last_key = Some(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_tree::cow::make_mut;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    /// Returns a tree left in the shape a random mix of inserts and deletes gives it
+    fn random_tree(mut btree: BTree<i64>, seed: u64) -> BTree<i64> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut set = BTreeSet::new();
+        for _ in 0..3000 {
+            let val = rng.random_range(-2000..2000);
+            if rng.random_bool(0.6) {
+                if set.insert(val) {
+                    btree.insert(val);
+                }
+            } else if set.remove(&val) {
+                btree.delete(val);
+            }
+        }
+        btree
+    }
+
+    fn snapshot<T: PartialOrd + Debug + Clone + SnapshotKey>(btree: &BTree<T>) -> Vec<u8> {
+        let mut bytes = vec![];
+        btree.write_snapshot(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn read_error(bytes: &[u8]) -> io::Error {
+        match BTree::<i64>::read_snapshot(bytes) {
+            Ok(_) => panic!("Snapshot was accepted"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn test_round_trip_keeps_layout() {
+        let trees = [
+            BTree::new(3),
+            BTree::new(4),
+            BTree::new(5),
+            BTree::with_strategy(6, InsertStrategy::BottomUp),
+            BTree::new_b_star(7),
+            BTree::builder().order(8).node_search(NodeSearch::Threshold(5)).build(),
+        ];
+        for (seed, btree) in trees.into_iter().enumerate() {
+            let btree = random_tree(btree, seed as u64);
+            let bytes = snapshot(&btree);
+            let mut loaded = BTree::<i64>::read_snapshot(bytes.as_slice()).unwrap();
+            assert_eq!(loaded.validate(), Ok(()));
+            assert_eq!(loaded.to_dot(), btree.to_dot());
+            assert_eq!(loaded.order(), btree.order());
+            assert_eq!(loaded.insert_strategy(), btree.insert_strategy());
+            assert_eq!(loaded.node_search(), btree.node_search());
+            assert_eq!(snapshot(&loaded), bytes);
+
+            // The loaded tree is an ordinary tree
+            for val in 0..500 {
+                if !loaded.search(val * 7) {
+                    loaded.insert(val * 7);
+                }
+            }
+            assert_eq!(loaded.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_header() {
+        let mut btree = BTree::new(4);
+        for val in [10i64, 20, 30, 40] {
+            btree.insert(val);
+        }
+        // [20] over [10] and [30, 40]
+        let bytes = snapshot(&btree);
+        assert_eq!(&bytes[..4], b"BTRS");
+        assert_eq!(&bytes[4..6], &[1, 0]);
+        assert_eq!(bytes[6], 0);
+        assert_eq!(bytes[7], 0);
+        assert_eq!(&bytes[8..16], &0u64.to_le_bytes());
+        assert_eq!(&bytes[16..24], &4u64.to_le_bytes());
+        assert_eq!(&bytes[24..32], &3u64.to_le_bytes());
+        // Root record: internal, 1 key, then the first leaf record
+        assert_eq!(bytes[32], 0);
+        assert_eq!(&bytes[33..37], &1u32.to_le_bytes());
+        assert_eq!(&bytes[37..45], &20i64.to_le_bytes());
+        assert_eq!(bytes[45], 1);
+        // 3 records of 5 bytes with 4 keys of 8 bytes, and the checksum
+        assert_eq!(bytes.len(), 32 + 3 * 5 + 4 * 8 + 4);
+    }
+
+    #[test]
+    fn test_round_trip_with_duplicates() {
+        for order in [3, 4, 5] {
+            let mut btree = BTree::new(order);
+            for val in 0..200i64 {
+                btree.insert(val % 7);
+            }
+            assert_eq!(btree.validate(), Ok(()));
+            let bytes = snapshot(&btree);
+            let loaded = BTree::<i64>::read_snapshot(bytes.as_slice()).unwrap();
+            assert_eq!(loaded.to_dot(), btree.to_dot());
+            assert_eq!(snapshot(&loaded), bytes);
+        }
+    }
+
+    #[test]
+    fn test_empty_and_string_trees() {
+        let empty: BTree<i64> = BTree::new(5);
+        let loaded = BTree::<i64>::read_snapshot(snapshot(&empty).as_slice()).unwrap();
+        assert!(loaded.root.is_none());
+
+        let mut words = BTree::new(3);
+        for word in "the quick brown fox jumps over the lazy dog".split(' ') {
+            if !words.search(word.to_string()) {
+                words.insert(word.to_string());
+            }
+        }
+        let loaded = BTree::<String>::read_snapshot(snapshot(&words).as_slice()).unwrap();
+        assert_eq!(loaded.to_dot(), words.to_dot());
+    }
+
+    #[test]
+    fn test_rejects_corruption() {
+        let mut btree = BTree::new(4);
+        for val in 0..20i64 {
+            btree.insert(val);
+        }
+        let bytes = snapshot(&btree);
+
+        // Every flipped bit is caught, by the header checks, the node table or the checksum
+        for i in 0..bytes.len() {
+            for bit in 0..8 {
+                let mut corrupt = bytes.clone();
+                corrupt[i] ^= 1 << bit;
+                assert!(BTree::<i64>::read_snapshot(corrupt.as_slice()).is_err(), "byte {} bit {} flipped", i, bit);
+            }
+        }
+        // As is every truncation
+        for len in 0..bytes.len() {
+            assert!(BTree::<i64>::read_snapshot(&bytes[..len]).is_err(), "cut to {} bytes", len);
+        }
+
+        let mut corrupt = bytes.clone();
+        corrupt[0] = b'X';
+        assert!(read_error(&corrupt).to_string().contains("not a BTree snapshot"));
+        let mut corrupt = bytes.clone();
+        corrupt[4] = 2;
+        assert!(read_error(&corrupt).to_string().contains("unsupported snapshot version 2"));
+        let mut corrupt = bytes.clone();
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        let err = read_error(&corrupt);
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+        assert_eq!(read_error(&bytes[..bytes.len() - 1]).kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_huge_order_and_key_count() {
+        // A root claiming u32::MAX - 1 keys in a tree of order 2^60: the missing keys are found without
+        // reserving room for all of them first
+        let mut bytes = snapshot(&BTree::<i64>::new(4));
+        bytes.truncate(24);
+        bytes[16..24].copy_from_slice(&(1u64 << 60).to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.push(1);
+        bytes.extend_from_slice(&(u32::MAX - 1).to_le_bytes());
+        bytes.extend_from_slice(&7i64.to_le_bytes());
+        assert_eq!(read_error(&bytes).kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_rejects_invalid_tree() {
+        // Keys out of order, written with a valid checksum
+        let mut btree = BTree::new(4);
+        for val in 0..20i64 {
+            btree.insert(val);
+        }
+        make_mut(btree.root.as_mut().unwrap()).keys[0] = 100;
+        let err = read_error(&snapshot(&btree));
+        assert!(err.to_string().contains("invalid tree in snapshot: key"), "{}", err);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut btree = random_tree(BTree::new(5), 9);
+        assert_eq!(btree.validate(), Ok(()));
+        assert_eq!(BTree::<i64>::new(3).validate(), Ok(()));
+
+        // Underfull leaf
+        let root = make_mut(btree.root.as_mut().unwrap());
+        let mut node = root;
+        while !node.leaf {
+            node = node.child_mut(0);
+        }
+        node.keys.truncate(1);
+        let problem = btree.validate().unwrap_err();
+        assert!(problem.contains("holds 1 keys (min 2, max 4)"), "{}", problem);
+
+        // Leaves at different depths: the root over two leaves, with the last leaf replaced by a copy of the root
+        let mut btree = BTree::new(4);
+        for val in 0..5 {
+            btree.insert(val);
+        }
+        let root = make_mut(btree.root.as_mut().unwrap());
+        assert!(root.children.iter().all(|child| child.leaf));
+        let copy = Child::new(root.clone());
+        *root.children.last_mut().unwrap() = copy;
+        let problem = btree.validate().unwrap_err();
+        assert!(problem.contains("at depth 2, but other leaves are at depth 1"), "{}", problem);
+
+        // An internal node missing a child
+        let mut btree = random_tree(BTree::new(4), 10);
+        make_mut(btree.root.as_mut().unwrap()).children.pop();
+        assert!(btree.validate().is_err());
+    }
+}
@@ -0,0 +1,203 @@
+// Insertion strategies
+//
+// TopDown (CLRS): a full child is split before descending into it, so the insert never has to come
+// back up the tree. Splitting a full node of K keys around its median leaves two halves of
+// floor(K/2) keys only when K is odd, so this needs an even order.
+//
+// BottomUp: the value is inserted into its leaf first, and a node that overflows (holds m keys) is
+// split by its parent on the way back up. m = K+1 keys always split into two halves of at least
+// floor(K/2), so this works for every order, including the 2-3 tree (order 3). It also only splits
+// nodes that actually overflow, whereas TopDown splits every full node on the path.
+//
+// BStar: bottom-up, but an overflowing node first shifts a key into a sibling, and is otherwise
+// split 2-to-3 with a full sibling (see b_star.rs).
+
+use std::fmt::Debug;
+
+use super::cow::Child;
+use super::observer::Hooks;
+use super::{BTree, Node};
+
+/// How BTree::insert keeps nodes from overflowing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertStrategy {
+    /// Split full nodes pre-emptively on the way down (even orders only)
+    TopDown,
+    /// Insert into the leaf, then split overflowing nodes on the way back up
+    BottomUp,
+    /// Bottom-up, shifting keys into siblings and splitting two nodes into three (see BTree::new_b_star)
+    BStar,
+}
+
+impl InsertStrategy {
+    /// Returns the strategy BTree::new uses for an order: TopDown for even orders, BottomUp for odd ones
+    pub fn default_for(order: usize) -> Self {
+        if order.is_multiple_of(2) { InsertStrategy::TopDown } else { InsertStrategy::BottomUp }
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Constructor method for a BTree that inserts with the given strategy
+    ///
+    /// Takes in a usize parameter m representing the knuth order of the tree
+    ///
+    /// Panics if the strategy is TopDown and m is odd
+    pub fn with_strategy(m: usize, strategy: InsertStrategy) -> Self {
+        assert!(
+            strategy != InsertStrategy::TopDown || m.is_multiple_of(2),
+            "TopDown insertion needs an even order (use BottomUp for odd orders)"
+        );
+        let mut btree = BTree::new(m);
+        btree.strategy = strategy;
+        btree
+    }
+
+    /// Returns the strategy the tree inserts with
+    pub fn insert_strategy(&self) -> InsertStrategy {
+        self.strategy
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    /// Inserts a value into the subtree, splitting nodes once they overflow (called recursively)
+    ///
+    /// The node may be left holding m keys, which the caller then fixes
+    pub(super) fn insert_bottom_up(&mut self, value: T, level: usize, hooks: &mut Hooks<T>) {
+        let (_, idx) = self.search(&value, hooks.node_search, hooks.counters);
+        hooks.visit(level, self);
+
+        if self.leaf {
+            // Base case: insert into the leaf even if it overflows
+            self.keys.insert(idx, value);
+            hooks.insert_key(level, idx, self);
+            return;
+        }
+
+        self.child_mut(idx).insert_bottom_up(value, level + 1, hooks);
+        if self.children[idx].keys.len() > self.max_keys() {
+            self.split_child(idx, level, hooks);
+        }
+    }
+
+    /// Makes the root node the only child of a new root and splits it
+    ///
+    /// Returns the new root
+    pub(super) fn split_root(root: Child<T>, hooks: &mut Hooks<T>) -> Child<T> {
+        let mut new_root: Node<T> = Node{ keys: vec![], children: vec![], leaf: false, order: root.order };
+        new_root.children.push(root);
+        hooks.root_grow(&new_root);
+        new_root.split_child(0, 0, hooks);
+        Child::new(new_root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::b_tree::BTreeObserver;
+    use rand::rngs::StdRng;
+    use rand::seq::SliceRandom;
+    use rand::SeedableRng;
+    use std::sync::{Arc, Mutex};
+
+    /// Counts splits
+    struct SplitCounter(Arc<Mutex<usize>>);
+
+    impl BTreeObserver<i32> for SplitCounter {
+        fn on_split(&mut self, _level: usize, _median: &i32) {
+            *self.0.lock().unwrap() += 1;
+        }
+    }
+
+    /// Inserts values into a tree with the given strategy and returns it with the number of splits
+    fn build(order: usize, strategy: InsertStrategy, values: &[i32]) -> (BTree<i32>, usize) {
+        let splits = Arc::new(Mutex::new(0));
+        let mut btree = BTree::with_strategy(order, strategy);
+        btree.set_observer(SplitCounter(Arc::clone(&splits)));
+        for &val in values {
+            btree.insert(val);
+        }
+        let splits = *splits.lock().unwrap();
+        (btree, splits)
+    }
+
+    #[test]
+    fn test_default_strategy() {
+        assert_eq!(BTree::<i32>::new(3).insert_strategy(), InsertStrategy::BottomUp);
+        assert_eq!(BTree::<i32>::new(4).insert_strategy(), InsertStrategy::TopDown);
+        assert_eq!(BTree::<i32>::new(7).insert_strategy(), InsertStrategy::BottomUp);
+        assert_eq!(BTree::<i32>::with_min_degree(3).insert_strategy(), InsertStrategy::TopDown);
+        assert_eq!(BTree::<i32>::new_b_star(5).insert_strategy(), InsertStrategy::BStar);
+    }
+
+    #[test]
+    #[should_panic(expected = "TopDown insertion needs an even order")]
+    fn test_top_down_odd_order() {
+        let _btree: BTree<i32> = BTree::with_strategy(5, InsertStrategy::TopDown);
+    }
+
+    #[test]
+    fn test_bottom_up_every_order() {
+        for order in 3..=10 {
+            let mut values: Vec<i32> = (0..300).collect();
+            values.shuffle(&mut StdRng::seed_from_u64(order as u64));
+
+            let (mut btree, _) = build(order, InsertStrategy::BottomUp, &values);
+            btree.check_invariants();
+            for &val in &values {
+                assert!(btree.search(val));
+            }
+
+            // Deletion is the same for every strategy
+            for &val in &values[..250] {
+                btree.delete(val);
+                btree.check_invariants();
+            }
+        }
+    }
+
+    #[test]
+    fn test_bottom_up_only_splits_on_overflow() {
+        // Order 4: the root [1, 2, 3] is full, but only splits once a fourth key is inserted
+        let (btree, splits) = build(4, InsertStrategy::BottomUp, &[1, 2, 3]);
+        assert_eq!(splits, 0);
+        assert!(btree.root.as_ref().unwrap().leaf);
+
+        let (btree, splits) = build(4, InsertStrategy::BottomUp, &[1, 2, 3, 4]);
+        assert_eq!(splits, 1);
+        let root = btree.root.as_ref().unwrap();
+        assert_eq!(root.keys, vec![3]);
+        assert_eq!(root.children[0].keys, vec![1, 2]);
+        assert_eq!(root.children[1].keys, vec![4]);
+
+        // TopDown splits the full root before inserting 4, with the median taken from [1, 2, 3]
+        let (btree, splits) = build(4, InsertStrategy::TopDown, &[1, 2, 3, 4]);
+        assert_eq!(splits, 1);
+        assert_eq!(btree.root.as_ref().unwrap().keys, vec![2]);
+    }
+
+    #[test]
+    fn test_top_down_splits_more_than_bottom_up() {
+        // TopDown splits every full node it passes, even when the insert would not overflow it
+        let mut values: Vec<i32> = (0..2000).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(7));
+        for order in [4, 6, 8, 16] {
+            let (top_down, top_down_splits) = build(order, InsertStrategy::TopDown, &values);
+            let (bottom_up, bottom_up_splits) = build(order, InsertStrategy::BottomUp, &values);
+
+            top_down.check_invariants();
+            bottom_up.check_invariants();
+            assert!(bottom_up_splits <= top_down_splits);
+        }
+    }
+
+    #[test]
+    fn test_order_3_bottom_up() {
+        // The 2-3 tree is just BottomUp with order 3
+        let (btree, splits) = build(3, InsertStrategy::BottomUp, &[1, 2, 3, 4, 5]);
+        assert_eq!(splits, 2);
+        let root = btree.root.as_ref().unwrap();
+        assert_eq!(root.keys, vec![2, 4]);
+        btree.check_invariants();
+    }
+}
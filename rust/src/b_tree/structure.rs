@@ -0,0 +1,225 @@
+// Printable view of the node layout of a BTree
+//
+// Plain style (the default) indents each level by two spaces:
+//   Node (leaf=false): [4]
+//     Node (leaf=true): [1, 2, 3]
+//
+// Box-drawing style draws the branches instead:
+//   Node (leaf=false): [4]
+//   ├── Node (leaf=true): [1, 2, 3]
+//   └── Node (leaf=true): [5, 6]
+
+use std::fmt::{self, Debug, Display};
+
+use super::{BTree, Node};
+
+/// A view of the node layout of a BTree that implements Display
+///
+/// Created by BTree::structure, and configured with max_depth, max_keys and box_drawing
+pub struct Structure<'a, T: PartialOrd + Debug + Clone> {
+    tree: &'a BTree<T>,
+    max_depth: Option<usize>,
+    max_keys: Option<usize>,
+    box_drawing: bool,
+}
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Returns a Display view of the b-tree's node layout
+    ///
+    /// The view can be printed, formatted into a string, or written to any fmt::Write
+    pub fn structure(&self) -> Structure<'_, T> {
+        Structure { tree: self, max_depth: None, max_keys: None, box_drawing: false }
+    }
+}
+
+impl<'a, T: PartialOrd + Debug + Clone> Structure<'a, T> {
+    /// Only prints nodes down to the given depth (the root is depth 0)
+    ///
+    /// Subtrees below that depth are replaced with a single elision line
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Only prints the first n keys of each node, followed by a count of the keys left out
+    pub fn max_keys(mut self, n: usize) -> Self {
+        self.max_keys = Some(n);
+        self
+    }
+
+    /// Draws branches with box-drawing characters instead of plain indentation
+    pub fn box_drawing(mut self, enabled: bool) -> Self {
+        self.box_drawing = enabled;
+        self
+    }
+
+    /// Writes the structure to any fmt::Write (e.g. a String)
+    pub fn write_to<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        match &self.tree.root {
+            Some(r) => {
+                writeln!(out, "=== BTree Structure (Order {}) ===", self.tree.order)?;
+                writeln!(out)?;
+                self.write_node(out, r, 0, "", "")
+            },
+            None => writeln!(out, "Empty tree"),
+        }
+    }
+
+    /// Writes a node and its subtree (called recursively)
+    ///
+    /// prefix is written before this node's line and child_prefix before the lines of its children
+    fn write_node<W: fmt::Write>(&self, out: &mut W, node: &Node<T>, depth: usize, prefix: &str, child_prefix: &str) -> fmt::Result {
+        write!(out, "{}Node (leaf={}): ", prefix, node.leaf)?;
+        self.write_keys(out, &node.keys)?;
+        writeln!(out)?;
+
+        if node.leaf {
+            return Ok(());
+        }
+
+        // Replace everything below the depth limit with a single line
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            let (branch, _) = self.branch(true);
+            return writeln!(out, "{}{}... ({} children hidden)", child_prefix, branch, node.children.len());
+        }
+
+        for (i, child) in node.children.iter().enumerate() {
+            let (branch, continuation) = self.branch(i == node.children.len() - 1);
+            self.write_node(
+                out,
+                child,
+                depth + 1,
+                &format!("{}{}", child_prefix, branch),
+                &format!("{}{}", child_prefix, continuation),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Writes a node's keys, truncated to max_keys if set
+    fn write_keys<W: fmt::Write>(&self, out: &mut W, keys: &[T]) -> fmt::Result {
+        match self.max_keys {
+            Some(n) if keys.len() > n => {
+                write!(out, "[")?;
+                for key in &keys[..n] {
+                    write!(out, "{:?}, ", key)?;
+                }
+                write!(out, "... +{} more]", keys.len() - n)
+            },
+            _ => write!(out, "{:?}", keys),
+        }
+    }
+
+    /// Returns the (branch, continuation) prefixes for a child line and the lines below it
+    fn branch(&self, last: bool) -> (&'static str, &'static str) {
+        match (self.box_drawing, last) {
+            (false, _) => ("  ", "  "),
+            (true, false) => ("├── ", "│   "),
+            (true, true) => ("└── ", "    "),
+        }
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Display for Structure<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_to(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_structure_empty_tree() {
+        let btree: BTree<i32> = BTree::new(3);
+        assert_eq!(btree.structure().to_string(), "Empty tree\n");
+    }
+
+    #[test]
+    fn test_structure_plain() {
+        let mut btree = BTree::new(3);
+        for i in 1..=7 {
+            btree.insert(i);
+        }
+
+        let expected = "\
+=== BTree Structure (Order 3) ===
+
+Node (leaf=false): [4]
+  Node (leaf=false): [2]
+    Node (leaf=true): [1]
+    Node (leaf=true): [3]
+  Node (leaf=false): [6]
+    Node (leaf=true): [5]
+    Node (leaf=true): [7]
+";
+        assert_eq!(btree.structure().to_string(), expected);
+    }
+
+    #[test]
+    fn test_structure_box_drawing() {
+        let mut btree = BTree::new(3);
+        for i in 1..=7 {
+            btree.insert(i);
+        }
+
+        let expected = "\
+=== BTree Structure (Order 3) ===
+
+Node (leaf=false): [4]
+├── Node (leaf=false): [2]
+│   ├── Node (leaf=true): [1]
+│   └── Node (leaf=true): [3]
+└── Node (leaf=false): [6]
+    ├── Node (leaf=true): [5]
+    └── Node (leaf=true): [7]
+";
+        assert_eq!(btree.structure().box_drawing(true).to_string(), expected);
+    }
+
+    #[test]
+    fn test_structure_max_depth() {
+        let mut btree = BTree::new(3);
+        for i in 1..=7 {
+            btree.insert(i);
+        }
+
+        let output = btree.structure().max_depth(1).to_string();
+
+        assert!(output.contains("  Node (leaf=false): [2]\n    ... (2 children hidden)\n"));
+        assert!(!output.contains("leaf=true"));
+
+        let output = btree.structure().max_depth(0).box_drawing(true).to_string();
+        assert!(output.ends_with("Node (leaf=false): [4]\n└── ... (2 children hidden)\n"));
+    }
+
+    #[test]
+    fn test_structure_max_keys() {
+        let mut btree = BTree::new(11);
+        for i in 1..=8 {
+            btree.insert(i);
+        }
+
+        let output = btree.structure().max_keys(3).to_string();
+        assert!(output.contains("Node (leaf=true): [1, 2, 3, ... +5 more]"));
+
+        // Nodes within the limit are printed in full
+        let output = btree.structure().max_keys(8).to_string();
+        assert!(output.contains("Node (leaf=true): [1, 2, 3, 4, 5, 6, 7, 8]"));
+    }
+
+    #[test]
+    fn test_structure_write_to_string() {
+        use std::fmt::Write;
+
+        let mut btree = BTree::new(3);
+        btree.insert(1);
+
+        let mut out = String::new();
+        writeln!(out, "before").unwrap();
+        btree.structure().write_to(&mut out).unwrap();
+        assert!(out.starts_with("before\n=== BTree Structure (Order 3) ==="));
+        assert!(out.ends_with("Node (leaf=true): [1]\n"));
+    }
+}
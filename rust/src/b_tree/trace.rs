@@ -0,0 +1,286 @@
+// Step-by-step traces of insert and delete, for animating the algorithms
+//
+// insert_traced and delete_traced run the normal operation with tracing switched on in the
+// Hooks passed through the node helpers. Each helper records the node it visits, the delete
+// case it takes and the structural changes it makes, along with a snapshot of the subtree
+// involved. The last step is always Finished with a snapshot of the whole tree.
+//
+// With the "serde" feature every type here is Serialize/Deserialize, so a trace can be written
+// out as JSON and replayed by a frontend.
+
+use std::fmt::Debug;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{BTree, Node};
+
+/// A copy of the keys of a node and all of its descendants
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct NodeSnapshot<T> {
+    pub keys: Vec<T>,
+    pub children: Vec<NodeSnapshot<T>>,
+}
+
+/// The cases of Node::delete (see the comments there)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DeleteCase {
+    /// The value is in a leaf node and is removed directly
+    #[cfg_attr(feature = "serde", serde(rename = "1"))]
+    Case1,
+    /// The value is in an internal node and is replaced with its predecessor
+    #[cfg_attr(feature = "serde", serde(rename = "2a"))]
+    Case2a,
+    /// The value is in an internal node and is replaced with its successor
+    #[cfg_attr(feature = "serde", serde(rename = "2b"))]
+    Case2b,
+    /// The value is in an internal node whose neighbouring children are merged around it
+    #[cfg_attr(feature = "serde", serde(rename = "2c"))]
+    Case2c,
+    /// The child to descend into borrows a key from its left sibling (rotate right)
+    #[cfg_attr(feature = "serde", serde(rename = "3a"))]
+    Case3a,
+    /// The child to descend into borrows a key from its right sibling (rotate left)
+    #[cfg_attr(feature = "serde", serde(rename = "3b"))]
+    Case3b,
+    /// The child to descend into is merged with a sibling
+    #[cfg_attr(feature = "serde", serde(rename = "3c"))]
+    Case3c,
+}
+
+/// What happened in a single step of a traced operation
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum TraceAction<T> {
+    /// The node was visited on the way down
+    Visit,
+    /// The value was inserted into the (leaf) node at index
+    InsertKey { index: usize },
+    /// Node::delete chose this case at the node
+    DeleteCase { case: DeleteCase },
+    /// A child was split and median moved up into the node
+    Split { median: T },
+    /// Two children were merged, pulling separator down from the node
+    Merge { separator: T },
+    /// A key moved from the right child through the node into the left child
+    RotateLeft { separator: T },
+    /// A key moved from the left child through the node into the right child
+    RotateRight { separator: T },
+    /// A new root was created above the old one
+    RootGrow,
+    /// An empty root was replaced by its only child
+    RootShrink,
+    /// The operation finished (the snapshot is the whole tree)
+    Finished,
+}
+
+/// A single step of a traced operation
+///
+/// level is the depth (root = 0) of the node the action happened to. For splits, merges and
+/// rotations that is the depth of the children involved, as with BTreeObserver, while snapshot
+/// is the subtree of their parent. Every other snapshot is the subtree of the node at level.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TraceStep<T> {
+    pub level: usize,
+    pub action: TraceAction<T>,
+    pub snapshot: NodeSnapshot<T>,
+}
+
+impl<T: PartialOrd + Debug + Clone> BTree<T> {
+    /// Inserts a value into the b-tree and returns every step the insertion took
+    pub fn insert_traced(&mut self, value: T) -> Vec<TraceStep<T>> {
+        let mut steps = Vec::new();
+        self.insert_inner(value, Some(&mut steps));
+        self.finish_trace(&mut steps);
+        steps
+    }
+
+    /// Deletes a value from the b-tree and returns every step the deletion took
+    pub fn delete_traced(&mut self, value: T) -> Vec<TraceStep<T>> {
+        let mut steps = Vec::new();
+        self.delete_inner(value, Some(&mut steps));
+        self.finish_trace(&mut steps);
+        steps
+    }
+
+    /// Appends the Finished step with a snapshot of the whole tree
+    fn finish_trace(&self, steps: &mut Vec<TraceStep<T>>) {
+        let snapshot = match &self.root {
+            Some(r) => r.snapshot(),
+            None => NodeSnapshot { keys: vec![], children: vec![] },
+        };
+        steps.push(TraceStep { level: 0, action: TraceAction::Finished, snapshot });
+    }
+}
+
+impl<T: PartialOrd + Debug + Clone> Node<T> {
+    /// Copies the keys of this node and its subtree
+    pub(super) fn snapshot(&self) -> NodeSnapshot<T> {
+        NodeSnapshot {
+            keys: self.keys.clone(),
+            children: self.children.iter().map(|child| child.snapshot()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions<T: Clone>(steps: &[TraceStep<T>]) -> Vec<TraceAction<T>> {
+        steps.iter().map(|step| step.action.clone()).collect()
+    }
+
+    fn cases<T>(steps: &[TraceStep<T>]) -> Vec<DeleteCase> {
+        steps
+            .iter()
+            .filter_map(|step| match step.action {
+                TraceAction::DeleteCase { case } => Some(case),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn leaf(keys: Vec<i32>) -> NodeSnapshot<i32> {
+        NodeSnapshot { keys, children: vec![] }
+    }
+
+    #[test]
+    fn test_insert_traced_into_empty_tree() {
+        let mut btree = BTree::new(3);
+        let steps = btree.insert_traced(1);
+
+        assert_eq!(actions(&steps), vec![TraceAction::InsertKey { index: 0 }, TraceAction::Finished]);
+        assert_eq!(steps[1].snapshot, leaf(vec![1]));
+    }
+
+    #[test]
+    fn test_insert_traced_root_split() {
+        let mut btree = BTree::new(5);
+        for i in 1..=4 {
+            btree.insert(i);
+        }
+        let steps = btree.insert_traced(5);
+
+        // Order 5 splits on overflow, so 5 goes into the root leaf before it splits
+        assert_eq!(
+            actions(&steps),
+            vec![
+                TraceAction::Visit,
+                TraceAction::InsertKey { index: 4 },
+                TraceAction::RootGrow,
+                TraceAction::Split { median: 3 },
+                TraceAction::Finished,
+            ]
+        );
+
+        assert_eq!(steps[1].level, 0);
+        assert_eq!(steps[1].snapshot, leaf(vec![1, 2, 3, 4, 5]));
+        // The split is reported at the level of the old root, with the new root as the snapshot
+        assert_eq!(steps[3].level, 1);
+        assert_eq!(steps[3].snapshot, NodeSnapshot { keys: vec![3], children: vec![leaf(vec![1, 2]), leaf(vec![4, 5])] });
+        assert_eq!(steps[4].snapshot, steps[3].snapshot);
+    }
+
+    #[test]
+    fn test_delete_traced_case_1() {
+        let mut btree = BTree::new(5);
+        for i in 1..=3 {
+            btree.insert(i);
+        }
+        let steps = btree.delete_traced(2);
+
+        assert_eq!(cases(&steps), vec![DeleteCase::Case1]);
+        assert_eq!(steps.last().unwrap().snapshot, leaf(vec![1, 3]));
+    }
+
+    #[test]
+    fn test_delete_traced_case_2a_and_2b() {
+        let mut btree = BTree::new(5);
+        for i in 1..=6 {
+            btree.insert(i);
+        }
+        // Root [3] with leaves [1, 2] and [4, 5, 6]: predecessor side is too small, so 2b
+        let steps = btree.delete_traced(3);
+        assert_eq!(cases(&steps), vec![DeleteCase::Case2b, DeleteCase::Case1]);
+
+        // Root [4] with leaves [1, 2] and [5, 6]: both too small, so 2c merges them
+        let steps = btree.delete_traced(4);
+        assert_eq!(cases(&steps), vec![DeleteCase::Case2c, DeleteCase::Case1]);
+        assert!(actions(&steps).contains(&TraceAction::Merge { separator: 4 }));
+        assert!(actions(&steps).contains(&TraceAction::RootShrink));
+
+        let mut btree = BTree::new(5);
+        for i in [1, 2, 3, 4, 5, 0] {
+            btree.insert(i);
+        }
+        // Root [3] with leaves [0, 1, 2] and [4, 5]: predecessor side has spare keys, so 2a
+        let steps = btree.delete_traced(3);
+        assert_eq!(cases(&steps), vec![DeleteCase::Case2a, DeleteCase::Case1]);
+    }
+
+    #[test]
+    fn test_delete_traced_case_3() {
+        let mut btree = BTree::new(5);
+        for i in 1..=6 {
+            btree.insert(i);
+        }
+        // Root [3] with leaves [1, 2] and [4, 5, 6]: the right sibling has a spare key, so 3b
+        let steps = btree.delete_traced(1);
+        assert_eq!(cases(&steps), vec![DeleteCase::Case3b, DeleteCase::Case1]);
+        assert!(actions(&steps).contains(&TraceAction::RotateLeft { separator: 4 }));
+
+        // Root [4] with leaves [2, 3] and [5, 6]: no spare keys on either side, so 3c
+        let steps = btree.delete_traced(6);
+        assert_eq!(cases(&steps), vec![DeleteCase::Case3c, DeleteCase::Case1]);
+        assert!(actions(&steps).contains(&TraceAction::Merge { separator: 4 }));
+        assert_eq!(steps.last().unwrap().snapshot, leaf(vec![2, 3, 4, 5]));
+
+        let mut btree = BTree::new(5);
+        for i in [1, 2, 3, 4, 5, 0] {
+            btree.insert(i);
+        }
+        // Root [3] with leaves [0, 1, 2] and [4, 5]: the left sibling has a spare key, so 3a
+        let steps = btree.delete_traced(5);
+        assert_eq!(cases(&steps), vec![DeleteCase::Case3a, DeleteCase::Case1]);
+        assert!(actions(&steps).contains(&TraceAction::RotateRight { separator: 2 }));
+        assert_eq!(steps.last().unwrap().snapshot, NodeSnapshot { keys: vec![2], children: vec![leaf(vec![0, 1]), leaf(vec![3, 4])] });
+    }
+
+    #[test]
+    fn test_traced_matches_untraced() {
+        let mut traced = BTree::new(4);
+        let mut plain = BTree::new(4);
+        for i in [8, 3, 14, 1, 9, 12, 5, 7, 2, 11, 6, 13, 4, 10] {
+            traced.insert_traced(i);
+            plain.insert(i);
+        }
+        for i in [3, 12, 8, 1, 13] {
+            traced.delete_traced(i);
+            plain.delete(i);
+        }
+
+        assert_eq!(traced.structure().to_string(), plain.structure().to_string());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_trace_json_round_trip() {
+        let mut btree = BTree::new(3);
+        for i in 1..=6 {
+            btree.insert(i);
+        }
+        let steps = btree.delete_traced(1);
+
+        let json = serde_json::to_string(&steps).unwrap();
+        assert!(json.contains("\"type\":\"delete_case\""));
+        assert!(json.contains("\"case\":\"3c\""));
+
+        let decoded: Vec<TraceStep<i32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, steps);
+    }
+}
@@ -1,5 +1,9 @@
 // Module declarations
+pub mod b_plus_tree;
 pub mod b_tree;
 
 // Re-exports for convenience
-pub use b_tree::BTree;
\ No newline at end of file
+pub use b_plus_tree::{BPlusTree, MvccBPlusTree};
+pub use b_tree::{ArenaBTree, BLinkTree, BTree, ConcurrentBTree, ConstBTree, OptimisticBTree, PagedBTree, PersistentBTree, PrefixBTree};
+#[cfg(feature = "mmap")]
+pub use b_tree::MmapBTree;